@@ -1,7 +1,10 @@
-//! Export MongoDB data to SQLite for user consumption
+//! Export MongoDB data to SQLite for user consumption, and SQLite data to a
+//! portable JSON document
 
 use std::path::Path;
 
+use serde::{Deserialize, Serialize};
+
 use crate::error::Result;
 use crate::models::*;
 use crate::mongo::MongoDatabase;
@@ -22,8 +25,8 @@ pub async fn export_to_sqlite(mongo: &MongoDatabase, output_path: &Path) -> Resu
             pkg_id: pkg_doc.pkg_id.clone(),
             pkg_name: pkg_doc.pkg_name.clone(),
             pkg_family: pkg_doc.pkg_family.clone(),
-            build_script: String::new(),
-            ghcr_pkg: String::new(),
+            build_script: pkg_doc.build_script.clone().unwrap_or_default(),
+            ghcr_pkg: pkg_doc.ghcr_pkg.clone().unwrap_or_default(),
             host_triplet: pkg_doc.host_triplet.clone(),
             current_version: pkg_doc.current_version.clone(),
             upstream_version: pkg_doc.upstream_version.clone(),
@@ -70,6 +73,7 @@ pub async fn export_to_sqlite(mongo: &MongoDatabase, output_path: &Path) -> Resu
                 ghcr_digest: None,
                 build_log_url: hist.build_log_url.clone(),
                 error_message: hist.error_message.clone(),
+                skip_reason: hist.skip_reason.clone(),
             };
 
             sqlite_db.import_build_history(&pkg_doc.pkg_id, &pkg_doc.host_triplet, &entry)?;
@@ -78,3 +82,196 @@ pub async fn export_to_sqlite(mongo: &MongoDatabase, output_path: &Path) -> Resu
 
     Ok(())
 }
+
+/// Portable JSON form of a cache database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheDump {
+    /// Schema version of the database the dump was taken from
+    pub schema_version: i32,
+    pub packages: Vec<PackageDump>,
+}
+
+/// A package with its build history, failure backoff and work queue claim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageDump {
+    #[serde(flatten)]
+    pub package: PackageRecord,
+    pub history: Vec<BuildHistoryEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailedPackage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claim: Option<PackageClaim>,
+}
+
+/// Dump every package with its history, failure record and claim. Row ids are
+/// dropped since they only mean something inside one database.
+pub fn export_to_json(db: &CacheDatabase) -> Result<CacheDump> {
+    let mut packages = Vec::new();
+    for mut package in db.list_all_packages()? {
+        let history = db
+            .get_build_history(&package.pkg_id, &package.host_triplet)?
+            .into_iter()
+            .map(|entry| BuildHistoryEntry {
+                id: None,
+                package_id: 0,
+                ..entry
+            })
+            .collect();
+        let failure = db
+            .get_failure(&package.pkg_id, &package.host_triplet)?
+            .map(|failure| FailedPackage {
+                id: None,
+                package_id: 0,
+                ..failure
+            });
+        let claim = db.get_claim(&package.pkg_id, &package.host_triplet)?;
+        package.id = None;
+        packages.push(PackageDump {
+            package,
+            history,
+            failure,
+            claim,
+        });
+    }
+
+    Ok(CacheDump {
+        schema_version: crate::schema::SCHEMA_VERSION,
+        packages,
+    })
+}
+
+/// Load a dump into `db`, replacing packages that already exist there.
+/// Returns the number of packages imported.
+pub fn import_from_json(db: &CacheDatabase, dump: &CacheDump) -> Result<usize> {
+    for entry in &dump.packages {
+        let package = &entry.package;
+        db.import_package(package)?;
+        for hist in &entry.history {
+            db.import_build_history(&package.pkg_id, &package.host_triplet, hist)?;
+        }
+        if let Some(failure) = &entry.failure {
+            db.import_failure(&package.pkg_id, &package.host_triplet, failure)?;
+        }
+        if let Some(claim) = &entry.claim {
+            db.import_claim(&package.pkg_id, &package.host_triplet, claim)?;
+        }
+    }
+    Ok(dump.packages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip_is_stable() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.get_or_create_package("github.com.test.pkg", "testpkg", "x86_64-linux")
+            .unwrap();
+        db.update_build_result(
+            "github.com.test.pkg",
+            "x86_64-linux",
+            "1.0.0",
+            BuildStatus::Success,
+            Some("build-1"),
+            Some("1.0.0-x86_64-linux"),
+            Some("hash"),
+            Some("1.0.0"),
+            None,
+            0,
+            None,
+        )
+        .unwrap();
+        db.update_build_result(
+            "github.com.test.pkg",
+            "x86_64-linux",
+            "1.0.1",
+            BuildStatus::Failed,
+            Some("build-2"),
+            None,
+            Some("hash"),
+            Some("1.0.1"),
+            None,
+            0,
+            None,
+        )
+        .unwrap();
+
+        let first = serde_json::to_string_pretty(&export_to_json(&db).unwrap()).unwrap();
+        assert_eq!(export_to_json(&db).unwrap().packages[0].history.len(), 2);
+
+        let dump: CacheDump = serde_json::from_str(&first).unwrap();
+        let fresh = CacheDatabase::in_memory().unwrap();
+        assert_eq!(import_from_json(&fresh, &dump).unwrap(), 1);
+
+        let second = serde_json::to_string_pretty(&export_to_json(&fresh).unwrap()).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn json_round_trip_keeps_failure_and_claim() {
+        let db = CacheDatabase::in_memory().unwrap();
+        for pkg_id in ["github.com.test.a", "github.com.test.b"] {
+            db.get_or_create_package(pkg_id, pkg_id, "x86_64-linux")
+                .unwrap();
+        }
+        db.record_failure("github.com.test.a", "x86_64-linux", "boom")
+            .unwrap();
+        db.record_failure("github.com.test.a", "x86_64-linux", "boom again")
+            .unwrap();
+        let claimed = db
+            .claim_next_rebuild("x86_64-linux", "worker-1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(claimed.pkg_id, "github.com.test.b");
+
+        let dump = export_to_json(&db).unwrap();
+        let json = serde_json::to_string(&dump).unwrap();
+        let fresh = CacheDatabase::in_memory().unwrap();
+        import_from_json(&fresh, &serde_json::from_str(&json).unwrap()).unwrap();
+
+        let before = db
+            .get_failure("github.com.test.a", "x86_64-linux")
+            .unwrap()
+            .unwrap();
+        let after = fresh
+            .get_failure("github.com.test.a", "x86_64-linux")
+            .unwrap()
+            .unwrap();
+        assert_eq!(after.failure_count, 2);
+        assert_eq!(after.last_error_message.as_deref(), Some("boom again"));
+        assert_eq!(
+            after.last_failure_date.timestamp(),
+            before.last_failure_date.timestamp()
+        );
+        assert_eq!(
+            after.next_retry_date.map(|d| d.timestamp()),
+            before.next_retry_date.map(|d| d.timestamp())
+        );
+        assert!(!fresh
+            .is_retry_allowed("github.com.test.a", "x86_64-linux")
+            .unwrap());
+
+        let claim = fresh
+            .get_claim("github.com.test.b", "x86_64-linux")
+            .unwrap()
+            .unwrap();
+        assert_eq!(claim.worker_id, "worker-1");
+        assert_eq!(
+            claim.claimed_at.timestamp(),
+            db.get_claim("github.com.test.b", "x86_64-linux")
+                .unwrap()
+                .unwrap()
+                .claimed_at
+                .timestamp()
+        );
+        assert!(fresh
+            .get_claim("github.com.test.a", "x86_64-linux")
+            .unwrap()
+            .is_none());
+        assert!(fresh
+            .claim_next_rebuild("x86_64-linux", "worker-2")
+            .unwrap()
+            .is_none());
+    }
+}
@@ -17,3 +17,5 @@ pub use error::{Error, Result};
 pub use models::*;
 pub use mongo::MongoDatabase;
 pub use sqlite::CacheDatabase;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -1,8 +1,10 @@
 //! Data models for the cache database
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::error::Error;
+
 /// Build status enum
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -11,6 +13,10 @@ pub enum BuildStatus {
     Failed,
     Pending,
     Skipped,
+    /// Build was killed after exceeding its time limit
+    Timeout,
+    /// Build was stopped before finishing (e.g. interrupted or superseded)
+    Cancelled,
 }
 
 impl BuildStatus {
@@ -20,18 +26,50 @@ impl BuildStatus {
             BuildStatus::Failed => "failed",
             BuildStatus::Pending => "pending",
             BuildStatus::Skipped => "skipped",
+            BuildStatus::Timeout => "timeout",
+            BuildStatus::Cancelled => "cancelled",
         }
     }
 
+    /// Lenient parse that maps unknown values to `None`.
+    ///
+    /// Use [`str::parse`] (or `TryFrom`) when reading stored data, so an
+    /// unknown status is reported instead of looking like "never built".
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "success" => Some(BuildStatus::Success),
             "failed" => Some(BuildStatus::Failed),
             "pending" => Some(BuildStatus::Pending),
             "skipped" => Some(BuildStatus::Skipped),
+            "timeout" => Some(BuildStatus::Timeout),
+            "cancelled" => Some(BuildStatus::Cancelled),
             _ => None,
         }
     }
+
+    /// Whether this outcome counts towards failure backoff.
+    ///
+    /// Cancelled builds say nothing about the package, so they don't.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, BuildStatus::Failed | BuildStatus::Timeout)
+    }
+}
+
+/// Canonical form of a host triplet used for cache keys and GHCR tags.
+///
+/// Hosts are `{ARCH}-{OS}` in lowercase (`x86_64-linux`), so `x86_64-Linux`
+/// and `X86_64-LINUX` refer to the same cache entries.
+pub fn normalize_host_triplet(host: &str) -> String {
+    host.trim().to_lowercase()
+}
+
+/// Host triplet of the machine running this binary
+pub fn current_host_triplet() -> String {
+    normalize_host_triplet(&format!(
+        "{}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    ))
 }
 
 impl std::fmt::Display for BuildStatus {
@@ -40,6 +78,22 @@ impl std::fmt::Display for BuildStatus {
     }
 }
 
+impl std::str::FromStr for BuildStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BuildStatus::from_str(s).ok_or_else(|| Error::InvalidStatus(s.to_string()))
+    }
+}
+
+impl TryFrom<&str> for BuildStatus {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
 /// Package record in the cache
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageRecord {
@@ -103,6 +157,67 @@ impl PackageRecord {
             updated_at: now,
         }
     }
+
+    /// Whether this package was built from `recipe_path`: one of it and the
+    /// recorded `build_script` ends with the other at a `/`, so a checkout
+    /// path matches a repo-relative path or a raw URL.
+    pub fn built_from(&self, recipe_path: &str) -> bool {
+        let recipe_path = recipe_path.trim_start_matches("./");
+        let script = self.build_script.trim_start_matches("./");
+        if script.is_empty() || recipe_path.is_empty() {
+            return false;
+        }
+        let ends_with = |long: &str, short: &str| {
+            long == short
+                || long
+                    .strip_suffix(short)
+                    .is_some_and(|prefix| prefix.ends_with('/'))
+        };
+        ends_with(script, recipe_path) || ends_with(recipe_path, script)
+    }
+}
+
+/// Descriptive fields for creating a package record
+///
+/// Unset optional fields are left untouched on existing records.
+#[derive(Debug, Clone, Default)]
+pub struct PackageRecordInit {
+    pub pkg_id: String,
+    pub pkg_name: String,
+    pub host_triplet: String,
+    pub pkg_family: Option<String>,
+    pub build_script: Option<String>,
+    pub ghcr_pkg: Option<String>,
+}
+
+impl PackageRecordInit {
+    pub fn new(
+        pkg_id: impl Into<String>,
+        pkg_name: impl Into<String>,
+        host_triplet: impl Into<String>,
+    ) -> Self {
+        Self {
+            pkg_id: pkg_id.into(),
+            pkg_name: pkg_name.into(),
+            host_triplet: host_triplet.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_family(mut self, pkg_family: impl Into<String>) -> Self {
+        self.pkg_family = Some(pkg_family.into());
+        self
+    }
+
+    pub fn with_build_script(mut self, build_script: impl Into<String>) -> Self {
+        self.build_script = Some(build_script.into());
+        self
+    }
+
+    pub fn with_ghcr_pkg(mut self, ghcr_pkg: impl Into<String>) -> Self {
+        self.ghcr_pkg = Some(ghcr_pkg.into());
+        self
+    }
 }
 
 /// Build history entry
@@ -120,6 +235,9 @@ pub struct BuildHistoryEntry {
     pub ghcr_digest: Option<String>,
     pub build_log_url: Option<String>,
     pub error_message: Option<String>,
+    /// Why the build was skipped (already built, disabled, backoff, ...)
+    #[serde(default)]
+    pub skip_reason: Option<String>,
 }
 
 /// Version cache entry
@@ -144,6 +262,48 @@ pub struct FailedPackage {
     pub next_retry_date: Option<DateTime<Utc>>,
 }
 
+/// Work queue claim held on a package
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageClaim {
+    pub worker_id: String,
+    pub claimed_at: DateTime<Utc>,
+    /// Status to restore if the claim is released without a result
+    #[serde(default)]
+    pub prior_status: Option<BuildStatus>,
+}
+
+/// Retry backoff applied after consecutive build failures
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BackoffPolicy {
+    /// Delay after the first failure
+    pub base_minutes: i64,
+    /// Upper bound on the delay
+    pub max_hours: i64,
+    /// Factor applied to the delay for each further failure
+    pub multiplier: f64,
+}
+
+impl Default for BackoffPolicy {
+    /// 1h, 2h, 4h, ... capped at 24h
+    fn default() -> Self {
+        Self {
+            base_minutes: 60,
+            max_hours: 24,
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// Delay before the next retry after `failure_count` consecutive failures
+    pub fn delay(&self, failure_count: i32) -> Duration {
+        let exponent = failure_count.saturating_sub(1).max(0);
+        let minutes = self.base_minutes as f64 * self.multiplier.powi(exponent);
+        let cap = (self.max_hours * 60) as f64;
+        Duration::minutes(minutes.min(cap) as i64)
+    }
+}
+
 /// Statistics for build operations
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct BuildStats {
@@ -154,6 +314,43 @@ pub struct BuildStats {
     pub outdated: i64,
 }
 
+/// Build outcomes for one UTC day, used for success-rate trends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyBuildStats {
+    pub date: NaiveDate,
+    pub successful: i64,
+    pub failed: i64,
+}
+
+impl DailyBuildStats {
+    /// Success rate in percent, or `None` on days without finished builds
+    pub fn success_rate(&self) -> Option<f64> {
+        let total = self.successful + self.failed;
+        (total > 0).then(|| self.successful as f64 / total as f64 * 100.0)
+    }
+
+    /// One entry per day for the `days` days ending at `today`, oldest
+    /// first, filling days missing from `counts` with zeros
+    pub fn series(
+        counts: &std::collections::HashMap<NaiveDate, (i64, i64)>,
+        today: NaiveDate,
+        days: i64,
+    ) -> Vec<Self> {
+        (0..days)
+            .rev()
+            .map(|offset| {
+                let date = today - Duration::days(offset);
+                let (successful, failed) = counts.get(&date).copied().unwrap_or((0, 0));
+                Self {
+                    date,
+                    successful,
+                    failed,
+                }
+            })
+            .collect()
+    }
+}
+
 /// Reason for rebuilding a package
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "reason")]
@@ -203,3 +400,63 @@ impl RebuildDecision {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_status_round_trip() {
+        for status in [
+            BuildStatus::Success,
+            BuildStatus::Failed,
+            BuildStatus::Pending,
+            BuildStatus::Skipped,
+            BuildStatus::Timeout,
+            BuildStatus::Cancelled,
+        ] {
+            assert_eq!(BuildStatus::from_str(status.as_str()), Some(status));
+            assert_eq!(status.to_string(), status.as_str());
+        }
+        assert!(matches!(
+            "bogus".parse::<BuildStatus>(),
+            Err(Error::InvalidStatus(s)) if s == "bogus"
+        ));
+        assert_eq!(
+            BuildStatus::try_from("FAILED").unwrap(),
+            BuildStatus::Failed
+        );
+        assert!(BuildStatus::Timeout.is_failure());
+        assert!(!BuildStatus::Cancelled.is_failure());
+    }
+
+    #[test]
+    fn test_built_from() {
+        let mut record = PackageRecord::new("a".into(), "a".into(), "x86_64-linux".into());
+        assert!(!record.built_from("binaries/a/static.yaml"));
+
+        record.build_script = "binaries/a/static.yaml".into();
+        assert!(record.built_from("binaries/a/static.yaml"));
+        assert!(record.built_from("./binaries/a/static.yaml"));
+        assert!(record.built_from("/src/soarpkgs/binaries/a/static.yaml"));
+        assert!(!record.built_from("/src/soarpkgs/binaries/ba/static.yaml"));
+        assert!(!record.built_from("binaries/a/appimage.yaml"));
+
+        record.build_script =
+            "https://raw.githubusercontent.com/pkgforge/soarpkgs/main/binaries/a/static.yaml"
+                .into();
+        assert!(record.built_from("binaries/a/static.yaml"));
+        assert!(!record.built_from("static.yaml/x"));
+    }
+
+    #[test]
+    fn test_normalize_host_triplet() {
+        assert_eq!(normalize_host_triplet("x86_64-Linux"), "x86_64-linux");
+        assert_eq!(normalize_host_triplet(" AARCH64-LINUX "), "aarch64-linux");
+        assert_eq!(normalize_host_triplet("riscv64-linux"), "riscv64-linux");
+        assert_eq!(
+            current_host_triplet(),
+            format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+        );
+    }
+}
@@ -18,6 +18,10 @@ pub struct PackageDocument {
     pub pkg_name: String,
     #[serde(default)]
     pub pkg_family: Option<String>,
+    #[serde(default)]
+    pub build_script: Option<String>,
+    #[serde(default)]
+    pub ghcr_pkg: Option<String>,
     pub host_triplet: String,
     #[serde(default)]
     pub current_version: Option<String>,
@@ -59,6 +63,8 @@ pub struct BuildHistoryDocument {
     pub error_message: Option<String>,
     #[serde(default)]
     pub build_log_url: Option<String>,
+    #[serde(default)]
+    pub skip_reason: Option<String>,
 }
 
 /// MongoDB cache database
@@ -90,6 +96,48 @@ impl MongoDatabase {
         Ok(mongo_db)
     }
 
+    /// Lowercase host triplets stored before they were normalized.
+    /// Returns the number of documents updated or removed.
+    ///
+    /// Mirrors the SQLite v9 migration: when a package exists under both
+    /// spellings, the most recently updated document is kept. This scans the
+    /// whole collection, so it's run once by hand (`cache migrate-hosts`)
+    /// rather than on every connect.
+    pub async fn normalize_host_triplets(&self) -> Result<usize> {
+        let filter = doc! { "host_triplet": { "$regex": "[A-Z]|^\\s|\\s$" } };
+        let mut cursor = self.collection.find(filter).await?;
+
+        let mut mixed = Vec::new();
+        while cursor.advance().await? {
+            mixed.push(cursor.deserialize_current()?);
+        }
+
+        let changed = mixed.len();
+        for pkg in mixed {
+            let host = normalize_host_triplet(&pkg.host_triplet);
+            let old_filter = doc! { "pkg_id": &pkg.pkg_id, "host_triplet": &pkg.host_triplet };
+            let new_filter = doc! { "pkg_id": &pkg.pkg_id, "host_triplet": &host };
+            match self.collection.find_one(new_filter.clone()).await? {
+                Some(existing) if existing.updated_at >= pkg.updated_at => {
+                    self.collection.delete_one(old_filter).await?;
+                }
+                Some(_) => {
+                    self.collection.delete_one(new_filter).await?;
+                    self.collection
+                        .update_one(old_filter, doc! { "$set": { "host_triplet": &host } })
+                        .await?;
+                }
+                None => {
+                    self.collection
+                        .update_one(old_filter, doc! { "$set": { "host_triplet": &host } })
+                        .await?;
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+
     /// Create indexes for efficient queries
     async fn ensure_indexes(&self) -> Result<()> {
         let unique_index = IndexModel::builder()
@@ -152,6 +200,54 @@ impl MongoDatabase {
             .ok_or_else(|| Error::Other("Failed to create package".to_string()))
     }
 
+    /// Get or create a package, filling in family, build script and GHCR package
+    ///
+    /// Fields set on `init` overwrite the stored values of an existing record.
+    pub async fn create_package_full(&self, init: &PackageRecordInit) -> Result<PackageRecord> {
+        let now = Utc::now();
+        let filter =
+            doc! { "pkg_id": init.pkg_id.as_str(), "host_triplet": init.host_triplet.as_str() };
+
+        let mut set = doc! { "updated_at": bson::DateTime::from_chrono(now) };
+        let mut set_on_insert = doc! {
+            "pkg_id": init.pkg_id.as_str(),
+            "pkg_name": init.pkg_name.as_str(),
+            "host_triplet": init.host_triplet.as_str(),
+            "current_version": Bson::Null,
+            "upstream_version": Bson::Null,
+            "is_outdated": false,
+            "recipe_hash": Bson::Null,
+            "base_version": Bson::Null,
+            "remote_version": Bson::Null,
+            "revision": 0_i32,
+            "build_history": Bson::Array(vec![]),
+            "snapshots": Bson::Array(vec![]),
+            "created_at": bson::DateTime::from_chrono(now),
+        };
+        for (field, value) in [
+            ("pkg_family", &init.pkg_family),
+            ("build_script", &init.build_script),
+            ("ghcr_pkg", &init.ghcr_pkg),
+        ] {
+            // A field may only appear in one of $set / $setOnInsert
+            match value {
+                Some(value) => set.insert(field, value.as_str()),
+                None => set_on_insert.insert(field, Bson::Null),
+            };
+        }
+
+        let update = doc! { "$set": set, "$setOnInsert": set_on_insert };
+        let options = UpdateOptions::builder().upsert(true).build();
+        self.raw_collection
+            .update_one(filter, update)
+            .with_options(options)
+            .await?;
+
+        self.get_package(&init.pkg_id, &init.host_triplet)
+            .await?
+            .ok_or_else(|| Error::Other("Failed to create package".to_string()))
+    }
+
     /// Get a package by ID and host
     pub async fn get_package(
         &self,
@@ -160,7 +256,7 @@ impl MongoDatabase {
     ) -> Result<Option<PackageRecord>> {
         let filter = doc! { "pkg_id": pkg_id, "host_triplet": host_triplet };
         let result = self.collection.find_one(filter).await?;
-        Ok(result.map(|d| pkg_doc_to_record(&d)))
+        result.map(|d| pkg_doc_to_record(&d)).transpose()
     }
 
     /// Find packages by name (or pkg_id suffix) and host
@@ -182,7 +278,7 @@ impl MongoDatabase {
         let mut results = Vec::new();
         while cursor.advance().await? {
             let doc = cursor.deserialize_current()?;
-            results.push(pkg_doc_to_record(&doc));
+            results.push(pkg_doc_to_record(&doc)?);
         }
         Ok(results)
     }
@@ -203,6 +299,7 @@ impl MongoDatabase {
         duration_seconds: Option<i64>,
         error_message: Option<&str>,
         build_log_url: Option<&str>,
+        skip_reason: Option<&str>,
     ) -> Result<()> {
         if version.is_empty() || version == "unknown" {
             return Err(Error::Other(format!(
@@ -224,6 +321,7 @@ impl MongoDatabase {
             "duration_seconds": duration_seconds,
             "error_message": error_message,
             "build_log_url": build_log_url,
+            "skip_reason": skip_reason,
         };
 
         // On success, also add version to snapshots (if not already present)
@@ -272,6 +370,39 @@ impl MongoDatabase {
         Ok(())
     }
 
+    /// Record a skipped build and why it was skipped. The version and
+    /// outdated flag stay as they are, since nothing was built.
+    ///
+    /// Matches [`CacheDatabase::mark_skipped`](crate::CacheDatabase::mark_skipped):
+    /// the last build date and status are read from the newest history
+    /// entry, so pushing it is what marks the package skipped. Documents
+    /// carry no claim to clear.
+    pub async fn mark_skipped(&self, pkg_id: &str, host_triplet: &str, reason: &str) -> Result<()> {
+        let record = self
+            .get_package(pkg_id, host_triplet)
+            .await?
+            .ok_or_else(|| Error::PackageNotFound(pkg_id.to_string()))?;
+        let now = bson::DateTime::from_chrono(Utc::now());
+        let filter = doc! { "pkg_id": pkg_id, "host_triplet": host_triplet };
+        let update = doc! {
+            "$set": { "updated_at": now },
+            "$push": {
+                "build_history": {
+                    "$each": [{
+                        "version": record.current_version.unwrap_or_default(),
+                        "build_date": now,
+                        "build_status": BuildStatus::Skipped.as_str(),
+                        "skip_reason": reason,
+                    }],
+                    "$slice": -3_i32,
+                }
+            }
+        };
+
+        self.raw_collection.update_one(filter, update).await?;
+        Ok(())
+    }
+
     /// Get the next revision number for a package version
     pub async fn get_revision(
         &self,
@@ -429,7 +560,7 @@ impl MongoDatabase {
         let mut results = Vec::new();
         while cursor.advance().await? {
             let doc = cursor.deserialize_current()?;
-            results.push(pkg_doc_to_record(&doc));
+            results.push(pkg_doc_to_record(&doc)?);
         }
 
         // Also check for failed packages with retry allowed
@@ -446,10 +577,10 @@ impl MongoDatabase {
         while all_cursor.advance().await? {
             let pkg_doc = all_cursor.deserialize_current()?;
             if let Some(last) = pkg_doc.build_history.last() {
-                if last.build_status == "failed"
+                if BuildStatus::from_str(&last.build_status).is_some_and(|s| s.is_failure())
                     && is_retry_allowed_from_history(&pkg_doc.build_history)
                 {
-                    results.push(pkg_doc_to_record(&pkg_doc));
+                    results.push(pkg_doc_to_record(&pkg_doc)?);
                 }
             }
         }
@@ -476,7 +607,7 @@ impl MongoDatabase {
                     "failed": {
                         "$sum": {
                             "$cond": [
-                                { "$eq": [{ "$last": "$build_history.build_status" }, "failed"] },
+                                { "$in": [{ "$last": "$build_history.build_status" }, ["failed", "timeout"]] },
                                 1, 0
                             ]
                         }
@@ -517,6 +648,60 @@ impl MongoDatabase {
         }
     }
 
+    /// Successful and failed builds per UTC day over the last `days` days
+    /// (including today), oldest first
+    pub async fn get_stats_history(
+        &self,
+        host_triplet: &str,
+        days: i64,
+    ) -> Result<Vec<DailyBuildStats>> {
+        let today = Utc::now().date_naive();
+        let start = (today - Duration::days(days - 1))
+            .and_hms_opt(0, 0, 0)
+            .map(|dt| dt.and_utc())
+            .unwrap_or_else(Utc::now);
+        let pipeline = vec![
+            doc! { "$match": { "host_triplet": host_triplet } },
+            doc! { "$unwind": "$build_history" },
+            doc! { "$match": {
+                "build_history.build_date": { "$gte": bson::DateTime::from_chrono(start) }
+            } },
+            doc! {
+                "$group": {
+                    "_id": {
+                        "$dateToString": { "format": "%Y-%m-%d", "date": "$build_history.build_date" }
+                    },
+                    "successful": {
+                        "$sum": { "$cond": [{ "$eq": ["$build_history.build_status", "success"] }, 1, 0] }
+                    },
+                    "failed": {
+                        "$sum": { "$cond": [{ "$in": ["$build_history.build_status", ["failed", "timeout"]] }, 1, 0] }
+                    },
+                }
+            },
+        ];
+
+        let mut cursor = self.raw_collection.aggregate(pipeline).await?;
+        let mut counts = std::collections::HashMap::new();
+        while cursor.advance().await? {
+            let doc = cursor.deserialize_current()?;
+            if let Some(date) = doc
+                .get_str("_id")
+                .ok()
+                .and_then(|d| d.parse::<chrono::NaiveDate>().ok())
+            {
+                counts.insert(
+                    date,
+                    (
+                        doc.get_i32("successful").unwrap_or(0) as i64,
+                        doc.get_i32("failed").unwrap_or(0) as i64,
+                    ),
+                );
+            }
+        }
+        Ok(DailyBuildStats::series(&counts, today, days))
+    }
+
     /// Check if retry is allowed for a package
     pub async fn is_retry_allowed(&self, pkg_id: &str, host_triplet: &str) -> Result<bool> {
         let filter = doc! { "pkg_id": pkg_id, "host_triplet": host_triplet };
@@ -577,7 +762,7 @@ impl MongoDatabase {
         let mut results = Vec::new();
         while cursor.advance().await? {
             let doc = cursor.deserialize_current()?;
-            results.push(pkg_doc_to_record(&doc));
+            results.push(pkg_doc_to_record(&doc)?);
         }
         Ok(results)
     }
@@ -596,6 +781,17 @@ impl MongoDatabase {
         Ok(results)
     }
 
+    /// Distinct host triplets with at least one package, sorted
+    pub async fn list_hosts(&self) -> Result<Vec<String>> {
+        let values = self.collection.distinct("host_triplet", doc! {}).await?;
+        let mut hosts: Vec<String> = values
+            .into_iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect();
+        hosts.sort();
+        Ok(hosts)
+    }
+
     /// Get recent build history
     pub async fn get_recent_builds(
         &self,
@@ -608,7 +804,29 @@ impl MongoDatabase {
             doc! { "$sort": { "build_history.build_date": -1 } },
             doc! { "$limit": limit },
         ];
+        self.query_recent_builds(pipeline).await
+    }
+
+    /// Get all builds since `since`, newest first
+    pub async fn get_recent_builds_since(
+        &self,
+        host_triplet: &str,
+        since: chrono::DateTime<Utc>,
+    ) -> Result<Vec<(PackageRecord, BuildHistoryEntry)>> {
+        let since = bson::DateTime::from_chrono(since);
+        let pipeline = vec![
+            doc! { "$match": { "host_triplet": host_triplet } },
+            doc! { "$unwind": "$build_history" },
+            doc! { "$match": { "build_history.build_date": { "$gte": since } } },
+            doc! { "$sort": { "build_history.build_date": -1 } },
+        ];
+        self.query_recent_builds(pipeline).await
+    }
 
+    async fn query_recent_builds(
+        &self,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<(PackageRecord, BuildHistoryEntry)>> {
         let mut cursor = self.raw_collection.aggregate(pipeline).await?;
         let mut results = Vec::new();
 
@@ -624,8 +842,8 @@ impl MongoDatabase {
                 pkg_id: pkg_id.to_string(),
                 pkg_name: pkg_name.to_string(),
                 pkg_family: doc.get_str("pkg_family").ok().map(|s| s.to_string()),
-                build_script: String::new(),
-                ghcr_pkg: String::new(),
+                build_script: doc.get_str("build_script").unwrap_or_default().to_string(),
+                ghcr_pkg: doc.get_str("ghcr_pkg").unwrap_or_default().to_string(),
                 host_triplet: host.to_string(),
                 current_version: doc.get_str("current_version").ok().map(|s| s.to_string()),
                 upstream_version: doc.get_str("upstream_version").ok().map(|s| s.to_string()),
@@ -677,7 +895,8 @@ impl MongoDatabase {
                     build_status: hist_doc
                         .get_str("build_status")
                         .ok()
-                        .and_then(BuildStatus::from_str)
+                        .map(str::parse)
+                        .transpose()?
                         .unwrap_or(BuildStatus::Pending),
                     duration_seconds: hist_doc
                         .get_i64("duration_seconds")
@@ -694,6 +913,7 @@ impl MongoDatabase {
                         .get_str("error_message")
                         .ok()
                         .map(|s| s.to_string()),
+                    skip_reason: hist_doc.get_str("skip_reason").ok().map(|s| s.to_string()),
                 };
                 results.push((record, entry));
             }
@@ -704,11 +924,16 @@ impl MongoDatabase {
 
 /// Check if retry is allowed based on build history
 fn is_retry_allowed_from_history(history: &[BuildHistoryDocument]) -> bool {
-    let consecutive_failures = history
+    // Cancelled builds neither count as failures nor break a failure streak
+    let failures: Vec<_> = history
         .iter()
         .rev()
-        .take_while(|entry| entry.build_status == "failed")
-        .count();
+        .filter(|entry| entry.build_status != BuildStatus::Cancelled.as_str())
+        .take_while(|entry| {
+            BuildStatus::from_str(&entry.build_status).is_some_and(|s| s.is_failure())
+        })
+        .collect();
+    let consecutive_failures = failures.len();
 
     if consecutive_failures == 0 {
         return true;
@@ -717,7 +942,7 @@ fn is_retry_allowed_from_history(history: &[BuildHistoryDocument]) -> bool {
     // Exponential backoff: 1h, 2h, 4h
     let backoff_hours = std::cmp::min(1_i64 << (consecutive_failures - 1), 24);
 
-    if let Some(last) = history.last() {
+    if let Some(last) = failures.first() {
         let last_date = last.build_date.to_chrono();
         let next_retry = last_date + Duration::hours(backoff_hours);
         Utc::now() >= next_retry
@@ -742,16 +967,18 @@ fn regex_escape(s: &str) -> String {
 }
 
 /// Convert a PackageDocument to a PackageRecord
-fn pkg_doc_to_record(doc: &PackageDocument) -> PackageRecord {
+///
+/// Fails if the latest history entry has an unknown status.
+fn pkg_doc_to_record(doc: &PackageDocument) -> Result<PackageRecord> {
     let last_build = doc.build_history.last();
 
-    PackageRecord {
+    Ok(PackageRecord {
         id: None,
         pkg_id: doc.pkg_id.clone(),
         pkg_name: doc.pkg_name.clone(),
         pkg_family: doc.pkg_family.clone(),
-        build_script: String::new(),
-        ghcr_pkg: String::new(),
+        build_script: doc.build_script.clone().unwrap_or_default(),
+        ghcr_pkg: doc.ghcr_pkg.clone().unwrap_or_default(),
         host_triplet: doc.host_triplet.clone(),
         current_version: doc.current_version.clone(),
         upstream_version: doc.upstream_version.clone(),
@@ -762,10 +989,10 @@ fn pkg_doc_to_record(doc: &PackageDocument) -> PackageRecord {
         revision: doc.revision,
         last_build_date: last_build.map(|h| h.build_date.to_chrono()),
         last_build_id: last_build.and_then(|h| h.build_id.clone()),
-        last_build_status: last_build.and_then(|h| BuildStatus::from_str(&h.build_status)),
+        last_build_status: last_build.map(|h| h.build_status.parse()).transpose()?,
         ghcr_tag: last_build.and_then(|h| h.ghcr_tag.clone()),
         snapshots: doc.snapshots.clone(),
         created_at: doc.created_at.to_chrono(),
         updated_at: doc.updated_at.to_chrono(),
-    }
+    })
 }
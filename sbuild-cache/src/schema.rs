@@ -1,7 +1,7 @@
 //! SQLite schema definitions
 
 /// Current schema version
-pub const SCHEMA_VERSION: i32 = 4;
+pub const SCHEMA_VERSION: i32 = 10;
 
 /// SQL to create the database schema
 pub const CREATE_SCHEMA: &str = r#"
@@ -36,12 +36,18 @@ CREATE TABLE IF NOT EXISTS packages (
     -- Build info
     last_build_date TEXT,
     last_build_id TEXT,
-    last_build_status TEXT CHECK(last_build_status IN ('success', 'failed', 'skipped', 'pending')),
+    last_build_status TEXT CHECK(last_build_status IN ('success', 'failed', 'skipped', 'pending', 'timeout', 'cancelled')),
     ghcr_tag TEXT,
 
     -- Snapshots (JSON array of historical versions)
     snapshots TEXT DEFAULT '[]',
 
+    -- Work queue claim (worker id, when it claimed the rebuild and the
+    -- status to restore if the claim is released)
+    claimed_by TEXT,
+    claimed_at TEXT,
+    claim_prior_status TEXT,
+
     -- Timestamps
     created_at TEXT NOT NULL DEFAULT (datetime('now')),
     updated_at TEXT NOT NULL DEFAULT (datetime('now')),
@@ -56,13 +62,14 @@ CREATE TABLE IF NOT EXISTS build_history (
     build_id TEXT,
     version TEXT NOT NULL,
     build_date TEXT NOT NULL,
-    build_status TEXT CHECK(build_status IN ('success', 'failed', 'skipped')),
+    build_status TEXT CHECK(build_status IN ('success', 'failed', 'skipped', 'timeout', 'cancelled')),
     duration_seconds INTEGER,
     artifact_size_bytes INTEGER,
     ghcr_tag TEXT,
     ghcr_digest TEXT,
     build_log_url TEXT,
     error_message TEXT,
+    skip_reason TEXT,
 
     FOREIGN KEY (package_id) REFERENCES packages(id) ON DELETE CASCADE
 );
@@ -104,6 +111,20 @@ CREATE INDEX IF NOT EXISTS idx_version_cache_expires ON version_cache(expires_at
 CREATE INDEX IF NOT EXISTS idx_failed_packages_retry ON failed_packages(next_retry_date);
 "#;
 
+/// Composite indexes for per-host listings on large caches.
+///
+/// Nearly every query is scoped to one host, so the single-column status and
+/// outdated indexes alone still leave SQLite filtering every host's rows:
+/// - `list_packages` filters by host plus status or outdated flag
+/// - `get_recent_builds` joins history per package and orders by date
+///
+/// Included in fresh schemas and applied to existing ones by the v6 migration.
+pub const CREATE_HOST_INDEXES: &str = r#"
+CREATE INDEX IF NOT EXISTS idx_packages_host_status ON packages(host_triplet, last_build_status);
+CREATE INDEX IF NOT EXISTS idx_packages_host_outdated ON packages(host_triplet, is_outdated);
+CREATE INDEX IF NOT EXISTS idx_build_history_package_date ON build_history(package_id, build_date);
+"#;
+
 /// SQL to migrate from schema v1 to v2
 pub const MIGRATE_V1_TO_V2: &str = r#"
 ALTER TABLE packages ADD COLUMN base_version TEXT;
@@ -120,6 +141,54 @@ pub const MIGRATE_V3_TO_V4: &str = r#"
 ALTER TABLE packages ADD COLUMN snapshots TEXT DEFAULT '[]';
 "#;
 
+/// SQL to migrate from schema v4 to v5
+pub const MIGRATE_V4_TO_V5: &str = r#"
+ALTER TABLE packages ADD COLUMN claimed_by TEXT;
+ALTER TABLE packages ADD COLUMN claimed_at TEXT;
+"#;
+
+/// SQL to migrate from schema v7 to v8
+pub const MIGRATE_V7_TO_V8: &str = r#"
+ALTER TABLE build_history ADD COLUMN skip_reason TEXT;
+"#;
+
+/// SQL to migrate from schema v8 to v9: lowercase stored host triplets.
+///
+/// Where a package has rows under both spellings (e.g. `x86_64-Linux` and
+/// `x86_64-linux`), the most recently updated row is kept. Child rows are
+/// removed explicitly since foreign keys may not be enforced.
+pub const MIGRATE_V8_TO_V9: &str = r#"
+CREATE TEMP TABLE host_case_dupes AS
+SELECT p.id FROM packages p
+JOIN packages q
+  ON q.pkg_id = p.pkg_id
+ AND lower(trim(q.host_triplet)) = lower(trim(p.host_triplet))
+ AND q.id != p.id
+WHERE p.updated_at < q.updated_at
+   OR (p.updated_at = q.updated_at AND p.id < q.id);
+
+DELETE FROM build_history WHERE package_id IN (SELECT id FROM host_case_dupes);
+DELETE FROM version_cache WHERE package_id IN (SELECT id FROM host_case_dupes);
+DELETE FROM failed_packages WHERE package_id IN (SELECT id FROM host_case_dupes);
+DELETE FROM packages WHERE id IN (SELECT id FROM host_case_dupes);
+DROP TABLE host_case_dupes;
+
+UPDATE packages SET host_triplet = lower(trim(host_triplet))
+WHERE host_triplet != lower(trim(host_triplet));
+"#;
+
+/// SQL to migrate from schema v9 to v10
+pub const MIGRATE_V9_TO_V10: &str = r#"
+ALTER TABLE packages ADD COLUMN claim_prior_status TEXT;
+"#;
+
+/// Allowed `packages.last_build_status` values from schema v7
+pub const PACKAGE_STATUS_VALUES: &str =
+    "'success', 'failed', 'skipped', 'pending', 'timeout', 'cancelled'";
+
+/// Allowed `build_history.build_status` values from schema v7
+pub const HISTORY_STATUS_VALUES: &str = "'success', 'failed', 'skipped', 'timeout', 'cancelled'";
+
 /// SQL for views
 pub const CREATE_VIEWS: &str = r#"
 -- View for packages needing rebuild
@@ -143,7 +212,7 @@ SELECT
     host_triplet,
     COUNT(*) as total_packages,
     SUM(CASE WHEN last_build_status = 'success' THEN 1 ELSE 0 END) as successful,
-    SUM(CASE WHEN last_build_status = 'failed' THEN 1 ELSE 0 END) as failed,
+    SUM(CASE WHEN last_build_status IN ('failed', 'timeout') THEN 1 ELSE 0 END) as failed,
     SUM(CASE WHEN last_build_status = 'pending' THEN 1 ELSE 0 END) as pending,
     SUM(CASE WHEN is_outdated = 1 THEN 1 ELSE 0 END) as outdated
 FROM packages
@@ -1,26 +1,73 @@
 //! Database operations for the build cache
 
-use chrono::{DateTime, Duration, Utc};
-use rusqlite::{params, Connection, OptionalExtension};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use std::collections::HashMap;
 use std::path::Path;
 
 use crate::error::{Error, Result};
 use crate::models::*;
 use crate::schema::{
-    CREATE_SCHEMA, CREATE_VIEWS, MIGRATE_V1_TO_V2, MIGRATE_V2_TO_V3, MIGRATE_V3_TO_V4,
-    SCHEMA_VERSION,
+    CREATE_HOST_INDEXES, CREATE_SCHEMA, CREATE_VIEWS, HISTORY_STATUS_VALUES, MIGRATE_V1_TO_V2,
+    MIGRATE_V2_TO_V3, MIGRATE_V3_TO_V4, MIGRATE_V4_TO_V5, MIGRATE_V7_TO_V8, MIGRATE_V8_TO_V9,
+    MIGRATE_V9_TO_V10, PACKAGE_STATUS_VALUES, SCHEMA_VERSION,
 };
 
+/// How long a connection waits on a locked database before failing
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(5000);
+
+/// How long a worker's claim holds before another worker may take over
+pub const DEFAULT_CLAIM_TIMEOUT_HOURS: i64 = 6;
+
+/// Stored statuses are parsed strictly so corrupt rows fail to load
+impl FromSql for BuildStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+/// Ids bound per query by [`CacheDatabase::get_packages_by_ids`]
+const MAX_IDS_PER_QUERY: usize = 500;
+
+/// Replace the value list of `CHECK(<column> IN (...))` in a table definition
+fn replace_status_check(sql: &str, column: &str, values: &str) -> Option<String> {
+    let marker = format!("CHECK({} IN (", column);
+    let start = sql.find(&marker)?;
+    let end = start + sql[start..].find("))")? + 2;
+    Some(format!(
+        "{}{}{})){}",
+        &sql[..start],
+        marker,
+        values,
+        &sql[end..]
+    ))
+}
+
 /// SQLite cache database
 pub struct CacheDatabase {
     conn: Connection,
+    backoff: BackoffPolicy,
+    claim_timeout: Duration,
 }
 
 impl CacheDatabase {
     /// Open or create a cache database
+    ///
+    /// Uses WAL journaling so readers don't block the writer, and waits up to
+    /// 5s for locks so concurrent processes don't fail with `database is locked`.
     pub fn open(path: &Path) -> Result<Self> {
         let conn = Connection::open(path)?;
-        let db = Self { conn };
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        let db = Self {
+            conn,
+            backoff: BackoffPolicy::default(),
+            claim_timeout: Duration::hours(DEFAULT_CLAIM_TIMEOUT_HOURS),
+        };
         db.initialize()?;
         Ok(db)
     }
@@ -28,11 +75,27 @@ impl CacheDatabase {
     /// Create an in-memory database (for testing)
     pub fn in_memory() -> Result<Self> {
         let conn = Connection::open_in_memory()?;
-        let db = Self { conn };
+        let db = Self {
+            conn,
+            backoff: BackoffPolicy::default(),
+            claim_timeout: Duration::hours(DEFAULT_CLAIM_TIMEOUT_HOURS),
+        };
         db.initialize()?;
         Ok(db)
     }
 
+    /// Use `policy` for the retry delays set by [`Self::record_failure`]
+    pub fn with_backoff_policy(mut self, policy: BackoffPolicy) -> Self {
+        self.backoff = policy;
+        self
+    }
+
+    /// Treat claims older than `timeout` as abandoned by a crashed worker
+    pub fn with_claim_timeout(mut self, timeout: Duration) -> Self {
+        self.claim_timeout = timeout;
+        self
+    }
+
     /// Initialize the database schema, running migrations if needed
     fn initialize(&self) -> Result<()> {
         let has_schema: bool = self
@@ -47,6 +110,7 @@ impl CacheDatabase {
         if !has_schema {
             // Fresh database: create schema at current version
             self.conn.execute_batch(CREATE_SCHEMA)?;
+            self.conn.execute_batch(CREATE_HOST_INDEXES)?;
             self.conn.execute_batch(CREATE_VIEWS)?;
             self.conn.execute(
                 "INSERT INTO schema_info (version, description) VALUES (?1, ?2)",
@@ -92,6 +156,129 @@ impl CacheDatabase {
             )?;
         }
 
+        if current_version < 5 {
+            // Migrate v4 -> v5: add work queue claim columns
+            self.conn.execute_batch(MIGRATE_V4_TO_V5)?;
+            self.conn.execute(
+                "INSERT INTO schema_info (version, description) VALUES (?1, ?2)",
+                params![5, "Add claim columns for worker pools"],
+            )?;
+        }
+
+        if current_version < 6 {
+            // Migrate v5 -> v6: add composite per-host indexes
+            self.conn.execute_batch(CREATE_HOST_INDEXES)?;
+            self.conn.execute(
+                "INSERT INTO schema_info (version, description) VALUES (?1, ?2)",
+                params![6, "Add composite per-host indexes"],
+            )?;
+        }
+
+        if current_version < 7 {
+            // Migrate v6 -> v7: allow timeout/cancelled statuses
+            self.migrate_status_checks()?;
+            self.conn.execute(
+                "INSERT INTO schema_info (version, description) VALUES (?1, ?2)",
+                params![7, "Add timeout and cancelled build statuses"],
+            )?;
+        }
+
+        if current_version < 8 {
+            // Migrate v7 -> v8: add skip_reason to build history
+            if !self.has_column("build_history", "skip_reason")? {
+                self.conn.execute_batch(MIGRATE_V7_TO_V8)?;
+            }
+            self.conn.execute(
+                "INSERT INTO schema_info (version, description) VALUES (?1, ?2)",
+                params![8, "Add skip_reason to build history"],
+            )?;
+        }
+
+        if current_version < 9 {
+            // Migrate v8 -> v9: host triplets are now stored lowercase
+            self.conn.execute_batch(MIGRATE_V8_TO_V9)?;
+            self.conn.execute(
+                "INSERT INTO schema_info (version, description) VALUES (?1, ?2)",
+                params![9, "Lowercase host triplets"],
+            )?;
+        }
+
+        if current_version < 10 {
+            // Migrate v9 -> v10: remember the status a claim replaced
+            if !self.has_column("packages", "claim_prior_status")? {
+                self.conn.execute_batch(MIGRATE_V9_TO_V10)?;
+            }
+            self.conn.execute(
+                "INSERT INTO schema_info (version, description) VALUES (?1, ?2)",
+                params![10, "Add claim_prior_status for released claims"],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn has_column(&self, table: &str, column: &str) -> Result<bool> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+            params![table, column],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Rebuild `packages` and `build_history` with widened status CHECKs.
+    ///
+    /// SQLite can't alter a CHECK constraint, so each table is recreated from
+    /// its stored definition with only the status list swapped. Views are
+    /// dropped first since renaming a table re-validates views that use it,
+    /// and foreign keys are off so dropping `packages` doesn't cascade.
+    fn migrate_status_checks(&self) -> Result<()> {
+        // Leave enforcement as the connection had it, rather than forcing it on
+        let foreign_keys: bool = self
+            .conn
+            .pragma_query_value(None, "foreign_keys", |row| row.get(0))?;
+        self.conn.pragma_update(None, "foreign_keys", false)?;
+        let result = self.rebuild_status_tables();
+        self.conn
+            .pragma_update(None, "foreign_keys", foreign_keys)?;
+        result
+    }
+
+    fn rebuild_status_tables(&self) -> Result<()> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        tx.execute_batch(
+            "DROP VIEW IF EXISTS v_packages_needing_rebuild;
+             DROP VIEW IF EXISTS v_build_stats;",
+        )?;
+
+        for (table, column, values) in [
+            ("packages", "last_build_status", PACKAGE_STATUS_VALUES),
+            ("build_history", "build_status", HISTORY_STATUS_VALUES),
+        ] {
+            let sql: String = tx.query_row(
+                "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table],
+                |row| row.get(0),
+            )?;
+            let sql = replace_status_check(&sql, column, values).ok_or_else(|| {
+                Error::Other(format!("No {} CHECK constraint found on {}", column, table))
+            })?;
+            let new_table = format!("{}_v7", table);
+            let create = sql.replacen(table, &new_table, 1);
+
+            tx.execute_batch(&format!(
+                "{create};
+                 INSERT INTO {new_table} SELECT * FROM {table};
+                 DROP TABLE {table};
+                 ALTER TABLE {new_table} RENAME TO {table};"
+            ))?;
+        }
+
+        // Dropping the tables removed their indexes
+        tx.execute_batch(CREATE_SCHEMA)?;
+        tx.execute_batch(CREATE_HOST_INDEXES)?;
+        tx.execute_batch(CREATE_VIEWS)?;
+        tx.commit()?;
         Ok(())
     }
 
@@ -119,6 +306,35 @@ impl CacheDatabase {
             .ok_or_else(|| Error::Other("Failed to create package".to_string()))
     }
 
+    /// Get or create a package, filling in family, build script and GHCR package
+    ///
+    /// Fields set on `init` overwrite the stored values of an existing record.
+    pub fn create_package_full(&self, init: &PackageRecordInit) -> Result<PackageRecord> {
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT INTO packages (pkg_id, pkg_name, pkg_family, build_script, ghcr_pkg,
+                                   host_triplet, created_at, updated_at)
+             VALUES (?1, ?2, ?3, COALESCE(?4, ''), COALESCE(?5, ''), ?6, ?7, ?7)
+             ON CONFLICT(pkg_id, host_triplet) DO UPDATE SET
+                pkg_family = COALESCE(?3, pkg_family),
+                build_script = COALESCE(?4, build_script),
+                ghcr_pkg = COALESCE(?5, ghcr_pkg),
+                updated_at = ?7",
+            params![
+                init.pkg_id,
+                init.pkg_name,
+                init.pkg_family,
+                init.build_script,
+                init.ghcr_pkg,
+                init.host_triplet,
+                now
+            ],
+        )?;
+
+        self.get_package(&init.pkg_id, &init.host_triplet)?
+            .ok_or_else(|| Error::Other("Failed to create package".to_string()))
+    }
+
     /// Get a package by ID and host
     pub fn get_package(&self, pkg_id: &str, host_triplet: &str) -> Result<Option<PackageRecord>> {
         let result = self
@@ -138,6 +354,43 @@ impl CacheDatabase {
         Ok(result)
     }
 
+    /// Get several packages on a host in as few queries as possible
+    ///
+    /// Ids are looked up in chunks of [`MAX_IDS_PER_QUERY`] to stay under
+    /// SQLite's bound-variable limit. Unknown ids are skipped; results are
+    /// ordered by `pkg_id`.
+    pub fn get_packages_by_ids(
+        &self,
+        host_triplet: &str,
+        pkg_ids: &[&str],
+    ) -> Result<Vec<PackageRecord>> {
+        let mut results = Vec::with_capacity(pkg_ids.len());
+        for chunk in pkg_ids.chunks(MAX_IDS_PER_QUERY) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            // Full chunks share the same SQL, so the cached statement is reused
+            let mut stmt = self.conn.prepare_cached(&format!(
+                "SELECT id, pkg_id, pkg_name, pkg_family, build_script, ghcr_pkg, host_triplet,
+                        current_version, upstream_version, is_outdated, recipe_hash,
+                        base_version, remote_version, revision,
+                        last_build_date, last_build_id, last_build_status, ghcr_tag,
+                        snapshots, created_at, updated_at
+                 FROM packages WHERE host_triplet = ? AND pkg_id IN ({})",
+                placeholders
+            ))?;
+            let params = std::iter::once(&host_triplet).chain(chunk);
+            let rows = stmt.query_map(
+                rusqlite::params_from_iter(params),
+                Self::row_to_package_record,
+            )?;
+            for row in rows {
+                results.push(row?);
+            }
+        }
+
+        results.sort_by(|a, b| a.pkg_id.cmp(&b.pkg_id));
+        Ok(results)
+    }
+
     /// Find packages by name (or pkg_id suffix) and host.
     /// Returns all matches since multiple packages can share the same name
     /// (e.g., coreutils from gnu, uutils, vlang).
@@ -197,6 +450,7 @@ impl CacheDatabase {
         base_version: Option<&str>,
         remote_version: Option<&str>,
         revision: i32,
+        skip_reason: Option<&str>,
     ) -> Result<()> {
         // Input validation
         if version.is_empty() || version == "unknown" {
@@ -221,6 +475,9 @@ impl CacheDatabase {
                 remote_version = ?8,
                 revision = ?9,
                 is_outdated = 0,
+                claimed_by = NULL,
+                claimed_at = NULL,
+                claim_prior_status = NULL,
                 updated_at = ?10
              WHERE pkg_id = ?11 AND host_triplet = ?12",
             params![
@@ -243,9 +500,9 @@ impl CacheDatabase {
         if let Some(record) = self.get_package(pkg_id, host_triplet)? {
             if let Some(id) = record.id {
                 self.conn.execute(
-                    "INSERT INTO build_history (package_id, build_id, version, build_date, build_status, ghcr_tag)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                    params![id, build_id, version, now, status_str, ghcr_tag],
+                    "INSERT INTO build_history (package_id, build_id, version, build_date, build_status, ghcr_tag, skip_reason)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![id, build_id, version, now, status_str, ghcr_tag, skip_reason],
                 )?;
             }
         }
@@ -253,6 +510,36 @@ impl CacheDatabase {
         Ok(())
     }
 
+    /// Record a skipped build and why it was skipped. The version and
+    /// outdated flag stay as they are, since nothing was built.
+    pub fn mark_skipped(&self, pkg_id: &str, host_triplet: &str, reason: &str) -> Result<()> {
+        let record = self
+            .get_package(pkg_id, host_triplet)?
+            .ok_or_else(|| Error::PackageNotFound(pkg_id.to_string()))?;
+        let id = record
+            .id
+            .ok_or_else(|| Error::PackageNotFound(pkg_id.to_string()))?;
+        let now = Utc::now().to_rfc3339();
+
+        self.conn.execute(
+            "UPDATE packages SET
+                last_build_date = ?1,
+                last_build_status = 'skipped',
+                claimed_by = NULL,
+                claimed_at = NULL,
+                claim_prior_status = NULL,
+                updated_at = ?1
+             WHERE id = ?2",
+            params![now, id],
+        )?;
+        self.conn.execute(
+            "INSERT INTO build_history (package_id, version, build_date, build_status, skip_reason)
+             VALUES (?1, ?2, ?3, 'skipped', ?4)",
+            params![id, record.current_version.unwrap_or_default(), now, reason],
+        )?;
+        Ok(())
+    }
+
     /// Get the next revision number for a package version
     pub fn get_revision(
         &self,
@@ -352,6 +639,112 @@ impl CacheDatabase {
             .map_err(Error::Sqlite)
     }
 
+    /// Claim the next package needing a rebuild for `worker_id`.
+    ///
+    /// Skips packages still in failure backoff and packages claimed by another
+    /// worker within the claim timeout. The claimed package is marked
+    /// `pending` until its build result is recorded or the claim is released,
+    /// which restores the status it had before.
+    pub fn claim_next_rebuild(
+        &self,
+        host_triplet: &str,
+        worker_id: &str,
+    ) -> Result<Option<PackageRecord>> {
+        // IMMEDIATE takes the write lock up front so two workers can't pick
+        // the same row between the SELECT and the UPDATE.
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        let now = Utc::now();
+        let stale_before = (now - self.claim_timeout).to_rfc3339();
+        let now = now.to_rfc3339();
+
+        let record = tx
+            .query_row(
+                "SELECT p.id, p.pkg_id, p.pkg_name, p.pkg_family, p.build_script, p.ghcr_pkg, p.host_triplet,
+                        p.current_version, p.upstream_version, p.is_outdated, p.recipe_hash,
+                        p.base_version, p.remote_version, p.revision,
+                        p.last_build_date, p.last_build_id, p.last_build_status, p.ghcr_tag,
+                        p.snapshots, p.created_at, p.updated_at
+                 FROM packages p
+                 LEFT JOIN failed_packages f ON f.package_id = p.id
+                 WHERE p.host_triplet = ?1
+                   AND (p.is_outdated = 1 OR p.last_build_status IS NULL OR p.last_build_status = 'pending')
+                   AND (f.next_retry_date IS NULL OR f.next_retry_date <= ?2)
+                   AND (p.claimed_by IS NULL OR p.claimed_at < ?3)
+                 ORDER BY p.pkg_name
+                 LIMIT 1",
+                params![host_triplet, now, stale_before],
+                Self::row_to_package_record,
+            )
+            .optional()?;
+
+        let Some(mut record) = record else {
+            tx.commit()?;
+            return Ok(None);
+        };
+
+        tx.execute(
+            "UPDATE packages SET
+                claim_prior_status = CASE WHEN claimed_by IS NULL
+                                          THEN last_build_status
+                                          ELSE claim_prior_status END,
+                last_build_status = 'pending',
+                claimed_by = ?1,
+                claimed_at = ?2
+             WHERE id = ?3",
+            params![worker_id, now, record.id],
+        )?;
+        tx.commit()?;
+
+        record.last_build_status = Some(BuildStatus::Pending);
+        Ok(Some(record))
+    }
+
+    /// Give up `worker_id`'s claim on a package without recording a result,
+    /// restoring the status it had before it was claimed.
+    /// Returns false if the worker didn't hold the claim.
+    pub fn release_claim(&self, pkg_id: &str, host_triplet: &str, worker_id: &str) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE packages SET
+                last_build_status = claim_prior_status,
+                claim_prior_status = NULL,
+                claimed_by = NULL,
+                claimed_at = NULL
+             WHERE pkg_id = ?1 AND host_triplet = ?2 AND claimed_by = ?3",
+            params![pkg_id, host_triplet, worker_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Current claim on a package, whether or not it has gone stale
+    pub fn get_claim(&self, pkg_id: &str, host_triplet: &str) -> Result<Option<PackageClaim>> {
+        let claim = self
+            .conn
+            .query_row(
+                "SELECT claimed_by, claimed_at, claim_prior_status FROM packages
+                 WHERE pkg_id = ?1 AND host_triplet = ?2 AND claimed_by IS NOT NULL",
+                params![pkg_id, host_triplet],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<BuildStatus>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        Ok(
+            claim.map(|(worker_id, claimed_at, prior_status)| PackageClaim {
+                worker_id,
+                claimed_at: claimed_at
+                    .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(Utc::now),
+                prior_status,
+            }),
+        )
+    }
+
     /// Get build statistics for a host
     pub fn get_stats(&self, host_triplet: &str) -> Result<BuildStats> {
         self.conn
@@ -359,7 +752,7 @@ impl CacheDatabase {
                 "SELECT
                     COUNT(*) as total,
                     COALESCE(SUM(CASE WHEN last_build_status = 'success' THEN 1 ELSE 0 END), 0) as successful,
-                    COALESCE(SUM(CASE WHEN last_build_status = 'failed' THEN 1 ELSE 0 END), 0) as failed,
+                    COALESCE(SUM(CASE WHEN last_build_status IN ('failed', 'timeout') THEN 1 ELSE 0 END), 0) as failed,
                     COALESCE(SUM(CASE WHEN last_build_status = 'pending' OR last_build_status IS NULL THEN 1 ELSE 0 END), 0) as pending,
                     COALESCE(SUM(CASE WHEN is_outdated = 1 THEN 1 ELSE 0 END), 0) as outdated
                  FROM packages WHERE host_triplet = ?1",
@@ -377,6 +770,50 @@ impl CacheDatabase {
             .map_err(Error::Sqlite)
     }
 
+    /// Successful and failed builds per UTC day over the last `days` days
+    /// (including today), oldest first
+    pub fn get_stats_history(&self, host_triplet: &str, days: i64) -> Result<Vec<DailyBuildStats>> {
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(days - 1);
+        let mut stmt = self.conn.prepare(
+            "SELECT substr(bh.build_date, 1, 10) AS day,
+                    SUM(CASE WHEN bh.build_status = 'success' THEN 1 ELSE 0 END),
+                    SUM(CASE WHEN bh.build_status IN ('failed', 'timeout') THEN 1 ELSE 0 END)
+             FROM build_history bh
+             JOIN packages p ON p.id = bh.package_id
+             WHERE p.host_triplet = ?1 AND bh.build_date >= ?2
+             GROUP BY day",
+        )?;
+
+        let rows = stmt.query_map(params![host_triplet, start.to_string()], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+
+        let mut counts = HashMap::new();
+        for row in rows {
+            let (day, successful, failed) = row?;
+            if let Ok(date) = day.parse::<NaiveDate>() {
+                counts.insert(date, (successful, failed));
+            }
+        }
+        Ok(DailyBuildStats::series(&counts, today, days))
+    }
+
+    /// Distinct host triplets with at least one package, sorted
+    pub fn list_hosts(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT host_triplet FROM packages ORDER BY host_triplet")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Error::Sqlite)
+    }
+
     /// Record a failed build with retry backoff
     pub fn record_failure(
         &self,
@@ -403,9 +840,7 @@ impl CacheDatabase {
 
         let new_count = failure_count + 1;
 
-        // Exponential backoff: 1h, 2h, 4h, 8h, max 24h
-        let backoff_hours = std::cmp::min(1 << failure_count, 24);
-        let next_retry = now + Duration::hours(backoff_hours as i64);
+        let next_retry = now + self.backoff.delay(new_count);
 
         self.conn.execute(
             "INSERT INTO failed_packages (package_id, failure_count, last_failure_date, last_error_message, next_retry_date)
@@ -440,6 +875,70 @@ impl CacheDatabase {
         Ok(())
     }
 
+    /// Failure record of a package, if it's currently in backoff
+    pub fn get_failure(&self, pkg_id: &str, host_triplet: &str) -> Result<Option<FailedPackage>> {
+        let result = self
+            .conn
+            .query_row(
+                "SELECT f.id, f.package_id, f.failure_count, f.last_failure_date,
+                        f.last_error_message, f.next_retry_date
+                 FROM failed_packages f
+                 JOIN packages p ON p.id = f.package_id
+                 WHERE p.pkg_id = ?1 AND p.host_triplet = ?2",
+                params![pkg_id, host_triplet],
+                |row| Self::row_to_failure(row, 0),
+            )
+            .optional()?;
+
+        Ok(result)
+    }
+
+    /// Failure record from the `failed_packages` columns starting at `start`
+    fn row_to_failure(row: &rusqlite::Row, start: usize) -> rusqlite::Result<FailedPackage> {
+        let parse_date = |s: String| {
+            DateTime::parse_from_rfc3339(&s)
+                .ok()
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+        Ok(FailedPackage {
+            id: Some(row.get(start)?),
+            package_id: row.get(start + 1)?,
+            failure_count: row.get::<_, Option<i32>>(start + 2)?.unwrap_or(1),
+            last_failure_date: parse_date(row.get(start + 3)?).unwrap_or_else(Utc::now),
+            last_error_message: row.get(start + 4)?,
+            next_retry_date: row
+                .get::<_, Option<String>>(start + 5)?
+                .and_then(parse_date),
+        })
+    }
+
+    /// Packages with a recorded failure, soonest allowed retry first
+    pub fn list_failures(&self, host_triplet: &str) -> Result<Vec<(PackageRecord, FailedPackage)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT p.id, p.pkg_id, p.pkg_name, p.pkg_family, p.build_script, p.ghcr_pkg, p.host_triplet,
+                    p.current_version, p.upstream_version, p.is_outdated, p.recipe_hash,
+                    p.base_version, p.remote_version, p.revision,
+                    p.last_build_date, p.last_build_id, p.last_build_status, p.ghcr_tag,
+                    p.snapshots, p.created_at, p.updated_at,
+                    f.id, f.package_id, f.failure_count, f.last_failure_date,
+                    f.last_error_message, f.next_retry_date
+             FROM failed_packages f
+             JOIN packages p ON p.id = f.package_id
+             WHERE p.host_triplet = ?1
+             ORDER BY f.next_retry_date IS NOT NULL, f.next_retry_date, p.pkg_name",
+        )?;
+
+        let rows = stmt.query_map(params![host_triplet], |row| {
+            Ok((
+                Self::row_to_package_record(row)?,
+                Self::row_to_failure(row, 21)?,
+            ))
+        })?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Error::Sqlite)
+    }
+
     /// List all packages with optional status filter
     pub fn list_packages(
         &self,
@@ -494,6 +993,26 @@ impl CacheDatabase {
         host_triplet: &str,
         limit: i64,
     ) -> Result<Vec<(PackageRecord, BuildHistoryEntry)>> {
+        self.query_recent_builds(host_triplet, None, limit)
+    }
+
+    /// Get all builds since `since`, newest first
+    pub fn get_recent_builds_since(
+        &self,
+        host_triplet: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(PackageRecord, BuildHistoryEntry)>> {
+        // LIMIT -1 means no limit in SQLite
+        self.query_recent_builds(host_triplet, Some(since), -1)
+    }
+
+    fn query_recent_builds(
+        &self,
+        host_triplet: &str,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<(PackageRecord, BuildHistoryEntry)>> {
+        let since = since.map(|d| d.to_rfc3339());
         let mut stmt = self.conn.prepare(
             "SELECT p.id, p.pkg_id, p.pkg_name, p.pkg_family, p.build_script, p.ghcr_pkg, p.host_triplet,
                     p.current_version, p.upstream_version, p.is_outdated, p.recipe_hash,
@@ -501,15 +1020,15 @@ impl CacheDatabase {
                     p.last_build_date, p.last_build_id, p.last_build_status, p.ghcr_tag,
                     p.created_at, p.updated_at,
                     bh.id, bh.build_id, bh.version, bh.build_date, bh.build_status,
-                    bh.duration_seconds, bh.ghcr_tag, bh.error_message
+                    bh.duration_seconds, bh.ghcr_tag, bh.error_message, bh.skip_reason
              FROM packages p
              JOIN build_history bh ON p.id = bh.package_id
-             WHERE p.host_triplet = ?1
+             WHERE p.host_triplet = ?1 AND (?2 IS NULL OR bh.build_date >= ?2)
              ORDER BY bh.build_date DESC
-             LIMIT ?2",
+             LIMIT ?3",
         )?;
 
-        let rows = stmt.query_map(params![host_triplet, limit], |row| {
+        let rows = stmt.query_map(params![host_triplet, since, limit], |row| {
             let pkg = Self::row_to_package_record(row)?;
             let history = BuildHistoryEntry {
                 id: Some(row.get(20)?),
@@ -523,9 +1042,7 @@ impl CacheDatabase {
                     .map(|dt| dt.with_timezone(&Utc))
                     .unwrap_or_else(Utc::now),
                 build_status: row
-                    .get::<_, String>(24)
-                    .ok()
-                    .and_then(|s| BuildStatus::from_str(&s))
+                    .get::<_, Option<BuildStatus>>(24)?
                     .unwrap_or(BuildStatus::Pending),
                 duration_seconds: row.get(25).ok(),
                 artifact_size_bytes: None,
@@ -533,6 +1050,7 @@ impl CacheDatabase {
                 ghcr_digest: None,
                 build_log_url: None,
                 error_message: row.get(27).ok(),
+                skip_reason: row.get(28)?,
             };
             Ok((pkg, history))
         })?;
@@ -575,9 +1093,7 @@ impl CacheDatabase {
                 .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
                 .map(|dt| dt.with_timezone(&Utc)),
             last_build_id: row.get(15)?,
-            last_build_status: row
-                .get::<_, Option<String>>(16)?
-                .and_then(|s| BuildStatus::from_str(&s)),
+            last_build_status: row.get(16)?,
             ghcr_tag: row.get(17)?,
             snapshots,
             created_at: row
@@ -609,6 +1125,21 @@ impl CacheDatabase {
         Ok(result as i64)
     }
 
+    /// Delete build history older than `cutoff`, always keeping each
+    /// package's most recent entry
+    pub fn prune_history_older_than(&self, cutoff: DateTime<Utc>) -> Result<i64> {
+        let result = self.conn.execute(
+            "DELETE FROM build_history WHERE build_date < ?1 AND id NOT IN (
+                SELECT id FROM build_history bh2
+                WHERE bh2.package_id = build_history.package_id
+                ORDER BY build_date DESC
+                LIMIT 1
+             )",
+            params![cutoff.to_rfc3339()],
+        )?;
+        Ok(result as i64)
+    }
+
     /// List all packages (no filters) - used for export
     pub fn list_all_packages(&self) -> Result<Vec<PackageRecord>> {
         let mut stmt = self.conn.prepare(
@@ -626,6 +1157,51 @@ impl CacheDatabase {
             .map_err(Error::Sqlite)
     }
 
+    /// Full build history of a package, oldest first
+    pub fn get_build_history(
+        &self,
+        pkg_id: &str,
+        host_triplet: &str,
+    ) -> Result<Vec<BuildHistoryEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT bh.id, bh.package_id, bh.build_id, bh.version, bh.build_date, bh.build_status,
+                    bh.duration_seconds, bh.artifact_size_bytes, bh.ghcr_tag, bh.ghcr_digest,
+                    bh.build_log_url, bh.error_message, bh.skip_reason
+             FROM build_history bh
+             JOIN packages p ON p.id = bh.package_id
+             WHERE p.pkg_id = ?1 AND p.host_triplet = ?2
+             ORDER BY bh.build_date, bh.id",
+        )?;
+
+        let rows = stmt.query_map(params![pkg_id, host_triplet], |row| {
+            Ok(BuildHistoryEntry {
+                id: Some(row.get(0)?),
+                package_id: row.get(1)?,
+                build_id: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                version: row.get(3)?,
+                build_date: row
+                    .get::<_, String>(4)
+                    .ok()
+                    .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(Utc::now),
+                build_status: row
+                    .get::<_, Option<BuildStatus>>(5)?
+                    .unwrap_or(BuildStatus::Pending),
+                duration_seconds: row.get(6)?,
+                artifact_size_bytes: row.get(7)?,
+                ghcr_tag: row.get(8)?,
+                ghcr_digest: row.get(9)?,
+                build_log_url: row.get(10)?,
+                error_message: row.get(11)?,
+                skip_reason: row.get(12)?,
+            })
+        })?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Error::Sqlite)
+    }
+
     /// Import a package record (used by export from MongoDB)
     pub fn import_package(&self, record: &PackageRecord) -> Result<()> {
         let created = record.created_at.to_rfc3339();
@@ -685,8 +1261,8 @@ impl CacheDatabase {
         };
 
         self.conn.execute(
-            "INSERT INTO build_history (package_id, build_id, version, build_date, build_status, duration_seconds, ghcr_tag, error_message, build_log_url)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            "INSERT INTO build_history (package_id, build_id, version, build_date, build_status, duration_seconds, artifact_size_bytes, ghcr_tag, ghcr_digest, error_message, build_log_url, skip_reason)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
             params![
                 package_id,
                 entry.build_id,
@@ -694,11 +1270,64 @@ impl CacheDatabase {
                 entry.build_date.to_rfc3339(),
                 entry.build_status.as_str(),
                 entry.duration_seconds,
+                entry.artifact_size_bytes,
                 entry.ghcr_tag,
+                entry.ghcr_digest,
                 entry.error_message,
                 entry.build_log_url,
+                entry.skip_reason,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Import a failure record (used by JSON import)
+    pub fn import_failure(
+        &self,
+        pkg_id: &str,
+        host_triplet: &str,
+        failure: &FailedPackage,
+    ) -> Result<()> {
+        let package_id = self
+            .get_package(pkg_id, host_triplet)?
+            .and_then(|r| r.id)
+            .ok_or_else(|| Error::PackageNotFound(pkg_id.to_string()))?;
+
+        self.conn.execute(
+            "INSERT OR REPLACE INTO failed_packages (package_id, failure_count, last_failure_date, last_error_message, next_retry_date)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                package_id,
+                failure.failure_count,
+                failure.last_failure_date.to_rfc3339(),
+                failure.last_error_message,
+                failure.next_retry_date.map(|d| d.to_rfc3339()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Import a work queue claim (used by JSON import)
+    pub fn import_claim(
+        &self,
+        pkg_id: &str,
+        host_triplet: &str,
+        claim: &PackageClaim,
+    ) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE packages SET claimed_by = ?1, claimed_at = ?2, claim_prior_status = ?3
+             WHERE pkg_id = ?4 AND host_triplet = ?5",
+            params![
+                claim.worker_id,
+                claim.claimed_at.to_rfc3339(),
+                claim.prior_status.map(|s| s.as_str()),
+                pkg_id,
+                host_triplet
             ],
         )?;
+        if updated == 0 {
+            return Err(Error::PackageNotFound(pkg_id.to_string()));
+        }
         Ok(())
     }
 
@@ -738,6 +1367,562 @@ mod tests {
         assert_eq!(stats.total_packages, 0);
     }
 
+    fn query_plan(db: &CacheDatabase, sql: &str) -> String {
+        let mut stmt = db
+            .conn
+            .prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .unwrap();
+        let rows = stmt
+            .query_map(["x86_64-linux", "failed"], |row| row.get::<_, String>(3))
+            .unwrap();
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap()
+            .join("\n")
+    }
+
+    fn insert_build_at(db: &CacheDatabase, pkg_id: &str, build_id: &str, date: DateTime<Utc>) {
+        let package_id: i64 = db
+            .conn
+            .query_row(
+                "SELECT id FROM packages WHERE pkg_id = ?1",
+                params![pkg_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        db.conn
+            .execute(
+                "INSERT INTO build_history (package_id, build_id, version, build_date, build_status)
+                 VALUES (?1, ?2, '1.0', ?3, 'success')",
+                params![package_id, build_id, date.to_rfc3339()],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_get_packages_by_ids_chunks() {
+        let db = CacheDatabase::in_memory().unwrap();
+        let ids: Vec<String> = (0..1200)
+            .map(|i| format!("github.com.test.pkg{:04}", i))
+            .collect();
+        for id in &ids {
+            db.get_or_create_package(id, id, "x86_64-linux").unwrap();
+        }
+        db.get_or_create_package(&ids[0], &ids[0], "aarch64-linux")
+            .unwrap();
+
+        let mut wanted: Vec<&str> = ids.iter().map(String::as_str).collect();
+        wanted.push("github.com.test.missing");
+        assert!(wanted.len() > 2 * MAX_IDS_PER_QUERY);
+
+        let found = db.get_packages_by_ids("x86_64-linux", &wanted).unwrap();
+        assert_eq!(found.len(), ids.len());
+        assert!(found.iter().all(|p| p.host_triplet == "x86_64-linux"));
+        assert_eq!(found[0].pkg_id, ids[0]);
+        assert_eq!(found[1199].pkg_id, ids[1199]);
+
+        assert!(db
+            .get_packages_by_ids("x86_64-linux", &[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_create_package_full() {
+        let db = CacheDatabase::in_memory().unwrap();
+        let init = PackageRecordInit::new("github.com.test.a", "a", "x86_64-linux")
+            .with_family("a")
+            .with_build_script("binaries/a/static.yaml")
+            .with_ghcr_pkg("pkgforge/bincache/a/static/a");
+
+        let created = db.create_package_full(&init).unwrap();
+        assert_eq!(created.pkg_family.as_deref(), Some("a"));
+        assert_eq!(created.build_script, "binaries/a/static.yaml");
+        assert_eq!(created.ghcr_pkg, "pkgforge/bincache/a/static/a");
+
+        // Unset fields keep their value; set fields replace it
+        let moved = PackageRecordInit::new("github.com.test.a", "a", "x86_64-linux")
+            .with_build_script("binaries/a/appimage.yaml");
+        let updated = db.create_package_full(&moved).unwrap();
+        assert_eq!(updated.id, created.id);
+        assert_eq!(updated.pkg_family.as_deref(), Some("a"));
+        assert_eq!(updated.build_script, "binaries/a/appimage.yaml");
+        assert_eq!(updated.ghcr_pkg, "pkgforge/bincache/a/static/a");
+    }
+
+    #[test]
+    fn test_unknown_status_is_an_error() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.get_or_create_package("github.com.test.a", "a", "x86_64-linux")
+            .unwrap();
+        db.conn
+            .execute_batch(
+                "PRAGMA ignore_check_constraints = ON;
+                 UPDATE packages SET last_build_status = 'exploded';
+                 PRAGMA ignore_check_constraints = OFF;",
+            )
+            .unwrap();
+
+        let err = db
+            .get_package("github.com.test.a", "x86_64-linux")
+            .unwrap_err();
+        assert!(err.to_string().contains("exploded"), "{}", err);
+    }
+
+    #[test]
+    fn test_migrate_status_checks() {
+        // Build a v6 database with the old status constraints
+        let conn = Connection::open_in_memory().unwrap();
+        let v6_schema = CREATE_SCHEMA
+            .replace(
+                PACKAGE_STATUS_VALUES,
+                "'success', 'failed', 'skipped', 'pending'",
+            )
+            .replace(HISTORY_STATUS_VALUES, "'success', 'failed', 'skipped'");
+        conn.execute_batch(&v6_schema).unwrap();
+        conn.execute_batch(CREATE_HOST_INDEXES).unwrap();
+        conn.execute_batch(CREATE_VIEWS).unwrap();
+        conn.execute_batch(
+            "INSERT INTO schema_info (version, description) VALUES (6, 'v6');
+             INSERT INTO packages (pkg_id, pkg_name, host_triplet, last_build_status)
+             VALUES ('github.com.test.a', 'a', 'x86_64-linux', 'failed');
+             INSERT INTO build_history (package_id, version, build_date, build_status)
+             VALUES (1, '1.0', '2026-01-01T00:00:00+00:00', 'failed');",
+        )
+        .unwrap();
+        assert!(conn
+            .execute(
+                "UPDATE packages SET last_build_status = 'timeout' WHERE id = 1",
+                []
+            )
+            .is_err());
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+
+        let db = CacheDatabase {
+            conn,
+            backoff: BackoffPolicy::default(),
+            claim_timeout: Duration::hours(DEFAULT_CLAIM_TIMEOUT_HOURS),
+        };
+        db.initialize().unwrap();
+
+        // Existing rows survive and the new statuses are accepted
+        db.update_build_result(
+            "github.com.test.a",
+            "x86_64-linux",
+            "1.1",
+            BuildStatus::Timeout,
+            None,
+            None,
+            None,
+            None,
+            None,
+            0,
+            None,
+        )
+        .unwrap();
+        let pkg = db
+            .get_package("github.com.test.a", "x86_64-linux")
+            .unwrap()
+            .unwrap();
+        assert_eq!(pkg.last_build_status, Some(BuildStatus::Timeout));
+        let history = db
+            .get_build_history("github.com.test.a", "x86_64-linux")
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].build_status, BuildStatus::Timeout);
+        assert_eq!(db.get_stats("x86_64-linux").unwrap().failed, 1);
+
+        let indexes: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = 'idx_packages_host_status'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(indexes, 1);
+        assert!(db.get_packages_needing_rebuild("x86_64-linux").is_ok());
+
+        // Foreign key enforcement is left as the connection had it
+        let foreign_keys: bool = db
+            .conn
+            .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .unwrap();
+        assert!(!foreign_keys);
+    }
+
+    #[test]
+    fn test_get_stats_history() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.get_or_create_package("github.com.test.a", "a", "x86_64-linux")
+            .unwrap();
+
+        let now = Utc::now();
+        insert_build_at(&db, "github.com.test.a", "a1", now - Duration::days(10));
+        insert_build_at(&db, "github.com.test.a", "a2", now - Duration::days(2));
+        insert_build_at(&db, "github.com.test.a", "a3", now);
+        db.conn
+            .execute(
+                "UPDATE build_history SET build_status = 'failed' WHERE build_id = 'a3'",
+                [],
+            )
+            .unwrap();
+
+        let history = db.get_stats_history("x86_64-linux", 7).unwrap();
+        assert_eq!(history.len(), 7);
+        assert_eq!(history[6].date, now.date_naive());
+        assert_eq!((history[6].successful, history[6].failed), (0, 1));
+        assert_eq!((history[4].successful, history[4].failed), (1, 0));
+        assert_eq!(history[4].success_rate(), Some(100.0));
+        assert_eq!(history[0].success_rate(), None);
+        let total: i64 = history.iter().map(|d| d.successful + d.failed).sum();
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    fn test_recent_builds_since_and_prune_older_than() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.get_or_create_package("github.com.test.a", "a", "x86_64-linux")
+            .unwrap();
+        db.get_or_create_package("github.com.test.b", "b", "x86_64-linux")
+            .unwrap();
+
+        let now = Utc::now();
+        insert_build_at(&db, "github.com.test.a", "a1", now - Duration::days(40));
+        insert_build_at(&db, "github.com.test.a", "a2", now - Duration::days(35));
+        insert_build_at(&db, "github.com.test.a", "a3", now - Duration::hours(2));
+        insert_build_at(&db, "github.com.test.b", "b1", now - Duration::days(60));
+
+        let recent = db
+            .get_recent_builds_since("x86_64-linux", now - Duration::hours(24))
+            .unwrap();
+        let ids: Vec<_> = recent.iter().map(|(_, h)| h.build_id.as_str()).collect();
+        assert_eq!(ids, vec!["a3"]);
+
+        // a1 and a2 are old; b1 is old but the only build of b
+        let deleted = db
+            .prune_history_older_than(now - Duration::days(30))
+            .unwrap();
+        assert_eq!(deleted, 2);
+        let remaining = db.get_recent_builds("x86_64-linux", 10).unwrap();
+        let ids: Vec<_> = remaining.iter().map(|(_, h)| h.build_id.as_str()).collect();
+        assert_eq!(ids, vec!["a3", "b1"]);
+    }
+
+    #[test]
+    fn test_concurrent_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.sdb");
+        let writer = CacheDatabase::open(&path).unwrap();
+        let reader = CacheDatabase::open(&path).unwrap();
+
+        let mode: String = reader
+            .conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        writer
+            .get_or_create_package("github.com.test.a", "a", "x86_64-linux")
+            .unwrap();
+
+        // A reader isn't blocked by an open write transaction and sees
+        // only committed data
+        writer.conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+        writer
+            .get_or_create_package("github.com.test.b", "b", "x86_64-linux")
+            .unwrap();
+        assert_eq!(
+            reader
+                .list_packages("x86_64-linux", None, false)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // A second writer waits for the lock instead of failing
+        let path2 = path.clone();
+        let handle = std::thread::spawn(move || {
+            let other = CacheDatabase::open(&path2).unwrap();
+            other
+                .get_or_create_package("github.com.test.c", "c", "x86_64-linux")
+                .unwrap();
+        });
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        writer.conn.execute_batch("COMMIT").unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(
+            reader
+                .list_packages("x86_64-linux", None, false)
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_host_queries_use_indexes() {
+        let db = CacheDatabase::in_memory().unwrap();
+
+        let plan = query_plan(
+            &db,
+            "SELECT id FROM packages WHERE host_triplet = ?1 AND last_build_status = ?2",
+        );
+        assert!(plan.contains("idx_packages_host_status"), "{}", plan);
+
+        let plan = query_plan(
+            &db,
+            "SELECT bh.build_date FROM packages p
+             JOIN build_history bh ON p.id = bh.package_id
+             WHERE p.host_triplet = ?1 AND bh.build_status = ?2",
+        );
+        assert!(!plan.contains("SCAN bh"), "{}", plan);
+    }
+
+    #[test]
+    fn test_migration_adds_host_indexes() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.conn
+            .execute_batch(
+                "DROP INDEX idx_packages_host_status;
+                 DROP INDEX idx_packages_host_outdated;
+                 DROP INDEX idx_build_history_package_date;
+                 DELETE FROM schema_info WHERE version > 5;
+                 INSERT INTO schema_info (version, description) VALUES (5, 'v5');",
+            )
+            .unwrap();
+        db.initialize().unwrap();
+
+        let count: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name IN
+                 ('idx_packages_host_status', 'idx_packages_host_outdated', 'idx_build_history_package_date')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_claim_next_rebuild() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.get_or_create_package("github.com.test.a", "a", "x86_64-linux")
+            .unwrap();
+        db.get_or_create_package("github.com.test.b", "b", "x86_64-linux")
+            .unwrap();
+
+        let first = db
+            .claim_next_rebuild("x86_64-linux", "w1")
+            .unwrap()
+            .unwrap();
+        let second = db
+            .claim_next_rebuild("x86_64-linux", "w2")
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.pkg_name, "a");
+        assert_eq!(second.pkg_name, "b");
+        assert_eq!(first.last_build_status, Some(BuildStatus::Pending));
+        assert!(db
+            .claim_next_rebuild("x86_64-linux", "w3")
+            .unwrap()
+            .is_none());
+
+        // Only the holder can release a claim
+        assert!(!db
+            .release_claim("github.com.test.a", "x86_64-linux", "w2")
+            .unwrap());
+        assert!(db
+            .release_claim("github.com.test.a", "x86_64-linux", "w1")
+            .unwrap());
+        let again = db
+            .claim_next_rebuild("x86_64-linux", "w3")
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.pkg_name, "a");
+
+        // Recording a result clears the claim and takes it off the queue
+        db.update_build_result(
+            "github.com.test.a",
+            "x86_64-linux",
+            "1.0",
+            BuildStatus::Success,
+            None,
+            None,
+            None,
+            None,
+            None,
+            0,
+            None,
+        )
+        .unwrap();
+        assert!(db
+            .claim_next_rebuild("x86_64-linux", "w4")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_claim_reclaims_stale_and_skips_backoff() {
+        let db = CacheDatabase::in_memory()
+            .unwrap()
+            .with_claim_timeout(Duration::zero());
+        db.get_or_create_package("github.com.test.a", "a", "x86_64-linux")
+            .unwrap();
+        db.get_or_create_package("github.com.test.b", "b", "x86_64-linux")
+            .unwrap();
+        db.record_failure("github.com.test.a", "x86_64-linux", "boom")
+            .unwrap();
+
+        let claimed = db
+            .claim_next_rebuild("x86_64-linux", "w1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(claimed.pkg_name, "b");
+
+        // With a zero timeout the crashed worker's claim is immediately stale
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let reclaimed = db
+            .claim_next_rebuild("x86_64-linux", "w2")
+            .unwrap()
+            .unwrap();
+        assert_eq!(reclaimed.pkg_name, "b");
+    }
+
+    #[test]
+    fn test_release_claim_restores_prior_status() {
+        let db = CacheDatabase::in_memory()
+            .unwrap()
+            .with_claim_timeout(Duration::zero());
+        db.get_or_create_package("github.com.test.a", "a", "x86_64-linux")
+            .unwrap();
+        db.update_build_result(
+            "github.com.test.a",
+            "x86_64-linux",
+            "1.0",
+            BuildStatus::Success,
+            None,
+            None,
+            None,
+            None,
+            None,
+            0,
+            None,
+        )
+        .unwrap();
+        db.mark_outdated("github.com.test.a", "x86_64-linux", "1.1")
+            .unwrap();
+
+        db.claim_next_rebuild("x86_64-linux", "w1")
+            .unwrap()
+            .unwrap();
+        // A stale reclaim must not record `pending` as the prior status
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        db.claim_next_rebuild("x86_64-linux", "w2")
+            .unwrap()
+            .unwrap();
+        assert!(db
+            .release_claim("github.com.test.a", "x86_64-linux", "w2")
+            .unwrap());
+
+        let pkg = db
+            .get_package("github.com.test.a", "x86_64-linux")
+            .unwrap()
+            .unwrap();
+        assert_eq!(pkg.last_build_status, Some(BuildStatus::Success));
+        assert!(pkg.is_outdated);
+        assert!(db
+            .get_claim("github.com.test.a", "x86_64-linux")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_default_backoff_policy() {
+        let policy = BackoffPolicy::default();
+        assert_eq!(policy.delay(1), Duration::hours(1));
+        assert_eq!(policy.delay(2), Duration::hours(2));
+        assert_eq!(policy.delay(3), Duration::hours(4));
+        assert_eq!(policy.delay(6), Duration::hours(24));
+        assert_eq!(policy.delay(100), Duration::hours(24));
+    }
+
+    #[test]
+    fn test_custom_backoff_policy() {
+        let policy = BackoffPolicy {
+            base_minutes: 15,
+            max_hours: 6,
+            multiplier: 3.0,
+        };
+        let db = CacheDatabase::in_memory()
+            .unwrap()
+            .with_backoff_policy(policy);
+        db.get_or_create_package("github.com.test.pkg", "pkg", "x86_64-linux")
+            .unwrap();
+
+        for expected in [15, 45, 135, 360, 360] {
+            db.record_failure("github.com.test.pkg", "x86_64-linux", "boom")
+                .unwrap();
+            let (_, failure) = db.list_failures("x86_64-linux").unwrap().remove(0);
+            let delay = failure.next_retry_date.unwrap() - failure.last_failure_date;
+            assert_eq!(delay, Duration::minutes(expected));
+        }
+    }
+
+    #[test]
+    fn test_list_failures_sorted_by_retry() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.get_or_create_package("github.com.test.a", "a", "x86_64-linux")
+            .unwrap();
+        db.get_or_create_package("github.com.test.b", "b", "x86_64-linux")
+            .unwrap();
+
+        // b fails twice so its backoff ends later than a's
+        db.record_failure("github.com.test.b", "x86_64-linux", "first")
+            .unwrap();
+        db.record_failure("github.com.test.b", "x86_64-linux", "second")
+            .unwrap();
+        db.record_failure("github.com.test.a", "x86_64-linux", "boom")
+            .unwrap();
+
+        let failures = db.list_failures("x86_64-linux").unwrap();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].0.pkg_name, "a");
+        assert_eq!(failures[1].0.pkg_name, "b");
+        assert_eq!(failures[1].1.failure_count, 2);
+        assert_eq!(failures[1].1.last_error_message.as_deref(), Some("second"));
+        assert!(db.list_failures("aarch64-linux").unwrap().is_empty());
+
+        let failure = db
+            .get_failure("github.com.test.b", "x86_64-linux")
+            .unwrap()
+            .unwrap();
+        assert_eq!(failure.id, failures[1].1.id);
+        assert_eq!(failure.package_id, failures[1].0.id.unwrap());
+        assert_eq!(failure.next_retry_date, failures[1].1.next_retry_date);
+        assert!(db
+            .get_failure("github.com.test.b", "aarch64-linux")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_list_hosts() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.get_or_create_package("github.com.test.pkg", "testpkg", "x86_64-linux")
+            .unwrap();
+        db.get_or_create_package("github.com.test.pkg", "testpkg", "aarch64-linux")
+            .unwrap();
+        db.get_or_create_package("github.com.test.other", "other", "x86_64-linux")
+            .unwrap();
+
+        assert_eq!(
+            db.list_hosts().unwrap(),
+            vec!["aarch64-linux".to_string(), "x86_64-linux".to_string()]
+        );
+    }
+
     #[test]
     fn test_package_crud() {
         let db = CacheDatabase::in_memory().unwrap();
@@ -766,6 +1951,7 @@ mod tests {
             Some("1.0.0"),
             None,
             0,
+            None,
         )
         .unwrap();
 
@@ -777,6 +1963,112 @@ mod tests {
         assert_eq!(updated.last_build_status, Some(BuildStatus::Success));
     }
 
+    #[test]
+    fn test_skip_reason() {
+        let db = CacheDatabase::in_memory().unwrap();
+        let (pkg, host) = ("github.com.test.pkg", "x86_64-linux");
+        db.get_or_create_package(pkg, "testpkg", host).unwrap();
+        assert!(matches!(
+            db.mark_skipped("github.com.test.missing", host, "disabled"),
+            Err(Error::PackageNotFound(_))
+        ));
+
+        db.update_build_result(
+            pkg,
+            host,
+            "1.0.0",
+            BuildStatus::Success,
+            None,
+            None,
+            None,
+            None,
+            None,
+            0,
+            None,
+        )
+        .unwrap();
+        db.update_build_result(
+            pkg,
+            host,
+            "1.0.0",
+            BuildStatus::Skipped,
+            None,
+            None,
+            None,
+            None,
+            None,
+            0,
+            Some("already built"),
+        )
+        .unwrap();
+        db.mark_skipped(pkg, host, "backoff").unwrap();
+
+        let record = db.get_package(pkg, host).unwrap().unwrap();
+        assert_eq!(record.last_build_status, Some(BuildStatus::Skipped));
+        assert_eq!(record.current_version.as_deref(), Some("1.0.0"));
+
+        let reasons: Vec<_> = db
+            .get_build_history(pkg, host)
+            .unwrap()
+            .into_iter()
+            .map(|h| h.skip_reason)
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![None, Some("already built".into()), Some("backoff".into())]
+        );
+        let recent = db.get_recent_builds(host, 1).unwrap();
+        assert_eq!(recent[0].1.version, "1.0.0");
+        assert_eq!(recent[0].1.skip_reason.as_deref(), Some("backoff"));
+    }
+
+    #[test]
+    fn test_migration_adds_skip_reason() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.conn
+            .execute_batch(
+                "ALTER TABLE build_history DROP COLUMN skip_reason;
+                 DELETE FROM schema_info WHERE version > 7;
+                 INSERT INTO schema_info (version, description) VALUES (7, 'v7');",
+            )
+            .unwrap();
+        assert!(!db.has_column("build_history", "skip_reason").unwrap());
+        db.initialize().unwrap();
+        assert!(db.has_column("build_history", "skip_reason").unwrap());
+    }
+
+    #[test]
+    fn test_migration_lowercases_host_triplet() {
+        let db = CacheDatabase::in_memory().unwrap();
+        db.conn
+            .execute_batch(
+                "INSERT INTO packages (pkg_id, pkg_name, host_triplet, current_version, updated_at)
+                 VALUES ('a', 'a', 'x86_64-Linux', '1.0', '2024-01-01 00:00:00');
+                 INSERT INTO packages (pkg_id, pkg_name, host_triplet, current_version, updated_at)
+                 VALUES ('b', 'b', 'x86_64-Linux', '1.0', '2024-01-01 00:00:00');
+                 INSERT INTO packages (pkg_id, pkg_name, host_triplet, current_version, updated_at)
+                 VALUES ('b', 'b', 'x86_64-linux', '2.0', '2024-02-01 00:00:00');
+                 DELETE FROM schema_info WHERE version > 8;
+                 INSERT INTO schema_info (version, description) VALUES (8, 'v8');",
+            )
+            .unwrap();
+        db.initialize().unwrap();
+
+        let a = db.get_package("a", "x86_64-linux").unwrap().unwrap();
+        assert_eq!(a.current_version.as_deref(), Some("1.0"));
+        let b = db.get_package("b", "x86_64-linux").unwrap().unwrap();
+        assert_eq!(b.current_version.as_deref(), Some("2.0"));
+        let mixed: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM packages WHERE host_triplet != lower(host_triplet)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(mixed, 0);
+    }
+
     #[test]
     fn test_stats() {
         let db = CacheDatabase::in_memory().unwrap();
@@ -797,6 +2089,7 @@ mod tests {
             Some("1.0"),
             None,
             0,
+            None,
         )
         .unwrap();
 
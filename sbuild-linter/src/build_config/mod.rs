@@ -1,9 +1,9 @@
-use std::{
-    fs::File,
-    io::{self, BufWriter, Write},
-};
+use std::io::{self, Write};
 
-use crate::{comments::Comments, description::Description, get_pkg_id, xexec::XExec, BuildAsset};
+use crate::{
+    comments::Comments, description::Description, get_pkg_id, xexec::XExec, BuildAsset, Resource,
+    XUpdate,
+};
 
 /// Per-package configuration for multi-package recipes
 #[derive(Debug, Clone, Default)]
@@ -11,7 +11,7 @@ pub struct PackageConfig {
     pub provides: Vec<String>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct BuildConfig {
     pub _disabled: bool,
     pub pkg: String,
@@ -25,6 +25,8 @@ pub struct BuildConfig {
     pub build_deps: Option<Vec<String>>,
     pub category: Vec<String>,
     pub description: Option<Description>,
+    pub icon: Option<Resource>,
+    pub desktop: Option<Resource>,
     pub homepage: Option<Vec<String>>,
     pub maintainer: Option<Vec<String>>,
     pub license: Option<Vec<String>>,
@@ -36,19 +38,31 @@ pub struct BuildConfig {
     pub tag: Option<Vec<String>>,
     pub ghcr_pkg: Option<String>,
     pub snapshots: Option<Vec<String>>,
+    /// Fields accepted by a registered [`FieldValidator`], with their source text
+    ///
+    /// [`FieldValidator`]: crate::field::FieldValidator
+    pub custom_fields: Vec<(String, String)>,
+    pub x_update: Option<XUpdate>,
     pub x_exec: XExec,
 }
 
 impl BuildConfig {
-    pub fn set_pkg_id_from_src_url(&mut self) {
+    /// Derive `pkg_id` from the first `src_url` when it isn't set
+    pub fn set_pkg_id_from_src_url(&mut self) -> Result<(), String> {
         if self.pkg_id.is_empty() && !self.src_url.is_empty() {
-            self.pkg_id = get_pkg_id(&self.src_url[0]);
+            self.pkg_id = get_pkg_id(&self.src_url[0]).ok_or_else(|| {
+                format!(
+                    "Cannot derive pkg_id from src_url '{}'; set pkg_id explicitly",
+                    self.src_url[0]
+                )
+            })?;
         }
+        Ok(())
     }
 
-    pub fn write_yaml(
+    pub fn write_yaml<W: Write>(
         &self,
-        writer: &mut BufWriter<File>,
+        writer: &mut W,
         indent: usize,
         comments: Comments,
     ) -> io::Result<()> {
@@ -58,7 +72,7 @@ impl BuildConfig {
             writeln!(writer, "{}", c)?;
         }
 
-        let write_field_comments = |writer: &mut BufWriter<File>, field: &str| -> io::Result<()> {
+        let write_field_comments = |writer: &mut W, field: &str| -> io::Result<()> {
             if let Some(comments) = comments.field_comments.get(field) {
                 for comment in comments {
                     writeln!(writer, "{}", comment)?;
@@ -138,6 +152,16 @@ impl BuildConfig {
             .unwrap()
             .write_yaml(writer, indent)?;
 
+        write_field_comments(writer, "icon")?;
+        if let Some(ref icon) = self.icon {
+            icon.write_yaml(writer, "icon", indent)?;
+        }
+
+        write_field_comments(writer, "desktop")?;
+        if let Some(ref desktop) = self.desktop {
+            desktop.write_yaml(writer, "desktop", indent)?;
+        }
+
         write_field_comments(writer, "homepage")?;
         if let Some(ref homepage) = self.homepage {
             writeln!(writer, "{}homepage:", indent_str)?;
@@ -222,6 +246,16 @@ impl BuildConfig {
             }
         }
 
+        for (field, source) in &self.custom_fields {
+            write_field_comments(writer, field)?;
+            writeln!(writer, "{}", source)?;
+        }
+
+        write_field_comments(writer, "x_update")?;
+        if let Some(ref x_update) = self.x_update {
+            x_update.write_yaml(writer, indent)?;
+        }
+
         write_field_comments(writer, "x_exec")?;
         writeln!(writer, "{}x_exec:", indent_str)?;
         self.x_exec.write_yaml(writer, indent + 2)?;
@@ -1,7 +1,4 @@
-use std::{
-    fs::File,
-    io::{self, BufRead, BufReader},
-};
+use std::{fs, io};
 
 use indexmap::IndexMap;
 
@@ -24,13 +21,15 @@ impl Comments {
     // this only works for root level fields
     // inner comments are assigned to adjacent lines, so won't work.
     pub fn parse_comments(&mut self, file_path: &str) -> io::Result<()> {
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
+        self.parse_comments_str(&fs::read_to_string(file_path)?);
+        Ok(())
+    }
+
+    pub fn parse_comments_str(&mut self, content: &str) {
         let mut current_comments = Vec::new();
         let mut shebang_added = false;
 
-        for line in reader.lines() {
-            let line = line?;
+        for line in content.lines() {
             let trimmed = line.trim();
 
             if trimmed.starts_with("#!/SBUILD") {
@@ -58,7 +57,5 @@ impl Comments {
         if !current_comments.is_empty() {
             self.header_comments.extend(current_comments);
         }
-
-        Ok(())
     }
 }
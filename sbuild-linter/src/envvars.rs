@@ -0,0 +1,377 @@
+//! Environment variables available to `x_exec` scripts
+//!
+//! The builder injects [`BUILD_ENV_VARS`] (each also exported uppercased) and
+//! passes [`INHERITED_ENV_VARS`] through from the host. The linter uses the
+//! same lists to flag script references to anything else.
+
+use std::collections::HashSet;
+
+/// Variables the builder sets for every script, also exported uppercased
+pub const BUILD_ENV_VARS: [&str; 12] = [
+    "pkg",
+    "pkg_id",
+    "pkg_type",
+    "sbuild_pkg",
+    "sbuild_pkgver",
+    "sbuild_outdir",
+    "sbuild_tmpdir",
+    "pkg_ver",
+    "pkgver",
+    "remote_pkgver",
+    "arch",
+    "arch_alt",
+];
+
+/// Host variables passed through to scripts when they're set
+pub const INHERITED_ENV_VARS: [&str; 16] = [
+    "DEBIAN_FRONTEND",
+    "EGET_TIMEOUT",
+    "GIT_ASKPASS",
+    "GIT_TERMINAL_PROMPT",
+    "GITHUB_TOKEN",
+    "GH_TOKEN",
+    "GITLAB_TOKEN",
+    "GL_TOKEN",
+    "HF_TOKEN",
+    "HOST_TRIPLET",
+    "NIXPKGS_ALLOW_BROKEN",
+    "NIXPKGS_ALLOW_UNFREE",
+    "NIXPKGS_ALLOW_UNSUPPORTED_SYSTEM",
+    "SYSTMP",
+    "TERM",
+    "USER_AGENT",
+];
+
+/// Inherited variables holding credentials, never to be logged
+pub const SECRET_ENV_VARS: [&str; 5] = [
+    "GITHUB_TOKEN",
+    "GH_TOKEN",
+    "GITLAB_TOKEN",
+    "GL_TOKEN",
+    "HF_TOKEN",
+];
+
+/// `value`, or a placeholder when `name` holds a credential
+pub fn masked_env_value<'a>(name: &str, value: &'a str) -> &'a str {
+    if SECRET_ENV_VARS.contains(&name) {
+        "***"
+    } else {
+        value
+    }
+}
+
+/// Set by the shell or present in any build environment
+const SHELL_ENV_VARS: &[&str] = &[
+    "BASH_SOURCE",
+    "BASHPID",
+    "EUID",
+    "FUNCNAME",
+    "HOME",
+    "HOSTNAME",
+    "HOSTTYPE",
+    "IFS",
+    "LANG",
+    "LC_ALL",
+    "LINENO",
+    "OLDPWD",
+    "OPTARG",
+    "OPTIND",
+    "OSTYPE",
+    "PATH",
+    "PIPESTATUS",
+    "PPID",
+    "PWD",
+    "RANDOM",
+    "REPLY",
+    "SECONDS",
+    "SHELL",
+    "TMPDIR",
+    "UID",
+    "USER",
+];
+
+/// Words after which identifiers name variables being declared
+const DECLARE_WORDS: &[&str] = &[
+    "declare", "export", "for", "local", "read", "readonly", "typeset",
+];
+
+/// Whether scripts can rely on `name` being set without defining it
+pub fn is_known_env_var(name: &str) -> bool {
+    BUILD_ENV_VARS
+        .iter()
+        .any(|var| *var == name || var.to_uppercase() == name)
+        || INHERITED_ENV_VARS.contains(&name)
+        || SHELL_ENV_VARS.contains(&name)
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// End of the identifier starting at `i`, if one does
+fn ident_end(chars: &[char], i: usize) -> Option<usize> {
+    if !chars.get(i).copied().is_some_and(is_ident_start) {
+        return None;
+    }
+    let len = chars[i..].iter().take_while(|c| is_ident_char(**c)).count();
+    Some(i + len)
+}
+
+fn word_boundary(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | '`')
+}
+
+#[derive(Default)]
+struct Scan {
+    /// Referenced names with the 0-based script line of first use
+    refs: Vec<(String, usize)>,
+    defined: HashSet<String>,
+    in_single: bool,
+    in_double: bool,
+    /// Open heredoc: delimiter, and whether its body expands variables
+    heredoc: Option<(String, bool)>,
+}
+
+impl Scan {
+    fn reference(&mut self, name: &str, line: usize) {
+        if !self.refs.iter().any(|(n, _)| n == name) {
+            self.refs.push((name.to_string(), line));
+        }
+    }
+
+    /// Handle a `$` at `i`, returning the index after the expansion
+    fn expansion(&mut self, chars: &[char], i: usize, line: usize) -> usize {
+        match chars.get(i + 1) {
+            // `$$` is the shell's PID
+            Some('$') => i + 2,
+            Some('{') => {
+                let Some(close) = chars[i..].iter().position(|c| *c == '}') else {
+                    return chars.len();
+                };
+                let inner: String = chars[i + 2..i + close].iter().collect();
+                let name_start = inner.strip_prefix('#').unwrap_or(&inner);
+                // `${!ref}` is indirect; the real name isn't known statically
+                if !name_start.starts_with('!') {
+                    let len = name_start.chars().take_while(|c| is_ident_char(*c)).count();
+                    let (name, rest) = name_start.split_at(len);
+                    let has_default = [":-", "-", ":=", "=", ":?", "?", ":+", "+"]
+                        .iter()
+                        .any(|op| rest.starts_with(op));
+                    if rest.starts_with(":=") || rest.starts_with('=') {
+                        self.defined.insert(name.to_string());
+                    }
+                    if name.starts_with(is_ident_start) && !has_default {
+                        self.reference(name, line);
+                    }
+                }
+                i + close + 1
+            }
+            _ => match ident_end(chars, i + 1) {
+                Some(end) => {
+                    let name: String = chars[i + 1..end].iter().collect();
+                    self.reference(&name, line);
+                    end
+                }
+                None => i + 1,
+            },
+        }
+    }
+
+    /// Parse a heredoc operator at `i` (just past `<<`)
+    fn heredoc_start(&mut self, chars: &[char], mut i: usize) -> usize {
+        if chars.get(i) == Some(&'-') {
+            i += 1;
+        }
+        while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+            i += 1;
+        }
+        let quote = chars.get(i).copied().filter(|c| matches!(c, '\'' | '"'));
+        if quote.is_some() {
+            i += 1;
+        }
+        let start = i;
+        while chars
+            .get(i)
+            .is_some_and(|c| !c.is_whitespace() && Some(*c) != quote && !matches!(c, ';' | '|'))
+        {
+            i += 1;
+        }
+        if i > start {
+            let delimiter: String = chars[start..i].iter().collect();
+            let quoted = quote.is_some() || delimiter.starts_with('\\');
+            self.heredoc = Some((delimiter.trim_start_matches('\\').to_string(), !quoted));
+        }
+        if quote.is_some() && i < chars.len() {
+            i += 1;
+        }
+        i
+    }
+
+    fn shell_line(&mut self, text: &str, line: usize) {
+        let chars: Vec<char> = text.chars().collect();
+        // After `export`, `read` etc., every name up to the next separator
+        let mut declaring = false;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if self.in_single {
+                self.in_single = c != '\'';
+                i += 1;
+                continue;
+            }
+            let at_word = i == 0 || word_boundary(chars[i - 1]);
+            match c {
+                '\\' => i += 2,
+                '\'' if !self.in_double => {
+                    self.in_single = true;
+                    i += 1;
+                }
+                '"' => {
+                    self.in_double = !self.in_double;
+                    i += 1;
+                }
+                '#' if at_word && !self.in_double => break,
+                '$' => i = self.expansion(&chars, i, line),
+                '<' if !self.in_double && chars.get(i + 1) == Some(&'<') => {
+                    // `<<<` is a here-string, not a heredoc
+                    i = if chars.get(i + 2) == Some(&'<') {
+                        i + 3
+                    } else {
+                        self.heredoc_start(&chars, i + 2)
+                    };
+                }
+                c if at_word && !self.in_double && is_ident_start(c) => {
+                    let end = ident_end(&chars, i).unwrap_or(i + 1);
+                    let word: String = chars[i..end].iter().collect();
+                    let assigns = matches!(chars.get(end), Some('=' | '['))
+                        || (chars.get(end) == Some(&'+') && chars.get(end + 1) == Some(&'='));
+                    if assigns || declaring {
+                        self.defined.insert(word.clone());
+                    }
+                    declaring |= DECLARE_WORDS.contains(&word.as_str());
+                    i = end;
+                }
+                ';' | '|' | '&' if !self.in_double => {
+                    declaring = false;
+                    i += 1;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+
+    /// Unquoted heredoc bodies expand variables but treat quotes literally
+    fn heredoc_line(&mut self, text: &str, line: usize) {
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' => i += 2,
+                '$' => i = self.expansion(&chars, i, line),
+                _ => i += 1,
+            }
+        }
+    }
+}
+
+/// Variables a script references but neither the builder nor the script
+/// sets, with the 0-based script line of their first use.
+///
+/// This is a heuristic: it skips single-quoted text, comments, quoted
+/// heredocs and `${VAR:-default}`-style expansions, and treats any
+/// assignment, `for`, `read`, `export` or `local` anywhere in the script as
+/// defining the name.
+pub fn undefined_env_refs(script: &str) -> Vec<(String, usize)> {
+    let mut scan = Scan::default();
+    for (line, text) in script.lines().enumerate() {
+        if let Some((delimiter, expands)) = scan.heredoc.clone() {
+            if text.trim() == delimiter {
+                scan.heredoc = None;
+            } else if expands {
+                scan.heredoc_line(text, line);
+            }
+            continue;
+        }
+        scan.shell_line(text, line);
+    }
+
+    let Scan { refs, defined, .. } = scan;
+    refs.into_iter()
+        .filter(|(name, _)| !defined.contains(name) && !is_known_env_var(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn undefined(script: &str) -> Vec<String> {
+        undefined_env_refs(script)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn reports_unknown_refs_with_their_line() {
+        let refs = undefined_env_refs("echo $pkg\necho \"$FOO\" ${BAR}\necho $FOO");
+        assert_eq!(refs, vec![("FOO".to_string(), 1), ("BAR".to_string(), 1)]);
+    }
+
+    #[test]
+    fn skips_expansions_with_defaults() {
+        assert!(undefined("echo ${VAR:-x} ${A-y} ${B:+z} ${C:?unset}").is_empty());
+        assert_eq!(undefined("echo ${D:=x}; echo $D $E"), vec!["E"]);
+    }
+
+    #[test]
+    fn assignments_and_declarations_define_names() {
+        let script = "\
+f() {
+  local dir=/tmp
+  echo $dir
+}
+export TARGET=x86_64
+read -r name rest
+for file in *.tar.gz; do echo $file; done
+echo $TARGET $name $rest $MISSING";
+        assert_eq!(undefined(script), vec!["MISSING"]);
+    }
+
+    #[test]
+    fn separators_end_a_declaration() {
+        assert_eq!(undefined("export A; echo $B"), vec!["B"]);
+        assert!(undefined("export A B\necho $A $B").is_empty());
+    }
+
+    #[test]
+    fn ignores_single_quotes_and_comments() {
+        assert!(undefined("echo '$NOPE ${ALSO_NOPE}'\n# echo $COMMENTED").is_empty());
+        assert_eq!(undefined("echo \"it's $HERE\""), vec!["HERE"]);
+    }
+
+    #[test]
+    fn heredocs_expand_unless_quoted() {
+        let script = "\
+cat <<EOF
+$EXPANDED 'quotes are literal $TOO'
+EOF
+cat <<'EOF'
+$LITERAL
+EOF
+cat <<-\\END
+$ALSO_LITERAL
+END
+echo $AFTER";
+        assert_eq!(undefined(script), vec!["EXPANDED", "TOO", "AFTER"]);
+    }
+
+    #[test]
+    fn known_vars_are_not_reported() {
+        assert!(undefined("echo $pkg $PKG_ID $GITHUB_TOKEN $HOME $$ ${#pkg} ${!ref}").is_empty());
+    }
+}
@@ -2,7 +2,7 @@ use colored::Colorize;
 
 use crate::logger::TaskLogger;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Severity {
     Warn,
     Error,
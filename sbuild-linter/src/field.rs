@@ -0,0 +1,65 @@
+//! Custom top-level fields
+//!
+//! Deployments can accept their own recipe fields (say, an internal `_ticket`)
+//! by registering a [`FieldValidator`] with [`Linter::with_field_validator`].
+//! Registered fields are validated like built-in ones instead of being warned
+//! about, and are kept in the validated output.
+//!
+//! [`Linter::with_field_validator`]: crate::Linter::with_field_validator
+
+pub use saphyr::MarkedYamlOwned;
+
+/// Validates one custom top-level field
+pub trait FieldValidator: Send + Sync {
+    /// Top-level key this validator handles
+    fn field(&self) -> &str;
+
+    /// Check the field's value, returning an error message if it's invalid
+    fn validate(&self, value: &MarkedYamlOwned) -> Result<(), String>;
+}
+
+/// [`FieldValidator`] backed by a closure
+pub struct FnValidator<F> {
+    field: String,
+    check: F,
+}
+
+impl<F> FnValidator<F>
+where
+    F: Fn(&MarkedYamlOwned) -> Result<(), String> + Send + Sync,
+{
+    pub fn new(field: impl Into<String>, check: F) -> Self {
+        Self {
+            field: field.into(),
+            check,
+        }
+    }
+}
+
+impl<F> FieldValidator for FnValidator<F>
+where
+    F: Fn(&MarkedYamlOwned) -> Result<(), String> + Send + Sync,
+{
+    fn field(&self) -> &str {
+        &self.field
+    }
+
+    fn validate(&self, value: &MarkedYamlOwned) -> Result<(), String> {
+        (self.check)(value)
+    }
+}
+
+/// Source text of the top-level field whose key is on `line` (1-based) of
+/// `yaml_str`, up to the next top-level key or comment.
+pub(crate) fn field_source(yaml_str: &str, line: usize) -> String {
+    let mut lines = yaml_str.lines().skip(line.saturating_sub(1));
+    let Some(first) = lines.next() else {
+        return String::new();
+    };
+    let mut out = vec![first];
+    out.extend(lines.take_while(|l| l.is_empty() || l.starts_with([' ', '\t', '-'])));
+    while out.last().is_some_and(|l| l.trim().is_empty()) {
+        out.pop();
+    }
+    out.join("\n")
+}
@@ -0,0 +1,216 @@
+//! `_include` directive for SBUILD recipes
+//!
+//! A recipe may name a shared fragment with a top-level `_include: path.yaml`,
+//! resolved relative to the recipe. The fragment's top-level fields are merged
+//! in before validation, and the recipe's own fields override them.
+//!
+//! Merging is textual: the directive line is blanked and the fragment's
+//! remaining fields are appended, so recipe line numbers stay the same and
+//! appended lines can be mapped back to the fragment.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const INCLUDE_KEY: &str = "_include";
+
+/// Fragment lines appended to a recipe by [`resolve`]
+#[derive(Debug, Clone)]
+pub struct IncludedLines {
+    /// Fragment path, resolved against the recipe
+    pub path: PathBuf,
+    /// Full fragment text, for highlighting errors in it
+    pub source: String,
+    /// Validator line number of the first appended line
+    pub start: usize,
+    /// Fragment line number (1-based) of each appended line
+    pub lines: Vec<usize>,
+}
+
+impl IncludedLines {
+    /// Fragment line for a validator line number, if it falls in the fragment
+    pub fn source_line(&self, line: usize) -> Option<usize> {
+        line.checked_sub(self.start)
+            .and_then(|i| self.lines.get(i).copied())
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches(|c| c == '"' || c == '\'')
+}
+
+/// Key of a top-level `key: value` line
+fn top_level_key(line: &str) -> Option<&str> {
+    if line.starts_with([' ', '\t', '#', '-']) {
+        return None;
+    }
+    let (key, _) = line.split_once(':')?;
+    let key = unquote(key);
+    (!key.is_empty()).then_some(key)
+}
+
+/// Line index and target of the `_include` directive
+fn find_directive(content: &str) -> Option<(usize, &str)> {
+    content.lines().enumerate().find_map(|(i, line)| {
+        if top_level_key(line)? != INCLUDE_KEY {
+            return None;
+        }
+        let value = line.split_once(':').map_or("", |(_, v)| v);
+        let value = value.split_once(" #").map_or(value, |(v, _)| v);
+        Some((i, unquote(value)))
+    })
+}
+
+fn resolve_target(target: &str, recipe_path: &Path) -> PathBuf {
+    recipe_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(target)
+}
+
+/// Fragment named by the recipe's `_include` directive, if any
+pub fn include_path(content: &str, recipe_path: &Path) -> Option<PathBuf> {
+    find_directive(content).map(|(_, target)| resolve_target(target, recipe_path))
+}
+
+/// Merge the fragment named by `_include` into `content`.
+///
+/// Returns `Ok(None)` when the recipe has no directive. Fragments can't
+/// include other fragments.
+pub fn resolve(
+    content: &str,
+    recipe_path: &Path,
+) -> Result<Option<(String, IncludedLines)>, String> {
+    let Some((directive_line, target)) = find_directive(content) else {
+        return Ok(None);
+    };
+    if target.is_empty() {
+        return Err(format!("'{}' must name a file", INCLUDE_KEY));
+    }
+
+    let path = resolve_target(target, recipe_path);
+    let source = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read include {}: {}", path.display(), e))?;
+    if find_directive(&source).is_some() {
+        return Err(format!(
+            "{}: nested '{}' is not supported",
+            path.display(),
+            INCLUDE_KEY
+        ));
+    }
+
+    let own_keys: HashSet<&str> = content.lines().filter_map(top_level_key).collect();
+    let mut merged: Vec<&str> = content.lines().collect();
+    merged[directive_line] = "";
+
+    // The validator drops the `#!/SBUILD` line, so its line N is index N here
+    let start = merged.len();
+    let mut lines = Vec::new();
+    let mut keep = false;
+    for (i, line) in source.lines().enumerate() {
+        if i == 0 && line.trim_start().starts_with("#!/SBUILD") {
+            continue;
+        }
+        if let Some(key) = top_level_key(line) {
+            keep = !own_keys.contains(key);
+        }
+        if keep {
+            merged.push(line);
+            lines.push(i + 1);
+        }
+    }
+
+    let mut out = merged.join("\n");
+    out.push('\n');
+    Ok(Some((
+        out,
+        IncludedLines {
+            path,
+            source,
+            start,
+            lines,
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn recipe_fields_override_fragment() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "common.yaml",
+            "#!/SBUILD\ndescription: \"shared\"\nlicense:\n  - MIT\npkg: fragment\n",
+        );
+        let recipe = write(
+            dir.path(),
+            "tool.yaml",
+            "_include: common.yaml\npkg: tool\ndescription: \"own\"\n",
+        );
+
+        let content = fs::read_to_string(&recipe).unwrap();
+        let (merged, included) = resolve(&content, &recipe).unwrap().unwrap();
+        assert_eq!(
+            merged,
+            "\npkg: tool\ndescription: \"own\"\nlicense:\n  - MIT\n"
+        );
+        assert_eq!(included.path, dir.path().join("common.yaml"));
+        assert_eq!(included.start, 3);
+        assert_eq!(included.lines, vec![3, 4]);
+        assert_eq!(included.source_line(4), Some(4));
+        assert_eq!(included.source_line(2), None);
+    }
+
+    #[test]
+    fn no_directive_is_left_alone() {
+        let recipe = Path::new("tool.yaml");
+        assert!(resolve("pkg: tool\n", recipe).unwrap().is_none());
+        assert!(resolve("x_exec:\n  _include: a.yaml\n", recipe)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_nested_and_cyclic_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "a.yaml",
+            "_include: b.yaml\nlicense:\n  - MIT\n",
+        );
+        write(dir.path(), "b.yaml", "_include: a.yaml\n");
+        let recipe = write(dir.path(), "tool.yaml", "_include: a.yaml\npkg: tool\n");
+        let content = fs::read_to_string(&recipe).unwrap();
+        let err = resolve(&content, &recipe).unwrap_err();
+        assert!(err.contains("nested"), "{}", err);
+
+        let own = write(dir.path(), "self.yaml", "_include: self.yaml\npkg: tool\n");
+        let content = fs::read_to_string(&own).unwrap();
+        let err = resolve(&content, &own).unwrap_err();
+        assert!(err.contains("nested"), "{}", err);
+    }
+
+    #[test]
+    fn reports_missing_and_empty_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let recipe = dir.path().join("tool.yaml");
+
+        let err = resolve("_include: missing.yaml\npkg: tool\n", &recipe).unwrap_err();
+        assert!(err.contains("Failed to read include"), "{}", err);
+        assert!(err.contains("missing.yaml"), "{}", err);
+
+        let err = resolve("_include: \"\"\npkg: tool\n", &recipe).unwrap_err();
+        assert!(err.contains("must name a file"), "{}", err);
+    }
+}
@@ -1,19 +1,27 @@
 use std::{
     env,
     fmt::Display,
-    fs::{File, Permissions},
-    io::{BufRead, BufReader, BufWriter, Write},
+    fs::{self, File, Permissions},
+    io::{BufReader, Read, Write},
     os::unix::fs::PermissionsExt,
-    path::Path,
+    path::{Path, PathBuf},
     process::{Command, ExitStatus},
-    sync, thread,
+    sync::{
+        self,
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
     time::Duration,
 };
 
 use build_config::BuildConfig;
 use colored::Colorize;
 use comments::Comments;
+use field::{FieldValidator, FnValidator};
+use include::IncludedLines;
 use logger::TaskLogger;
+use regex::Regex;
 use saphyr::{LoadableYamlNode, MarkedYamlOwned};
 use tempfile::NamedTempFile;
 use validator::ValidationContext;
@@ -21,12 +29,20 @@ use validator::ValidationContext;
 pub mod build_config;
 pub mod comments;
 pub mod description;
+pub mod envvars;
 pub mod error;
+pub mod field;
+pub mod include;
 pub mod logger;
+pub mod policy;
 pub mod semaphore;
+pub mod toml;
 pub mod validator;
 pub mod xexec;
 
+/// Linter version, for keying cached lint results
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 pub const VALID_PKG_TYPES: [&str; 10] = [
     "appbundle",
     "appimage",
@@ -42,6 +58,7 @@ pub const VALID_PKG_TYPES: [&str; 10] = [
 pub const VALID_CATEGORIES: &str = include_str!("categories");
 pub const VALID_ARCH: [&str; 4] = ["aarch64", "loongarch64", "riscv64", "x86_64"];
 pub const VALID_OS: [&str; 6] = ["freebsd", "illumos", "linux", "netbsd", "openbsd", "redox"];
+pub const VALID_UPDATE_SOURCES: [&str; 3] = ["github_release", "repology", "script"];
 
 #[derive(Debug, Clone)]
 pub struct BuildAsset {
@@ -49,28 +66,173 @@ pub struct BuildAsset {
     pub out: String,
 }
 
+/// Double-quoted YAML scalar for `value`, escaping `\\` and `"`
+pub(crate) fn yaml_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Source of an `icon`/`desktop` file: a URL, a file, a directory, or any mix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Resource {
+    pub url: Option<String>,
+    pub file: Option<String>,
+    pub dir: Option<String>,
+}
+
+impl Resource {
+    pub fn write_yaml<W: Write>(
+        &self,
+        writer: &mut W,
+        field: &str,
+        indent: usize,
+    ) -> std::io::Result<()> {
+        let indent_str = " ".repeat(indent);
+        writeln!(writer, "{}{}:", indent_str, field)?;
+        for (key, value) in [("url", &self.url), ("file", &self.file), ("dir", &self.dir)] {
+            if let Some(value) = value {
+                writeln!(writer, "{}  {}: {}", indent_str, key, yaml_quote(value))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where `check-updates` looks up new upstream versions (`x_update`)
+#[derive(Debug, Clone, Default)]
+pub struct XUpdate {
+    /// One of [`VALID_UPDATE_SOURCES`]
+    pub source: String,
+    /// `owner/name`, for `github_release`
+    pub repo: Option<String>,
+    /// Repology project name, for `repology`
+    pub project: Option<String>,
+}
+
+impl XUpdate {
+    pub fn write_yaml<W: Write>(&self, writer: &mut W, indent: usize) -> std::io::Result<()> {
+        let indent_str = " ".repeat(indent);
+        writeln!(writer, "{}x_update:", indent_str)?;
+        writeln!(writer, "{}  source: \"{}\"", indent_str, self.source)?;
+        for (key, value) in [("repo", &self.repo), ("project", &self.project)] {
+            if let Some(value) = value {
+                writeln!(writer, "{}  {}: \"{}\"", indent_str, key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options for a single [`Linter::lint`] run
+#[derive(Debug, Clone)]
+pub struct LintOptions {
+    /// Replace the original file with the validated output
+    pub inplace: bool,
+    /// Run shellcheck on the `x_exec` scripts
+    pub shellcheck: bool,
+    /// Run `x_exec.pkgver` and write the result to `<file>.pkgver`
+    pub pkgver: bool,
+    /// Require `icon`/`desktop` `file` and `dir` resources to exist under this directory
+    pub resource_base: Option<PathBuf>,
+    /// Reject `x_exec.run`/`x_exec.pkgver` lines matching any of these
+    pub deny_patterns: Vec<Regex>,
+    /// Fail on unknown fields and packages without provides instead of warning
+    pub strict: bool,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            inplace: false,
+            shellcheck: true,
+            pkgver: false,
+            resource_base: None,
+            deny_patterns: Vec::new(),
+            strict: false,
+        }
+    }
+}
+
+impl LintOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_inplace(mut self, inplace: bool) -> Self {
+        self.inplace = inplace;
+        self
+    }
+
+    pub fn with_shellcheck(mut self, shellcheck: bool) -> Self {
+        self.shellcheck = shellcheck;
+        self
+    }
+
+    pub fn with_pkgver(mut self, pkgver: bool) -> Self {
+        self.pkgver = pkgver;
+        self
+    }
+
+    pub fn with_resource_base(mut self, base: Option<PathBuf>) -> Self {
+        self.resource_base = base;
+        self
+    }
+
+    pub fn with_deny_patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.deny_patterns = patterns;
+        self
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
+
 pub struct Linter {
     logger: TaskLogger,
     timeout: Duration,
+    warnings: AtomicUsize,
+    errors: AtomicUsize,
+    field_validators: Vec<Arc<dyn FieldValidator>>,
 }
 
 impl Linter {
     pub fn new(logger: TaskLogger, timeout: Duration) -> Self {
-        Linter { logger, timeout }
+        Linter {
+            logger,
+            timeout,
+            warnings: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+            field_validators: Vec::new(),
+        }
     }
 
-    pub fn lint(
-        &self,
-        file_path: &str,
-        inplace: bool,
-        disable_shellcheck: bool,
-        pkgver: bool,
-    ) -> Option<BuildConfig> {
+    /// Accept `validator`'s field as a first-class recipe field
+    pub fn with_field_validator(mut self, validator: impl FieldValidator + 'static) -> Self {
+        self.field_validators.push(Arc::new(validator));
+        self
+    }
+
+    /// Accept `field`, checking its value with `check`
+    pub fn with_field_fn<F>(self, field: impl Into<String>, check: F) -> Self
+    where
+        F: Fn(&MarkedYamlOwned) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.with_field_validator(FnValidator::new(field, check))
+    }
+
+    pub fn lint(&self, file_path: &str, options: &LintOptions) -> Option<BuildConfig> {
+        self.reset_counts();
+        let config = self.lint_file(file_path, options);
+        self.count_failure(config)
+    }
+
+    fn lint_file(&self, file_path: &str, options: &LintOptions) -> Option<BuildConfig> {
         let logger = &self.logger;
-        let yaml_str = match self.read_yaml(file_path) {
+        let content = match self.read_yaml(file_path) {
             Ok(y) => y,
             Err(err) => {
-                eprintln!("{}", err);
+                logger.custom_error(err.to_string());
                 return None;
             }
         };
@@ -83,81 +245,193 @@ impl Linter {
             &current_dir.join(path)
         };
         logger.info(format!("Linting {} ({})\n", file_path, real_path.display()));
-        match self.validate_yaml(&yaml_str) {
-            Ok(config) => {
-                if disable_shellcheck {
-                    logger.info("Skipping shellcheck");
-                } else {
-                    logger.info("Performing shellcheck");
-                    if !self.is_shellcheck_success(&config) {
-                        return None;
-                    }
-                    logger.success("Shellcheck passed");
+
+        let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+        let content = if is_toml {
+            match toml::toml_to_yaml(&content) {
+                Ok(yaml) => yaml,
+                Err(err) => {
+                    logger.error(format!("Invalid TOML: {}", err));
+                    return None;
                 }
-                if let Some(pkgver_path) = pkgver.then(|| format!("{}.pkgver", file_path)) {
-                    if !self.generate_pkgver(&config, &pkgver_path) {
-                        return None;
-                    }
-                };
+            }
+        } else {
+            content
+        };
+        let (content, included) = match include::resolve(&content, real_path) {
+            Ok(Some((merged, included))) => {
+                logger.info(format!("Including {}", included.path.display()));
+                (merged, Some(included))
+            }
+            Ok(None) => (content, None),
+            Err(err) => {
+                logger.error(err);
+                return None;
+            }
+        };
+        let has_include = included.is_some();
+        let (config, validated) = self.lint_content(&content, options, included)?;
+
+        if let Some(pkgver_path) = options.pkgver.then(|| format!("{}.pkgver", file_path)) {
+            if self.generate_pkgver(&config, &pkgver_path).is_err() {
+                return None;
+            }
+        };
 
-                let mut comments = Comments::new();
-                comments.parse_comments(file_path).unwrap();
+        // The validated output is always YAML, so never overwrite a TOML source.
+        if is_toml && options.inplace {
+            logger.warn("Not rewriting a TOML recipe in place; writing .validated instead");
+        }
+        // Nor one using `_include`, whose output has the fragment merged in.
+        if has_include && options.inplace {
+            logger
+                .warn("Not rewriting a recipe with _include in place; writing .validated instead");
+        }
+        let output_path = if options.inplace && !is_toml && !has_include {
+            file_path.to_string()
+        } else {
+            format!("{}.validated", file_path)
+        };
+        fs::write(&output_path, validated).unwrap();
+        logger.info("SBUILD validation successful.");
+        logger.info(&format!(
+            "Validated YAML has been written to {}",
+            output_path
+        ));
+        Some(config)
+    }
 
-                let output_path = inplace
-                    .then_some(file_path.to_string())
-                    .unwrap_or_else(|| format!("{}.validated", file_path));
-                let file = File::create(&output_path).unwrap();
-                let mut writer = BufWriter::new(file);
+    /// Validate SBUILD content and shellcheck its scripts, returning the
+    /// config along with the canonical recipe text.
+    ///
+    /// `options.pkgver` and `options.inplace` only apply to [`Linter::lint`].
+    pub fn lint_str(&self, content: &str, options: &LintOptions) -> Option<(BuildConfig, String)> {
+        self.reset_counts();
+        let result = self.lint_content(content, options, None);
+        self.count_failure(result)
+    }
 
-                config.write_yaml(&mut writer, 0, comments).unwrap();
-                logger.info("SBUILD validation successful.");
-                logger.info(&format!(
-                    "Validated YAML has been written to {}",
-                    output_path
-                ));
-                return Some(config);
+    fn lint_content(
+        &self,
+        content: &str,
+        options: &LintOptions,
+        included: Option<IncludedLines>,
+    ) -> Option<(BuildConfig, String)> {
+        let logger = &self.logger;
+        let config = self.validate_str(content, options, included)?;
+
+        if !options.shellcheck {
+            logger.info("Skipping shellcheck");
+        } else {
+            logger.info("Performing shellcheck");
+            if !self.is_shellcheck_success(&config) {
+                return None;
             }
+            logger.success("Shellcheck passed");
+        }
+
+        let validated = Self::render(&config, content);
+        Some((config, validated))
+    }
+
+    /// Canonicalize SBUILD content without running shellcheck or pkgver.
+    ///
+    /// Warnings don't prevent formatting; returns `None` only on validation errors.
+    pub fn format_str(&self, content: &str) -> Option<String> {
+        let config = self.validate_str(content, &LintOptions::new(), None)?;
+        Some(Self::render(&config, content))
+    }
+
+    fn validate_str(
+        &self,
+        content: &str,
+        options: &LintOptions,
+        included: Option<IncludedLines>,
+    ) -> Option<BuildConfig> {
+        let logger = &self.logger;
+
+        if content.is_empty() {
+            logger.error("SBUILD content is empty.");
+            return None;
+        }
+        let (first_line, yaml_str) = content.split_once('\n').unwrap_or((content, ""));
+        if !first_line.trim_start().starts_with("#!/SBUILD") {
+            logger.warn("File doesn't start with '#!/SBUILD'");
+        }
+
+        match self.validate_yaml(yaml_str, options, included) {
+            Ok(config) => Some(config),
             Err(_) => {
                 logger.error("SBUILD validation failed.");
+                None
             }
-        };
-        None
+        }
+    }
+
+    /// Write `config` back out as YAML, keeping the comments from `content`
+    fn render(config: &BuildConfig, content: &str) -> String {
+        let mut comments = Comments::new();
+        comments.parse_comments_str(content);
+
+        let mut out = Vec::new();
+        config.write_yaml(&mut out, 0, comments).unwrap();
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// Warnings reported while validating the last linted file
+    pub fn warning_count(&self) -> usize {
+        self.warnings.load(Ordering::SeqCst)
+    }
+
+    /// Errors reported for the last linted file. A failure outside field
+    /// validation (unreadable file, invalid YAML, shellcheck, pkgver) counts as one.
+    pub fn error_count(&self) -> usize {
+        self.errors.load(Ordering::SeqCst)
+    }
+
+    fn reset_counts(&self) {
+        self.warnings.store(0, Ordering::SeqCst);
+        self.errors.store(0, Ordering::SeqCst);
+    }
+
+    fn count_failure<T>(&self, result: Option<T>) -> Option<T> {
+        if result.is_none() && self.error_count() == 0 {
+            self.errors.store(1, Ordering::SeqCst);
+        }
+        result
     }
 
-    fn validate_yaml(&self, yaml_str: &str) -> Result<BuildConfig, String> {
+    fn validate_yaml(
+        &self,
+        yaml_str: &str,
+        options: &LintOptions,
+        included: Option<IncludedLines>,
+    ) -> Result<BuildConfig, String> {
         let docs = MarkedYamlOwned::load_from_str(yaml_str)
             .map_err(|e: saphyr::ScanError| e.to_string())?;
         let doc = docs.into_iter().next().ok_or("Empty YAML")?;
-        let mut ctx = ValidationContext::new(yaml_str, self.logger.clone());
-        ctx.validate(&doc).ok_or_else(|| "Validation failed".into())
+        let mut ctx = ValidationContext::new(yaml_str, self.logger.clone())
+            .with_resource_base(options.resource_base.clone())
+            .with_deny_patterns(options.deny_patterns.clone())
+            .with_strict(options.strict)
+            .with_included(included)
+            .with_field_validators(self.field_validators.clone());
+        let result = ctx.validate(&doc).ok_or_else(|| "Validation failed".into());
+        self.warnings.store(ctx.warning_count(), Ordering::SeqCst);
+        self.errors.store(ctx.error_count(), Ordering::SeqCst);
+        result
     }
 
     fn read_yaml(&self, file_path: &str) -> Result<String, FileError> {
-        let logger = &self.logger;
         let Ok(file) = File::open(file_path) else {
             return Err(FileError::NotFound(file_path.into()));
         };
-        let reader = BufReader::new(file);
-
-        let mut yaml_content = String::new();
-        let mut lines = reader.lines();
-
-        if let Some(line) = lines.next() {
-            let line = line.map_err(|_| FileError::InvalidFile(file_path.into()))?;
-            if !line.trim_start().starts_with("#!/SBUILD") {
-                logger.warn("File doesn't start with '#!/SBUILD'");
-            }
-        } else {
-            return Err(FileError::InvalidFile(file_path.into()));
-        }
 
-        for line in lines {
-            let line = line.map_err(|_| FileError::InvalidFile(file_path.into()))?;
-            yaml_content.push_str(&line);
-            yaml_content.push('\n');
+        let mut content = String::new();
+        match BufReader::new(file).read_to_string(&mut content) {
+            Ok(n) if n > 0 => Ok(content),
+            _ => Err(FileError::InvalidFile(file_path.into())),
         }
-
-        Ok(yaml_content)
     }
 
     fn run_shellcheck(&self, script: &str, severity: &str) -> std::io::Result<ExitStatus> {
@@ -185,135 +459,141 @@ impl Linter {
         Ok(())
     }
 
-    pub fn generate_pkgver(&self, config: &BuildConfig, pkgver_path: &str) -> bool {
+    /// Work out the recipe's version from `pkgver` or by running
+    /// `x_exec.pkgver`, and write it (plus any remote_pkgver, on a second
+    /// line) to `pkgver_path`. Returns `Ok(None)` when the recipe sets
+    /// neither.
+    pub fn generate_pkgver(
+        &self,
+        config: &BuildConfig,
+        pkgver_path: &str,
+    ) -> Result<Option<Pkgver>, PkgverError> {
         let logger = &self.logger;
-        let x_exec = &config.x_exec;
-        let mut success = false;
 
-        match config.pkgver {
-            Some(ref pkgver) => {
+        let (version, source) = match (&config.pkgver, &config.x_exec.pkgver) {
+            (Some(pkgver), _) => {
                 logger.info("Using hard-coded pkgver");
-                let file = File::create(pkgver_path).unwrap();
-                let mut writer = BufWriter::new(file);
-                let _ = writer.write_all(pkgver.as_bytes());
-
-                if let Some(ref remote_pkgver) = config.remote_pkgver {
-                    let _ = writer.write_all(b"\n");
-                    let _ = writer.write_all(remote_pkgver.as_bytes());
-                    logger.success(&format!(
-                        "Version ({}) with remote_pkgver ({}) written to {}",
-                        pkgver,
-                        remote_pkgver,
-                        pkgver_path.bright_cyan()
-                    ));
-                } else {
-                    logger.success(&format!(
-                        "Version ({}) from pkgver written to {}",
-                        pkgver,
-                        pkgver_path.bright_cyan()
-                    ));
-                }
-                success = true;
+                let version = Pkgver {
+                    pkgver: pkgver.clone(),
+                    remote_pkgver: config.remote_pkgver.clone(),
+                };
+                (version, "from pkgver")
             }
-            None => {
-                if let Some(ref pkgver) = x_exec.pkgver {
-                    let script = format!("#!/usr/bin/env {}\n{}", x_exec.shell, pkgver);
-                    let tmp = temp_script_file(&script);
-                    let tmp_path = tmp.into_temp_path();
-
-                    let (tx, rx) = sync::mpsc::channel();
-                    thread::spawn({
-                        let tmp_path = tmp_path.to_path_buf();
-                        move || {
-                            let cmd = Command::new(&tmp_path).output();
-                            let _ = tx.send(cmd);
-                        }
-                    });
-
-                    match rx.recv_timeout(self.timeout) {
-                        Ok(cmd_result) => {
-                            match cmd_result {
-                                Ok(cmd) => {
-                                    if cmd.status.success() {
-                                        if !cmd.stderr.is_empty() {
-                                            logger.error("x.exec.pkgver script produced error.");
-                                            logger.error(String::from_utf8_lossy(&cmd.stderr));
-                                        } else {
-                                            let out = cmd.stdout;
-                                            let output_str = String::from_utf8_lossy(&out);
-                                            let output_str = output_str.trim();
-                                            if output_str.is_empty() {
-                                                logger.warn("x_exec.pkgver produced empty result. Skipping...");
-                                            } else {
-                                                let lines: Vec<&str> = output_str.lines().collect();
-                                                if lines.len() > 2 {
-                                                    logger.error(
-                                                        "x_exec.pkgver should produce at most two outputs (pkgver and optionally remote_pkgver)",
-                                                    );
-                                                    lines.iter().for_each(|line| {
-                                                        logger.info(format!("-> {}", line.trim()));
-                                                    });
-                                                } else {
-                                                    let pkgver = lines[0].trim();
-                                                    let file = File::create(pkgver_path).unwrap();
-                                                    let mut writer = BufWriter::new(file);
-                                                    let _ = writer.write_all(pkgver.as_bytes());
-
-                                                    if lines.len() == 2 {
-                                                        let remote_pkgver = lines[1].trim();
-                                                        let _ = writer.write_all(b"\n");
-                                                        let _ = writer
-                                                            .write_all(remote_pkgver.as_bytes());
-                                                        logger.success(format!("Fetched version ({}) with remote_pkgver ({}) using x_exec.pkgver written to {}",
-                                                            pkgver, remote_pkgver, pkgver_path.bright_cyan()));
-                                                    } else if let Some(ref remote_pkgver) =
-                                                        config.remote_pkgver
-                                                    {
-                                                        let _ = writer.write_all(b"\n");
-                                                        let _ = writer
-                                                            .write_all(remote_pkgver.as_bytes());
-                                                        logger.success(format!("Fetched version ({}) with remote_pkgver ({}) from config written to {}",
-                                                            pkgver, remote_pkgver, pkgver_path.bright_cyan()));
-                                                    } else {
-                                                        logger.success(format!("Fetched version ({}) using x_exec.pkgver written to {}", pkgver, pkgver_path.bright_cyan()));
-                                                    }
-                                                    success = true;
-                                                }
-                                            }
-                                        }
-                                    } else {
-                                        logger.error(format!("{} -> Failed to read output from pkgver script. Please make sure the script is valid.", "x_exec.pkgver".bold()));
-                                        if !cmd.stderr.is_empty() {
-                                            logger.error(String::from_utf8_lossy(&cmd.stderr));
-                                        }
-                                    }
-                                }
-                                Err(err) => {
-                                    logger.error(format!(
-                                        "{} -> pkgver script failed to execute. {}",
-                                        "x_exec.pkgver".bold(),
-                                        err
-                                    ));
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            logger.error(format!(
-                                "{} -> pkgver script timed out after {:#?}",
-                                "x_exec.pkgver".bold(),
-                                self.timeout
-                            ));
-                        }
-                    }
-
-                    let _ = tmp_path.close();
+            (None, Some(script)) => {
+                let mut version = self.run_pkgver_script(config, script)?;
+                let source = if version.remote_pkgver.is_none() && config.remote_pkgver.is_some() {
+                    version.remote_pkgver = config.remote_pkgver.clone();
+                    "using x_exec.pkgver, remote_pkgver from config,"
                 } else {
-                    // we don't care if the pkgver is not set
-                    success = true;
-                }
+                    "using x_exec.pkgver"
+                };
+                (version, source)
+            }
+            // we don't care if the pkgver is not set
+            (None, None) => return Ok(None),
+        };
+
+        let content = match version.remote_pkgver {
+            Some(ref remote_pkgver) => format!("{}\n{}", version.pkgver, remote_pkgver),
+            None => version.pkgver.clone(),
+        };
+        fs::write(pkgver_path, content).map_err(|e| PkgverError::Io(e.to_string()))?;
+
+        match version.remote_pkgver {
+            Some(ref remote_pkgver) => logger.success(format!(
+                "Version ({}) with remote_pkgver ({}) {} written to {}",
+                version.pkgver,
+                remote_pkgver,
+                source,
+                pkgver_path.bright_cyan()
+            )),
+            None => logger.success(format!(
+                "Version ({}) {} written to {}",
+                version.pkgver,
+                source,
+                pkgver_path.bright_cyan()
+            )),
+        }
+        Ok(Some(version))
+    }
+
+    /// Run an `x_exec.pkgver` script, logging why it failed if it did
+    fn run_pkgver_script(&self, config: &BuildConfig, pkgver: &str) -> Result<Pkgver, PkgverError> {
+        let logger = &self.logger;
+        let script = format!("#!/usr/bin/env {}\n{}", config.x_exec.shell, pkgver);
+        let tmp = temp_script_file(&script);
+        let tmp_path = tmp.into_temp_path();
+
+        let (tx, rx) = sync::mpsc::channel();
+        thread::spawn({
+            let tmp_path = tmp_path.to_path_buf();
+            move || {
+                let cmd = Command::new(&tmp_path).output();
+                let _ = tx.send(cmd);
+            }
+        });
+        let result = rx.recv_timeout(self.timeout);
+        let _ = tmp_path.close();
+
+        let cmd = match result {
+            Ok(Ok(cmd)) => cmd,
+            Ok(Err(err)) => {
+                logger.error(format!(
+                    "{} -> pkgver script failed to execute. {}",
+                    "x_exec.pkgver".bold(),
+                    err
+                ));
+                return Err(PkgverError::Exec(err.to_string()));
+            }
+            Err(_) => {
+                logger.error(format!(
+                    "{} -> pkgver script timed out after {:#?}",
+                    "x_exec.pkgver".bold(),
+                    self.timeout
+                ));
+                return Err(PkgverError::Timeout(self.timeout));
+            }
+        };
+
+        let stderr = String::from_utf8_lossy(&cmd.stderr).to_string();
+        if !cmd.status.success() {
+            logger.error(format!("{} -> Failed to read output from pkgver script. Please make sure the script is valid.", "x_exec.pkgver".bold()));
+            if !stderr.is_empty() {
+                logger.error(&stderr);
+            }
+            return Err(PkgverError::Failed(stderr));
+        }
+        if !stderr.is_empty() {
+            logger.error("x.exec.pkgver script produced error.");
+            logger.error(&stderr);
+            return Err(PkgverError::Stderr(stderr));
+        }
+
+        let output_str = String::from_utf8_lossy(&cmd.stdout);
+        let lines: Vec<&str> = output_str.trim().lines().collect();
+        match lines[..] {
+            [] => {
+                logger.warn("x_exec.pkgver produced empty result. Skipping...");
+                Err(PkgverError::Empty)
+            }
+            [pkgver] => Ok(Pkgver {
+                pkgver: pkgver.trim().to_string(),
+                remote_pkgver: None,
+            }),
+            [pkgver, remote_pkgver] => Ok(Pkgver {
+                pkgver: pkgver.trim().to_string(),
+                remote_pkgver: Some(remote_pkgver.trim().to_string()),
+            }),
+            _ => {
+                logger.error(
+                    "x_exec.pkgver should produce at most two outputs (pkgver and optionally remote_pkgver)",
+                );
+                lines.iter().for_each(|line| {
+                    logger.info(format!("-> {}", line.trim()));
+                });
+                Err(PkgverError::TooManyLines(lines.len()))
             }
         }
-        success
     }
 
     fn is_shellcheck_success(&self, config: &BuildConfig) -> bool {
@@ -347,6 +627,48 @@ impl Linter {
     }
 }
 
+/// Version worked out by [`Linter::generate_pkgver`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pkgver {
+    pub pkgver: String,
+    pub remote_pkgver: Option<String>,
+}
+
+/// Why a recipe's version couldn't be worked out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PkgverError {
+    /// The `x_exec.pkgver` script couldn't be started
+    Exec(String),
+    Timeout(Duration),
+    /// The script exited unsuccessfully, with this stderr
+    Failed(String),
+    /// The script succeeded but wrote this to stderr
+    Stderr(String),
+    Empty,
+    /// The script printed more than pkgver and remote_pkgver
+    TooManyLines(usize),
+    /// The version couldn't be written out
+    Io(String),
+}
+
+impl Display for PkgverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PkgverError::Exec(e) => write!(f, "pkgver script failed to execute: {}", e),
+            PkgverError::Timeout(t) => write!(f, "pkgver script timed out after {:?}", t),
+            PkgverError::Failed(_) => write!(f, "pkgver script failed"),
+            PkgverError::Stderr(_) => write!(f, "pkgver script wrote to stderr"),
+            PkgverError::Empty => write!(f, "pkgver script produced no output"),
+            PkgverError::TooManyLines(n) => {
+                write!(f, "pkgver script printed {} lines, expected at most 2", n)
+            }
+            PkgverError::Io(e) => write!(f, "failed to write pkgver: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PkgverError {}
+
 enum FileError {
     InvalidFile(String),
     NotFound(String),
@@ -363,10 +685,31 @@ impl Display for FileError {
     }
 }
 
-fn get_pkg_id(src: &str) -> String {
-    let (_, url) = src.split_once("://").unwrap();
-    let (url, _) = url.split_once('?').unwrap_or((url, ""));
-    url.replace('/', ".").trim_matches('.').to_string()
+/// `pkg_id` derived from a source URL: its host and path joined with dots,
+/// without scheme, user or query. SSH-style git remotes (`git@host:path`)
+/// are accepted too. `None` if `src` is neither form.
+pub fn get_pkg_id(src: &str) -> Option<String> {
+    let url = match src.split_once("://") {
+        Some((_, rest)) => rest.to_string(),
+        None => {
+            let (user_host, path) = src.split_once(':')?;
+            let (_, host) = user_host.split_once('@')?;
+            if host.is_empty() || host.contains('/') {
+                return None;
+            }
+            format!("{}/{}", host, path)
+        }
+    };
+    let (url, _) = url.split_once(['?', '#']).unwrap_or((&url, ""));
+    let url = match url.split_once('/') {
+        Some((authority, path)) => {
+            let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+            format!("{}/{}", host, path)
+        }
+        None => url.rsplit_once('@').map_or(url, |(_, h)| h).to_string(),
+    };
+    let id = url.replace('/', ".").trim_matches('.').to_string();
+    (!id.is_empty()).then_some(id)
 }
 
 fn temp_script_file(script: &str) -> NamedTempFile {
@@ -385,3 +728,98 @@ fn temp_script_file(script: &str) -> NamedTempFile {
 
     named_temp_file
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::logger::LogManager;
+
+    const RECIPE: &str = r#"#!/SBUILD
+_disabled: false
+pkg: bat
+description: A cat clone
+src_url:
+  - https://github.com/sharkdp/bat
+_ticket:
+  id: OPS-42
+x_exec:
+  shell: bash
+  run: echo hi
+"#;
+
+    fn linter() -> Linter {
+        let (sender, _receiver) = mpsc::channel();
+        let logger = LogManager::new(sender).create_logger(None::<PathBuf>);
+        Linter::new(logger, Duration::from_secs(5))
+    }
+
+    fn lint(linter: &Linter) -> Option<(BuildConfig, String)> {
+        linter.lint_str(RECIPE, &LintOptions::new().with_shellcheck(false))
+    }
+
+    #[test]
+    fn test_custom_field_accepted() {
+        let linter = linter().with_field_fn("_ticket", |value| {
+            value
+                .data
+                .as_mapping_get("id")
+                .map(|_| ())
+                .ok_or_else(|| "_ticket needs an id".to_string())
+        });
+        let (config, validated) = lint(&linter).unwrap();
+
+        assert_eq!(linter.error_count(), 0);
+        assert_eq!(linter.warning_count(), 0);
+        assert_eq!(
+            config.custom_fields,
+            vec![("_ticket".to_string(), "_ticket:\n  id: OPS-42".to_string())]
+        );
+        assert!(validated.contains("_ticket:\n  id: OPS-42\n"));
+    }
+
+    #[test]
+    fn test_custom_field_rejected() {
+        let linter = linter().with_field_fn("_ticket", |_| Err("unknown ticket".to_string()));
+
+        assert!(lint(&linter).is_none());
+        assert_eq!(linter.error_count(), 1);
+    }
+
+    #[test]
+    fn test_unregistered_custom_field_warns() {
+        let linter = linter();
+        let (config, validated) = lint(&linter).unwrap();
+
+        assert_eq!(linter.warning_count(), 1);
+        assert!(config.custom_fields.is_empty());
+        assert!(!validated.contains("_ticket"));
+    }
+
+    #[test]
+    fn test_get_pkg_id() {
+        let id = |src: &str| get_pkg_id(src);
+        assert_eq!(
+            id("https://github.com/sharkdp/bat").as_deref(),
+            Some("github.com.sharkdp.bat")
+        );
+        assert_eq!(
+            id("https://example.com/app/?ref=x").as_deref(),
+            Some("example.com.app")
+        );
+        assert_eq!(
+            id("git@github.com:sharkdp/bat.git").as_deref(),
+            Some("github.com.sharkdp.bat.git")
+        );
+        assert_eq!(
+            id("ssh://git@github.com/sharkdp/bat").as_deref(),
+            Some("github.com.sharkdp.bat")
+        );
+
+        assert_eq!(id("binaries/bat/static.yaml"), None);
+        assert_eq!(id("github.com/sharkdp/bat"), None);
+        assert_eq!(id("https://"), None);
+        assert_eq!(id("@:"), None);
+    }
+}
@@ -15,6 +15,14 @@ pub enum LogMessage {
     Done,
 }
 
+/// Prefix every line of `msg` with `[prefix]`
+pub fn prefix_lines(prefix: &str, msg: &str) -> String {
+    msg.lines()
+        .map(|line| format!("[{}] {}", prefix, line).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[derive(Clone)]
 pub struct LogManager {
     sender: Sender<LogMessage>,
@@ -48,6 +56,7 @@ impl LogManager {
             sender: self.sender.clone(),
             file,
             start_time: Instant::now(),
+            prefix: None,
         }
     }
 }
@@ -57,6 +66,7 @@ pub struct TaskLogger {
     sender: Sender<LogMessage>,
     file: Option<Arc<Mutex<LogFile>>>,
     start_time: Instant,
+    prefix: Option<Arc<str>>,
 }
 
 struct LogFile {
@@ -65,6 +75,20 @@ struct LogFile {
 }
 
 impl TaskLogger {
+    /// Prefix every console line with `[prefix]`, so output from builds
+    /// running side by side can be told apart. The log file is unaffected.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into().into());
+        self
+    }
+
+    fn console(&self, msg: String) -> String {
+        match &self.prefix {
+            Some(prefix) => prefix_lines(prefix, &msg),
+            None => msg,
+        }
+    }
+
     pub fn write_to_file(&self, msg: impl Into<String>) {
         let msg = msg.into();
         if let Some(file) = &self.file {
@@ -123,30 +147,65 @@ impl TaskLogger {
     pub fn info(&self, msg: impl Into<String>) {
         let msg = msg.into();
         self.write_to_file(&msg);
-        let _ = self.sender.send(LogMessage::Info(msg.to_string()));
+        let _ = self.sender.send(LogMessage::Info(self.console(msg)));
     }
 
     pub fn warn(&self, msg: impl Into<String>) {
         let msg = msg.into();
         self.write_to_file(&msg);
-        let _ = self.sender.send(LogMessage::Warn(msg.to_string()));
+        let _ = self.sender.send(LogMessage::Warn(self.console(msg)));
     }
 
     pub fn error(&self, msg: impl Into<String>) {
         let msg = msg.into();
         self.write_to_file(&msg);
-        let _ = self.sender.send(LogMessage::Error(msg.to_string()));
+        let _ = self.sender.send(LogMessage::Error(self.console(msg)));
     }
 
     pub fn success(&self, msg: impl Into<String>) {
         let msg = msg.into();
         self.write_to_file(&msg);
-        let _ = self.sender.send(LogMessage::Success(msg.to_string()));
+        let _ = self.sender.send(LogMessage::Success(self.console(msg)));
     }
 
     pub fn custom_error(&self, msg: impl Into<String>) {
         let msg = msg.into();
         self.write_to_file(&msg);
-        let _ = self.sender.send(LogMessage::CustomError(msg.to_string()));
+        let _ = self.sender.send(LogMessage::CustomError(self.console(msg)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_prefix_lines() {
+        assert_eq!(prefix_lines("htop/static", "one"), "[htop/static] one");
+        assert_eq!(
+            prefix_lines("htop/static", "one\n\ntwo"),
+            "[htop/static] one\n[htop/static]\n[htop/static] two"
+        );
+    }
+
+    #[test]
+    fn test_prefix_only_applies_to_console() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("build.log");
+        let (sender, receiver) = mpsc::channel();
+        let logger = LogManager::new(sender)
+            .create_logger(Some(&log_path))
+            .with_prefix("htop/static");
+
+        logger.info("Building");
+        match receiver.recv().unwrap() {
+            LogMessage::Info(msg) => assert_eq!(msg, "[htop/static] Building"),
+            _ => panic!("expected an info message"),
+        }
+        let file = fs::read_to_string(&log_path).unwrap();
+        assert!(file.trim_end().ends_with("\u{279c} Building"), "{}", file);
+        assert!(!file.contains("htop/static"));
     }
 }
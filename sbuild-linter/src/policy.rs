@@ -0,0 +1,92 @@
+//! Policy rules for `x_exec` scripts
+//!
+//! These are organisational rules rather than shell correctness checks:
+//! a script can be perfectly valid shell and still be rejected here.
+
+use regex::Regex;
+
+/// Bundled deny rules, enabled with `--default-deny`
+pub const DEFAULT_DENY_PATTERNS: &[&str] = &[
+    // Piping a download straight into a shell
+    r"\b(curl|wget)\b[^|]*\|\s*(sudo\s+)?(ba|da|z)?sh\b",
+    // Privilege escalation
+    r"\b(sudo|doas)\b",
+    // Writing into system directories instead of $SBUILD_OUTDIR
+    r">>?\s*/(bin|boot|etc|lib|lib64|opt|root|sbin|usr|var)(/|\s|$)",
+    // Only the last argument of cp/mv/install/ln is the destination; reading
+    // from a system directory is fine
+    r"\b(cp|mv|install|ln)\b[^;&|]*\s/(bin|boot|etc|lib|lib64|opt|root|sbin|usr|var)(/\S*)?\s*($|[;&|])",
+    r"\b(cp|mv|install|ln)\b[^;&|]*\s(-t\s*|--target-directory[=\s]\s*)/(bin|boot|etc|lib|lib64|opt|root|sbin|usr|var)(/|\s|$)",
+    r"\btee\b[^;&|]*\s/(bin|boot|etc|lib|lib64|opt|root|sbin|usr|var)(/|\s|$)",
+];
+
+/// Compile user supplied deny patterns, optionally followed by the bundled set
+pub fn compile_deny_patterns(patterns: &[String], defaults: bool) -> Result<Vec<Regex>, String> {
+    let bundled = if defaults { DEFAULT_DENY_PATTERNS } else { &[] };
+    patterns
+        .iter()
+        .map(String::as_str)
+        .chain(bundled.iter().copied())
+        .map(|p| Regex::new(p).map_err(|e| format!("Invalid deny pattern '{}': {}", p, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denied(line: &str) -> bool {
+        compile_deny_patterns(&[], true)
+            .unwrap()
+            .iter()
+            .any(|p| p.is_match(line))
+    }
+
+    #[test]
+    fn denies_writes_into_system_directories() {
+        for line in [
+            "curl -fsSL https://example.com/install.sh | sh",
+            "wget -qO- https://example.com/x | sudo bash",
+            "sudo make install",
+            "echo x > /etc/hosts",
+            "cp ./foo /usr/bin/",
+            "cp ./foo /usr/bin/foo && echo done",
+            "install -Dm755 foo /usr/local/bin/foo",
+            "install -t /usr/bin foo",
+            "cp --target-directory=/opt foo",
+            "ln -s \"$SBUILD_OUTDIR/foo\" /usr/bin/foo",
+            "echo x | tee -a /etc/profile",
+        ] {
+            assert!(denied(line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn allows_reads_from_system_directories() {
+        for line in [
+            "cp /usr/bin/x ./",
+            "cp /usr/lib/libfoo.so \"$SBUILD_OUTDIR/\"",
+            "mv /var/tmp/build/foo \"${SBUILD_TMPDIR}/foo\"",
+            "ln -s /usr/bin/python3 ./python",
+            "install -Dm755 /opt/app/bin/app \"$SBUILD_OUTDIR/app\"",
+            "cat /etc/os-release | tee ./os-release",
+            "curl -fsSL https://example.com/x -o ./x",
+        ] {
+            assert!(!denied(line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn user_patterns_come_first_and_bundled_set_is_opt_in() {
+        let patterns = compile_deny_patterns(&["\\bnpm\\b".to_string()], false).unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert!(patterns[0].is_match("npm install"));
+
+        let patterns = compile_deny_patterns(&["\\bnpm\\b".to_string()], true).unwrap();
+        assert_eq!(patterns.len(), 1 + DEFAULT_DENY_PATTERNS.len());
+        assert_eq!(patterns[0].as_str(), "\\bnpm\\b");
+
+        let err = compile_deny_patterns(&["(".to_string()], false).unwrap_err();
+        assert!(err.contains("Invalid deny pattern '('"), "{}", err);
+    }
+}
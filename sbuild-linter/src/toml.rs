@@ -0,0 +1,150 @@
+//! TOML front-end for SBUILD recipes
+//!
+//! TOML recipes are rewritten into equivalent YAML so they go through the
+//! same validation as YAML ones. Each top-level field becomes one line of
+//! flow-style YAML after the `#!/SBUILD` header, so line numbers in
+//! validation errors count top-level fields rather than TOML source lines.
+
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+/// Convert a TOML recipe into SBUILD YAML, including the `#!/SBUILD` header
+pub fn toml_to_yaml(content: &str) -> Result<String, String> {
+    let doc: DocumentMut = content.parse().map_err(|e| format!("{}", e))?;
+
+    let mut out = String::from("#!/SBUILD\n");
+    for (key, item) in doc.as_table().iter() {
+        if let Some(value) = item_to_yaml(item) {
+            out.push_str(&format!("{}: {}\n", key, value));
+        }
+    }
+    Ok(out)
+}
+
+fn item_to_yaml(item: &Item) -> Option<String> {
+    match item {
+        Item::None => None,
+        Item::Value(value) => Some(value_to_yaml(value)),
+        Item::Table(table) => Some(table_to_yaml(table)),
+        Item::ArrayOfTables(tables) => Some(format!(
+            "[{}]",
+            tables
+                .iter()
+                .map(table_to_yaml)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn table_to_yaml(table: &Table) -> String {
+    let entries: Vec<String> = table
+        .iter()
+        .filter_map(|(k, v)| item_to_yaml(v).map(|v| format!("{}: {}", quote(k), v)))
+        .collect();
+    format!("{{{}}}", entries.join(", "))
+}
+
+fn value_to_yaml(value: &Value) -> String {
+    match value {
+        Value::String(s) => quote(s.value()),
+        Value::Integer(i) => i.value().to_string(),
+        Value::Float(f) => f.value().to_string(),
+        Value::Boolean(b) => b.value().to_string(),
+        Value::Datetime(d) => quote(&d.value().to_string()),
+        Value::Array(arr) => format!(
+            "[{}]",
+            arr.iter().map(value_to_yaml).collect::<Vec<_>>().join(", ")
+        ),
+        Value::InlineTable(table) => {
+            let entries: Vec<String> = table
+                .iter()
+                .map(|(k, v)| format!("{}: {}", quote(k), value_to_yaml(v)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
+
+/// YAML double-quoted scalar
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use saphyr::{LoadableYamlNode, Yaml};
+
+    #[test]
+    fn converts_each_top_level_field_to_one_line() {
+        let toml = r#"
+pkg = "hello"
+version = 2
+ratio = 1.5
+pkg_type = "static"
+category = ["utility", "cli"]
+
+[build_util]
+shell = "bash"
+tools = ["curl"]
+
+[[src]]
+url = "https://example.com/a"
+
+[[src]]
+url = "https://example.com/b"
+"#;
+        assert_eq!(
+            toml_to_yaml(toml).unwrap(),
+            "#!/SBUILD\n\
+             pkg: \"hello\"\n\
+             version: 2\n\
+             ratio: 1.5\n\
+             pkg_type: \"static\"\n\
+             category: [\"utility\", \"cli\"]\n\
+             build_util: {\"shell\": \"bash\", \"tools\": [\"curl\"]}\n\
+             src: [{\"url\": \"https://example.com/a\"}, {\"url\": \"https://example.com/b\"}]\n"
+        );
+    }
+
+    #[test]
+    fn multiline_strings_round_trip_through_yaml() {
+        let toml = r#"
+description = 'say "hi" \ bye'
+x_exec = { shell = "sh", run = """
+echo "one"
+\tprintf '%s' two
+""" }
+"#;
+        let yaml = toml_to_yaml(toml).unwrap();
+        assert_eq!(yaml.lines().count(), 3);
+
+        let docs = Yaml::load_from_str(&yaml).unwrap();
+        let doc = &docs[0];
+        assert_eq!(doc["description"].as_str(), Some(r#"say "hi" \ bye"#));
+        assert_eq!(
+            doc["x_exec"]["run"].as_str(),
+            Some("echo \"one\"\n\tprintf '%s' two\n")
+        );
+    }
+
+    #[test]
+    fn reports_invalid_toml() {
+        assert!(toml_to_yaml("pkg = ").is_err());
+        assert!(toml_to_yaml("pkg = \"a\"\npkg = \"b\"").is_err());
+    }
+}
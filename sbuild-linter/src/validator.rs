@@ -1,24 +1,44 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use colored::Colorize;
 use indexmap::IndexMap;
+use regex::Regex;
 use saphyr::MarkedYamlOwned;
 use url::Url;
 
 use crate::{
     build_config::BuildConfig,
     description::Description,
+    envvars::undefined_env_refs,
     error::{highlight_error_line, ErrorDetails, Severity},
+    field::{field_source, FieldValidator},
+    get_pkg_id,
+    include::IncludedLines,
     logger::TaskLogger,
     xexec::XExec,
-    BuildAsset, VALID_ARCH, VALID_CATEGORIES, VALID_OS, VALID_PKG_TYPES,
+    BuildAsset, Resource, XUpdate, VALID_ARCH, VALID_CATEGORIES, VALID_OS, VALID_PKG_TYPES,
+    VALID_UPDATE_SOURCES,
 };
 
+/// Old field names that are still accepted, with their replacements
+const DEPRECATED_FIELDS: &[(&str, &str)] = &[("version", "pkgver")];
+
 pub struct ValidationContext {
     yaml_str: String,
     logger: TaskLogger,
     errors: Vec<ErrorDetails>,
     visited: HashSet<String>,
+    resource_base: Option<PathBuf>,
+    deny_patterns: Vec<Regex>,
+    included: Option<IncludedLines>,
+    field_validators: Vec<Arc<dyn FieldValidator>>,
+    /// Severity of unknown fields and empty package entries;
+    /// [`Severity::Error`] closes the schema
+    strict_severity: Severity,
 }
 
 impl ValidationContext {
@@ -28,9 +48,49 @@ impl ValidationContext {
             logger,
             errors: Vec::new(),
             visited: HashSet::new(),
+            resource_base: None,
+            deny_patterns: Vec::new(),
+            included: None,
+            field_validators: Vec::new(),
+            strict_severity: Severity::Warn,
         }
     }
 
+    /// Check that `file`/`dir` resources exist relative to `base`
+    pub fn with_resource_base(mut self, base: Option<PathBuf>) -> Self {
+        self.resource_base = base;
+        self
+    }
+
+    /// Reject script lines matching any of `patterns`
+    pub fn with_deny_patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.deny_patterns = patterns;
+        self
+    }
+
+    /// Report errors on lines merged from an `_include` against that file
+    pub fn with_included(mut self, included: Option<IncludedLines>) -> Self {
+        self.included = included;
+        self
+    }
+
+    /// Accept the custom fields handled by `validators`
+    pub fn with_field_validators(mut self, validators: Vec<Arc<dyn FieldValidator>>) -> Self {
+        self.field_validators = validators;
+        self
+    }
+
+    /// Treat unknown fields and empty package entries as errors rather
+    /// than warnings
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict_severity = if strict {
+            Severity::Error
+        } else {
+            Severity::Warn
+        };
+        self
+    }
+
     fn line_of(node: &MarkedYamlOwned) -> usize {
         let line = node.span.start.line();
         if line != 0 {
@@ -69,6 +129,20 @@ impl ValidationContext {
         });
     }
 
+    /// Warning that `--strict` promotes to an error
+    fn strict_warn(&mut self, field: &str, message: &str, line: usize) {
+        self.errors.push(ErrorDetails {
+            field: field.to_string(),
+            message: message.to_string(),
+            line_number: line,
+            severity: self.strict_severity,
+        });
+    }
+
+    fn unknown_field(&mut self, field: &str, line: usize) {
+        self.strict_warn(field, &format!("'{}' is not a valid field.", field), line);
+    }
+
     fn expect_bool(&mut self, node: &MarkedYamlOwned, field: &str) -> Option<bool> {
         let line = Self::line_of(node);
         if let Some(b) = node.data.as_bool() {
@@ -166,6 +240,73 @@ impl ValidationContext {
         }
     }
 
+    /// Error on provides entries whose `==`/`=>` targets collide, since they
+    /// would all install the same symlink.
+    fn check_provide_targets(&mut self, provides: &[String], field: &str, line: usize) {
+        let mut targets: IndexMap<&str, Vec<&str>> = IndexMap::new();
+        for entry in provides {
+            if let Some(target) = provide_target(entry) {
+                targets.entry(target).or_default().push(entry);
+            }
+        }
+
+        for (target, entries) in targets.iter().filter(|(_, e)| e.len() > 1) {
+            self.error(
+                field,
+                &format!(
+                    "Entries {} all resolve to the same target '{}'.",
+                    entries
+                        .iter()
+                        .map(|e| format!("'{}'", e))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    target
+                ),
+                line,
+            );
+        }
+    }
+
+    /// Warn when `x_exec.entrypoint` names nothing in provides; the builder
+    /// only notices a missing entrypoint after the build has run.
+    fn check_entrypoint_provided(&mut self, config: &BuildConfig, line: usize) {
+        let Some(entrypoint) = config.x_exec.entrypoint.as_deref() else {
+            return;
+        };
+        let entrypoint = entrypoint.trim().trim_start_matches('/');
+        if entrypoint.is_empty() {
+            return;
+        }
+
+        let provides: Vec<&String> = match config.packages {
+            Some(ref packages) => packages.iter().flat_map(|(_, p)| &p.provides).collect(),
+            None => config.provides.iter().flatten().collect(),
+        };
+        if provides.is_empty() {
+            return;
+        }
+
+        let file_name = entrypoint.rsplit('/').next().unwrap_or(entrypoint);
+        let provided = provides.iter().any(|entry| {
+            let entry = entry.strip_prefix('@').unwrap_or(entry);
+            [Some(provide_base(entry)), provide_target(entry)]
+                .into_iter()
+                .flatten()
+                .any(|name| name == entrypoint || name == file_name)
+        });
+
+        if !provided {
+            self.warn(
+                "x_exec.entrypoint",
+                &format!(
+                    "Entrypoint '{}' is not listed in provides; the build may not produce it.",
+                    entrypoint
+                ),
+                line,
+            );
+        }
+    }
+
     fn mapping_get<'a>(node: &'a MarkedYamlOwned, key: &str) -> Option<&'a MarkedYamlOwned> {
         node.data.as_mapping_get(key)
     }
@@ -214,12 +355,17 @@ impl ValidationContext {
                 .unwrap_or_default();
 
             if provides.is_empty() {
-                self.warn(
+                self.strict_warn(
                     &format!("packages.{}", pkg_name),
                     &format!("Package '{}' has no provides.", pkg_name),
                     pkg_line,
                 );
             }
+            self.check_provide_targets(
+                &provides,
+                &format!("packages.{}.provides", pkg_name),
+                pkg_line,
+            );
 
             packages.push((
                 pkg_name.to_string(),
@@ -276,6 +422,10 @@ impl ValidationContext {
         // run (optional)
         if let Some(run_node) = Self::mapping_get(node, "run") {
             if let Some(s) = self.expect_non_empty_string(run_node, "x_exec.run") {
+                if !self.check_deny_patterns(run_node, "x_exec.run", &s) {
+                    valid = false;
+                }
+                self.check_env_refs(run_node, "x_exec.run", &s);
                 x_exec.run = Some(s);
             } else {
                 valid = false;
@@ -285,6 +435,10 @@ impl ValidationContext {
         // pkgver (optional)
         if let Some(pkgver_node) = Self::mapping_get(node, "pkgver") {
             if let Some(s) = self.expect_string(pkgver_node, "x_exec.pkgver") {
+                if !self.check_deny_patterns(pkgver_node, "x_exec.pkgver", &s) {
+                    valid = false;
+                }
+                self.check_env_refs(pkgver_node, "x_exec.pkgver", &s);
                 x_exec.pkgver = Some(s);
             } else {
                 valid = false;
@@ -525,6 +679,8 @@ impl ValidationContext {
         };
 
         let mut assets = Vec::new();
+        // out path -> url of the first asset written there
+        let mut outs: HashMap<String, String> = HashMap::new();
         for asset_node in seq {
             let asset_line = Self::line_of(asset_node);
             if asset_node.data.as_mapping().is_none() {
@@ -562,6 +718,20 @@ impl ValidationContext {
 
             if let Some(out_node) = Self::mapping_get(asset_node, "out") {
                 if let Some(o) = self.expect_non_empty_string(out_node, "build_asset.out") {
+                    let key = o.trim_start_matches("./").to_string();
+                    if let Some(first_url) = outs.get(&key) {
+                        self.error(
+                            "build_asset.out",
+                            &format!(
+                                "'{}' is already the out of {}; {} would overwrite it",
+                                o, first_url, url
+                            ),
+                            Self::line_of(out_node),
+                        );
+                        valid = false;
+                    } else {
+                        outs.insert(key, url.clone());
+                    }
                     out = o;
                 } else {
                     valid = false;
@@ -584,6 +754,277 @@ impl ValidationContext {
         }
     }
 
+    /// Warn when an explicit `pkg_id` doesn't look like the one derived from
+    /// `src_url[0]`. Overrides are allowed, so ids that only differ in case,
+    /// a `www.` prefix, a `.git` suffix, or by one being a `.`-separated
+    /// prefix of the other (say, a release page URL) pass.
+    fn check_pkg_id_matches_src_url(&mut self, config: &BuildConfig, line: usize) {
+        if config.pkg_id.is_empty() {
+            return;
+        }
+        let Some(derived) = config.src_url.first().and_then(|u| get_pkg_id(u)) else {
+            return;
+        };
+
+        let normalize = |id: &str| {
+            let id = id.to_lowercase();
+            let id = id.strip_prefix("www.").unwrap_or(&id);
+            id.strip_suffix(".git").unwrap_or(id).to_string()
+        };
+        let (explicit, expected) = (normalize(&config.pkg_id), normalize(&derived));
+        let nested = |a: &str, b: &str| a.strip_prefix(b).is_some_and(|r| r.starts_with('.'));
+        if explicit == expected || nested(&explicit, &expected) || nested(&expected, &explicit) {
+            return;
+        }
+
+        self.warn(
+            "pkg_id",
+            &format!(
+                "'pkg_id' '{}' doesn't match src_url; expected something like '{}'",
+                config.pkg_id, derived
+            ),
+            line,
+        );
+    }
+
+    /// Validate an `icon`/`desktop` resource: a mapping with at least one of
+    /// `url`, `file` or `dir`. A plain string is accepted as a URL.
+    fn validate_resource(&mut self, node: &MarkedYamlOwned, field: &str) -> Option<Resource> {
+        let line = Self::line_of(node);
+
+        if let Some(url) = node.data.as_str() {
+            if !is_valid_url(url) {
+                self.error(
+                    field,
+                    &format!("{} is not a valid URL: '{}'", field, url),
+                    line,
+                );
+                return None;
+            }
+            return Some(Resource {
+                url: Some(url.to_string()),
+                ..Default::default()
+            });
+        }
+
+        let Some(map) = node.data.as_mapping() else {
+            self.error(
+                field,
+                &format!("'{}' must be a mapping with url, file or dir", field),
+                line,
+            );
+            return None;
+        };
+
+        let mut valid = true;
+        let mut resource = Resource::default();
+
+        for (k, v) in map {
+            let Some(key) = k.data.as_str() else {
+                continue;
+            };
+            let key_field = format!("{}.{}", field, key);
+            let value = match key {
+                "url" | "file" | "dir" => self.expect_non_empty_string(v, &key_field),
+                _ => {
+                    self.unknown_field(&key_field, Self::line_of(k));
+                    continue;
+                }
+            };
+            let Some(value) = value else {
+                valid = false;
+                continue;
+            };
+
+            match key {
+                "url" => {
+                    if !is_valid_url(&value) {
+                        self.error(
+                            &key_field,
+                            &format!("{} is not a valid URL: '{}'", key_field, value),
+                            Self::line_of(v),
+                        );
+                        valid = false;
+                    }
+                    resource.url = Some(value);
+                }
+                "file" => {
+                    valid &= self.check_resource_path(&value, false, &key_field, Self::line_of(v));
+                    resource.file = Some(value);
+                }
+                _ => {
+                    valid &= self.check_resource_path(&value, true, &key_field, Self::line_of(v));
+                    resource.dir = Some(value);
+                }
+            }
+        }
+
+        if resource.url.is_none() && resource.file.is_none() && resource.dir.is_none() {
+            if valid {
+                self.error(
+                    field,
+                    &format!("'{}' must have at least one of url, file or dir", field),
+                    line,
+                );
+            }
+            return None;
+        }
+
+        valid.then_some(resource)
+    }
+
+    /// Validate `x_update`: a `source`, plus the `repo` a `github_release`
+    /// source needs.
+    fn validate_x_update(&mut self, node: &MarkedYamlOwned) -> Option<XUpdate> {
+        let line = Self::line_of(node);
+        let Some(map) = node.data.as_mapping() else {
+            self.error("x_update", "Must be an object", line);
+            return None;
+        };
+
+        let mut valid = true;
+        let mut x_update = XUpdate::default();
+        for (k, v) in map {
+            let Some(key) = k.data.as_str() else {
+                continue;
+            };
+            let key_field = format!("x_update.{}", key);
+            if !matches!(key, "source" | "repo" | "project") {
+                self.unknown_field(&key_field, Self::line_of(k));
+                continue;
+            }
+            let Some(value) = self.expect_non_empty_string(v, &key_field) else {
+                valid = false;
+                continue;
+            };
+            match key {
+                "source" => {
+                    if !VALID_UPDATE_SOURCES.contains(&value.as_str()) {
+                        self.error(
+                            &key_field,
+                            &format!(
+                                "Invalid 'x_update.source': '{}'. Valid values are: {:?}",
+                                value, VALID_UPDATE_SOURCES
+                            ),
+                            Self::line_of(v),
+                        );
+                        valid = false;
+                    }
+                    x_update.source = value;
+                }
+                "repo" => {
+                    let parts: Vec<&str> = value.split('/').collect();
+                    if parts.len() != 2 || parts.iter().any(|p| p.is_empty()) {
+                        self.error(
+                            &key_field,
+                            &format!("'{}' must be in owner/name form", value),
+                            Self::line_of(v),
+                        );
+                        valid = false;
+                    }
+                    x_update.repo = Some(value);
+                }
+                _ => x_update.project = Some(value),
+            }
+        }
+
+        if x_update.source.is_empty() {
+            self.error("x_update", "Missing required 'source' field", line);
+            return None;
+        }
+        if x_update.source == "github_release" && x_update.repo.is_none() && valid {
+            self.error(
+                "x_update",
+                "'github_release' source needs a 'repo' (owner/name)",
+                line,
+            );
+            valid = false;
+        }
+
+        valid.then_some(x_update)
+    }
+
+    /// Error for each script line matching a deny pattern. Returns false if
+    /// anything matched.
+    fn check_deny_patterns(&mut self, node: &MarkedYamlOwned, field: &str, script: &str) -> bool {
+        let mut clean = true;
+        let start = Self::line_of(node);
+        for script_line in script.lines() {
+            let Some(pattern) = self.deny_patterns.iter().find(|p| p.is_match(script_line)) else {
+                continue;
+            };
+            let pattern = pattern.as_str().to_string();
+            let trimmed = script_line.trim();
+            let line = self.script_source_line(start, trimmed);
+            self.error(
+                field,
+                &format!("Forbidden pattern '{}' matched: {}", pattern, trimmed),
+                line,
+            );
+            clean = false;
+        }
+        clean
+    }
+
+    /// Source line at or after `start` containing `text`.
+    ///
+    /// Block scalars start on the line after the key; this finds the actual
+    /// line so the highlight points at the offending command.
+    fn script_source_line(&self, start: usize, text: &str) -> usize {
+        self.yaml_str
+            .lines()
+            .enumerate()
+            .skip(start.saturating_sub(1))
+            .find(|(_, l)| l.contains(text))
+            .map(|(i, _)| i + 1)
+            .unwrap_or(start)
+    }
+
+    /// Warn about variables the script uses that the builder doesn't set and
+    /// the script doesn't define; they'd expand to empty strings at build time.
+    fn check_env_refs(&mut self, node: &MarkedYamlOwned, field: &str, script: &str) {
+        let start = Self::line_of(node);
+        for (name, script_line) in undefined_env_refs(script) {
+            let text = script.lines().nth(script_line).unwrap_or_default().trim();
+            let line = self.script_source_line(start, text);
+            self.warn(
+                field,
+                &format!(
+                    "'${}' is not set by the builder or defined in the script",
+                    name
+                ),
+                line,
+            );
+        }
+    }
+
+    /// With a resource base set, error if `path` isn't an existing file (or
+    /// directory) under it. Always passes when no base is set.
+    fn check_resource_path(&mut self, path: &str, is_dir: bool, field: &str, line: usize) -> bool {
+        let Some(ref base) = self.resource_base else {
+            return true;
+        };
+        let full_path = base.join(path);
+        let exists = if is_dir {
+            full_path.is_dir()
+        } else {
+            full_path.is_file()
+        };
+        if !exists {
+            self.error(
+                field,
+                &format!(
+                    "{} '{}' does not exist under {}",
+                    if is_dir { "Directory" } else { "File" },
+                    path,
+                    base.display()
+                ),
+                line,
+            );
+        }
+        exists
+    }
+
     pub fn validate(&mut self, doc: &MarkedYamlOwned) -> Option<BuildConfig> {
         let map = match doc.data.as_mapping() {
             Some(m) => m,
@@ -600,6 +1041,10 @@ impl ValidationContext {
         let mut has_description = false;
         let mut has_src_url = false;
         let mut has_x_exec = false;
+        let mut x_exec_line = 0;
+        let mut x_update_line = 0;
+        let mut pkg_id_line = 0;
+        let mut src_url_line = 0;
 
         for (key_node, val_node) in map {
             let key = match key_node.data.as_str() {
@@ -608,6 +1053,18 @@ impl ValidationContext {
             };
             let line = Self::line_of(key_node);
 
+            let key = match DEPRECATED_FIELDS.iter().find(|(old, _)| *old == key) {
+                Some((old, new)) => {
+                    self.warn(
+                        old,
+                        &format!("'{}' is deprecated, use '{}' instead.", old, new),
+                        line,
+                    );
+                    new.to_string()
+                }
+                None => key,
+            };
+
             if self.visited.contains(&key) {
                 self.error(&key, &format!("'{}' field is duplicated", key), line);
                 continue;
@@ -650,6 +1107,7 @@ impl ValidationContext {
                             );
                         }
                         config.pkg_id = v;
+                        pkg_id_line = line;
                     }
                 }
                 "app_id" => {
@@ -663,6 +1121,15 @@ impl ValidationContext {
                                 ),
                                 line,
                             );
+                        } else if !is_reverse_dns(&v) {
+                            self.warn(
+                                "app_id",
+                                &format!(
+                                    "'app_id': '{}' should be in reverse-DNS form (e.g. org.gnome.Calculator).",
+                                    v
+                                ),
+                                line,
+                            );
                         }
                         config.app_id = Some(v);
                     }
@@ -682,7 +1149,7 @@ impl ValidationContext {
                         config.pkg_type = Some(v);
                     }
                 }
-                "pkgver" | "version" => {
+                "pkgver" => {
                     if let Some(v) = self.expect_string(val_node, &key) {
                         if !v.trim().is_empty() {
                             config.pkgver = Some(v);
@@ -728,6 +1195,12 @@ impl ValidationContext {
                         has_description = true;
                     }
                 }
+                "icon" => {
+                    config.icon = self.validate_resource(val_node, "icon");
+                }
+                "desktop" => {
+                    config.desktop = self.validate_resource(val_node, "desktop");
+                }
                 "homepage" => {
                     if let Some(urls) = self.expect_string_array(val_node, "homepage", false) {
                         for u in &urls {
@@ -753,6 +1226,9 @@ impl ValidationContext {
                 }
                 "provides" => {
                     config.provides = self.expect_string_array(val_node, "provides", false);
+                    if let Some(ref provides) = config.provides {
+                        self.check_provide_targets(provides, "provides", line);
+                    }
                 }
                 "packages" => {
                     config.packages = self.validate_packages(val_node);
@@ -773,6 +1249,7 @@ impl ValidationContext {
                         }
                         config.src_url = urls;
                         has_src_url = true;
+                        src_url_line = line;
                     }
                 }
                 "tag" => {
@@ -788,18 +1265,30 @@ impl ValidationContext {
                 "snapshots" => {
                     config.snapshots = self.expect_string_array(val_node, "snapshots", false);
                 }
+                "x_update" => {
+                    config.x_update = self.validate_x_update(val_node);
+                    x_update_line = line;
+                }
                 "x_exec" => {
                     if let Some(x) = self.validate_x_exec(val_node) {
                         config.x_exec = x;
                         has_x_exec = true;
+                        x_exec_line = line;
                     }
                 }
                 unknown => {
-                    self.warn(
-                        unknown,
-                        &format!("'{}' is not a valid field.", unknown),
-                        line,
-                    );
+                    let validator = self
+                        .field_validators
+                        .iter()
+                        .find(|v| v.field() == unknown)
+                        .cloned();
+                    match validator.map(|v| v.validate(val_node)) {
+                        Some(Ok(())) => config
+                            .custom_fields
+                            .push((key.clone(), field_source(&self.yaml_str, line))),
+                        Some(Err(message)) => self.error(unknown, &message, line),
+                        None => self.unknown_field(unknown, line),
+                    }
                 }
             }
         }
@@ -821,13 +1310,33 @@ impl ValidationContext {
             self.error("x_exec", "Missing required field: x_exec", 0);
         }
 
+        self.check_entrypoint_provided(&config, x_exec_line);
+
+        if config
+            .x_update
+            .as_ref()
+            .is_some_and(|u| u.source == "script")
+            && has_x_exec
+            && config.x_exec.pkgver.is_none()
+        {
+            self.error(
+                "x_update",
+                "'script' source needs an x_exec.pkgver script",
+                x_update_line,
+            );
+        }
+
+        self.check_pkg_id_matches_src_url(&config, pkg_id_line);
+
         // Set default category if empty
         if config.category.is_empty() {
             config.category = vec!["Utility".to_string()];
         }
 
         // Derive pkg_id from src_url if not explicitly set
-        config.set_pkg_id_from_src_url();
+        if let Err(e) = config.set_pkg_id_from_src_url() {
+            self.error("pkg_id", &e, src_url_line);
+        }
 
         if self.has_fatal_errors() {
             self.report_errors();
@@ -838,6 +1347,22 @@ impl ValidationContext {
         }
     }
 
+    /// Number of non-fatal issues recorded so far
+    pub fn warning_count(&self) -> usize {
+        self.errors
+            .iter()
+            .filter(|e| matches!(e.severity, Severity::Warn))
+            .count()
+    }
+
+    /// Number of fatal issues recorded so far
+    pub fn error_count(&self) -> usize {
+        self.errors
+            .iter()
+            .filter(|e| matches!(e.severity, Severity::Error))
+            .count()
+    }
+
     fn has_fatal_errors(&self) -> bool {
         self.errors
             .iter()
@@ -871,8 +1396,22 @@ impl ValidationContext {
                     error.message.yellow()
                 ));
             }
-            if error.line_number != 0 {
-                highlight_error_line(&self.yaml_str, error.line_number, is_fatal, &self.logger);
+            if error.line_number == 0 {
+                continue;
+            }
+            let origin = self
+                .included
+                .as_ref()
+                .and_then(|included| Some((included, included.source_line(error.line_number)?)));
+            match origin {
+                Some((included, line)) => {
+                    self.logger
+                        .custom_error(format!("    in {}", included.path.display()));
+                    highlight_error_line(&included.source, line, is_fatal, &self.logger);
+                }
+                None => {
+                    highlight_error_line(&self.yaml_str, error.line_number, is_fatal, &self.logger)
+                }
             }
         }
 
@@ -901,6 +1440,61 @@ pub fn is_valid_alpha(value: &str) -> bool {
         .all(|c| c.is_alphanumeric() || c == '+' || c == '-' || c == '_' || c == '.')
 }
 
+/// A `provides` entry split into its parts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProvideParts<'a> {
+    /// `@bin`: shipped with its package rather than as a package of its own
+    pub binary_only: bool,
+    /// Binary the build produces
+    pub command: &'a str,
+    /// `:`, `==` or `=>`, if the entry names the binary another way
+    pub separator: Option<&'static str>,
+    pub target: Option<&'a str>,
+}
+
+/// Split a provides entry. `==` and `=>` take precedence over `:`, and a
+/// leading `@` is dropped from the command.
+pub fn split_provide(entry: &str) -> ProvideParts<'_> {
+    let entry = entry.trim();
+    let (binary_only, rest) = match entry.strip_prefix('@') {
+        Some(rest) => (true, rest),
+        None => (false, entry),
+    };
+    let split = ["==", "=>", ":"]
+        .into_iter()
+        .find_map(|sep| rest.split_once(sep).map(|(cmd, target)| (cmd, sep, target)));
+    let (command, separator, target) = match split {
+        Some((cmd, sep, target)) => (cmd, Some(sep), Some(target.trim())),
+        None => (rest, None, None),
+    };
+    ProvideParts {
+        binary_only,
+        command: command.trim(),
+        separator,
+        target: target.filter(|t| !t.is_empty()),
+    }
+}
+
+/// The binary a provides entry refers to
+pub fn provide_base(entry: &str) -> &str {
+    split_provide(entry).command
+}
+
+/// The name a `bin==link` or `bin=>name` provides entry installs as, if it
+/// uses either form.
+pub fn provide_target(entry: &str) -> Option<&str> {
+    let parts = split_provide(entry);
+    parts
+        .target
+        .filter(|_| matches!(parts.separator, Some("==" | "=>")))
+}
+
+/// At least three non-empty dot-separated segments, e.g. `org.gnome.Calculator`
+pub fn is_reverse_dns(value: &str) -> bool {
+    let segments: Vec<&str> = value.split('.').collect();
+    segments.len() >= 3 && segments.iter().all(|s| !s.is_empty())
+}
+
 pub fn is_valid_category(value: &str) -> bool {
     VALID_CATEGORIES.lines().any(|line| line.trim() == value)
 }
@@ -917,3 +1511,236 @@ pub fn is_valid_url(value: &str) -> bool {
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use saphyr::LoadableYamlNode;
+
+    use super::*;
+    use crate::logger::LogManager;
+
+    fn context(yaml: &str) -> (ValidationContext, MarkedYamlOwned) {
+        let (sender, _receiver) = mpsc::channel();
+        let logger = LogManager::new(sender).create_logger(None::<PathBuf>);
+        let doc = MarkedYamlOwned::load_from_str(yaml)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        (ValidationContext::new(yaml, logger), doc)
+    }
+
+    fn parse_icon(yaml: &str) -> Option<Resource> {
+        let (mut ctx, doc) = context(yaml);
+        let node = ValidationContext::mapping_get(&doc, "icon").unwrap();
+        ctx.validate_resource(node, "icon")
+    }
+
+    #[test]
+    fn test_pkg_id_mismatch_warning() {
+        let warnings = |pkg_id: &str, src_url: &str| {
+            let (mut ctx, _) = context("{}");
+            let config = BuildConfig {
+                pkg_id: pkg_id.to_string(),
+                src_url: vec![src_url.to_string()],
+                ..Default::default()
+            };
+            ctx.check_pkg_id_matches_src_url(&config, 3);
+            ctx.errors
+        };
+
+        let bat = "https://github.com/sharkdp/bat";
+        assert!(warnings("github.com.sharkdp.bat", bat).is_empty());
+        assert!(warnings(
+            "github.com.sharkdp.bat",
+            "https://www.github.com/sharkdp/bat.git"
+        )
+        .is_empty());
+        assert!(warnings("github.com.sharkdp.bat", &format!("{}/releases", bat)).is_empty());
+        assert!(warnings("github.com.sharkdp.bat.nightly", bat).is_empty());
+
+        let errors = warnings("github.com.BurntSushi.ripgrep", bat);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].severity, Severity::Warn);
+        assert_eq!(errors[0].line_number, 3);
+        assert!(errors[0].message.contains("github.com.sharkdp.bat"));
+    }
+
+    #[test]
+    fn test_underivable_pkg_id_is_an_error() {
+        for src_url in ["binaries/bat", "github.com/sharkdp/bat"] {
+            let yaml = format!(
+                "_disabled: false\npkg: bat\ndescription: A cat clone\nsrc_url:\n  - {}\nx_exec:\n  shell: bash\n  run: echo hi\n",
+                src_url
+            );
+            let (mut ctx, doc) = context(&yaml);
+            assert!(ctx.validate(&doc).is_none());
+            assert!(ctx
+                .errors
+                .iter()
+                .any(|e| e.field == "pkg_id" && e.message.contains("Cannot derive pkg_id")));
+        }
+    }
+
+    #[test]
+    fn test_strict_promotes_unknown_fields_and_empty_packages() {
+        let yaml = "#!/SBUILD\n_disabled: false\npkg: bat\ndescription: A cat clone\nsrc_url:\n  - https://github.com/sharkdp/bat\nmaintainers:\n  - someone\npackages:\n  bat: {}\nx_exec:\n  shell: bash\n  run: echo hi\n";
+        let severities = |strict: bool| {
+            let (ctx, doc) = context(yaml);
+            let mut ctx = ctx.with_strict(strict);
+            ctx.validate(&doc);
+            ["maintainers", "packages.bat"].map(|field| {
+                ctx.errors
+                    .iter()
+                    .find(|e| e.field == field)
+                    .map(|e| e.severity)
+                    .unwrap_or_else(|| panic!("nothing reported for {}", field))
+            })
+        };
+
+        assert_eq!(severities(false), [Severity::Warn; 2]);
+        assert_eq!(severities(true), [Severity::Error; 2]);
+    }
+
+    #[test]
+    fn test_split_provide() {
+        let split = |entry| {
+            let parts = split_provide(entry);
+            (
+                parts.binary_only,
+                parts.command,
+                parts.separator,
+                parts.target,
+            )
+        };
+        assert_eq!(split(" bat "), (false, "bat", None, None));
+        assert_eq!(split("@bat"), (true, "bat", None, None));
+        assert_eq!(split("bat:cat"), (false, "bat", Some(":"), Some("cat")));
+        assert_eq!(split("a:b==c"), (false, "a:b", Some("=="), Some("c")));
+        assert_eq!(split("@a:b=>c"), (true, "a:b", Some("=>"), Some("c")));
+        assert_eq!(split("bat=="), (false, "bat", Some("=="), None));
+
+        assert_eq!(provide_base("@a:b==c"), "a:b");
+        assert_eq!(provide_target("a:b==c"), Some("c"));
+        assert_eq!(provide_target("bat:cat"), None);
+    }
+
+    #[test]
+    fn test_validate_x_update() {
+        let x_update = |yaml: &str| {
+            let (mut ctx, doc) = context(yaml);
+            let node = ValidationContext::mapping_get(&doc, "x_update").unwrap();
+            let x_update = ctx.validate_x_update(node);
+            let messages: Vec<_> = ctx.errors.into_iter().map(|e| e.message).collect();
+            (x_update, messages)
+        };
+
+        let (parsed, errors) = x_update("x_update:\n  source: github_release\n  repo: owner/name");
+        assert!(errors.is_empty());
+        assert_eq!(parsed.unwrap().repo.as_deref(), Some("owner/name"));
+
+        for (yaml, expected) in [
+            (
+                "x_update:\n  source: gitlab",
+                "Invalid 'x_update.source': 'gitlab'",
+            ),
+            (
+                "x_update:\n  repo: owner/name",
+                "Missing required 'source' field",
+            ),
+            (
+                "x_update:\n  source: github_release\n  repo: owner/name/extra",
+                "must be in owner/name form",
+            ),
+            (
+                "x_update:\n  source: github_release",
+                "'github_release' source needs a 'repo'",
+            ),
+        ] {
+            let (parsed, errors) = x_update(yaml);
+            assert!(parsed.is_none(), "{} was accepted", yaml);
+            assert!(
+                errors.iter().any(|e| e.contains(expected)),
+                "{} reported {:?}",
+                yaml,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_script_update_needs_pkgver() {
+        let recipe = |pkgver: &str| {
+            format!(
+                "#!/SBUILD\n_disabled: false\npkg: bat\ndescription: A cat clone\nsrc_url:\n  - https://github.com/sharkdp/bat\nx_update:\n  source: script\nx_exec:\n  shell: bash\n{}  run: echo hi\n",
+                pkgver
+            )
+        };
+        let errors = |yaml: &str| {
+            let (mut ctx, doc) = context(yaml);
+            ctx.validate(&doc);
+            ctx.errors
+                .into_iter()
+                .filter(|e| e.field == "x_update")
+                .map(|e| e.message)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            errors(&recipe("")),
+            ["'script' source needs an x_exec.pkgver script"]
+        );
+        assert!(errors(&recipe("  pkgver: echo 1.0\n")).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_build_asset_out() {
+        let yaml = r#"
+build_asset:
+  - url: https://example.com/a.tar.gz
+    out: src.tar.gz
+  - url: https://example.com/b.tar.gz
+    out: ./src.tar.gz
+"#;
+        let (mut ctx, doc) = context(yaml);
+        let node = ValidationContext::mapping_get(&doc, "build_asset").unwrap();
+        let assets = ctx.validate_build_asset(node).unwrap();
+
+        assert_eq!(assets.len(), 1);
+        assert_eq!(ctx.errors.len(), 1);
+        assert_eq!(ctx.errors[0].field, "build_asset.out");
+        assert_eq!(ctx.errors[0].line_number, 6);
+        assert!(ctx.errors[0]
+            .message
+            .contains("https://example.com/a.tar.gz"));
+        assert!(ctx.errors[0]
+            .message
+            .contains("https://example.com/b.tar.gz"));
+    }
+
+    #[test]
+    fn test_resource_write_yaml_round_trip() {
+        let cases = [
+            "icon: https://example.com/icon.png",
+            "icon:\n  url: https://example.com/icon.png",
+            "icon:\n  file: icons/app.png",
+            "icon:\n  dir: icons",
+            "icon:\n  url: https://example.com/icon.png\n  file: icons/app.png\n  dir: icons",
+            "icon:\n  dir: icons\n  file: icons/app.png",
+            "icon:\n  file: 'icons/\"quoted\".png'\n  dir: 'C:\\icons\\new'",
+        ];
+        for yaml in cases {
+            let resource = parse_icon(yaml).unwrap_or_else(|| panic!("{} didn't parse", yaml));
+
+            let mut out = Vec::new();
+            resource.write_yaml(&mut out, "icon", 0).unwrap();
+            let written = String::from_utf8(out).unwrap();
+            let reparsed = parse_icon(&written)
+                .unwrap_or_else(|| panic!("{} was written as invalid {}", yaml, written));
+
+            assert_eq!(reparsed, resource, "{} was written as {}", yaml, written);
+        }
+    }
+}
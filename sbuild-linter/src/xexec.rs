@@ -1,7 +1,4 @@
-use std::{
-    fs::File,
-    io::{self, BufWriter, Write},
-};
+use std::io::{self, Write};
 
 #[derive(Debug, Default, Clone)]
 pub struct XExec {
@@ -18,7 +15,7 @@ pub struct XExec {
 }
 
 impl XExec {
-    pub fn write_yaml(&self, writer: &mut BufWriter<File>, indent: usize) -> io::Result<()> {
+    pub fn write_yaml<W: Write>(&self, writer: &mut W, indent: usize) -> io::Result<()> {
         let indent_str = " ".repeat(indent);
 
         if let Some(ref arch) = self.arch {
@@ -32,6 +32,9 @@ pub enum Error {
     #[error("pkgver execution failed: {0}")]
     PkgverFailed(String),
 
+    #[error("Upstream lookup failed: {0}")]
+    Upstream(String),
+
     #[error("Version parse error: {0}")]
     VersionParse(String),
 
@@ -5,6 +5,31 @@
 
 use crate::Result;
 
+/// Optional normalizations on top of the ones [`compute_recipe_hash`] always does
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashOptions {
+    /// Skip the `version` field
+    pub exclude_version: bool,
+    /// Strip trailing comments (` # ...` outside quotes) from each line
+    pub ignore_comments: bool,
+}
+
+impl HashOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_exclude_version(mut self, exclude_version: bool) -> Self {
+        self.exclude_version = exclude_version;
+        self
+    }
+
+    pub fn with_ignore_comments(mut self, ignore_comments: bool) -> Self {
+        self.ignore_comments = ignore_comments;
+        self
+    }
+}
+
 /// Compute a normalized hash of recipe content.
 ///
 /// The normalization process:
@@ -15,7 +40,7 @@ use crate::Result;
 ///
 /// This ensures minor formatting changes don't trigger rebuilds.
 pub fn compute_recipe_hash(content: &str) -> String {
-    compute_recipe_hash_internal(content, false)
+    compute_recipe_hash_with(content, HashOptions::new())
 }
 
 /// Compute hash excluding the version field.
@@ -23,14 +48,27 @@ pub fn compute_recipe_hash(content: &str) -> String {
 /// Used for detecting recipe changes that should trigger rebuilds,
 /// where version changes are handled separately by the bot.
 pub fn compute_recipe_hash_excluding_version(content: &str) -> String {
-    compute_recipe_hash_internal(content, true)
+    compute_recipe_hash_with(content, HashOptions::new().with_exclude_version(true))
 }
 
-fn compute_recipe_hash_internal(content: &str, exclude_version: bool) -> String {
+/// Compute a normalized hash with extra normalizations from `options`.
+///
+/// With `ignore_comments`, a `#` preceded by whitespace and outside single or
+/// double quotes starts a comment, which is dropped along with the whitespace
+/// before it. This applies inside `x_exec` scripts too, where it also drops
+/// shell comments. A line with an unbalanced quote keeps its rest, so an
+/// apostrophe in a script only ever makes the hash more sensitive.
+pub fn compute_recipe_hash_with(content: &str, options: HashOptions) -> String {
     let normalized: String = content
         .lines()
-        .filter(|line| {
-            let trimmed = line.trim();
+        .map(|line| {
+            if options.ignore_comments {
+                strip_trailing_comment(line).trim()
+            } else {
+                line.trim()
+            }
+        })
+        .filter(|trimmed| {
             // Skip empty lines
             if trimmed.is_empty() {
                 return false;
@@ -40,18 +78,33 @@ fn compute_recipe_hash_internal(content: &str, exclude_version: bool) -> String
                 return false;
             }
             // Optionally skip version field
-            if exclude_version && trimmed.starts_with("version:") {
+            if options.exclude_version && trimmed.starts_with("version:") {
                 return false;
             }
             true
         })
-        .map(|line| line.trim())
         .collect::<Vec<_>>()
         .join("\n");
 
     blake3::hash(normalized.as_bytes()).to_hex().to_string()
 }
 
+/// `line` without a trailing ` # comment`
+fn strip_trailing_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if prev.is_whitespace() && i > 0 => return &line[..i],
+            _ => {}
+        }
+        prev = c;
+    }
+    line
+}
+
 /// Verify that a hash matches the expected value.
 pub fn verify_hash(content: &str, expected: &str) -> bool {
     compute_recipe_hash(content) == expected
@@ -104,6 +157,32 @@ mod tests {
         assert_ne!(compute_recipe_hash(content1), compute_recipe_hash(content2));
     }
 
+    #[test]
+    fn test_hash_ignores_trailing_comments() {
+        let content1 = "pkg: test # the name\ndescription: \"a # b\"\nurl: https://x.org/#top";
+        let content2 = "pkg: test\ndescription: \"a # b\"\nurl: https://x.org/#top";
+        let options = HashOptions::new().with_ignore_comments(true);
+        assert_ne!(compute_recipe_hash(content1), compute_recipe_hash(content2));
+        assert_eq!(
+            compute_recipe_hash_with(content1, options),
+            compute_recipe_hash_with(content2, options)
+        );
+        // Quoted text and URL fragments aren't comments
+        assert_ne!(
+            compute_recipe_hash_with(content2, options),
+            compute_recipe_hash_with(
+                "pkg: test\ndescription: \"a\"\nurl: https://x.org/",
+                options
+            )
+        );
+        // Composes with excluding the version
+        let options = options.with_exclude_version(true);
+        assert_eq!(
+            compute_recipe_hash_with("version: 1 # old\n#!/SBUILD", options),
+            compute_recipe_hash_with("version: 2\n#!/SBUILD", options)
+        );
+    }
+
     #[test]
     fn test_hash_excludes_version() {
         let content1 = "pkg: test\nversion: 1.0.0\ndescription: foo";
@@ -5,6 +5,7 @@
 //! - Generating package metadata from SBUILD recipes
 //! - Recipe hashing for change detection
 //! - Version comparison and update detection
+//! - Upstream version lookups on GitHub and Repology
 //! - Historical cache management
 
 pub mod error;
@@ -13,10 +14,16 @@ pub mod manifest;
 pub mod metadata;
 pub mod recipe;
 pub mod registry;
+pub mod upstream;
 
 pub use error::{Error, Result};
 pub use hash::compute_recipe_hash;
 pub use manifest::OciManifest;
-pub use metadata::{format_size, PackageMetadata};
+pub use metadata::{
+    assign_ranks, combine_arches, format_size, merge_index, CombinedMetadata, PackageMetadata,
+};
 pub use recipe::{sanitize_oci_name, GhcrPackageInfo, SBuildRecipe};
 pub use registry::RegistryClient;
+pub use upstream::{UpdateSource, UpstreamClient};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
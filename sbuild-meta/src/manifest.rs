@@ -8,6 +8,49 @@ use std::collections::HashMap;
 
 use crate::{metadata::format_size, Error, Result};
 
+/// Media type of an OCI image manifest
+pub const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Media type of an OCI image index (a list of per-platform manifests)
+pub const OCI_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// Media type of a Docker v2 image manifest, laid out like an OCI one
+pub const DOCKER_MANIFEST_MEDIA_TYPE: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+/// Base URL of the pkgforge GHCR download gateway
+pub const DOWNLOAD_GATEWAY: &str = "https://api.ghcr.pkgforge.dev";
+
+/// Gateway URL that downloads `filename` from `repository:tag`
+pub fn download_url(repository: &str, tag: &str, filename: &str) -> String {
+    format!(
+        "{}/{}?tag={}&download={}",
+        DOWNLOAD_GATEWAY, repository, tag, filename
+    )
+}
+
+/// Annotation holding the package JSON written by the builder
+pub const PACKAGE_JSON_ANNOTATION: &str = "dev.pkgforge.soar.json";
+
+/// Field of the package JSON naming its format version. Annotations
+/// written before it was introduced have none and are version 1.
+pub const PACKAGE_JSON_SCHEMA_KEY: &str = "schema_version";
+
+/// Package JSON format version this crate reads and the builder writes
+pub const PACKAGE_JSON_SCHEMA: u64 = 1;
+
+/// Format version of an embedded package JSON
+pub fn package_json_schema(json: &serde_json::Value) -> Option<u64> {
+    match json.get(PACKAGE_JSON_SCHEMA_KEY) {
+        None => Some(1),
+        Some(version) => version.as_u64(),
+    }
+}
+
+/// Gateway URL that returns the manifest of `repository:tag`
+pub fn manifest_url(repository: &str, tag: &str) -> String {
+    format!("{}/{}?tag={}&manifest", DOWNLOAD_GATEWAY, repository, tag)
+}
+
 /// OCI manifest layer descriptor
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LayerDescriptor {
@@ -59,6 +102,120 @@ pub struct OciManifest {
     pub annotations: HashMap<String, String>,
 }
 
+/// Platform an index entry was built for
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+}
+
+/// Manifest listed in an [`OciIndex`]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IndexEntry {
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+
+    pub size: u64,
+
+    pub digest: String,
+
+    #[serde(default)]
+    pub platform: Option<Platform>,
+
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+}
+
+/// OCI image index
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OciIndex {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+
+    #[serde(rename = "mediaType", default)]
+    pub media_type: Option<String>,
+
+    #[serde(default)]
+    pub manifests: Vec<IndexEntry>,
+
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+}
+
+impl OciIndex {
+    /// Image manifest for `host` (e.g. `x86_64-linux`), by the platform each
+    /// entry lists. An entry without a platform is used only when none
+    /// matches; attestations (`unknown/unknown`) never are.
+    pub fn image_entry(&self, host: &str) -> Option<&IndexEntry> {
+        let (arch, os) = host.split_once('-').unwrap_or((host, "linux"));
+        let arch = oci_architecture(arch);
+        let images = || {
+            self.manifests.iter().filter(|entry| {
+                entry.media_type == OCI_MANIFEST_MEDIA_TYPE
+                    || entry.media_type == DOCKER_MANIFEST_MEDIA_TYPE
+            })
+        };
+        images()
+            .find(|entry| {
+                entry.platform.as_ref().is_some_and(|p| {
+                    p.architecture.eq_ignore_ascii_case(arch) && p.os.eq_ignore_ascii_case(os)
+                })
+            })
+            .or_else(|| images().find(|entry| entry.platform.is_none()))
+    }
+}
+
+/// OCI (GOARCH) name of a host triplet's architecture
+fn oci_architecture(arch: &str) -> &str {
+    match arch.to_ascii_lowercase().as_str() {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "loongarch64" => "loong64",
+        "i686" => "386",
+        _ => arch,
+    }
+}
+
+/// Manifest fetched from a registry, by what the registry returned
+#[derive(Debug, Clone)]
+pub enum Manifest {
+    Image(OciManifest),
+    Index(OciIndex),
+}
+
+impl Manifest {
+    /// Parse `body` by its media type.
+    ///
+    /// The type comes from the response's `Content-Type`, falling back to the
+    /// body's own `mediaType`; with neither, the body is read as an image
+    /// manifest.
+    pub fn parse(content_type: Option<&str>, body: &str) -> Result<Self> {
+        let content_type = content_type
+            .map(|ct| ct.split(';').next().unwrap_or(ct).trim())
+            .filter(|ct| !ct.is_empty() && *ct != "application/json");
+        let media_type = match content_type {
+            Some(ct) => Some(ct.to_string()),
+            None => serde_json::from_str::<serde_json::Value>(body)?
+                .get("mediaType")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+        };
+
+        match media_type.as_deref() {
+            None | Some(OCI_MANIFEST_MEDIA_TYPE) | Some(DOCKER_MANIFEST_MEDIA_TYPE) => {
+                OciManifest::from_json(body).map(Manifest::Image)
+            }
+            Some(OCI_INDEX_MEDIA_TYPE) => serde_json::from_str(body)
+                .map(Manifest::Index)
+                .map_err(Error::Json),
+            Some(other) => Err(Error::Registry(format!(
+                "Unsupported manifest media type: {}",
+                other
+            ))),
+        }
+    }
+}
+
 impl OciManifest {
     /// Parse manifest from JSON string
     pub fn from_json(json: &str) -> Result<Self> {
@@ -72,7 +229,7 @@ impl OciManifest {
 
     /// Get the embedded package JSON from annotations
     pub fn get_package_json(&self) -> Result<Option<serde_json::Value>> {
-        match self.annotations.get("dev.pkgforge.soar.json") {
+        match self.annotations.get(PACKAGE_JSON_ANNOTATION) {
             Some(json_str) => {
                 let value: serde_json::Value = serde_json::from_str(json_str)?;
                 Ok(Some(value))
@@ -111,11 +268,29 @@ impl OciManifest {
         self.layers.iter().filter_map(|l| l.filename()).collect()
     }
 
+    /// Get layers ordered by size, largest first
+    pub fn layers_by_size(&self) -> Vec<&LayerDescriptor> {
+        let mut layers: Vec<&LayerDescriptor> = self.layers.iter().collect();
+        layers.sort_by_key(|l| std::cmp::Reverse(l.size));
+        layers
+    }
+
     /// Get layer by filename
     pub fn get_layer_by_filename(&self, filename: &str) -> Option<&LayerDescriptor> {
         self.layers.iter().find(|l| l.filename() == Some(filename))
     }
 
+    /// Get layer by content digest
+    pub fn get_layer_by_digest(&self, digest: &str) -> Option<&LayerDescriptor> {
+        self.layers.iter().find(|l| l.digest == digest)
+    }
+
+    /// Get the gateway download URL for a file in this manifest
+    pub fn download_url_for(&self, repository: &str, tag: &str, filename: &str) -> Option<String> {
+        self.get_layer_by_filename(filename)?;
+        Some(download_url(repository, tag, filename))
+    }
+
     /// Get blob reference for a file (ghcr_pkg@digest format)
     pub fn get_blob_ref(&self, filename: &str) -> Option<String> {
         let ghcr_pkg = self.ghcr_pkg()?;
@@ -173,10 +348,141 @@ impl ManifestMetadata {
     }
 }
 
+/// Fields listed first when diffing package JSON, as they're the ones that
+/// show whether a rebuild actually produced something new.
+const PRIORITY_DIFF_FIELDS: &[&str] = &[
+    "version",
+    "build_id",
+    "size",
+    "size_raw",
+    "ghcr_size",
+    "ghcr_size_raw",
+    "ghcr_files",
+];
+
+/// A single top-level field that differs between two package JSON documents
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub remote: Option<serde_json::Value>,
+    pub local: Option<serde_json::Value>,
+}
+
+/// Compare two package JSON objects field by field.
+///
+/// Priority fields (version, build_id, sizes, file list) come first, the rest
+/// follow in alphabetical order. Non-object inputs are treated as empty.
+pub fn diff_package_json(remote: &serde_json::Value, local: &serde_json::Value) -> Vec<FieldDiff> {
+    let empty = serde_json::Map::new();
+    let remote = remote.as_object().unwrap_or(&empty);
+    let local = local.as_object().unwrap_or(&empty);
+
+    let mut fields: Vec<&str> = remote
+        .keys()
+        .chain(local.keys())
+        .map(|k| k.as_str())
+        .filter(|k| *k != PACKAGE_JSON_SCHEMA_KEY)
+        .collect();
+    fields.sort_by_key(|f| {
+        let rank = PRIORITY_DIFF_FIELDS
+            .iter()
+            .position(|p| p == f)
+            .unwrap_or(PRIORITY_DIFF_FIELDS.len());
+        (rank, *f)
+    });
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let r = remote.get(field);
+            let l = local.get(field);
+            (r != l).then(|| FieldDiff {
+                field: field.to_string(),
+                remote: r.cloned(),
+                local: l.cloned(),
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const IMAGE_BODY: &str = r#"{
+        "schemaVersion": 2,
+        "layers": [
+            {"mediaType": "application/octet-stream", "size": 10, "digest": "sha256:a"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_oci_manifest_media_type() {
+        let manifest = Manifest::parse(
+            Some("application/vnd.oci.image.manifest.v1+json; charset=utf-8"),
+            IMAGE_BODY,
+        )
+        .unwrap();
+        let Manifest::Image(manifest) = manifest else {
+            panic!("expected an image manifest");
+        };
+        assert_eq!(manifest.total_size(), 10);
+    }
+
+    #[test]
+    fn test_parse_docker_manifest_media_type() {
+        let manifest = Manifest::parse(Some(DOCKER_MANIFEST_MEDIA_TYPE), IMAGE_BODY).unwrap();
+        assert!(matches!(manifest, Manifest::Image(_)));
+    }
+
+    #[test]
+    fn test_parse_oci_index_media_type() {
+        let body = r#"{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": 500,
+                    "digest": "sha256:att",
+                    "platform": {"architecture": "unknown", "os": "unknown"}
+                },
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": 600,
+                    "digest": "sha256:img",
+                    "platform": {"architecture": "amd64", "os": "linux"}
+                }
+            ]
+        }"#;
+        let Manifest::Index(index) = Manifest::parse(Some(OCI_INDEX_MEDIA_TYPE), body).unwrap()
+        else {
+            panic!("expected an index");
+        };
+        assert_eq!(index.manifests.len(), 2);
+        assert_eq!(
+            index.image_entry("x86_64-linux").unwrap().digest,
+            "sha256:img"
+        );
+        assert!(index.image_entry("aarch64-linux").is_none());
+
+        // Without a Content-Type the body's mediaType decides
+        assert!(matches!(
+            Manifest::parse(None, body).unwrap(),
+            Manifest::Index(_)
+        ));
+        assert!(matches!(
+            Manifest::parse(Some("application/json"), body).unwrap(),
+            Manifest::Index(_)
+        ));
+        assert!(matches!(
+            Manifest::parse(None, IMAGE_BODY).unwrap(),
+            Manifest::Image(_)
+        ));
+        assert!(Manifest::parse(Some("text/html"), IMAGE_BODY).is_err());
+    }
+
     #[test]
     fn test_parse_manifest() {
         let json = r#"{
@@ -226,4 +532,109 @@ mod tests {
         let manifest = OciManifest::from_json(json).unwrap();
         assert_eq!(manifest.total_size(), 300);
     }
+
+    #[test]
+    fn test_diff_package_json() {
+        let remote = serde_json::json!({
+            "pkg": "mybin",
+            "version": "1.0",
+            "build_id": "1",
+            "ghcr_files": ["mybin"],
+            "description": "same"
+        });
+        let local = serde_json::json!({
+            "pkg": "mybin",
+            "version": "1.1",
+            "build_id": "2",
+            "ghcr_files": ["mybin", "mybin.sig"],
+            "description": "same",
+            "homepage": ["https://example.com"]
+        });
+
+        let diffs = diff_package_json(&remote, &local);
+        let fields: Vec<&str> = diffs.iter().map(|d| d.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["version", "build_id", "ghcr_files", "homepage"]
+        );
+        assert_eq!(diffs[0].remote, Some(serde_json::json!("1.0")));
+        assert_eq!(diffs[0].local, Some(serde_json::json!("1.1")));
+        assert_eq!(diffs[3].remote, None);
+    }
+
+    #[test]
+    fn test_diff_package_json_identical() {
+        let value = serde_json::json!({"pkg": "mybin", "version": "1.0"});
+        assert!(diff_package_json(&value, &value).is_empty());
+    }
+
+    #[test]
+    fn test_layers_by_size() {
+        let json = r#"{
+            "schemaVersion": 2,
+            "layers": [
+                {"mediaType": "application/octet-stream", "size": 100, "digest": "sha256:a"},
+                {"mediaType": "application/octet-stream", "size": 300, "digest": "sha256:b"},
+                {"mediaType": "application/octet-stream", "size": 200, "digest": "sha256:c"}
+            ]
+        }"#;
+
+        let manifest = OciManifest::from_json(json).unwrap();
+        let sizes: Vec<u64> = manifest.layers_by_size().iter().map(|l| l.size).collect();
+        assert_eq!(sizes, vec![300, 200, 100]);
+    }
+
+    #[test]
+    fn test_get_layer_by_digest() {
+        let json = r#"{
+            "schemaVersion": 2,
+            "layers": [
+                {"mediaType": "application/octet-stream", "size": 100, "digest": "sha256:a"},
+                {"mediaType": "application/octet-stream", "size": 200, "digest": "sha256:b"}
+            ]
+        }"#;
+
+        let manifest = OciManifest::from_json(json).unwrap();
+        assert_eq!(manifest.get_layer_by_digest("sha256:b").unwrap().size, 200);
+        assert!(manifest.get_layer_by_digest("sha256:c").is_none());
+    }
+
+    #[test]
+    fn test_download_url_format() {
+        assert_eq!(
+            download_url("pkgforge/bincache/bat", "v0.24.0-x86_64-linux", "bat"),
+            "https://api.ghcr.pkgforge.dev/pkgforge/bincache/bat?tag=v0.24.0-x86_64-linux&download=bat"
+        );
+        assert_eq!(
+            manifest_url("pkgforge/bincache/bat", "{{version}}-x86_64-linux"),
+            "https://api.ghcr.pkgforge.dev/pkgforge/bincache/bat?tag={{version}}-x86_64-linux&manifest"
+        );
+    }
+
+    #[test]
+    fn test_download_url_for() {
+        let json = r#"{
+            "schemaVersion": 2,
+            "layers": [
+                {
+                    "mediaType": "application/octet-stream",
+                    "size": 1024,
+                    "digest": "sha256:abc123",
+                    "annotations": {"org.opencontainers.image.title": "mybin"}
+                }
+            ]
+        }"#;
+
+        let manifest = OciManifest::from_json(json).unwrap();
+        assert_eq!(
+            manifest.download_url_for("pkgforge/bincache/mybin", "v1.0-x86_64-linux", "mybin"),
+            Some(
+                "https://api.ghcr.pkgforge.dev/pkgforge/bincache/mybin?tag=v1.0-x86_64-linux&download=mybin"
+                    .to_string()
+            )
+        );
+        assert!(manifest
+            .download_url_for("pkgforge/bincache/mybin", "v1.0-x86_64-linux", "other")
+            .is_none());
+    }
 }
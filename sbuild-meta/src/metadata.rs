@@ -5,15 +5,68 @@
 //! - OCI manifest annotations
 //! - Registry information
 
-use crate::manifest::OciManifest;
-use crate::recipe::SBuildRecipe;
+use crate::manifest::{
+    package_json_schema, OciManifest, PACKAGE_JSON_ANNOTATION, PACKAGE_JSON_SCHEMA,
+};
+use crate::recipe::{Provide, SBuildRecipe};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Which side wins when a recipe and the pushed manifest disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Source {
+    #[default]
+    Manifest,
+    Recipe,
+}
+
+/// Per field group choice of [`Source`] when enriching from a manifest.
+///
+/// A recipe only wins where it sets the field. The default lets the manifest
+/// win everywhere, since it describes what was actually built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergePolicy {
+    /// `description`
+    pub description: Source,
+    /// `version`. Download URLs are tagged with it, so a recipe version that
+    /// hasn't been built yet points them at a tag that doesn't exist.
+    pub version: Source,
+}
+
+impl MergePolicy {
+    /// The same source for every field group
+    pub fn prefer(source: Source) -> Self {
+        Self {
+            description: source,
+            version: source,
+        }
+    }
+}
+
+/// Whether a manifest value should replace `recipe_value`
+fn manifest_wins(source: Source, recipe_value: &str) -> bool {
+    source == Source::Manifest || recipe_value.is_empty()
+}
 
 /// Helper to skip serializing empty vectors
 fn is_empty_vec<T>(v: &Option<Vec<T>>) -> bool {
     v.as_ref().map(|v| v.is_empty()).unwrap_or(true)
 }
 
+/// Files pushed alongside the package payload (logs, signatures, recipe...)
+fn is_auxiliary_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.ends_with(".version")
+        || name.ends_with(".log")
+        || name.ends_with(".sig")
+        || name.ends_with(".json")
+        || name == "CHECKSUM"
+        || name == "SBUILD"
+        || name == "LICENSE"
+        || name == "BUILD.log"
+}
+
 /// Helper to skip serializing empty strings
 fn is_empty_string(s: &str) -> bool {
     s.is_empty()
@@ -186,6 +239,14 @@ pub struct PackageMetadata {
 
     #[serde(skip_serializing_if = "is_empty_vec")]
     pub repology: Option<Vec<String>>,
+
+    /// Position in search results, 1 being the most relevant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank: Option<u32>,
+
+    /// Set when `download_url` failed verification at generation time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url_broken: Option<bool>,
 }
 
 impl PackageMetadata {
@@ -259,9 +320,29 @@ impl PackageMetadata {
     /// `ghcr_path` is the repository path (e.g., "pkgforge/hello/static")
     /// `arch` is the target architecture (e.g., "x86_64-linux")
     pub fn enrich_from_manifest(&mut self, manifest: &OciManifest, ghcr_path: &str, arch: &str) {
-        // Get embedded JSON if available
+        self.enrich_from_manifest_with(manifest, ghcr_path, arch, MergePolicy::default());
+    }
+
+    /// [`enrich_from_manifest`](Self::enrich_from_manifest), resolving
+    /// conflicts with the recipe according to `policy`
+    pub fn enrich_from_manifest_with(
+        &mut self,
+        manifest: &OciManifest,
+        ghcr_path: &str,
+        arch: &str,
+        policy: MergePolicy,
+    ) {
+        // Get embedded JSON if available, unless it's in a format we don't know
         if let Ok(Some(pkg_json)) = manifest.get_package_json() {
-            self.merge_from_json(&pkg_json);
+            match package_json_schema(&pkg_json) {
+                Some(PACKAGE_JSON_SCHEMA) => self.merge_from_json(&pkg_json, policy),
+                schema => log::warn!(
+                    "Ignoring {} of {}: unsupported schema version {}",
+                    PACKAGE_JSON_ANNOTATION,
+                    ghcr_path,
+                    schema.map_or_else(|| "(invalid)".to_string(), |v| v.to_string())
+                ),
+            }
         }
 
         // GHCR info - construct with version placeholder for snapshots support
@@ -281,10 +362,13 @@ impl PackageMetadata {
         );
 
         // Version from annotations
-        if let Some(version) = manifest.get_annotation("dev.pkgforge.soar.version") {
-            self.version = version.to_string();
-        } else if let Some(version) = manifest.get_annotation("org.opencontainers.image.version") {
-            self.version = version.to_string();
+        let version = manifest
+            .get_annotation("dev.pkgforge.soar.version")
+            .or_else(|| manifest.get_annotation("org.opencontainers.image.version"));
+        if let Some(version) = version {
+            if manifest_wins(policy.version, &self.version) {
+                self.version = version.to_string();
+            }
         }
 
         // Build date from annotations
@@ -338,17 +422,7 @@ impl PackageMetadata {
             })
             .or_else(|| {
                 // Fallback: find first file that's not an auxiliary file
-                filenames.iter().find(|f| {
-                    let name = f.rsplit('/').next().unwrap_or(f);
-                    !name.ends_with(".version")
-                        && !name.ends_with(".log")
-                        && !name.ends_with(".sig")
-                        && !name.ends_with(".json")
-                        && name != "CHECKSUM"
-                        && name != "SBUILD"
-                        && name != "LICENSE"
-                        && name != "BUILD.log"
-                })
+                filenames.iter().find(|f| !is_auxiliary_file(f))
             })
             .or_else(|| filenames.first());
 
@@ -357,14 +431,8 @@ impl PackageMetadata {
 
             // Generate download URL and manifest URL with version placeholder for snapshots support
             let version_tag = format!("{{{{version}}}}-{}", arch);
-            self.download_url = format!(
-                "https://api.ghcr.pkgforge.dev/{}?tag={}&download={}",
-                ghcr_path, version_tag, filename
-            );
-            self.manifest_url = Some(format!(
-                "https://api.ghcr.pkgforge.dev/{}?tag={}&manifest",
-                ghcr_path, version_tag
-            ));
+            self.download_url = crate::manifest::download_url(ghcr_path, &version_tag, filename);
+            self.manifest_url = Some(crate::manifest::manifest_url(ghcr_path, &version_tag));
 
             // Get size of main binary if available
             if let Some(layer) = manifest.get_layer_by_filename(filename) {
@@ -378,7 +446,7 @@ impl PackageMetadata {
     }
 
     /// Merge data from embedded JSON
-    fn merge_from_json(&mut self, json: &serde_json::Value) {
+    fn merge_from_json(&mut self, json: &serde_json::Value, policy: MergePolicy) {
         let get_str = |key: &str| -> Option<String> {
             json.get(key)
                 .and_then(|v| v.as_str())
@@ -396,10 +464,14 @@ impl PackageMetadata {
 
         // Override with manifest values if present
         if let Some(v) = get_str("version") {
-            self.version = v;
+            if manifest_wins(policy.version, &self.version) {
+                self.version = v;
+            }
         }
         if let Some(v) = get_str("description") {
-            self.description = v;
+            if manifest_wins(policy.description, &self.description) {
+                self.description = v;
+            }
         }
         if let Some(v) = get_str("build_date") {
             self.build_date = Some(v);
@@ -429,12 +501,59 @@ impl PackageMetadata {
             self.appstream = Some(v);
         }
 
+        // Install flags
+        let get_bool = |key: &str| json.get(key).and_then(|v| v.as_bool());
+        if let Some(v) = get_bool("desktop_integration") {
+            self.desktop_integration = Some(v);
+        }
+        if let Some(v) = get_bool("portable") {
+            self.portable = Some(v);
+        }
+        if let Some(v) = get_bool("recurse_provides") {
+            self.recurse_provides = Some(v);
+        }
+
         // Array fields
         if self.provides.is_none() {
             self.provides = get_vec("provides");
         }
     }
 
+    /// Infer install flags that soar relies on but recipes don't state.
+    ///
+    /// Flags already set (e.g. from embedded JSON) are left alone.
+    /// - `desktop_integration` when both an icon and a desktop entry exist
+    /// - `portable` when the recipe is tagged `portable`
+    /// - `recurse_provides` when `provides` spans more than one base package
+    pub fn infer_flags(&mut self) {
+        if self.desktop_integration.is_none() && self.icon.is_some() && self.desktop.is_some() {
+            self.desktop_integration = Some(true);
+        }
+
+        if self.portable.is_none()
+            && self
+                .tag
+                .as_ref()
+                .is_some_and(|tags| tags.iter().any(|t| t.eq_ignore_ascii_case("portable")))
+        {
+            self.portable = Some(true);
+        }
+
+        if self.recurse_provides.is_none() {
+            let mut bases: Vec<String> = self
+                .provides
+                .iter()
+                .flatten()
+                .map(|p| Provide::parse(p).command)
+                .collect();
+            bases.sort_unstable();
+            bases.dedup();
+            if bases.len() > 1 {
+                self.recurse_provides = Some(true);
+            }
+        }
+    }
+
     /// Merge snapshots from an external source (e.g., MongoDB cache)
     /// Combines existing snapshots with new ones, deduplicating
     pub fn merge_snapshots(&mut self, external_snapshots: &[String]) {
@@ -457,12 +576,16 @@ impl PackageMetadata {
             if notes.iter().any(|n| n.contains("[DEPRECATED]")) {
                 self.deprecated = Some(true);
             }
+            if notes.iter().any(|n| n.contains("[PORTABLE]")) {
+                self.portable = Some(true);
+            }
 
             // Filter out internal flag messages - these are for CI/internal use only
             let filtered: Vec<String> = notes
                 .into_iter()
                 .filter(|note| {
                     !note.contains("[DEPRECATED]")
+                        && !note.contains("[PORTABLE]")
                         && !note.contains("[EXTERNAL]")
                         && !note.contains("[NO_INSTALL]")
                         && !note.contains("[UNTRUSTED]")
@@ -477,6 +600,67 @@ impl PackageMetadata {
         }
     }
 
+    /// Layer digest of the payload when checksums are missing and can be
+    /// computed by downloading it.
+    ///
+    /// Only single-file packages qualify: the manifest must carry exactly one
+    /// non-auxiliary file, so the blob is the file soar downloads.
+    pub fn checksum_blob_digest(&self, manifest: &OciManifest) -> Option<String> {
+        if self.bsum.is_some() && self.shasum.is_some() {
+            return None;
+        }
+        let mut payload = manifest
+            .layers
+            .iter()
+            .filter(|l| l.filename().is_some_and(|f| !is_auxiliary_file(f)));
+        let layer = payload.next()?;
+        if payload.next().is_some() {
+            return None;
+        }
+        Some(layer.digest.clone())
+    }
+
+    /// Fill missing `bsum` (BLAKE3) and `shasum` (SHA256) from the payload.
+    pub fn apply_blob_checksums(&mut self, data: &[u8]) {
+        if self.bsum.is_none() {
+            self.bsum = Some(blake3::hash(data).to_hex().to_string());
+        }
+        if self.shasum.is_none() {
+            self.shasum = Some(format!("{:x}", Sha256::digest(data)));
+        }
+    }
+
+    /// Relevance score used to compute `rank`.
+    ///
+    /// - +10 per provided binary, counting at most 5
+    /// - +20 if the package ships an icon
+    /// - +20 if the package ships a desktop entry
+    /// - deprecated packages always score 0
+    pub fn rank_score(&self) -> u32 {
+        if self.deprecated == Some(true) {
+            return 0;
+        }
+        let provides = self.provides.as_ref().map_or(0, |p| p.len().min(5)) as u32;
+        let mut score = provides * 10;
+        if self.icon.is_some() {
+            score += 20;
+        }
+        if self.desktop.is_some() {
+            score += 20;
+        }
+        score
+    }
+
+    /// Key identifying this entry in an index: (`pkg_id`, `pkg`)
+    pub fn index_key(&self) -> (String, String) {
+        (self.pkg_id.clone(), self.pkg.clone())
+    }
+
+    /// `download_url` with the `{{version}}` placeholder filled in
+    pub fn resolved_download_url(&self) -> String {
+        self.download_url.replace("{{version}}", &self.version)
+    }
+
     /// Validate that required fields are present
     pub fn is_valid(&self) -> bool {
         !self.pkg.is_empty()
@@ -488,6 +672,197 @@ impl PackageMetadata {
     }
 }
 
+/// Merge freshly generated entries into a previous index.
+///
+/// Entries in `current` replace previous ones with the same
+/// [`index_key`](PackageMetadata::index_key). A previous entry is only kept
+/// when its key is in `errored` (its fetch failed this run); anything else
+/// missing from `current` was removed, gone from GHCR or dropped as broken.
+pub fn merge_index(
+    previous: Vec<PackageMetadata>,
+    current: Vec<PackageMetadata>,
+    errored: &HashSet<(String, String)>,
+) -> Vec<PackageMetadata> {
+    let current_keys: HashSet<_> = current.iter().map(|p| p.index_key()).collect();
+    let mut merged: Vec<_> = previous
+        .into_iter()
+        .filter(|p| {
+            let key = p.index_key();
+            errored.contains(&key) && !current_keys.contains(&key)
+        })
+        .collect();
+    merged.extend(current);
+    merged
+}
+
+/// Fields of a [`PackageMetadata`] that differ per architecture
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ArchMetadata {
+    #[serde(skip_serializing_if = "is_empty_string")]
+    pub version: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pkg_webpage: Option<String>,
+
+    #[serde(skip_serializing_if = "is_empty_string")]
+    pub download_url: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_raw: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ghcr_pkg: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ghcr_size: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ghcr_size_raw: Option<u64>,
+
+    #[serde(skip_serializing_if = "is_empty_vec")]
+    pub ghcr_files: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ghcr_blob: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ghcr_url: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest_url: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bsum: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shasum: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum_bsum: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_date: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_gha: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_log: Option<String>,
+
+    #[serde(skip_serializing_if = "is_empty_vec")]
+    pub snapshots: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url_broken: Option<bool>,
+}
+
+impl ArchMetadata {
+    /// Move the per-architecture fields out of `pkg`, leaving them empty
+    fn take_from(pkg: &mut PackageMetadata) -> Self {
+        Self {
+            version: std::mem::take(&mut pkg.version),
+            pkg_webpage: pkg.pkg_webpage.take(),
+            download_url: std::mem::take(&mut pkg.download_url),
+            size: pkg.size.take(),
+            size_raw: pkg.size_raw.take(),
+            ghcr_pkg: pkg.ghcr_pkg.take(),
+            ghcr_size: pkg.ghcr_size.take(),
+            ghcr_size_raw: pkg.ghcr_size_raw.take(),
+            ghcr_files: pkg.ghcr_files.take(),
+            ghcr_blob: pkg.ghcr_blob.take(),
+            ghcr_url: pkg.ghcr_url.take(),
+            manifest_url: pkg.manifest_url.take(),
+            bsum: pkg.bsum.take(),
+            shasum: pkg.shasum.take(),
+            checksum_bsum: pkg.checksum_bsum.take(),
+            build_id: pkg.build_id.take(),
+            build_date: pkg.build_date.take(),
+            build_gha: pkg.build_gha.take(),
+            build_log: pkg.build_log.take(),
+            snapshots: pkg.snapshots.take(),
+            rank: pkg.rank.take(),
+            download_url_broken: pkg.download_url_broken.take(),
+        }
+    }
+}
+
+/// One package across architectures.
+///
+/// Serializes as the shared [`PackageMetadata`] fields (description,
+/// license, provides...) plus an `arches` map from architecture name to its
+/// [`ArchMetadata`]. Shared fields come from the first input that has the
+/// package.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CombinedMetadata {
+    #[serde(flatten)]
+    pub common: PackageMetadata,
+
+    pub arches: BTreeMap<String, ArchMetadata>,
+}
+
+/// Group per-architecture indexes by [`index_key`](PackageMetadata::index_key).
+///
+/// `inputs` pairs each architecture name with its index. The result is sorted
+/// by `pkg`, then `pkg_id`.
+pub fn combine_arches(inputs: Vec<(String, Vec<PackageMetadata>)>) -> Vec<CombinedMetadata> {
+    let mut combined: BTreeMap<(String, String), CombinedMetadata> = BTreeMap::new();
+    for (arch, packages) in inputs {
+        for mut pkg in packages {
+            let (pkg_id, name) = pkg.index_key();
+            let arch_fields = ArchMetadata::take_from(&mut pkg);
+            combined
+                .entry((name, pkg_id))
+                .or_insert_with(|| CombinedMetadata {
+                    common: pkg,
+                    arches: BTreeMap::new(),
+                })
+                .arches
+                .insert(arch.clone(), arch_fields);
+        }
+    }
+    combined.into_values().collect()
+}
+
+/// Assign a `rank` to every package.
+///
+/// Packages listed in `external` (pkg_id -> rank, e.g. popularity data) keep
+/// that rank. The rest are ordered by [`PackageMetadata::rank_score`]
+/// (highest first, ties broken by `pkg` then `pkg_id`) and ranked after the
+/// highest external rank, so the output is the same for the same input.
+pub fn assign_ranks(packages: &mut [PackageMetadata], external: &HashMap<String, u32>) {
+    let mut next = external.values().max().copied().unwrap_or(0);
+
+    let mut computed: Vec<usize> = Vec::new();
+    for (i, pkg) in packages.iter_mut().enumerate() {
+        match external.get(&pkg.pkg_id) {
+            Some(rank) => pkg.rank = Some(*rank),
+            None => computed.push(i),
+        }
+    }
+
+    computed.sort_by(|&a, &b| {
+        let (a, b) = (&packages[a], &packages[b]);
+        b.rank_score()
+            .cmp(&a.rank_score())
+            .then_with(|| a.pkg.cmp(&b.pkg))
+            .then_with(|| a.pkg_id.cmp(&b.pkg_id))
+    });
+
+    for i in computed {
+        next += 1;
+        packages[i].rank = Some(next);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -541,4 +916,310 @@ category:
         // All notes were internal flags, so note should be None
         assert_eq!(metadata.note, None);
     }
+
+    fn manifest_with_files(files: &[&str]) -> OciManifest {
+        let layers: Vec<_> = files
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                serde_json::json!({
+                    "mediaType": "application/octet-stream",
+                    "digest": format!("sha256:{:064}", i),
+                    "size": 1,
+                    "annotations": {"org.opencontainers.image.title": f},
+                })
+            })
+            .collect();
+        let json = serde_json::json!({"schemaVersion": 2, "layers": layers});
+        OciManifest::from_json(&json.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_enrich_checks_package_json_schema() {
+        let manifest_with_json = |json: serde_json::Value| {
+            let manifest = serde_json::json!({
+                "schemaVersion": 2,
+                "annotations": {PACKAGE_JSON_ANNOTATION: json.to_string()},
+            });
+            OciManifest::from_json(&manifest.to_string()).unwrap()
+        };
+        let enriched = |json: serde_json::Value| {
+            let mut metadata = PackageMetadata::default();
+            metadata.enrich_from_manifest(&manifest_with_json(json), "pkgforge/hello", "x86_64");
+            metadata.description
+        };
+
+        // Annotations from before the schema field are version 1
+        assert_eq!(enriched(serde_json::json!({"description": "old"})), "old");
+        assert_eq!(
+            enriched(serde_json::json!({"schema_version": 1, "description": "v1"})),
+            "v1"
+        );
+        assert_eq!(
+            enriched(serde_json::json!({"schema_version": 2, "description": "v2"})),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_merge_policy() {
+        let manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "annotations": {
+                PACKAGE_JSON_ANNOTATION:
+                    serde_json::json!({"version": "1.0", "description": "built"}).to_string(),
+                "dev.pkgforge.soar.version": "1.0",
+            },
+        });
+        let manifest = OciManifest::from_json(&manifest.to_string()).unwrap();
+        let enriched = |description: &str, policy: MergePolicy| {
+            let mut metadata = PackageMetadata {
+                version: "1.1".to_string(),
+                description: description.to_string(),
+                ..Default::default()
+            };
+            metadata.enrich_from_manifest_with(&manifest, "pkgforge/hello", "x86_64", policy);
+            (metadata.version, metadata.description)
+        };
+
+        assert_eq!(
+            enriched("fixed", MergePolicy::default()),
+            ("1.0".to_string(), "built".to_string())
+        );
+        assert_eq!(
+            enriched("fixed", MergePolicy::prefer(Source::Recipe)),
+            ("1.1".to_string(), "fixed".to_string())
+        );
+        let description_only = MergePolicy {
+            description: Source::Recipe,
+            ..Default::default()
+        };
+        assert_eq!(
+            enriched("fixed", description_only),
+            ("1.0".to_string(), "fixed".to_string())
+        );
+        // The recipe only wins where it has a value
+        assert_eq!(
+            enriched("", description_only),
+            ("1.0".to_string(), "built".to_string())
+        );
+    }
+
+    #[test]
+    fn test_checksum_blob_digest() {
+        let metadata = PackageMetadata::default();
+
+        let single = manifest_with_files(&["hello", "BUILD.log", "SBUILD", "hello.version"]);
+        assert_eq!(
+            metadata.checksum_blob_digest(&single).as_deref(),
+            Some("sha256:0000000000000000000000000000000000000000000000000000000000000000")
+        );
+
+        let multi = manifest_with_files(&["hello", "hello-helper"]);
+        assert_eq!(metadata.checksum_blob_digest(&multi), None);
+
+        let mut metadata = PackageMetadata::default();
+        metadata.apply_blob_checksums(b"hello");
+        assert_eq!(
+            metadata.shasum.as_deref(),
+            Some("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+        );
+        assert_eq!(
+            metadata.bsum.as_deref(),
+            Some(blake3::hash(b"hello").to_hex().as_str())
+        );
+        assert_eq!(metadata.checksum_blob_digest(&single), None);
+    }
+
+    #[test]
+    fn test_merge_index() {
+        let entry = |pkg: &str, version: &str| PackageMetadata {
+            pkg: pkg.to_string(),
+            pkg_id: format!("example.com.{}", pkg),
+            version: version.to_string(),
+            ..Default::default()
+        };
+        // b failed to fetch this run; c's recipe was deleted so it wasn't
+        // processed at all
+        let previous = vec![entry("a", "1"), entry("b", "1"), entry("c", "1")];
+        let current = vec![entry("a", "2"), entry("d", "1")];
+        let errored = HashSet::from([entry("b", "1").index_key()]);
+
+        let mut merged = merge_index(previous, current, &errored);
+        merged.sort_by(|a, b| a.pkg.cmp(&b.pkg));
+        let summary: Vec<_> = merged
+            .iter()
+            .map(|p| (p.pkg.as_str(), p.version.as_str()))
+            .collect();
+        assert_eq!(summary, vec![("a", "2"), ("b", "1"), ("d", "1")]);
+    }
+
+    #[test]
+    fn test_combine_arches() {
+        let entry = |pkg: &str, version: &str, arch: &str| PackageMetadata {
+            pkg: pkg.to_string(),
+            pkg_id: format!("example.com.{}", pkg),
+            description: format!("{} tool", pkg),
+            version: version.to_string(),
+            download_url: format!("https://example.com/{}/{}", arch, pkg),
+            ..Default::default()
+        };
+        let inputs = vec![
+            (
+                "x86_64-linux".to_string(),
+                vec![entry("b", "2", "x86_64"), entry("a", "1", "x86_64")],
+            ),
+            (
+                "aarch64-linux".to_string(),
+                vec![entry("a", "1", "aarch64")],
+            ),
+        ];
+
+        let combined = combine_arches(inputs);
+        assert_eq!(combined.len(), 2);
+        assert_eq!(combined[0].common.pkg, "a");
+        assert_eq!(combined[0].common.description, "a tool");
+        assert!(combined[0].common.download_url.is_empty());
+        assert_eq!(
+            combined[0].arches.keys().collect::<Vec<_>>(),
+            vec!["aarch64-linux", "x86_64-linux"]
+        );
+        assert_eq!(
+            combined[0].arches["aarch64-linux"].download_url,
+            "https://example.com/aarch64/a"
+        );
+        assert_eq!(combined[1].arches["x86_64-linux"].version, "2");
+
+        let json = serde_json::to_value(&combined[1]).unwrap();
+        assert_eq!(json["pkg"], "b");
+        assert!(json.get("version").is_none());
+        assert_eq!(json["arches"]["x86_64-linux"]["version"], "2");
+    }
+
+    #[test]
+    fn test_combine_arches_keeps_per_arch_snapshots_and_rank() {
+        let entry = |snapshots: &[&str], rank: u32| PackageMetadata {
+            pkg: "a".to_string(),
+            pkg_id: "example.com.a".to_string(),
+            snapshots: Some(snapshots.iter().map(|s| s.to_string()).collect()),
+            rank: Some(rank),
+            ..Default::default()
+        };
+        let inputs = vec![
+            ("x86_64-linux".to_string(), vec![entry(&["2", "1"], 3)]),
+            ("aarch64-linux".to_string(), vec![entry(&["1"], 7)]),
+        ];
+
+        let combined = combine_arches(inputs);
+        assert_eq!(combined.len(), 1);
+        assert!(combined[0].common.snapshots.is_none());
+        assert!(combined[0].common.rank.is_none());
+
+        let x86 = &combined[0].arches["x86_64-linux"];
+        let arm = &combined[0].arches["aarch64-linux"];
+        assert_eq!(x86.snapshots, Some(vec!["2".to_string(), "1".to_string()]));
+        assert_eq!(arm.snapshots, Some(vec!["1".to_string()]));
+        assert_eq!((x86.rank, arm.rank), (Some(3), Some(7)));
+
+        let json = serde_json::to_value(&combined[0]).unwrap();
+        assert!(json.get("snapshots").is_none());
+        assert_eq!(json["arches"]["aarch64-linux"]["rank"], 7);
+    }
+
+    #[test]
+    fn test_resolved_download_url() {
+        let metadata = PackageMetadata {
+            version: "1.2.3".to_string(),
+            download_url: crate::manifest::download_url(
+                "pkgforge/bincache/hello/static",
+                "{{version}}-x86_64-linux",
+                "hello",
+            ),
+            ..Default::default()
+        };
+        assert_eq!(
+            metadata.resolved_download_url(),
+            "https://api.ghcr.pkgforge.dev/pkgforge/bincache/hello/static?tag=1.2.3-x86_64-linux&download=hello"
+        );
+    }
+
+    #[test]
+    fn test_infer_flags() {
+        let mut metadata = PackageMetadata {
+            icon: Some("icon.png".to_string()),
+            desktop: Some("app.desktop".to_string()),
+            tag: Some(vec!["Portable".to_string()]),
+            provides: Some(vec!["foo".to_string(), "foo==bar".to_string()]),
+            ..Default::default()
+        };
+        metadata.infer_flags();
+        assert_eq!(metadata.desktop_integration, Some(true));
+        assert_eq!(metadata.portable, Some(true));
+        assert_eq!(metadata.recurse_provides, None);
+
+        metadata.provides = Some(vec!["foo".to_string(), "baz=>qux".to_string()]);
+        metadata.infer_flags();
+        assert_eq!(metadata.recurse_provides, Some(true));
+
+        // Explicit values are not overridden
+        let mut metadata = PackageMetadata {
+            icon: Some("icon.png".to_string()),
+            desktop: Some("app.desktop".to_string()),
+            desktop_integration: Some(false),
+            ..Default::default()
+        };
+        metadata.infer_flags();
+        assert_eq!(metadata.desktop_integration, Some(false));
+        assert_eq!(metadata.portable, None);
+    }
+
+    #[test]
+    fn test_parse_note_flags_portable() {
+        let mut metadata = PackageMetadata {
+            note: Some(vec!["[PORTABLE] Runs from any directory".to_string()]),
+            ..Default::default()
+        };
+        metadata.parse_note_flags();
+        assert_eq!(metadata.portable, Some(true));
+        assert_eq!(metadata.note, None);
+    }
+
+    fn ranked_pkg(pkg: &str, provides: usize, icon: bool) -> PackageMetadata {
+        PackageMetadata {
+            pkg: pkg.to_string(),
+            pkg_id: format!("example.com.{}", pkg),
+            provides: Some((0..provides).map(|i| format!("bin{}", i)).collect()),
+            icon: icon.then(|| "icon.png".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rank_score() {
+        assert_eq!(ranked_pkg("a", 1, false).rank_score(), 10);
+        assert_eq!(ranked_pkg("a", 8, true).rank_score(), 70);
+
+        let mut deprecated = ranked_pkg("a", 3, true);
+        deprecated.deprecated = Some(true);
+        assert_eq!(deprecated.rank_score(), 0);
+    }
+
+    #[test]
+    fn test_assign_ranks() {
+        let mut packages = vec![
+            ranked_pkg("b", 1, false),
+            ranked_pkg("a", 1, false),
+            ranked_pkg("c", 2, true),
+            ranked_pkg("d", 0, false),
+        ];
+
+        assign_ranks(&mut packages, &HashMap::new());
+        let ranks: Vec<_> = packages.iter().map(|p| p.rank.unwrap()).collect();
+        assert_eq!(ranks, vec![3, 2, 1, 4]);
+
+        let external = HashMap::from([("example.com.d".to_string(), 2)]);
+        assign_ranks(&mut packages, &external);
+        let ranks: Vec<_> = packages.iter().map(|p| p.rank.unwrap()).collect();
+        assert_eq!(ranks, vec![5, 4, 3, 2]);
+    }
 }
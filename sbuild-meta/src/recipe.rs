@@ -1,9 +1,14 @@
 //! SBUILD recipe parsing and handling
 
 use saphyr::{LoadableYamlNode, YamlOwned};
-use std::path::Path;
+use sbuild_linter::{include, validator::split_provide};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 
-use crate::{Error, Result};
+use crate::{upstream::UpdateSource, Error, Result};
 
 /// Sanitize a name to be OCI repository name compliant
 /// OCI repository names must be lowercase and only contain [a-z0-9._-]
@@ -63,6 +68,58 @@ pub struct PackageConfig {
     pub provides: Vec<String>,
 }
 
+/// How a `provides` entry installs its binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvideKind {
+    /// `bin`: installed under its own name
+    Binary,
+    /// `bin:name`: also available as `name`
+    Alias,
+    /// `bin==name`: symlinked as `name`
+    Symlink,
+    /// `bin=>name`: installed as `name` instead
+    Rename,
+}
+
+/// A parsed `provides` entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Provide {
+    /// The entry as written in the recipe
+    pub raw: String,
+    /// Binary the build produces
+    pub command: String,
+    pub kind: ProvideKind,
+    /// Alias, link or new name, for anything but [`ProvideKind::Binary`]
+    pub target: Option<String>,
+    /// `@bin`: shipped with its package rather than as a package of its own
+    pub binary_only: bool,
+    /// GHCR package the binary ends up in, if it gets one
+    pub package: Option<String>,
+}
+
+impl Provide {
+    /// Parse one entry with the linter's [`split_provide`]
+    pub fn parse(entry: &str) -> Self {
+        let parts = split_provide(entry);
+        let kind = match parts.separator {
+            Some("==") => ProvideKind::Symlink,
+            Some("=>") => ProvideKind::Rename,
+            Some(_) => ProvideKind::Alias,
+            None => ProvideKind::Binary,
+        };
+        let command = parts.command.to_string();
+        Self {
+            raw: entry.trim().to_string(),
+            package: (!parts.binary_only).then(|| command.clone()),
+            command,
+            kind,
+            target: parts.target.map(String::from),
+            binary_only: parts.binary_only,
+        }
+    }
+}
+
 /// Execution configuration for building packages
 #[derive(Debug, Clone, Default)]
 pub struct ExecConfig {
@@ -96,6 +153,7 @@ pub struct SBuildRecipe {
     pub tag: Vec<String>,
     pub snapshots: Vec<String>,
     pub ghcr_pkg: Option<String>,
+    pub x_update: Option<UpdateSource>,
     pub x_exec: Option<ExecConfig>,
 }
 
@@ -165,6 +223,41 @@ fn parse_exec_config(yaml: &YamlOwned) -> Option<ExecConfig> {
     })
 }
 
+/// `x_update` block. A Repology project defaults to the recipe's first
+/// `repology` name, then `pkg`. A block the linter would reject is an error
+/// rather than a silent fallback to the pkgver script.
+fn parse_update_source(
+    yaml: &YamlOwned,
+    repology: &[String],
+    pkg: &str,
+) -> Result<Option<UpdateSource>> {
+    let Some(update) = yaml.as_mapping_get("x_update") else {
+        return Ok(None);
+    };
+    let source = get_str(update, "source")
+        .ok_or_else(|| Error::Recipe("x_update is missing 'source'".to_string()))?;
+    let source = match source.as_str() {
+        "github_release" => UpdateSource::GithubRelease {
+            repo: get_str(update, "repo").ok_or_else(|| {
+                Error::Recipe("'github_release' x_update needs a 'repo'".to_string())
+            })?,
+        },
+        "repology" => UpdateSource::Repology {
+            project: get_str(update, "project")
+                .or_else(|| repology.first().cloned())
+                .unwrap_or_else(|| pkg.to_string()),
+        },
+        "script" => UpdateSource::Script,
+        other => {
+            return Err(Error::Recipe(format!(
+                "Unknown x_update source '{}'",
+                other
+            )))
+        }
+    };
+    Ok(Some(source))
+}
+
 fn parse_packages(yaml: &YamlOwned) -> Vec<(String, PackageConfig)> {
     let Some(pkgs) = yaml.as_mapping_get("packages") else {
         return Vec::new();
@@ -185,40 +278,40 @@ fn parse_packages(yaml: &YamlOwned) -> Vec<(String, PackageConfig)> {
 impl SBuildRecipe {
     /// Parse a recipe from YAML content
     pub fn from_yaml(content: &str) -> Result<Self> {
-        let docs = YamlOwned::load_from_str(content).map_err(|e| Error::Yaml(e.to_string()))?;
-        let yaml = docs
-            .into_iter()
-            .next()
-            .ok_or_else(|| Error::Yaml("Empty YAML document".into()))?;
+        Self::from_node(&load_yaml(content)?)
+    }
 
+    fn from_node(yaml: &YamlOwned) -> Result<Self> {
+        let pkg = get_str(yaml, "pkg").unwrap_or_default();
+        let repology = get_string_vec(yaml, "repology");
         Ok(Self {
-            disabled: get_bool(&yaml, "_disabled").unwrap_or(false),
-            pkg: get_str(&yaml, "pkg").unwrap_or_default(),
-            pkg_id: get_str(&yaml, "pkg_id").unwrap_or_default(),
-            pkg_type: get_str(&yaml, "pkg_type"),
-            pkgver: get_str(&yaml, "pkgver").or_else(|| get_str(&yaml, "version")),
-            remote_pkgver: get_str(&yaml, "remote_pkgver"),
-            category: get_string_vec(&yaml, "category"),
-            description: get_description(&yaml),
-            homepage: get_string_vec(&yaml, "homepage"),
-            license: get_string_vec(&yaml, "license"),
-            maintainer: get_string_vec(&yaml, "maintainer"),
-            note: get_string_vec(&yaml, "note"),
-            provides: get_string_vec(&yaml, "provides"),
-            packages: parse_packages(&yaml),
-            repology: get_string_vec(&yaml, "repology"),
-            src_url: get_string_vec(&yaml, "src_url"),
-            tag: get_string_vec(&yaml, "tag"),
-            snapshots: get_string_vec(&yaml, "snapshots"),
-            ghcr_pkg: get_str(&yaml, "ghcr_pkg"),
-            x_exec: parse_exec_config(&yaml),
+            disabled: get_bool(yaml, "_disabled").unwrap_or(false),
+            x_update: parse_update_source(yaml, &repology, &pkg)?,
+            pkg,
+            pkg_id: get_str(yaml, "pkg_id").unwrap_or_default(),
+            pkg_type: get_str(yaml, "pkg_type"),
+            pkgver: get_str(yaml, "pkgver").or_else(|| get_str(yaml, "version")),
+            remote_pkgver: get_str(yaml, "remote_pkgver"),
+            category: get_string_vec(yaml, "category"),
+            description: get_description(yaml),
+            homepage: get_string_vec(yaml, "homepage"),
+            license: get_string_vec(yaml, "license"),
+            maintainer: get_string_vec(yaml, "maintainer"),
+            note: get_string_vec(yaml, "note"),
+            provides: get_string_vec(yaml, "provides"),
+            packages: parse_packages(yaml),
+            repology,
+            src_url: get_string_vec(yaml, "src_url"),
+            tag: get_string_vec(yaml, "tag"),
+            snapshots: get_string_vec(yaml, "snapshots"),
+            ghcr_pkg: get_str(yaml, "ghcr_pkg"),
+            x_exec: parse_exec_config(yaml),
         })
     }
 
-    /// Parse a recipe from a file path
+    /// Parse a recipe from a file path, merging in its `_include` fragment
     pub fn from_file(path: &Path) -> Result<Self> {
-        let content = std::fs::read_to_string(path)?;
-        Self::from_yaml(&content)
+        load_recipe(path).map(|(recipe, _)| recipe)
     }
 
     /// Check if this recipe supports a given architecture
@@ -259,6 +352,20 @@ impl SBuildRecipe {
         self.x_exec.as_ref()?.pkgver.as_deref()
     }
 
+    /// Where `check-updates` looks up new versions, the pkgver script if the
+    /// recipe has no `x_update`
+    pub fn update_source(&self) -> UpdateSource {
+        self.x_update.clone().unwrap_or_default()
+    }
+
+    /// Shell the `x_exec` scripts run with, `bash` if the recipe doesn't say
+    pub fn shell(&self) -> &str {
+        self.x_exec
+            .as_ref()
+            .and_then(|x| x.shell.as_deref())
+            .unwrap_or("bash")
+    }
+
     /// Check if recipe is disabled
     pub fn is_disabled(&self) -> bool {
         self.disabled
@@ -283,27 +390,14 @@ impl SBuildRecipe {
         let mut seen = std::collections::HashSet::new();
         let mut packages = Vec::new();
 
-        for entry in &self.provides {
-            // Skip binary-only entries (starting with @)
-            if entry.starts_with('@') {
-                continue;
-            }
-
-            // Extract base name before any separator (:, ==, =>)
-            let base = entry
-                .split("=>")
-                .next()
-                .unwrap_or(entry)
-                .split("==")
-                .next()
-                .unwrap_or(entry)
-                .split(':')
-                .next()
-                .unwrap_or(entry)
-                .to_string();
-
-            if !base.is_empty() && seen.insert(base.clone()) {
-                packages.push(base);
+        // Binary-only (`@`) entries have no package of their own
+        for package in self
+            .provides
+            .iter()
+            .filter_map(|e| Provide::parse(e).package)
+        {
+            if !package.is_empty() && seen.insert(package.clone()) {
+                packages.push(package);
             }
         }
 
@@ -314,17 +408,36 @@ impl SBuildRecipe {
         }
     }
 
+    /// Every `provides` entry, parsed. In `packages` recipes each entry
+    /// belongs to the package it's listed under.
+    pub fn provides_breakdown(&self) -> Vec<Provide> {
+        if !self.packages.is_empty() {
+            return self
+                .packages
+                .iter()
+                .flat_map(|(name, config)| {
+                    config.provides.iter().map(move |entry| Provide {
+                        package: Some(name.clone()),
+                        ..Provide::parse(entry)
+                    })
+                })
+                .collect();
+        }
+        self.provides.iter().map(|e| Provide::parse(e)).collect()
+    }
+
     /// Extract binary-only entries from provides field
     pub fn get_binaries(&self) -> Vec<String> {
         let mut seen = std::collections::HashSet::new();
         let mut binaries = Vec::new();
 
         for entry in &self.provides {
-            if let Some(bin_name) = entry.strip_prefix('@') {
-                let bin_name = bin_name.to_string();
-                if !bin_name.is_empty() && seen.insert(bin_name.clone()) {
-                    binaries.push(bin_name);
-                }
+            let parts = split_provide(entry);
+            if parts.binary_only
+                && !parts.command.is_empty()
+                && seen.insert(parts.command.to_string())
+            {
+                binaries.push(parts.command.to_string());
             }
         }
 
@@ -389,6 +502,29 @@ impl SBuildRecipe {
         packages
     }
 
+    /// Whether `ghcr_ref` names one of the GHCR packages this recipe pushes,
+    /// or the repository they share (e.g. "pkgforge/bincache/bat/static").
+    ///
+    /// Accepts a `ghcr.io/` prefix and a `:tag` or `@digest`. The owner isn't
+    /// known up front, so only the path after it is compared.
+    pub fn produces_ghcr_path(&self, recipe_path: &Path, ghcr_ref: &str) -> bool {
+        let ghcr_ref = normalize_ghcr_ref(ghcr_ref);
+        let ends_with = |suffix: &str| {
+            ghcr_ref == suffix
+                || ghcr_ref
+                    .strip_suffix(suffix)
+                    .is_some_and(|rest| rest.ends_with('/'))
+        };
+
+        self.ghcr_packages_from_path(recipe_path, "")
+            .iter()
+            .any(|info| {
+                let path = info.ghcr_path.trim_start_matches('/');
+                let repo = path.rsplit_once('/').map_or(path, |(repo, _)| repo);
+                ends_with(path) || ends_with(repo)
+            })
+    }
+
     /// Get the build script URL
     pub fn build_script_url(&self) -> String {
         format!(
@@ -398,12 +534,51 @@ impl SBuildRecipe {
     }
 }
 
-/// Scan a directory for SBUILD recipes
+/// Strip the registry host, tag and digest from a GHCR reference
+pub fn normalize_ghcr_ref(ghcr_ref: &str) -> &str {
+    let ghcr_ref = ghcr_ref.trim();
+    let ghcr_ref = ghcr_ref
+        .strip_prefix("https://")
+        .unwrap_or(ghcr_ref)
+        .trim_start_matches("ghcr.io/");
+    let ghcr_ref = ghcr_ref.split_once('@').map_or(ghcr_ref, |(r, _)| r);
+    // A ':' after the last '/' starts the tag
+    let ghcr_ref = match ghcr_ref.rfind(':') {
+        Some(i) if !ghcr_ref[i..].contains('/') => &ghcr_ref[..i],
+        _ => ghcr_ref,
+    };
+    ghcr_ref.trim_matches('/')
+}
+
+fn load_yaml(content: &str) -> Result<YamlOwned> {
+    let docs = YamlOwned::load_from_str(content).map_err(|e| Error::Yaml(e.to_string()))?;
+    docs.into_iter()
+        .next()
+        .ok_or_else(|| Error::Yaml("Empty YAML document".into()))
+}
+
+/// Parse the recipe at `path` and return it with the fragment it includes.
+///
+/// The fragment is merged with the linter's [`include::resolve`], so both
+/// see the same recipe.
+fn load_recipe(path: &Path) -> Result<(SBuildRecipe, Option<PathBuf>)> {
+    let content = std::fs::read_to_string(path)?;
+    match include::resolve(&content, path).map_err(Error::Recipe)? {
+        Some((merged, included)) => Ok((SBuildRecipe::from_yaml(&merged)?, Some(included.path))),
+        None => Ok((SBuildRecipe::from_yaml(&content)?, None)),
+    }
+}
+
+/// Scan a directory for SBUILD recipes.
+///
+/// Files pulled in through another recipe's `_include` are fragments, not
+/// recipes, and are left out.
 pub fn scan_recipes(dir: &Path) -> Result<Vec<(std::path::PathBuf, SBuildRecipe)>> {
     let pattern = dir.join("**/*.yaml");
     let pattern_str = pattern.to_string_lossy();
 
     let mut recipes = Vec::new();
+    let mut fragments = HashSet::new();
 
     for entry in glob::glob(&pattern_str)? {
         match entry {
@@ -416,8 +591,13 @@ pub fn scan_recipes(dir: &Path) -> Result<Vec<(std::path::PathBuf, SBuildRecipe)
                     continue;
                 }
 
-                match SBuildRecipe::from_file(&path) {
-                    Ok(recipe) => recipes.push((path, recipe)),
+                match load_recipe(&path) {
+                    Ok((recipe, fragment)) => {
+                        if let Some(fragment) = fragment {
+                            fragments.insert(fragment.canonicalize().unwrap_or(fragment));
+                        }
+                        recipes.push((path, recipe));
+                    }
                     Err(e) => {
                         log::warn!("Failed to parse recipe {:?}: {}", path, e);
                     }
@@ -429,6 +609,9 @@ pub fn scan_recipes(dir: &Path) -> Result<Vec<(std::path::PathBuf, SBuildRecipe)
         }
     }
 
+    recipes.retain(|(path, _)| {
+        !fragments.contains(&path.canonicalize().unwrap_or_else(|_| path.clone()))
+    });
     Ok(recipes)
 }
 
@@ -480,6 +663,54 @@ version: "1.2.3"
         assert_eq!(recipe.pkgver, Some("1.2.3".to_string()));
     }
 
+    #[test]
+    fn test_parse_update_source() {
+        let recipe = SBuildRecipe::from_yaml("pkg: test\n").unwrap();
+        assert_eq!(recipe.update_source(), UpdateSource::Script);
+
+        let yaml = r#"
+pkg: test
+x_update:
+  source: github_release
+  repo: "owner/test"
+"#;
+        let recipe = SBuildRecipe::from_yaml(yaml).unwrap();
+        assert_eq!(
+            recipe.update_source(),
+            UpdateSource::GithubRelease {
+                repo: "owner/test".to_string()
+            }
+        );
+
+        let yaml = r#"
+pkg: test
+repology:
+  - "test-cli"
+x_update:
+  source: repology
+"#;
+        let recipe = SBuildRecipe::from_yaml(yaml).unwrap();
+        assert_eq!(
+            recipe.update_source(),
+            UpdateSource::Repology {
+                project: "test-cli".to_string()
+            }
+        );
+
+        for x_update in [
+            "x_update:\n  repo: owner/test\n",
+            "x_update:\n  source: gitlab_release\n",
+            "x_update:\n  source: github_release\n",
+        ] {
+            let yaml = format!("pkg: test\n{}", x_update);
+            assert!(
+                matches!(SBuildRecipe::from_yaml(&yaml), Err(Error::Recipe(_))),
+                "{} parsed",
+                x_update
+            );
+        }
+    }
+
     #[test]
     fn test_supports_arch() {
         let yaml = r#"
@@ -514,6 +745,30 @@ provides:
         assert_eq!(packages[0].recipe_name, "static");
     }
 
+    #[test]
+    fn test_produces_ghcr_path() {
+        let yaml = r#"
+pkg: batcat
+provides:
+  - "bat==batcat"
+"#;
+        let recipe = SBuildRecipe::from_yaml(yaml).unwrap();
+        let path = Path::new("binaries/bat/static.yaml");
+
+        assert!(recipe.produces_ghcr_path(path, "pkgforge/bincache/bat/static"));
+        assert!(recipe.produces_ghcr_path(path, "pkgforge/bincache/bat/static/bat"));
+        assert!(recipe.produces_ghcr_path(
+            path,
+            "ghcr.io/pkgforge/bincache/bat/static/bat:v0.24.0-x86_64-linux"
+        ));
+        assert!(!recipe.produces_ghcr_path(path, "pkgforge/bincache/bat/appimage"));
+        assert!(!recipe.produces_ghcr_path(path, "pkgforge/bincache/xbat/static"));
+
+        assert_eq!(normalize_ghcr_ref("ghcr.io/a/b:tag"), "a/b");
+        assert_eq!(normalize_ghcr_ref("a/b@sha256:abc"), "a/b");
+        assert_eq!(normalize_ghcr_ref("a/b/"), "a/b");
+    }
+
     #[test]
     fn test_ghcr_packages_multiple_binaries() {
         let yaml = r#"
@@ -687,6 +942,51 @@ provides:
         assert!(packages.contains(&"app3".to_string()));
     }
 
+    #[test]
+    fn test_provides_breakdown() {
+        let yaml = r#"
+pkg: test
+provides:
+  - "tool"
+  - "tool:t"
+  - "tool-cli==tc"
+  - "old=>new"
+  - "@helper"
+"#;
+        let recipe = SBuildRecipe::from_yaml(yaml).unwrap();
+        let provides = recipe.provides_breakdown();
+        let summary: Vec<_> = provides
+            .iter()
+            .map(|p| (p.command.as_str(), p.kind, p.target.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("tool", ProvideKind::Binary, None),
+                ("tool", ProvideKind::Alias, Some("t")),
+                ("tool-cli", ProvideKind::Symlink, Some("tc")),
+                ("old", ProvideKind::Rename, Some("new")),
+                ("helper", ProvideKind::Binary, None),
+            ]
+        );
+        assert_eq!(provides[3].package.as_deref(), Some("old"));
+        assert!(provides[4].binary_only);
+        assert_eq!(provides[4].package, None);
+
+        let yaml = r#"
+pkg: suite
+packages:
+  suite-cli:
+    provides:
+      - "cli==s"
+"#;
+        let recipe = SBuildRecipe::from_yaml(yaml).unwrap();
+        let provides = recipe.provides_breakdown();
+        assert_eq!(provides.len(), 1);
+        assert_eq!(provides[0].package.as_deref(), Some("suite-cli"));
+        assert_eq!(provides[0].command, "cli");
+    }
+
     #[test]
     fn test_get_binaries() {
         let yaml = r#"
@@ -769,4 +1069,68 @@ provides:
         assert_eq!(packages[0].ghcr_path, "pkgforge/yazi/static/yazi");
         assert_eq!(packages[0].pkg_name, "yazi");
     }
+
+    #[test]
+    fn test_scan_recipes_resolves_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("common.yaml"),
+            "pkg: shared\ndescription: \"Shared description\"\nlicense:\n  - MIT\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("tool.yaml"),
+            "#!/SBUILD\n_include: common.yaml\npkg: tool\npkg_id: example.com.tool\n",
+        )
+        .unwrap();
+
+        let recipes = scan_recipes(dir.path()).unwrap();
+        assert_eq!(recipes.len(), 1);
+        let recipe = &recipes[0].1;
+        assert_eq!(recipe.pkg, "tool");
+        assert_eq!(recipe.description, "Shared description");
+        assert_eq!(recipe.license, vec!["MIT"]);
+    }
+
+    #[test]
+    fn test_from_file_matches_linter_include_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("common.yaml"),
+            "#!/SBUILD\nlicense:\n  - MIT\nx_exec:\n  shell: sh\n  pkgver: echo 1\n  run: true\n",
+        )
+        .unwrap();
+        let recipe = dir.path().join("tool.yaml");
+        std::fs::write(
+            &recipe,
+            "#!/SBUILD\n_include: common.yaml\npkg: tool\nx_exec:\n  shell: bash\n  run: make\n",
+        )
+        .unwrap();
+
+        // The recipe's nested map replaces the fragment's as a whole
+        let parsed = SBuildRecipe::from_file(&recipe).unwrap();
+        let exec = parsed.x_exec.as_ref().unwrap();
+        assert_eq!(exec.shell.as_deref(), Some("bash"));
+        assert_eq!(exec.pkgver, None);
+        assert_eq!(parsed.license, vec!["MIT"]);
+
+        let content = std::fs::read_to_string(&recipe).unwrap();
+        let (merged, _) = include::resolve(&content, &recipe).unwrap().unwrap();
+        let linted = SBuildRecipe::from_yaml(&merged).unwrap();
+        assert_eq!(linted.x_exec.unwrap().shell, exec.shell);
+        assert_eq!(linted.license, parsed.license);
+    }
+
+    #[test]
+    fn test_from_file_rejects_missing_and_nested_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let recipe = dir.path().join("tool.yaml");
+
+        std::fs::write(&recipe, "_include: missing.yaml\npkg: tool\n").unwrap();
+        assert!(SBuildRecipe::from_file(&recipe).is_err());
+
+        std::fs::write(dir.path().join("a.yaml"), "_include: tool.yaml\n").unwrap();
+        std::fs::write(&recipe, "_include: a.yaml\npkg: tool\n").unwrap();
+        assert!(SBuildRecipe::from_file(&recipe).is_err());
+    }
 }
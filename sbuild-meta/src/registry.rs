@@ -5,10 +5,13 @@
 
 use std::cmp::Ordering;
 
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 use serde::Deserialize;
 
-use crate::{Error, Result};
+use crate::{
+    manifest::{Manifest, OciManifest},
+    Error, Result,
+};
 
 const GHCR_API_BASE: &str = "https://ghcr.io/v2";
 
@@ -44,11 +47,9 @@ impl RegistryClient {
         headers.insert(
             ACCEPT,
             HeaderValue::from_static(
-                "application/vnd.docker.distribution.manifest.v2+json, \
-                 application/vnd.docker.distribution.manifest.list.v2+json, \
-                 application/vnd.oci.image.manifest.v1+json, \
+                "application/vnd.oci.image.manifest.v1+json, \
                  application/vnd.oci.image.index.v1+json, \
-                 application/vnd.oci.artifact.manifest.v1+json",
+                 application/vnd.docker.distribution.manifest.v2+json",
             ),
         );
         headers
@@ -96,8 +97,13 @@ impl RegistryClient {
             .max_by(|a, b| version_compare_tags(a, b, arch))
     }
 
-    /// Fetch manifest for a specific tag
-    pub async fn fetch_manifest(&self, repository: &str, tag: &str) -> Result<String> {
+    /// Fetch the manifest for a tag or digest, with the response's
+    /// `Content-Type`
+    async fn fetch_manifest_raw(
+        &self,
+        repository: &str,
+        tag: &str,
+    ) -> Result<(Option<String>, String)> {
         let url = format!("{}/{}/manifests/{}", GHCR_API_BASE, repository, tag);
 
         let response = self
@@ -120,7 +126,92 @@ impl RegistryClient {
             )));
         }
 
-        response.text().await.map_err(Error::Http)
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = response.text().await.map_err(Error::Http)?;
+        Ok((content_type, body))
+    }
+
+    /// Fetch the manifest for a tag, parsed by the media type returned
+    pub async fn fetch_manifest(&self, repository: &str, tag: &str) -> Result<Manifest> {
+        let (content_type, body) = self.fetch_manifest_raw(repository, tag).await?;
+        Manifest::parse(content_type.as_deref(), &body)
+    }
+
+    /// Fetch the image manifest for a tag, following an index to its image
+    /// manifest for `host`
+    pub async fn fetch_image_manifest(
+        &self,
+        repository: &str,
+        tag: &str,
+        host: &str,
+    ) -> Result<OciManifest> {
+        match self.fetch_manifest(repository, tag).await? {
+            Manifest::Image(manifest) => Ok(manifest),
+            Manifest::Index(index) => {
+                let entry = index.image_entry(host).ok_or_else(|| {
+                    Error::ManifestNotFound(format!(
+                        "{}:{} is an index with no image manifest for {}",
+                        repository, tag, host
+                    ))
+                })?;
+                match self.fetch_manifest(repository, &entry.digest).await? {
+                    Manifest::Image(manifest) => Ok(manifest),
+                    Manifest::Index(_) => Err(Error::Registry(format!(
+                        "{}@{} is a nested index",
+                        repository, entry.digest
+                    ))),
+                }
+            }
+        }
+    }
+
+    /// Download a blob by digest (e.g. "sha256:...")
+    pub async fn fetch_blob(&self, repository: &str, digest: &str) -> Result<Vec<u8>> {
+        let url = format!("{}/{}/blobs/{}", GHCR_API_BASE, repository, digest);
+
+        let response = self
+            .client
+            .get(&url)
+            .headers(Self::build_headers())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Registry(format!(
+                "Failed to fetch blob {} from {}: {}",
+                digest,
+                repository,
+                response.status()
+            )));
+        }
+
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Check that `url` resolves to a successful response.
+    ///
+    /// Tries HEAD first and falls back to a one-byte ranged GET for servers
+    /// that don't implement HEAD.
+    pub async fn check_url(&self, url: &str) -> Result<()> {
+        let mut response = self.client.head(url).send().await?;
+
+        if matches!(response.status().as_u16(), 405 | 501) {
+            response = self
+                .client
+                .get(url)
+                .header(reqwest::header::RANGE, "bytes=0-0")
+                .send()
+                .await?;
+        }
+
+        if !response.status().is_success() {
+            return Err(Error::Registry(format!("{}: {}", url, response.status())));
+        }
+        Ok(())
     }
 
     /// Fetch manifest as parsed JSON
@@ -129,8 +220,8 @@ impl RegistryClient {
         repository: &str,
         tag: &str,
     ) -> Result<serde_json::Value> {
-        let manifest_str = self.fetch_manifest(repository, tag).await?;
-        serde_json::from_str(&manifest_str).map_err(Error::Json)
+        let (_, body) = self.fetch_manifest_raw(repository, tag).await?;
+        serde_json::from_str(&body).map_err(Error::Json)
     }
 
     /// Check if a package exists in the registry
@@ -143,10 +234,7 @@ impl RegistryClient {
 
     /// Get download URL for a package
     pub fn get_download_url(repository: &str, tag: &str, filename: &str) -> String {
-        format!(
-            "https://api.ghcr.pkgforge.dev/{}?tag={}&download={}",
-            repository, tag, filename
-        )
+        crate::manifest::download_url(repository, tag, filename)
     }
 
     /// Get GHCR web URL for a package
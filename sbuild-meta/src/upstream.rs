@@ -0,0 +1,159 @@
+//! Upstream version lookups for `x_update` sources
+//!
+//! Recipes can pin where `check-updates` finds the latest upstream version:
+//! a GitHub repository's latest release, a Repology project, or the recipe's
+//! own `x_exec.pkgver` script (the default).
+
+use std::{env, time::Duration};
+
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
+use serde::Deserialize;
+
+use crate::{Error, Result, VERSION};
+
+const GITHUB_API_BASE: &str = "https://api.github.com";
+const REPOLOGY_API_BASE: &str = "https://repology.org/api/v1";
+
+/// Where to look up a recipe's latest upstream version
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum UpdateSource {
+    /// Tag of the latest release of `owner/name` on GitHub
+    GithubRelease { repo: String },
+    /// Newest version Repology knows for a project
+    Repology { project: String },
+    /// Output of the recipe's `x_exec.pkgver` script
+    #[default]
+    Script,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepologyPackage {
+    status: String,
+    version: String,
+}
+
+/// Release tag without a `v` prefix, if it's followed by a digit
+pub fn strip_tag_prefix(tag: &str) -> &str {
+    let tag = tag.trim();
+    match tag.strip_prefix(['v', 'V']) {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => rest,
+        _ => tag,
+    }
+}
+
+/// Version of the first repository that has the project's newest release
+fn newest_repology_version(packages: &[RepologyPackage]) -> Option<&str> {
+    packages
+        .iter()
+        .find(|p| p.status == "newest")
+        .map(|p| p.version.as_str())
+}
+
+/// Client for GitHub and Repology version lookups
+#[derive(Clone)]
+pub struct UpstreamClient {
+    client: reqwest::Client,
+}
+
+impl UpstreamClient {
+    /// Create a client. GitHub requests use `$GITHUB_TOKEN` (or `$GH_TOKEN`)
+    /// when set, for the higher rate limit.
+    pub fn new(timeout: Duration) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        let token = env::var("GITHUB_TOKEN").or_else(|_| env::var("GH_TOKEN"));
+        if let Ok(token) = token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|e| Error::Other(format!("Invalid GitHub token: {}", e)))?;
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .user_agent(format!("sbuild-meta/{}", VERSION))
+                .timeout(timeout)
+                .default_headers(headers)
+                .build()?,
+        })
+    }
+
+    /// Latest version published by a remote source. Script sources run
+    /// locally, so they aren't handled here.
+    pub async fn latest_version(&self, source: &UpdateSource) -> Result<String> {
+        match source {
+            UpdateSource::GithubRelease { repo } => self.github_release(repo).await,
+            UpdateSource::Repology { project } => self.repology(project).await,
+            UpdateSource::Script => Err(Error::Other(
+                "script sources are run with the recipe's shell".to_string(),
+            )),
+        }
+    }
+
+    async fn github_release(&self, repo: &str) -> Result<String> {
+        let url = format!("{}/repos/{}/releases/latest", GITHUB_API_BASE, repo);
+        let response = self
+            .client
+            .get(&url)
+            .header(ACCEPT, "application/vnd.github+json")
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::Upstream(format!(
+                "no latest release for {}: {}",
+                repo,
+                response.status()
+            )));
+        }
+        let release: GithubRelease = response.json().await?;
+        Ok(strip_tag_prefix(&release.tag_name).to_string())
+    }
+
+    async fn repology(&self, project: &str) -> Result<String> {
+        let url = format!("{}/project/{}", REPOLOGY_API_BASE, project);
+        let response = self.client.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::Upstream(format!(
+                "Repology lookup for {} failed: {}",
+                project,
+                response.status()
+            )));
+        }
+        let packages: Vec<RepologyPackage> = response.json().await?;
+        newest_repology_version(&packages)
+            .map(str::to_string)
+            .ok_or_else(|| {
+                Error::Upstream(format!("Repology has no newest version of {}", project))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_tag_prefix() {
+        assert_eq!(strip_tag_prefix("v1.2.3"), "1.2.3");
+        assert_eq!(strip_tag_prefix("V2.0"), "2.0");
+        assert_eq!(strip_tag_prefix("1.2.3"), "1.2.3");
+        assert_eq!(strip_tag_prefix("vendor-1.0"), "vendor-1.0");
+        assert_eq!(strip_tag_prefix(" v0.1 "), "0.1");
+    }
+
+    #[test]
+    fn test_newest_repology_version() {
+        let packages: Vec<RepologyPackage> = serde_json::from_str(
+            r#"[
+                {"repo": "debian_12", "status": "outdated", "version": "1.0"},
+                {"repo": "arch", "status": "newest", "version": "1.4"},
+                {"repo": "aur", "status": "devel", "version": "1.5rc1"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(newest_repology_version(&packages), Some("1.4"));
+        assert_eq!(newest_repology_version(&packages[..1]), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! Shared cache for `build_asset` downloads
+//!
+//! Entries are keyed by the BLAKE3 hash of the asset URL and stored next to a
+//! `.b3` file holding the content hash recorded when the entry was written.
+//! An entry is only reused if the content still matches that hash and it is
+//! younger than the cache's max age, so a URL whose content changes upstream
+//! (e.g. a `latest` release) is fetched again once the entry expires.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::checksum::b3sum;
+
+pub struct AssetCache {
+    dir: PathBuf,
+    max_age: Duration,
+}
+
+impl AssetCache {
+    pub fn new<P: Into<PathBuf>>(dir: P, max_age: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            max_age,
+        }
+    }
+
+    fn entry_path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join(blake3::hash(url.as_bytes()).to_hex().as_str())
+    }
+
+    fn is_expired(&self, stamp: &Path) -> bool {
+        fs::metadata(stamp)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_none_or(|age| age >= self.max_age)
+    }
+
+    /// Copy the cached copy of `url` to `out`. Returns false on a miss, when
+    /// the entry expired, or when it no longer matches its recorded hash.
+    ///
+    /// The entry is always copied, never linked, so the build can modify its
+    /// asset without touching the cache.
+    pub fn restore<P: AsRef<Path>>(&self, url: &str, out: P) -> bool {
+        let entry = self.entry_path(url);
+        let stamp = entry.with_extension("b3");
+        if self.is_expired(&stamp) {
+            let _ = fs::remove_file(&entry);
+            let _ = fs::remove_file(&stamp);
+            return false;
+        }
+        let Ok(expected) = fs::read_to_string(&stamp) else {
+            return false;
+        };
+        if b3sum(&entry).ok().as_deref() != Some(expected.trim()) {
+            let _ = fs::remove_file(&entry);
+            return false;
+        }
+
+        let out = out.as_ref();
+        if let Some(parent) = out.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let _ = fs::remove_file(out);
+        fs::copy(&entry, out).is_ok()
+    }
+
+    /// Add the downloaded `path` to the cache under `url`.
+    pub fn store<P: AsRef<Path>>(&self, url: &str, path: P) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = self.entry_path(url);
+        let hash = b3sum(path.as_ref())?;
+
+        // Write under a temporary name first so concurrent builds never see
+        // a partial entry.
+        let tmp = entry.with_extension("part");
+        let _ = fs::remove_file(&tmp);
+        fs::copy(path.as_ref(), &tmp)?;
+        fs::rename(&tmp, &entry)?;
+        fs::write(entry.with_extension("b3"), hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_stored_asset_and_rejects_tampered_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AssetCache::new(dir.path().join("cache"), Duration::from_secs(3600));
+        let url = "https://example.com/asset.tar.gz";
+
+        let src = dir.path().join("asset");
+        fs::write(&src, b"asset contents").unwrap();
+        assert!(!cache.restore(url, dir.path().join("miss")));
+
+        cache.store(url, &src).unwrap();
+        let out = dir.path().join("out/asset");
+        assert!(cache.restore(url, &out));
+        assert_eq!(fs::read(&out).unwrap(), b"asset contents");
+
+        fs::remove_file(&out).unwrap();
+        fs::remove_file(&src).unwrap();
+        fs::write(cache.entry_path(url), b"tampered").unwrap();
+        assert!(!cache.restore(url, &out));
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn restore_copies_instead_of_linking() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AssetCache::new(dir.path().join("cache"), Duration::from_secs(3600));
+        let url = "https://example.com/asset.tar.gz";
+
+        let src = dir.path().join("asset");
+        fs::write(&src, b"asset contents").unwrap();
+        cache.store(url, &src).unwrap();
+        fs::write(&src, b"patched by the build").unwrap();
+
+        let out = dir.path().join("out");
+        assert!(cache.restore(url, &out));
+        fs::write(&out, b"patched again").unwrap();
+        assert!(cache.restore(url, &out));
+        assert_eq!(fs::read(&out).unwrap(), b"asset contents");
+    }
+
+    #[test]
+    fn expired_entry_is_a_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AssetCache::new(dir.path().join("cache"), Duration::ZERO);
+        let url = "https://example.com/latest.tar.gz";
+
+        let src = dir.path().join("asset");
+        fs::write(&src, b"asset contents").unwrap();
+        cache.store(url, &src).unwrap();
+
+        assert!(!cache.restore(url, dir.path().join("out")));
+        assert!(!cache.entry_path(url).exists());
+    }
+}
@@ -5,32 +5,237 @@ use std::{
         consts::{ARCH, OS},
     },
     fs,
-    io::{BufRead, BufReader},
-    os::unix::fs::{symlink, PermissionsExt},
+    io::{self, BufRead, BufReader},
+    os::unix::{
+        fs::{symlink, PermissionsExt},
+        process::CommandExt,
+    },
     path::{Path, PathBuf},
     process::{Child, Command, Stdio},
-    sync::{self, Arc},
+    sync::{
+        self,
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use sbuild_linter::{build_config::BuildConfig, logger::TaskLogger, BuildAsset, Linter};
+use futures::{future::BoxFuture, stream, StreamExt};
+use log::trace;
+use sbuild_linter::{
+    build_config::BuildConfig,
+    envvars::{masked_env_value, BUILD_ENV_VARS, INHERITED_ENV_VARS},
+    logger::TaskLogger,
+    validator::provide_base,
+    BuildAsset, LintOptions, Linter,
+};
 use squishy::appimage::{get_offset, AppImage, AppImageEntryKind, FilesystemType};
+use thiserror::Error;
 
 use crate::{
+    asset_cache::AssetCache,
     cleanup::Finalize,
     constant::{
         APPIMAGE_MAGIC_BYTES, ELF_MAGIC_BYTES, FLATIMAGE_MAGIC_BYTES, PNG_MAGIC_BYTES,
         SVG_MAGIC_BYTES, XML_MAGIC_BYTES,
     },
+    ghcr::GhcrError,
+    interrupt,
     onelf::OnelfPackage,
+    signing::SignError,
     types::{OutputStream, PackageType, SoarEnv},
     utils::{
-        calc_magic_bytes, download, expand_env_vars, is_onelf, is_static_elf, pack_appimage,
-        self_extract_appimage, temp_file,
+        available_space, calc_magic_bytes, dir_size, download, expand_env_vars,
+        find_squashfs_offset, flatimage_extract_desktop, flatimage_extract_icon, is_archive,
+        is_onelf, is_static_elf, pack_appimage, self_extract_appimage, temp_file,
+        unsquashfs_extract, unsquashfs_list,
     },
 };
 
+/// Build assets downloaded at the same time
+const MAX_PARALLEL_ASSET_DOWNLOADS: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum BuildError {
+    #[error("lint failed for {0}")]
+    LintFailed(String),
+
+    #[error("{0} is disabled")]
+    Disabled(String),
+
+    #[error("unsupported {0}")]
+    Unsupported(String),
+
+    #[error("failed to download build asset from {url}: {reason}")]
+    AssetDownload { url: String, reason: String },
+
+    #[error("{name} not found - install {name} to continue")]
+    MissingTool { name: String },
+
+    #[error("build script failed: {0}")]
+    ScriptFailed(String),
+
+    #[error("build timed out after {}s", .0.as_secs())]
+    Timeout(Duration),
+
+    #[error("build output is {size} bytes, over the {limit} byte limit")]
+    OutputTooLarge { size: u64, limit: u64 },
+
+    #[error("invalid provides: {0}")]
+    Provides(String),
+
+    #[error("push failed: {0}")]
+    Push(String),
+
+    #[error("signing failed: {0}")]
+    Sign(String),
+
+    #[error("output directory {} is not empty", .0.display())]
+    OutdirNotEmpty(PathBuf),
+
+    #[error(
+        "output directory {} was already used by {other} in this run",
+        .outdir.display()
+    )]
+    OutdirCollision { outdir: PathBuf, other: String },
+
+    #[error("skipped: {0}")]
+    Skipped(String),
+
+    #[error("resource {} is outside the recipe directory", .0.display())]
+    ResourceOutsideRecipe(PathBuf),
+
+    #[error("no desktop integration: {0}")]
+    NoDesktopIntegration(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl BuildError {
+    /// Wrap an IO error with the operation that failed, keeping its kind.
+    pub fn io(err: io::Error, context: impl std::fmt::Display) -> Self {
+        BuildError::Io(io::Error::new(err.kind(), format!("{}: {}", context, err)))
+    }
+
+    /// Process exit code the CLI uses for this failure
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BuildError::LintFailed(_) => 2,
+            BuildError::AssetDownload { .. } => 3,
+            BuildError::MissingTool { .. } => 4,
+            BuildError::Push(_) => 5,
+            BuildError::Sign(_) => 6,
+            BuildError::Timeout(_) => 7,
+            BuildError::OutputTooLarge { .. } => 8,
+            _ => 1,
+        }
+    }
+}
+
+impl From<GhcrError> for BuildError {
+    fn from(err: GhcrError) -> Self {
+        match err {
+            GhcrError::OrasNotFound => BuildError::MissingTool {
+                name: "oras".to_string(),
+            },
+            err => BuildError::Push(err.to_string()),
+        }
+    }
+}
+
+impl From<SignError> for BuildError {
+    fn from(err: SignError) -> Self {
+        match err {
+            SignError::MinisignNotFound => BuildError::MissingTool {
+                name: "minisign".to_string(),
+            },
+            err => BuildError::Sign(err.to_string()),
+        }
+    }
+}
+
+/// Program and arguments of `cmd` for logging. The environment is left out
+/// and `KEY=VALUE` arguments (docker's `-e`) have credentials masked.
+fn describe_command(cmd: &Command) -> String {
+    let args = cmd.get_args().map(|arg| {
+        let arg = arg.to_string_lossy();
+        match arg.split_once('=') {
+            Some((key, value)) => format!("{}={}", key, masked_env_value(key, value)),
+            None => arg.into_owned(),
+        }
+    });
+    std::iter::once(cmd.get_program().to_string_lossy().into_owned())
+        .chain(args)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Spawn `cmd`, reporting a missing executable as [`BuildError::MissingTool`].
+fn spawn_tool(cmd: &mut Command, name: &str) -> Result<Child, BuildError> {
+    cmd.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => BuildError::MissingTool {
+            name: name.to_string(),
+        },
+        _ => BuildError::Io(e),
+    })
+}
+
+/// Locate `unshare` and make sure it can actually create a network namespace
+/// here, which needs unprivileged user namespaces when not running as root.
+fn network_sandbox() -> Result<PathBuf, BuildError> {
+    let unshare = which::which("unshare").map_err(|_| BuildError::MissingTool {
+        name: "unshare".to_string(),
+    })?;
+    let probe = Command::new(&unshare)
+        .args(["--net", "--map-root-user", "true"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !probe.success() {
+        return Err(BuildError::Unsupported(
+            "--no-network: unshare can't create a network namespace (are user namespaces disabled?)"
+                .to_string(),
+        ));
+    }
+    Ok(unshare)
+}
+
+/// Where a build's artifacts go under an explicitly given output directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputLayout {
+    /// `<outdir>/<pkg_id>`
+    #[default]
+    PkgId,
+    /// `<outdir>/<pkg>`
+    Pkg,
+    /// Directly in `<outdir>`, which must be empty
+    Flat,
+}
+
+/// Output directories used so far in this run, keyed to the pkg_id that
+/// produced them. Shared by every [`Builder`] so two recipes that resolve to
+/// the same directory (e.g. the same `pkg` under [`OutputLayout::Pkg`]) fail
+/// instead of one wiping the other's artifacts.
+#[derive(Debug, Clone, Default)]
+pub struct OutdirClaims(Arc<Mutex<HashMap<PathBuf, String>>>);
+
+impl OutdirClaims {
+    /// Record `outdir` for `pkg_id`, or return the pkg_id that already used it
+    fn claim(&self, outdir: &Path, pkg_id: &str) -> Result<(), String> {
+        let mut claims = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match claims.get(outdir) {
+            Some(other) if other != pkg_id => Err(other.clone()),
+            Some(_) => Ok(()),
+            None => {
+                claims.insert(outdir.to_path_buf(), pkg_id.to_string());
+                Ok(())
+            }
+        }
+    }
+}
+
 pub struct BuildContext {
     pkg: String,
     pkg_id: String,
@@ -41,6 +246,8 @@ pub struct BuildContext {
     remote_pkgver: String,
     pkgver: String,
     recipe_dir: Option<PathBuf>,
+    /// Whether `outdir` is this build's own directory, safe to wipe
+    owns_outdir: bool,
 }
 
 impl BuildContext {
@@ -50,6 +257,7 @@ impl BuildContext {
         remote_pkgver: String,
         pkgver: String,
         outdir: Option<String>,
+        layout: OutputLayout,
         recipe_dir: Option<PathBuf>,
     ) -> Self {
         let sbuild_pkg = build_config
@@ -58,15 +266,20 @@ impl BuildContext {
             .map(|t| format!("{}.{}", build_config.pkg, t))
             .unwrap_or(build_config.pkg.clone());
 
+        let owns_outdir = outdir.is_none() || layout != OutputLayout::Flat;
         let outdir = outdir
             .map(|dir| {
                 let path = Path::new(&dir);
-                if path.is_absolute() {
-                    // Always add pkg_id subdirectory
-                    path.join(&build_config.pkg_id)
+                let path = if path.is_absolute() {
+                    path.to_path_buf()
                 } else {
                     let current_dir = env::current_dir().expect("Failed to get current directory");
-                    current_dir.join(dir).join(&build_config.pkg_id)
+                    current_dir.join(dir)
+                };
+                match layout {
+                    OutputLayout::PkgId => path.join(&build_config.pkg_id),
+                    OutputLayout::Pkg => path.join(&build_config.pkg),
+                    OutputLayout::Flat => path,
                 }
             })
             .unwrap_or_else(|| {
@@ -87,36 +300,20 @@ impl BuildContext {
             remote_pkgver,
             pkgver,
             recipe_dir,
+            owns_outdir,
         }
     }
 
     fn env_vars(&self, soar_bin: &str) -> Vec<(String, String)> {
         let paths = env::var("PATH").unwrap_or_default();
 
-        let inherit_keys = [
-            "DEBIAN_FRONTEND",
-            "EGET_TIMEOUT",
-            "GIT_ASKPASS",
-            "GIT_TERMINAL_PROMPT",
-            "GITHUB_TOKEN",
-            "GH_TOKEN",
-            "GITLAB_TOKEN",
-            "GL_TOKEN",
-            "HF_TOKEN",
-            "HOST_TRIPLET",
-            "NIXPKGS_ALLOW_BROKEN",
-            "NIXPKGS_ALLOW_UNFREE",
-            "NIXPKGS_ALLOW_UNSUPPORTED_SYSTEM",
-            "SYSTMP",
-            "TERM",
-            "USER_AGENT",
-        ];
-
         let get_env_var =
             |key: &str| -> (String, Option<String>) { (key.to_string(), env::var(key).ok()) };
 
-        let existing_envs: Vec<(String, Option<String>)> =
-            inherit_keys.iter().map(|key| get_env_var(key)).collect();
+        let existing_envs: Vec<(String, Option<String>)> = INHERITED_ENV_VARS
+            .iter()
+            .map(|key| get_env_var(key))
+            .collect();
 
         let arch = ARCH.to_string();
         let arch_alt = match ARCH {
@@ -127,7 +324,8 @@ impl BuildContext {
         .to_string();
 
         let paths = format!("{}:{}", soar_bin, paths);
-        let mut vars: Vec<(String, String)> = [
+        // Every name in BUILD_ENV_VARS, which the linter checks scripts against
+        let build_vars: [(&str, String); BUILD_ENV_VARS.len()] = [
             ("pkg", self.pkg.clone()),
             ("pkg_id", self.pkg_id.clone()),
             ("pkg_type", self.pkg_type.clone().unwrap_or_default()),
@@ -140,20 +338,24 @@ impl BuildContext {
             ("remote_pkgver", self.remote_pkgver.clone()),
             ("arch", arch),
             ("arch_alt", arch_alt),
-        ]
-        .into_iter()
-        .flat_map(|(key, value)| {
-            let value = match key {
-                "sbuild_outdir" | "sbuild_tmpdir" => value,
-                _ => value.replace(|c: char| c.is_whitespace(), ""),
-            };
-            vec![
-                (key.to_string(), value.clone()),
-                (key.to_uppercase(), value),
-            ]
-        })
-        .chain(std::iter::once(("PATH".to_string(), paths)))
-        .collect();
+        ];
+        debug_assert!(build_vars
+            .iter()
+            .all(|(key, _)| BUILD_ENV_VARS.contains(key)));
+        let mut vars: Vec<(String, String)> = build_vars
+            .into_iter()
+            .flat_map(|(key, value)| {
+                let value = match key {
+                    "sbuild_outdir" | "sbuild_tmpdir" => value,
+                    _ => value.replace(|c: char| c.is_whitespace(), ""),
+                };
+                vec![
+                    (key.to_string(), value.clone()),
+                    (key.to_uppercase(), value),
+                ]
+            })
+            .chain(std::iter::once(("PATH".to_string(), paths)))
+            .collect();
 
         vars.extend(
             existing_envs
@@ -164,10 +366,19 @@ impl BuildContext {
     }
 }
 
+/// Decides, after linting, whether a build can be skipped. Called with the
+/// validated recipe, its pkgver and its remote pkgver (when it differs);
+/// `Some(reason)` skips the build with [`BuildError::Skipped`].
+pub type SkipCheck = Arc<
+    dyn Fn(PathBuf, String, Option<String>) -> BoxFuture<'static, Option<String>> + Send + Sync,
+>;
+
 pub struct Builder {
     logger: TaskLogger,
     soar_env: SoarEnv,
     external: bool,
+    /// Desktop entries, icons and appstream files already in the outdir,
+    /// keyed by the name they were written under (`<name>.desktop`, ...)
     desktop: HashMap<String, bool>,
     icon: HashMap<String, bool>,
     appstream: HashMap<String, bool>,
@@ -175,6 +386,17 @@ pub struct Builder {
     log_level: u8,
     keep: bool,
     timeout: Duration,
+    validate_appstream: bool,
+    no_network: bool,
+    max_output_size: Option<u64>,
+    min_free_space: u64,
+    asset_cache: Option<AssetCache>,
+    build_config: Option<BuildConfig>,
+    output_layout: OutputLayout,
+    outdir_claims: OutdirClaims,
+    skip_check: Option<SkipCheck>,
+    recipe_root: Option<PathBuf>,
+    require_desktop_integration: bool,
 }
 
 impl Builder {
@@ -197,55 +419,171 @@ impl Builder {
             log_level,
             keep,
             timeout,
+            validate_appstream: false,
+            no_network: false,
+            max_output_size: None,
+            min_free_space: 0,
+            asset_cache: None,
+            build_config: None,
+            output_layout: OutputLayout::default(),
+            outdir_claims: OutdirClaims::default(),
+            skip_check: None,
+            recipe_root: None,
+            require_desktop_integration: false,
         }
     }
 
+    /// The validated recipe of the last build, if it passed linting
+    pub fn build_config(&self) -> Option<&BuildConfig> {
+        self.build_config.as_ref()
+    }
+
+    /// Package type detected while handling provides
+    pub fn pkg_type(&self) -> &PackageType {
+        &self.pkg_type
+    }
+
+    /// Run `appstreamcli validate` on appstream files extracted from AppImages.
+    pub fn with_appstream_validation(mut self, enabled: bool) -> Self {
+        self.validate_appstream = enabled;
+        self
+    }
+
+    /// Run `x_exec.run` without network access. Build assets are still
+    /// downloaded beforehand.
+    pub fn with_network_isolation(mut self, enabled: bool) -> Self {
+        self.no_network = enabled;
+        self
+    }
+
+    /// Fail the build when the finalized output directory is larger than `limit` bytes.
+    pub fn with_max_output_size(mut self, limit: Option<u64>) -> Self {
+        self.max_output_size = limit;
+        self
+    }
+
+    /// Warn before building when the output filesystem has less than `bytes` free.
+    pub fn with_min_free_space(mut self, bytes: u64) -> Self {
+        self.min_free_space = bytes;
+        self
+    }
+
+    /// Reuse `build_asset` downloads from `dir` across builds, refetching
+    /// entries older than `max_age`.
+    pub fn with_asset_cache(mut self, dir: Option<PathBuf>, max_age: Duration) -> Self {
+        self.asset_cache = dir.map(|dir| AssetCache::new(dir, max_age));
+        self
+    }
+
+    /// Where artifacts go under the `outdir` passed to [`Builder::build`]
+    pub fn with_output_layout(mut self, layout: OutputLayout) -> Self {
+        self.output_layout = layout;
+        self
+    }
+
+    /// Share output directory claims with the other builds of this run
+    pub fn with_outdir_claims(mut self, claims: OutdirClaims) -> Self {
+        self.outdir_claims = claims;
+        self
+    }
+
+    /// Resolve `files/` and `file`/`dir` resources against `root` instead of
+    /// the recipe's own directory, e.g. for fetched recipes.
+    pub fn with_recipe_root(mut self, root: Option<PathBuf>) -> Self {
+        // Builds run from the outdir, so a relative root must be pinned now
+        self.recipe_root = root.map(|root| root.canonicalize().unwrap_or(root));
+        self
+    }
+
+    /// Fail GUI package builds (AppImage and the like) that end up with
+    /// neither a desktop entry nor an icon.
+    pub fn with_desktop_integration_required(mut self, required: bool) -> Self {
+        self.require_desktop_integration = required;
+        self
+    }
+
+    /// Skip builds `check` says are unnecessary, e.g. already published ones
+    pub fn with_skip_check(mut self, check: Option<SkipCheck>) -> Self {
+        self.skip_check = check;
+        self
+    }
+
     pub async fn download_build_assets(
         &mut self,
         build_assets: &[BuildAsset],
         context: &BuildContext,
-    ) {
+    ) -> Result<(), BuildError> {
         let env_vars = context.env_vars(&self.soar_env.bin_path);
-        for asset in build_assets {
+        let (logger, asset_cache) = (&self.logger, &self.asset_cache);
+
+        let fetch = |asset: &BuildAsset| {
             let url = expand_env_vars(&asset.url, &env_vars);
-            let out = expand_env_vars(&asset.out, &env_vars);
+            let out_path = expand_env_vars(&asset.out, &env_vars);
+            async move {
+                let cached = asset_cache
+                    .as_ref()
+                    .is_some_and(|cache| cache.restore(&url, &out_path));
 
-            self.logger
-                .info(format!("Downloading build asset from {}", url));
+                if cached {
+                    logger.info(format!("Using cached build asset for {}", url));
+                } else {
+                    logger.info(format!("Downloading build asset from {}", url));
 
-            let out_path = out.to_string();
-            if download(&url, &out_path).await.is_err() {
-                self.logger
-                    .error(format!("Failed to download build asset from {}", url));
-                std::process::exit(1);
-            };
+                    if let Err(reason) = download(&url, &out_path).await {
+                        logger.error(format!(
+                            "Failed to download build asset from {}: {}",
+                            url, reason
+                        ));
+                        return Err(BuildError::AssetDownload { url, reason });
+                    };
+
+                    if let Some(cache) = asset_cache {
+                        if let Err(e) = cache.store(&url, &out_path) {
+                            logger.warn(format!("Failed to cache build asset {}: {}", url, e));
+                        }
+                    }
+                }
 
-            let magic = calc_magic_bytes(&out_path, 4);
-            if magic == ELF_MAGIC_BYTES {
-                let perms = std::fs::Permissions::from_mode(0o755);
-                std::fs::set_permissions(&out_path, perms).unwrap();
+                let magic = calc_magic_bytes(&out_path, 4);
+                if magic == ELF_MAGIC_BYTES {
+                    let perms = std::fs::Permissions::from_mode(0o755);
+                    std::fs::set_permissions(&out_path, perms)?;
+                }
+                Ok(())
             }
-        }
+        };
+
+        // Let every download finish so each failure is logged, then report
+        // the first one
+        let results: Vec<Result<(), BuildError>> = stream::iter(build_assets)
+            .map(fetch)
+            .buffer_unordered(MAX_PARALLEL_ASSET_DOWNLOADS)
+            .collect()
+            .await;
+        results.into_iter().collect()
     }
 
-    fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<(), String> {
-        fs::create_dir_all(dst)
-            .map_err(|e| format!("Failed to create directory {}: {}", dst.display(), e))?;
+    fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<(), BuildError> {
+        fs::create_dir_all(dst).map_err(|e| {
+            BuildError::io(e, format!("Failed to create directory {}", dst.display()))
+        })?;
         for entry in fs::read_dir(src)
-            .map_err(|e| format!("Failed to read directory {}: {}", src.display(), e))?
+            .map_err(|e| BuildError::io(e, format!("Failed to read directory {}", src.display())))?
         {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+            let entry = entry.map_err(|e| BuildError::io(e, "Failed to read entry"))?;
             let src_path = entry.path();
             let dst_path = dst.join(entry.file_name());
             if src_path.is_dir() {
                 Self::copy_dir_recursive(&src_path, &dst_path)?;
             } else {
                 fs::copy(&src_path, &dst_path).map_err(|e| {
-                    format!(
-                        "Failed to copy {} to {}: {}",
-                        src_path.display(),
-                        dst_path.display(),
-                        e
+                    BuildError::io(
+                        e,
+                        format!(
+                            "Failed to copy {} to {}",
+                            src_path.display(),
+                            dst_path.display()
+                        ),
                     )
                 })?;
             }
@@ -253,42 +591,73 @@ impl Builder {
         Ok(())
     }
 
+    /// Resolve a resource path relative to `recipe_dir`, refusing anything
+    /// that leads outside it (`..`, absolute paths, symlinks).
+    fn resource_path(recipe_dir: &Path, path: &str) -> Result<PathBuf, BuildError> {
+        let root = recipe_dir.canonicalize().map_err(|e| {
+            BuildError::io(e, format!("Failed to resolve {}", recipe_dir.display()))
+        })?;
+        let joined = recipe_dir.join(path);
+        let resolved = joined
+            .canonicalize()
+            .map_err(|e| BuildError::io(e, format!("Failed to resolve {}", joined.display())))?;
+        if !resolved.starts_with(&root) {
+            return Err(BuildError::ResourceOutsideRecipe(joined));
+        }
+        Ok(resolved)
+    }
+
+    /// Copy the recipe's `files/` directory and its `icon`/`desktop` `file`
+    /// and `dir` resources into the outdir. Resource paths are relative to
+    /// the recipe root and can't leave it.
     fn prepare_resources(
         &mut self,
-        _build_config: &BuildConfig,
+        build_config: &BuildConfig,
         context: &BuildContext,
-    ) -> Result<(), String> {
+    ) -> Result<(), BuildError> {
         let recipe_dir = match &context.recipe_dir {
             Some(dir) => dir,
             None => return Ok(()),
         };
 
         let files_dir = recipe_dir.join("files");
-        if !files_dir.is_dir() {
-            return Ok(());
+        if files_dir.is_dir() {
+            self.logger
+                .info(format!("Copying recipe files from {}", files_dir.display()));
+            Self::copy_dir_recursive(&files_dir, &context.outdir)?;
         }
 
-        self.logger
-            .info(format!("Copying recipe files from {}", files_dir.display()));
-
-        for entry in fs::read_dir(&files_dir)
-            .map_err(|e| format!("Failed to read files directory: {}", e))?
-        {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-            let src_path = entry.path();
-            let dst_path = context.outdir.join(entry.file_name());
-
-            if src_path.is_dir() {
-                Self::copy_dir_recursive(&src_path, &dst_path)?;
-            } else {
-                fs::copy(&src_path, &dst_path).map_err(|e| {
-                    format!(
-                        "Failed to copy {} to {}: {}",
-                        src_path.display(),
-                        dst_path.display(),
-                        e
-                    )
+        let pkg = &build_config.pkg;
+        for (field, resource) in [
+            ("icon", &build_config.icon),
+            ("desktop", &build_config.desktop),
+        ] {
+            let Some(resource) = resource else {
+                continue;
+            };
+            if let Some(ref dir) = resource.dir {
+                let src_dir = Self::resource_path(recipe_dir, dir)?;
+                self.logger
+                    .info(format!("Copying {} directory {}", field, src_dir.display()));
+                Self::copy_dir_recursive(&src_dir, &context.outdir)?;
+            }
+            if let Some(ref file) = resource.file {
+                let src_path = Self::resource_path(recipe_dir, file)?;
+                let dest = match field {
+                    "icon" => format!("{}.DirIcon", pkg),
+                    _ => format!("{}.desktop", pkg),
+                };
+                fs::copy(&src_path, context.outdir.join(&dest)).map_err(|e| {
+                    BuildError::io(e, format!("Failed to copy {}", src_path.display()))
                 })?;
+                self.logger
+                    .info(format!("Copied {} to {}", src_path.display(), dest));
+                // Recipe-provided files take precedence over extracted ones
+                if field == "icon" {
+                    self.rename_icon(&dest, context, pkg);
+                } else {
+                    self.desktop.insert(pkg.clone(), true);
+                }
             }
         }
 
@@ -350,42 +719,67 @@ impl Builder {
         context: &BuildContext,
         build_config: BuildConfig,
         exec_file: Option<String>,
-    ) -> bool {
-        env::set_current_dir(&context.outdir).unwrap();
+    ) -> Result<(), BuildError> {
+        env::set_current_dir(&context.outdir)?;
 
-        fs::create_dir_all(&context.tmpdir).unwrap();
+        fs::create_dir_all(&context.tmpdir)?;
+
+        if let Some(available) = available_space(&context.outdir) {
+            if available < self.min_free_space {
+                self.logger.warn(format!(
+                    "Only {} bytes free on the output filesystem (want at least {})",
+                    available, self.min_free_space
+                ));
+            }
+        }
 
         let is_container = build_config.x_exec.container.is_some();
 
+        // Check up front so we don't download assets for a build that can't run.
+        let unshare = if self.no_network && !is_container && exec_file.is_some() {
+            Some(network_sandbox()?)
+        } else {
+            None
+        };
+
         if self.external && !is_container {
             if let Some(build_utils) = build_config.build_util.clone() {
-                let mut child = Command::new("soar")
-                    .env_clear()
-                    .envs(context.env_vars(&self.soar_env.bin_path))
-                    .args(["add".to_string()].iter().chain(build_utils.iter()))
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .stdin(Stdio::null())
-                    .spawn()
-                    .unwrap();
+                let mut child = spawn_tool(
+                    Command::new("soar")
+                        .env_clear()
+                        .envs(context.env_vars(&self.soar_env.bin_path))
+                        .args(["add".to_string()].iter().chain(build_utils.iter()))
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::piped())
+                        .stdin(Stdio::null()),
+                    "soar",
+                )?;
                 self.setup_cmd_logging(&mut child);
-                let status = child.wait().unwrap();
+                let status = child.wait()?;
                 if !status.success() {
                     self.logger.error("Failed to install build utils");
-                    return false;
+                    return Err(BuildError::ScriptFailed(
+                        "failed to install build utils".to_string(),
+                    ));
                 }
             };
         }
 
         if let Err(err) = self.prepare_resources(&build_config, context) {
-            self.logger.warn(&err);
+            self.logger.warn(err.to_string());
         }
 
         if let Some(ref build_assets) = build_config.build_asset {
-            self.download_build_assets(build_assets, context).await;
+            let started = Instant::now();
+            self.download_build_assets(build_assets, context).await?;
+            trace!("Build assets downloaded in {:.2?}", started.elapsed());
         }
 
         if let Some(ref exec_file) = exec_file {
+            for (key, value) in context.env_vars(&self.soar_env.bin_path) {
+                trace!("env {}={}", key, masked_env_value(&key, &value));
+            }
+            let started = Instant::now();
             let mut child = if let Some(ref container) = build_config.x_exec.container {
                 let image = if container.contains(':') {
                     container.clone()
@@ -395,7 +789,12 @@ impl Builder {
 
                 let env_vars = context.env_vars(&self.soar_env.bin_path);
                 let mut cmd = Command::new("docker");
-                cmd.args(["run", "--rm", "--privileged", "--net=host", "--pull=always"]);
+                cmd.args(["run", "--rm", "--privileged", "--pull=always"]);
+                cmd.arg(if self.no_network {
+                    "--network=none"
+                } else {
+                    "--net=host"
+                });
 
                 for (key, value) in &env_vars {
                     if key == "PATH" {
@@ -418,54 +817,88 @@ impl Builder {
                 cmd.env_clear()
                     .stdout(Stdio::piped())
                     .stderr(Stdio::piped())
-                    .stdin(Stdio::null())
-                    .spawn()
-                    .unwrap()
+                    .stdin(Stdio::null());
+                trace!("Running {}", describe_command(&cmd));
+                spawn_tool(&mut cmd, "docker")?
             } else {
-                Command::new(exec_file)
-                    .env_clear()
+                // Run the script in its own process group so a timeout also
+                // kills anything it spawned that still holds the output pipes.
+                // unshare execs the script directly, so the group is the same.
+                let mut cmd = match unshare {
+                    Some(ref unshare) => {
+                        let mut cmd = Command::new(unshare);
+                        cmd.args(["--net", "--map-root-user", "--"]).arg(exec_file);
+                        cmd
+                    }
+                    None => Command::new(exec_file),
+                };
+                cmd.env_clear()
                     .envs(context.env_vars(&self.soar_env.bin_path))
                     .stdout(Stdio::piped())
                     .stderr(Stdio::piped())
                     .stdin(Stdio::null())
-                    .spawn()
-                    .unwrap()
+                    .process_group(0);
+                trace!("Running {}", describe_command(&cmd));
+                cmd.spawn()?
             };
 
-            self.setup_cmd_logging(&mut child);
-
+            // The watchdog has to be running before we block on the output
+            // pipes, which only close once the script (or its kill) is done.
             let timeout = self.timeout;
-            let child_pid = child.id();
+            let kill_target = if is_container {
+                child.id().to_string()
+            } else {
+                format!("-{}", child.id())
+            };
+            interrupt::set_child(Some(kill_target.clone()));
+            let timed_out = Arc::new(AtomicBool::new(false));
             let (kill_tx, kill_rx) = sync::mpsc::channel::<()>();
 
-            let timeout_handle = thread::spawn(move || {
-                if kill_rx.recv_timeout(timeout).is_err() {
-                    let _ = Command::new("kill")
-                        .arg("-9")
-                        .arg(child_pid.to_string())
-                        .output();
+            let timeout_handle = thread::spawn({
+                let timed_out = timed_out.clone();
+                move || {
+                    if kill_rx.recv_timeout(timeout).is_err() {
+                        timed_out.store(true, Ordering::SeqCst);
+                        let _ = Command::new("kill")
+                            .args(["-9", "--", &kill_target])
+                            .output();
+                    }
                 }
             });
 
-            let success = match child.wait() {
+            self.setup_cmd_logging(&mut child);
+
+            let wait_result = child.wait();
+            interrupt::set_child(None);
+            trace!("Build script finished in {:.2?}", started.elapsed());
+
+            let result = match wait_result {
                 Ok(status) => {
                     let _ = kill_tx.send(());
                     let _ = timeout_handle.join();
-                    status.success()
+                    if timed_out.load(Ordering::SeqCst) {
+                        self.logger.error(format!(
+                            "Build timed out after {}s, killed build script",
+                            timeout.as_secs()
+                        ));
+                        Err(BuildError::Timeout(timeout))
+                    } else if status.success() {
+                        Ok(())
+                    } else {
+                        Err(BuildError::ScriptFailed(format!("exited with {}", status)))
+                    }
                 }
                 Err(e) => {
                     let _ = kill_tx.send(());
                     let _ = timeout_handle.join();
                     self.logger.error(format!("Build process error: {}", e));
-                    false
+                    Err(BuildError::Io(e))
                 }
             };
 
             let _ = fs::remove_file(exec_file);
 
-            if !success {
-                return false;
-            }
+            result?;
         }
 
         if let Some(entrypoint) = build_config
@@ -476,18 +909,24 @@ impl Builder {
         {
             let entry_path = Path::new(&entrypoint);
             if entry_path.exists() {
-                symlink(entrypoint, build_config.pkg.clone()).unwrap();
+                symlink(entrypoint, build_config.pkg.clone())?;
             } else {
                 self.logger.error(format!(
                     "Entrypoint {} should exist in {} but doesn't.",
                     entrypoint,
                     context.outdir.display()
                 ));
-                return false;
+                return Err(BuildError::Provides(format!(
+                    "entrypoint {} does not exist",
+                    entrypoint
+                )));
             }
         }
 
-        self.handle_provides(&context, &build_config);
+        self.handle_provides(context, &build_config)?;
+        if self.require_desktop_integration {
+            self.check_desktop_integration(context, &build_config)?;
+        }
 
         let mut finalize = Finalize::new(
             &context.outdir,
@@ -495,12 +934,25 @@ impl Builder {
             self.pkg_type.clone(),
             self.keep,
         );
+        let started = Instant::now();
         if let Err(e) = finalize.update().await {
             self.logger
                 .error(format!("Failed to finalize build: {}", e));
-            return false;
+            return Err(BuildError::Io(e));
         }
-        true
+        trace!("Finalized output in {:.2?}", started.elapsed());
+
+        if let Some(limit) = self.max_output_size {
+            let size = dir_size(&context.outdir, &context.tmpdir);
+            if size > limit {
+                self.logger.error(format!(
+                    "Build output is {} bytes, over the {} byte limit",
+                    size, limit
+                ));
+                return Err(BuildError::OutputTooLarge { size, limit });
+            }
+        }
+        Ok(())
     }
 
     pub async fn build(
@@ -509,158 +961,281 @@ impl Builder {
         outdir: Option<String>,
         timeout: Duration,
         skip_existing: bool,
-    ) -> Option<PathBuf> {
-        let logger = self.logger.clone();
-        let linter = Linter::new(logger.clone(), timeout);
-
-        let pwd = env::current_dir().unwrap();
-        let mut result: Option<PathBuf> = None;
+    ) -> Result<PathBuf, BuildError> {
+        let pwd = env::current_dir()?;
+        interrupt::begin(pwd.clone(), self.keep);
 
         let validated_file = format!("{}.validated", file_path);
         let version_file = format!("{}.pkgver", file_path);
 
-        if let Some(build_config) = linter.lint(file_path, false, false, true) {
-            logger.info(format!("{}", fs::read_to_string(&validated_file).unwrap()));
-            if build_config._disabled {
-                logger.error(format!("{} -> Disabled package. Skipping...", file_path));
-            } else {
-                let version = fs::read_to_string(&version_file).ok();
+        let result = self
+            .build_recipe(
+                file_path,
+                outdir,
+                timeout,
+                skip_existing,
+                &validated_file,
+                &version_file,
+            )
+            .await;
 
-                if version.is_none() {
-                    return None;
-                }
+        interrupt::finish();
+        env::set_current_dir(pwd)?;
 
-                let version = version.unwrap();
-                let x_exec = &build_config.x_exec;
-                let pkg_id = &build_config.pkg_id;
+        let _ = fs::remove_file(validated_file);
+        let _ = fs::remove_file(version_file);
+        result
+    }
 
-                let lines: Vec<&str> = version.lines().collect();
-                let pkgver = lines[0].trim().to_string();
-                let remote_pkgver = if lines.len() > 1 {
-                    lines[1].trim().to_string()
-                } else {
-                    pkgver.clone()
-                };
+    async fn build_recipe(
+        &mut self,
+        file_path: &str,
+        outdir: Option<String>,
+        timeout: Duration,
+        skip_existing: bool,
+        validated_file: &str,
+        version_file: &str,
+    ) -> Result<PathBuf, BuildError> {
+        let logger = self.logger.clone();
+        let linter = Linter::new(logger.clone(), timeout);
 
-                let recipe_dir = Path::new(file_path)
-                    .canonicalize()
-                    .ok()
-                    .and_then(|p| p.parent().map(|p| p.to_path_buf()));
-
-                let context = BuildContext::new(
-                    &build_config,
-                    &self.soar_env.cache_path,
-                    remote_pkgver,
-                    pkgver.to_string(),
-                    outdir,
-                    recipe_dir,
-                );
-
-                if skip_existing && context.outdir.exists() {
-                    logger.warn(format!(
-                        "Skipping build for {} (output directory already exists: {})",
-                        file_path,
-                        context.outdir.display()
-                    ));
-                    return Some(context.outdir);
-                }
+        let options = LintOptions::new();
+        let Some(build_config) = linter.lint(file_path, &options) else {
+            return Err(BuildError::LintFailed(file_path.to_string()));
+        };
+        self.build_config = Some(build_config.clone());
+        logger.info(fs::read_to_string(validated_file)?);
+        if build_config._disabled {
+            logger.error(format!("{} -> Disabled package. Skipping...", file_path));
+            return Err(BuildError::Disabled(file_path.to_string()));
+        }
 
-                let _ = fs::remove_dir_all(&context.outdir);
-                fs::create_dir_all(&context.outdir).unwrap();
-                let final_version_file =
-                    format!("{}/{}.version", context.outdir.display(), context.pkg);
-                let final_validated_file = format!("{}/SBUILD", context.outdir.display());
-                fs::copy(&version_file, &final_version_file).unwrap();
-                fs::copy(&validated_file, &final_validated_file).unwrap();
+        let version = match linter.generate_pkgver(&build_config, version_file) {
+            Ok(Some(version)) => version,
+            Ok(None) => {
+                return Err(BuildError::LintFailed(format!(
+                    "{} (no pkgver generated)",
+                    file_path
+                )))
+            }
+            Err(e) => return Err(BuildError::LintFailed(format!("{} ({})", file_path, e))),
+        };
+        let x_exec = &build_config.x_exec;
+        let pkg_id = &build_config.pkg_id;
+
+        let distinct_remote = version
+            .remote_pkgver
+            .clone()
+            .filter(|remote| *remote != version.pkgver);
+        let remote_pkgver = version
+            .remote_pkgver
+            .unwrap_or_else(|| version.pkgver.clone());
+        let pkgver = version.pkgver;
+
+        if let Some(check) = &self.skip_check {
+            let validated = PathBuf::from(validated_file);
+            if let Some(reason) = check(validated, pkgver.clone(), distinct_remote).await {
+                logger.info(format!("{} -> {}. Skipping...", file_path, reason));
+                return Err(BuildError::Skipped(reason));
+            }
+        }
 
-                let log_path = context.outdir.join("BUILD.log");
-                logger.move_log_file(log_path).unwrap();
+        let recipe_dir = self.recipe_root.clone().or_else(|| {
+            Path::new(file_path)
+                .canonicalize()
+                .ok()
+                .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        });
 
-                if let Some(ref arch) = x_exec.arch {
-                    if !arch
-                        .iter()
-                        .any(|a| a.eq_ignore_ascii_case(ARCH.to_string().as_str()))
-                    {
-                        logger.error(format!("Unsupported architecture. Aborting..."));
-                        return None;
-                    }
-                }
+        let context = BuildContext::new(
+            &build_config,
+            &self.soar_env.cache_path,
+            remote_pkgver,
+            pkgver.to_string(),
+            outdir,
+            self.output_layout,
+            recipe_dir,
+        );
 
-                if let Some(ref arch) = x_exec.os {
-                    if !arch
-                        .iter()
-                        .any(|o| o.eq_ignore_ascii_case(OS.to_string().as_str()))
-                    {
-                        logger.error(format!("Unsupported OS. Aborting..."));
-                        return None;
-                    }
-                }
+        if let Err(other) = self.outdir_claims.claim(&context.outdir, pkg_id) {
+            logger.error(format!(
+                "{} -> output directory {} was already used by {}",
+                file_path,
+                context.outdir.display(),
+                other
+            ));
+            return Err(BuildError::OutdirCollision {
+                outdir: context.outdir,
+                other,
+            });
+        }
 
-                if let Some(ref host) = x_exec.host {
-                    let current_host = format!("{ARCH}-{OS}");
-                    if !host
-                        .iter()
-                        .any(|h| h.eq_ignore_ascii_case(current_host.as_str()))
-                    {
-                        logger.error(format!("Unsupported HOST. Aborting..."));
-                        return None;
-                    }
-                }
+        // A flat outdir exists before the build, so look for its output instead
+        let already_built = if context.owns_outdir {
+            context.outdir.exists()
+        } else {
+            context.outdir.join("SBUILD").exists()
+        };
+        if skip_existing && already_built {
+            logger.warn(format!(
+                "Skipping build for {} (output directory already exists: {})",
+                file_path,
+                context.outdir.display()
+            ));
+            return Ok(context.outdir);
+        }
 
-                let exec_file = x_exec.run.as_ref().map(|run| {
-                    let setx = match self.log_level {
-                        2 => "set -x",
-                        3 => "set -xv",
-                        _ => "",
-                    };
-                    // In a container the script runs as root, so subdirs the
-                    // recipe creates under the mounted /sbuild (e.g.
-                    // packages/<pkg>/) aren't writable by the host user, which
-                    // blocks later host-side steps (icon/desktop extraction,
-                    // signing). Relax permissions so the host user can write
-                    // regardless of ownership. chmod (not chown) is used
-                    // deliberately: under rootless docker/podman the container
-                    // root maps to the host user via a subuid range, so
-                    // chown-ing to a host uid would map it back out of reach;
-                    // chmod works the same under both rootful and rootless
-                    // runtimes. Preserve the script's real exit status.
-                    let trailer = if build_config.x_exec.container.is_some() {
-                        "\n__sbuild_rc=$?\nchmod -R a+rwX /sbuild 2>/dev/null || true\nexit $__sbuild_rc"
-                    } else {
-                        ""
-                    };
-                    let script =
-                        format!("#!/usr/bin/env {}\n{}\n{}{}", x_exec.shell, setx, run, trailer);
-                    let tmp = temp_file(pkg_id, &script);
-                    tmp.to_string_lossy().to_string()
-                });
+        if context.owns_outdir {
+            let _ = fs::remove_dir_all(&context.outdir);
+        } else if fs::read_dir(&context.outdir).is_ok_and(|mut entries| entries.next().is_some()) {
+            // Never wipe a directory the user handed us
+            return Err(BuildError::OutdirNotEmpty(context.outdir));
+        }
+        fs::create_dir_all(&context.outdir)?;
+        interrupt::set_outdir(context.outdir.clone());
+        let final_version_file = format!("{}/{}.version", context.outdir.display(), context.pkg);
+        let final_validated_file = format!("{}/SBUILD", context.outdir.display());
+        fs::copy(version_file, &final_version_file)?;
+        fs::copy(validated_file, &final_validated_file)?;
+
+        let log_path = context.outdir.join("BUILD.log");
+        logger.move_log_file(log_path)?;
+
+        if let Some(ref arch) = x_exec.arch {
+            if !arch
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(ARCH.to_string().as_str()))
+            {
+                logger.error("Unsupported architecture. Aborting...");
+                return Err(BuildError::Unsupported(format!("architecture {}", ARCH)));
+            }
+        }
 
-                let success = self.exec(&context, build_config, exec_file).await;
-                if success {
-                    logger.success(format!(
-                        "Successfully built the package at {}",
-                        context.outdir.display()
-                    ));
-                    result = Some(context.outdir.clone());
-                } else {
-                    logger.success(&format!("Failed to build the package: {}", context.pkg));
-                }
+        if let Some(ref arch) = x_exec.os {
+            if !arch
+                .iter()
+                .any(|o| o.eq_ignore_ascii_case(OS.to_string().as_str()))
+            {
+                logger.error("Unsupported OS. Aborting...");
+                return Err(BuildError::Unsupported(format!("OS {}", OS)));
+            }
+        }
+
+        if let Some(ref host) = x_exec.host {
+            let current_host = format!("{ARCH}-{OS}");
+            if !host
+                .iter()
+                .any(|h| h.eq_ignore_ascii_case(current_host.as_str()))
+            {
+                logger.error("Unsupported HOST. Aborting...");
+                return Err(BuildError::Unsupported(format!("host {}", current_host)));
             }
         }
 
-        env::set_current_dir(pwd).unwrap();
+        let exec_file = x_exec.run.as_ref().map(|run| {
+            let setx = match self.log_level {
+                2 => "set -x",
+                3 => "set -xv",
+                _ => "",
+            };
+            // In a container the script runs as root, so subdirs the
+            // recipe creates under the mounted /sbuild (e.g.
+            // packages/<pkg>/) aren't writable by the host user, which
+            // blocks later host-side steps (icon/desktop extraction,
+            // signing). Relax permissions so the host user can write
+            // regardless of ownership. chmod (not chown) is used
+            // deliberately: under rootless docker/podman the container
+            // root maps to the host user via a subuid range, so
+            // chown-ing to a host uid would map it back out of reach;
+            // chmod works the same under both rootful and rootless
+            // runtimes. Preserve the script's real exit status.
+            let trailer = if build_config.x_exec.container.is_some() {
+                "\n__sbuild_rc=$?\nchmod -R a+rwX /sbuild 2>/dev/null || true\nexit $__sbuild_rc"
+            } else {
+                ""
+            };
+            let script = format!(
+                "#!/usr/bin/env {}\n{}\n{}{}",
+                x_exec.shell, setx, run, trailer
+            );
+            let tmp = temp_file(pkg_id, &script);
+            tmp.to_string_lossy().to_string()
+        });
 
-        let _ = fs::remove_file(validated_file);
-        let _ = fs::remove_file(version_file);
-        result
+        match self.exec(&context, build_config, exec_file).await {
+            Ok(()) => {
+                logger.success(format!(
+                    "Successfully built the package at {}",
+                    context.outdir.display()
+                ));
+                Ok(context.outdir.clone())
+            }
+            Err(e) => {
+                logger.error(format!(
+                    "Failed to build the package {}: {}",
+                    context.pkg, e
+                ));
+                Err(e)
+            }
+        }
     }
 
-    pub fn handle_provides(&mut self, context: &BuildContext, build_config: &BuildConfig) {
-        let pkg_name = &build_config.pkg;
-        let pkg_type = &build_config.pkg_type;
+    /// Require a desktop entry or icon for GUI package types, whether
+    /// extracted from the provides or shipped with the recipe. Runs before
+    /// finalizing, which would otherwise fill in placeholders.
+    fn check_desktop_integration(
+        &self,
+        context: &BuildContext,
+        build_config: &BuildConfig,
+    ) -> Result<(), BuildError> {
+        let gui = matches!(
+            self.pkg_type,
+            PackageType::AppImage | PackageType::NixAppImage | PackageType::FlatImage
+        ) || matches!(
+            build_config.pkg_type.as_deref(),
+            Some("appimage" | "nixappimage" | "flatimage" | "gameimage")
+        );
+        if !gui || !self.desktop.is_empty() || !self.icon.is_empty() {
+            return Ok(());
+        }
+
+        // Only files named after a provide count, as extraction would name them
+        let shipped = Self::provides(build_config)
+            .into_iter()
+            .any(|(parent_pkg, provide)| {
+                let cmd = provide_base(&provide);
+                let dirs = parent_pkg
+                    .map(|parent| context.outdir.join("packages").join(parent))
+                    .into_iter()
+                    .chain([context.outdir.clone()]);
+                dirs.flat_map(|dir| {
+                    ["desktop", "png", "svg"].map(|ext| dir.join(format!("{}.{}", cmd, ext)))
+                })
+                .any(|path| path.is_file())
+            });
+        if shipped {
+            return Ok(());
+        }
+
+        let pkg_type = build_config
+            .pkg_type
+            .clone()
+            .unwrap_or_else(|| self.pkg_type.to_string());
+        self.logger.error(format!(
+            "No desktop entry or icon for {} package {}. Aborting.",
+            pkg_type, build_config.pkg
+        ));
+        Err(BuildError::NoDesktopIntegration(format!(
+            "{} package {} has no desktop entry or icon",
+            pkg_type, build_config.pkg
+        )))
+    }
 
-        // Collect all provides: from packages field or global provides
-        let provides = if let Some(ref packages) = build_config.packages {
+    /// Every provide of the recipe, with the package it belongs to when the
+    /// recipe splits into `packages`
+    fn provides(build_config: &BuildConfig) -> Vec<(Option<&str>, String)> {
+        let pkg_name = &build_config.pkg;
+        if let Some(ref packages) = build_config.packages {
             packages
                 .iter()
                 .flat_map(|(pkg, config)| {
@@ -680,17 +1255,24 @@ impl Builder {
                 .into_iter()
                 .map(|p| (None, p))
                 .collect()
-        };
+        }
+    }
+
+    /// Inspect each provide to detect the package type and extract desktop
+    /// integration files. Fails if the package can't be shipped.
+    pub fn handle_provides(
+        &mut self,
+        context: &BuildContext,
+        build_config: &BuildConfig,
+    ) -> Result<(), BuildError> {
+        let pkg_name = &build_config.pkg;
+        let pkg_type = &build_config.pkg_type;
+        let provides = Self::provides(build_config);
 
         let mut exists_any = false;
 
         for (parent_pkg, provide) in provides {
-            let cmd = provide
-                .split_once(|c| c == ':' || c == '=')
-                .map(|(p1, _)| p1.to_string())
-                .unwrap_or_else(|| provide.to_string());
-            // Strip @ prefix for binary-only entries
-            let cmd = cmd.strip_prefix('@').unwrap_or(&cmd);
+            let cmd = provide_base(&provide);
 
             // Check in packages/<parent>/ first, then root outdir
             let provide_path = if let Some(parent) = parent_pkg {
@@ -712,6 +1294,12 @@ impl Builder {
 
             exists_any = true;
 
+            // Archives are shipped as-is, so none of the binary checks apply
+            if pkg_type.as_deref() == Some("archive") {
+                self.pkg_type = PackageType::Archive;
+                continue;
+            }
+
             let magic_bytes = calc_magic_bytes(&provide_path, 12);
 
             if magic_bytes[8..] == APPIMAGE_MAGIC_BYTES {
@@ -728,9 +1316,14 @@ impl Builder {
                     _ => None,
                 };
 
-                let offset = get_offset(&provide_path).unwrap();
-
-                let Ok(mut appimage) = AppImage::new(filter, &provide_path, None) else {
+                let parsed = get_offset(&provide_path).ok().and_then(|offset| {
+                    let appimage = AppImage::new(filter, &provide_path, None).ok()?;
+                    Some((offset, appimage))
+                });
+                let Some((offset, mut appimage)) = parsed else {
+                    if self.unsquashfs_extract_assets(&provide_path, context, cmd) {
+                        continue;
+                    }
                     self.logger.warn(format!(
                         "Failed to read {} as AppImage. Trying self-extract approach.",
                         provide_path.display()
@@ -743,7 +1336,7 @@ impl Builder {
                     );
                     self_extract_appimage(&cmd, ".DirIcon".to_string(), ".DirIcon");
 
-                    self.rename_icon(".DirIcon", context, &provide, &cmd);
+                    self.rename_icon(".DirIcon", context, cmd);
 
                     continue;
                 };
@@ -793,7 +1386,7 @@ impl Builder {
                         ));
                     };
                 }
-                if self.icon.get(&provide).is_none() {
+                if self.icon.get(cmd).is_none() {
                     if let Some(entry) = appimage.find_icon() {
                         if let AppImageEntryKind::File = entry.kind {
                             let dest = format!("{}.DirIcon", cmd);
@@ -804,11 +1397,11 @@ impl Builder {
                                 dest
                             ));
 
-                            self.rename_icon(dest, context, &provide, &cmd);
+                            self.rename_icon(dest, context, cmd);
                         }
                     }
                 }
-                if self.desktop.get(&provide).is_none() {
+                if self.desktop.get(cmd).is_none() {
                     if let Some(entry) = appimage.find_desktop() {
                         if let AppImageEntryKind::File = entry.kind {
                             let dest = format!("{}.desktop", cmd);
@@ -818,11 +1411,11 @@ impl Builder {
                                 entry.path.display(),
                                 dest
                             ));
-                            self.desktop.insert(provide.clone(), true);
+                            self.desktop.insert(cmd.to_string(), true);
                         }
                     };
                 }
-                if self.appstream.get(&provide).is_none() {
+                if self.appstream.get(cmd).is_none() {
                     if let Some(entry) = appimage.find_appstream() {
                         if let AppImageEntryKind::File = entry.kind {
                             let file_name = if entry
@@ -843,7 +1436,10 @@ impl Builder {
                                 entry.path.display(),
                                 dest
                             ));
-                            self.appstream.insert(provide.clone(), true);
+                            self.appstream.insert(cmd.to_string(), true);
+                            if self.validate_appstream {
+                                self.validate_appstream_file(&dest);
+                            }
                         }
                     };
                 }
@@ -852,6 +1448,42 @@ impl Builder {
                 if self.pkg_type == PackageType::Unknown {
                     self.pkg_type = PackageType::FlatImage;
                 }
+
+                let dest_dir = provide_path.parent().unwrap_or_else(|| Path::new(""));
+
+                if !self.icon.contains_key(cmd) {
+                    let dest = dest_dir.join(format!("{}.DirIcon", cmd));
+                    if flatimage_extract_icon(&provide_path, &dest) {
+                        self.logger
+                            .info(format!("Extracted icon to {}", dest.display()));
+                        self.rename_icon(dest, context, cmd);
+                    } else {
+                        self.logger.warn(format!(
+                            "Failed to extract icon from FlatImage {}",
+                            provide_path.display()
+                        ));
+                    }
+                }
+                if !self.desktop.contains_key(cmd) {
+                    let dest = dest_dir.join(format!("{}.desktop", cmd));
+                    if flatimage_extract_desktop(&provide_path, &dest) {
+                        self.logger
+                            .info(format!("Extracted desktop to {}", dest.display()));
+                        self.desktop.insert(cmd.to_string(), true);
+                    } else {
+                        self.logger.warn(format!(
+                            "Failed to extract desktop entry from FlatImage {}",
+                            provide_path.display()
+                        ));
+                    }
+                }
+                if !self.appstream.contains_key(cmd) {
+                    // FlatImage has no dump command for appstream metadata.
+                    self.logger.warn(format!(
+                        "Appstream extraction is not supported for FlatImage {}",
+                        provide_path.display()
+                    ));
+                }
             } else if is_onelf(&provide_path) {
                 // onelf packs a directory into a self-extracting ELF, so its
                 // leading magic is ELF; it's identified by a trailing footer.
@@ -868,13 +1500,13 @@ impl Builder {
 
                 match OnelfPackage::open(&provide_path) {
                     Ok(mut pkg) => {
-                        if self.icon.get(&provide).is_none() {
+                        if self.icon.get(cmd).is_none() {
                             let dest = dest_dir.join(format!("{}.DirIcon", cmd));
                             match pkg.extract_icon(cmd, &dest) {
                                 Ok(Some(())) => {
                                     self.logger
                                         .info(&format!("Extracted icon to {}", dest.display()));
-                                    self.rename_icon(dest, context, &provide, cmd);
+                                    self.rename_icon(dest, context, cmd);
                                 }
                                 Ok(None) => {}
                                 Err(e) => self.logger.warn(format!(
@@ -884,13 +1516,13 @@ impl Builder {
                                 )),
                             }
                         }
-                        if self.desktop.get(&provide).is_none() {
+                        if self.desktop.get(cmd).is_none() {
                             let dest = dest_dir.join(format!("{}.desktop", cmd));
                             match pkg.extract_desktop(cmd, &dest) {
                                 Ok(Some(())) => {
                                     self.logger
                                         .info(&format!("Extracted desktop to {}", dest.display()));
-                                    self.desktop.insert(provide.clone(), true);
+                                    self.desktop.insert(cmd.to_string(), true);
                                 }
                                 Ok(None) => {}
                                 Err(e) => self.logger.warn(format!(
@@ -909,7 +1541,27 @@ impl Builder {
                         ));
                     }
                 }
+            } else if is_archive(&provide_path) {
+                // Only auto-detect if pkg_type is not already set
+                if self.pkg_type == PackageType::Unknown {
+                    self.pkg_type = PackageType::Archive;
+                }
             } else if magic_bytes[..4] == ELF_MAGIC_BYTES {
+                // EI_CLASS: 1 = ELFCLASS32, 2 = ELFCLASS64
+                if magic_bytes[4] != 2 {
+                    let class = match magic_bytes[4] {
+                        1 => "ELFCLASS32".to_string(),
+                        other => format!("unknown ELF class {}", other),
+                    };
+                    self.logger.error(format!(
+                        "Provide '{}' is not a 64-bit binary ({}). Aborting.",
+                        provide, class
+                    ));
+                    return Err(BuildError::Provides(format!(
+                        "'{}' is not a 64-bit binary ({})",
+                        provide, class
+                    )));
+                }
                 // Only auto-detect if pkg_type is not already set
                 if self.pkg_type == PackageType::Unknown {
                     self.pkg_type = if is_static_elf(&provide_path) {
@@ -923,17 +1575,135 @@ impl Builder {
 
         if !exists_any {
             self.logger.error("None of the provides exist. Aborting.");
-            std::process::exit(1);
+            return Err(BuildError::Provides(
+                "none of the provides exist".to_string(),
+            ));
         }
+        Ok(())
     }
 
-    fn rename_icon<P: AsRef<Path>>(
+    /// Extract the icon, desktop entry and appstream file of an AppImage
+    /// squishy can't read by listing its SquashFS with unsquashfs. Returns
+    /// false if unsquashfs can't read the image either.
+    fn unsquashfs_extract_assets(
         &mut self,
-        file_path: P,
+        provide_path: &Path,
         context: &BuildContext,
-        provide: &str,
         cmd: &str,
-    ) {
+    ) -> bool {
+        let Ok(unsquashfs) = which::which("unsquashfs") else {
+            return false;
+        };
+        let Some(offset) = find_squashfs_offset(provide_path) else {
+            return false;
+        };
+        let Some(entries) = unsquashfs_list(&unsquashfs, provide_path, offset) else {
+            return false;
+        };
+        self.logger.warn(format!(
+            "Failed to read {} as AppImage. Extracting with unsquashfs.",
+            provide_path.display()
+        ));
+
+        let dest_dir = provide_path.parent().unwrap_or_else(|| Path::new(""));
+        let tmp_dir = Path::new("SBUILD_TEMP/unsquashfs_extract");
+        let logger = &self.logger;
+        let extract = |entry: &str, dest: &Path| {
+            let ok = unsquashfs_extract(&unsquashfs, provide_path, offset, entry, tmp_dir, dest);
+            if ok {
+                logger.info(format!("Extracted {} to {}", entry, dest.display()));
+            }
+            ok
+        };
+
+        let icon = Some(dest_dir.join(format!("{}.DirIcon", cmd)))
+            .filter(|_| !self.icon.contains_key(cmd))
+            .filter(|_| entries.iter().any(|e| e == ".DirIcon"))
+            .filter(|dest| extract(".DirIcon", dest));
+
+        let desktop = entries
+            .iter()
+            .filter(|_| !self.desktop.contains_key(cmd))
+            .find(|e| !e.contains('/') && e.ends_with(".desktop"))
+            .is_some_and(|entry| extract(entry, &dest_dir.join(format!("{}.desktop", cmd))));
+
+        let appstream = entries
+            .iter()
+            .filter(|_| !self.appstream.contains_key(cmd))
+            .find(|e| {
+                (e.starts_with("usr/share/metainfo/") || e.starts_with("usr/share/appdata/"))
+                    && e.ends_with(".xml")
+            })
+            .map(|entry| {
+                let kind = if entry.contains("appdata") {
+                    "appdata"
+                } else {
+                    "metainfo"
+                };
+                (entry, dest_dir.join(format!("{}.{}.xml", cmd, kind)))
+            })
+            .filter(|(entry, dest)| extract(entry, dest));
+        let _ = fs::remove_dir_all(tmp_dir);
+
+        if let Some(dest) = icon {
+            self.rename_icon(dest, context, cmd);
+        }
+        if desktop {
+            self.desktop.insert(cmd.to_string(), true);
+        }
+        if let Some((_, dest)) = appstream {
+            self.appstream.insert(cmd.to_string(), true);
+            if self.validate_appstream {
+                self.validate_appstream_file(&dest);
+            }
+        }
+        true
+    }
+
+    /// Validate an extracted metainfo/appdata file. Problems are logged but
+    /// never fail the build.
+    fn validate_appstream_file<P: AsRef<Path>>(&self, file_path: P) {
+        let file_path = file_path.as_ref();
+        let Ok(appstreamcli) = which::which("appstreamcli") else {
+            self.logger
+                .warn("appstreamcli not found. Skipping appstream validation.");
+            return;
+        };
+
+        let output = match Command::new(appstreamcli)
+            .args(["validate", "--no-net"])
+            .arg(file_path)
+            .stdin(Stdio::null())
+            .output()
+        {
+            Ok(output) => output,
+            Err(e) => {
+                self.logger
+                    .warn(format!("Failed to run appstreamcli: {}", e));
+                return;
+            }
+        };
+
+        if output.status.success() {
+            self.logger.info(format!(
+                "{} -> Appstream validation passed.",
+                file_path.display()
+            ));
+            return;
+        }
+
+        let report = String::from_utf8_lossy(&output.stdout);
+        for line in report.lines().filter(|l| !l.trim().is_empty()) {
+            self.logger
+                .warn(format!("{}: {}", file_path.display(), line));
+        }
+        self.logger.error(format!(
+            "{} -> Appstream validation failed.",
+            file_path.display()
+        ));
+    }
+
+    fn rename_icon<P: AsRef<Path>>(&mut self, file_path: P, context: &BuildContext, cmd: &str) {
         let file_path = file_path.as_ref();
         if !file_path.exists() {
             return;
@@ -956,7 +1726,7 @@ impl Builder {
                 file_path.display(),
                 final_path.display()
             ));
-            self.icon.insert(provide.to_string(), true);
+            self.icon.insert(cmd.to_string(), true);
         } else {
             let tmp_path = context.tmpdir.join(&file_path);
             fs::rename(&file_path, &tmp_path).unwrap();
@@ -967,3 +1737,218 @@ impl Builder {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use sbuild_linter::{logger::LogManager, Resource};
+
+    use super::*;
+
+    fn test_builder() -> Builder {
+        let (sender, _receiver) = mpsc::channel();
+        let logger = LogManager::new(sender).create_logger(None::<PathBuf>);
+        Builder::new(
+            logger,
+            SoarEnv::default(),
+            false,
+            0,
+            false,
+            Duration::from_secs(5),
+        )
+    }
+
+    fn context(recipe_dir: &Path, outdir: &Path) -> BuildContext {
+        BuildContext {
+            pkg: "app".to_string(),
+            pkg_id: "example.com.app".to_string(),
+            pkg_type: None,
+            sbuild_pkg: "app".to_string(),
+            outdir: outdir.to_path_buf(),
+            tmpdir: outdir.join("SBUILD_TEMP"),
+            remote_pkgver: "1.0".to_string(),
+            pkgver: "1.0".to_string(),
+            recipe_dir: Some(recipe_dir.to_path_buf()),
+            owns_outdir: true,
+        }
+    }
+
+    fn desktop_file(file: &str) -> BuildConfig {
+        BuildConfig {
+            pkg: "app".to_string(),
+            desktop: Some(Resource {
+                url: None,
+                file: Some(file.to_string()),
+                dir: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resource_path_stays_in_recipe_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let recipe_dir = dir.path().join("recipe");
+        fs::create_dir_all(recipe_dir.join("assets")).unwrap();
+        fs::write(recipe_dir.join("assets/app.desktop"), "[Desktop Entry]").unwrap();
+        fs::write(dir.path().join("secret"), "secret").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret"), recipe_dir.join("link")).unwrap();
+
+        assert_eq!(
+            Builder::resource_path(&recipe_dir, "assets/app.desktop").unwrap(),
+            recipe_dir
+                .join("assets/app.desktop")
+                .canonicalize()
+                .unwrap()
+        );
+        for path in ["../secret", "link"] {
+            assert!(matches!(
+                Builder::resource_path(&recipe_dir, path),
+                Err(BuildError::ResourceOutsideRecipe(_))
+            ));
+        }
+        let absolute = dir.path().join("secret");
+        assert!(matches!(
+            Builder::resource_path(&recipe_dir, &absolute.to_string_lossy()),
+            Err(BuildError::ResourceOutsideRecipe(_))
+        ));
+    }
+
+    #[test]
+    fn test_prepare_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        let (recipe_dir, outdir) = (dir.path().join("recipe"), dir.path().join("out"));
+        fs::create_dir_all(recipe_dir.join("assets")).unwrap();
+        fs::create_dir_all(&outdir).unwrap();
+        fs::write(recipe_dir.join("assets/app.desktop"), "[Desktop Entry]").unwrap();
+        fs::write(dir.path().join("other.desktop"), "[Desktop Entry]").unwrap();
+        let context = context(&recipe_dir, &outdir);
+
+        let mut builder = test_builder();
+        builder
+            .prepare_resources(&desktop_file("assets/app.desktop"), &context)
+            .unwrap();
+        assert!(outdir.join("app.desktop").is_file());
+        // Keyed like extracted entries, so extraction won't overwrite it
+        assert!(builder.desktop.contains_key("app"));
+
+        let mut builder = test_builder();
+        assert!(matches!(
+            builder.prepare_resources(&desktop_file("../other.desktop"), &context),
+            Err(BuildError::ResourceOutsideRecipe(_))
+        ));
+        assert!(builder.desktop.is_empty());
+    }
+
+    #[test]
+    fn test_desktop_integration_required() {
+        let dir = tempfile::tempdir().unwrap();
+        let outdir = dir.path().join("out");
+        fs::create_dir_all(&outdir).unwrap();
+        let context = context(dir.path(), &outdir);
+        let appimage = BuildConfig {
+            pkg: "app".to_string(),
+            pkg_type: Some("appimage".to_string()),
+            provides: Some(vec!["app-bin:app".to_string()]),
+            ..Default::default()
+        };
+        let missing = |builder: &Builder| {
+            matches!(
+                builder.check_desktop_integration(&context, &appimage),
+                Err(BuildError::NoDesktopIntegration(_))
+            )
+        };
+
+        let builder = test_builder();
+        assert!(missing(&builder));
+        // Only GUI package types need one
+        let static_bin = BuildConfig {
+            pkg_type: Some("static".to_string()),
+            ..appimage.clone()
+        };
+        assert!(builder
+            .check_desktop_integration(&context, &static_bin)
+            .is_ok());
+
+        // Unrelated images aren't an icon
+        fs::write(outdir.join("screenshot.png"), "").unwrap();
+        fs::write(outdir.join("app.png"), "").unwrap();
+        assert!(missing(&builder));
+
+        fs::write(outdir.join("app-bin.svg"), "").unwrap();
+        assert!(builder
+            .check_desktop_integration(&context, &appimage)
+            .is_ok());
+
+        let mut builder = test_builder();
+        fs::remove_file(outdir.join("app-bin.svg")).unwrap();
+        builder.desktop.insert("app-bin".to_string(), true);
+        assert!(builder
+            .check_desktop_integration(&context, &appimage)
+            .is_ok());
+    }
+
+    /// Serve `requests` requests on localhost: `/ok` gets a small body,
+    /// anything else a 404
+    fn serve(requests: usize) -> String {
+        use std::{
+            io::{Read, Write},
+            net::TcpListener,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut request = [0u8; 1024];
+                let n = stream.read(&mut request).unwrap();
+                let response = if request[..n].starts_with(b"GET /ok ") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nasset"
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_failed_asset_download_lets_the_others_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = serve(3);
+        let out = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        let assets = [
+            BuildAsset {
+                url: format!("{}/ok", base),
+                out: out("first"),
+            },
+            BuildAsset {
+                url: format!("{}/missing", base),
+                out: out("missing"),
+            },
+            BuildAsset {
+                url: format!("{}/ok", base),
+                out: out("last"),
+            },
+        ];
+
+        let mut builder = test_builder();
+        let result = builder
+            .download_build_assets(&assets, &context(dir.path(), dir.path()))
+            .await;
+        match result {
+            Err(BuildError::AssetDownload { url, reason }) => {
+                assert_eq!(url, format!("{}/missing", base));
+                assert!(reason.contains("404"), "{}", reason);
+            }
+            other => panic!("expected an asset download error, got {:?}", other),
+        }
+        for name in ["first", "last"] {
+            assert_eq!(fs::read_to_string(dir.path().join(name)).unwrap(), "asset");
+        }
+        assert!(!dir.path().join("missing").exists());
+    }
+}
@@ -4,9 +4,10 @@
 
 use std::fs::File;
 use std::io::{BufReader, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use blake3::Hasher as Blake3Hasher;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 /// Compute BLAKE3 hash of a file
@@ -122,6 +123,144 @@ pub fn generate_checksum_file<P: AsRef<Path>>(dir: P) -> std::io::Result<String>
     Ok(content)
 }
 
+/// Suffix of the artifact manifest written by [`write_files_manifest`]
+pub const FILES_MANIFEST_SUFFIX: &str = ".files.json";
+
+/// One artifact in a files manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Path relative to the output directory, `/`-separated
+    pub path: String,
+    pub size: u64,
+    pub b3sum: String,
+}
+
+fn collect_files(root: &Path, dir: &Path, entries: &mut Vec<FileEntry>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            collect_files(root, &path, entries)?;
+        } else if file_type.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if relative.ends_with(FILES_MANIFEST_SUFFIX) {
+                continue;
+            }
+            entries.push(FileEntry {
+                path: relative,
+                size: entry.metadata()?.len(),
+                b3sum: b3sum(&path)?,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Every regular file under `dir`, sorted by path. Symlinks and earlier
+/// files manifests are left out.
+pub fn list_files<P: AsRef<Path>>(dir: P) -> std::io::Result<Vec<FileEntry>> {
+    let dir = dir.as_ref();
+    let mut entries = Vec::new();
+    collect_files(dir, dir, &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Write `<name>.files.json` into `dir`, listing the artifacts in it
+pub fn write_files_manifest<P: AsRef<Path>>(dir: P, name: &str) -> std::io::Result<PathBuf> {
+    let dir = dir.as_ref();
+    let entries = list_files(dir)?;
+    let manifest_path = dir.join(format!("{}{}", name, FILES_MANIFEST_SUFFIX));
+    let content = serde_json::to_string_pretty(&entries).map_err(std::io::Error::other)?;
+    std::fs::write(&manifest_path, content)?;
+    Ok(manifest_path)
+}
+
+/// Paths whose digest differs between two [`list_files`] listings, or that
+/// only one of them has, sorted
+pub fn diff_files(a: &[FileEntry], b: &[FileEntry]) -> Vec<String> {
+    let mut differing: Vec<String> = a
+        .iter()
+        .filter(|x| !b.iter().any(|y| y.path == x.path && y.b3sum == x.b3sum))
+        .chain(b.iter().filter(|y| !a.iter().any(|x| x.path == y.path)))
+        .map(|e| e.path.clone())
+        .collect();
+    differing.sort();
+    differing.dedup();
+    differing
+}
+
+/// Result of checking a single `CHECKSUM` entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyStatus {
+    Ok,
+    Mismatch { expected: String, actual: String },
+    Missing,
+}
+
+/// A `CHECKSUM` entry along with its verification result
+#[derive(Debug, Clone)]
+pub struct VerifyEntry {
+    pub algorithm: String,
+    pub filename: String,
+    pub status: VerifyStatus,
+}
+
+/// Verify the files in a directory against its `CHECKSUM` file
+///
+/// Reads the `BLAKE3: <hash> <file>` / `SHA256: <hash> <file>` lines written by
+/// [`generate_checksum_file`] and recomputes each digest with the same algorithm.
+pub fn verify_checksum_file<P: AsRef<Path>>(dir: P) -> std::io::Result<Vec<VerifyEntry>> {
+    let dir = dir.as_ref();
+    let content = std::fs::read_to_string(dir.join("CHECKSUM"))?;
+    let mut entries = Vec::new();
+
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("malformed CHECKSUM line: {}", line),
+            )
+        };
+        let (algorithm, rest) = line.split_once(": ").ok_or_else(invalid)?;
+        let (expected, filename) = rest.split_once(' ').ok_or_else(invalid)?;
+
+        let path = dir.join(filename);
+        let status = if !path.is_file() {
+            VerifyStatus::Missing
+        } else {
+            let actual = match algorithm {
+                "BLAKE3" => b3sum(&path)?,
+                "SHA256" => sha256sum(&path)?,
+                _ => return Err(invalid()),
+            };
+            if actual == expected {
+                VerifyStatus::Ok
+            } else {
+                VerifyStatus::Mismatch {
+                    expected: expected.to_string(),
+                    actual,
+                }
+            }
+        };
+
+        entries.push(VerifyEntry {
+            algorithm: algorithm.to_string(),
+            filename: filename.to_string(),
+            status,
+        });
+    }
+
+    Ok(entries)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,4 +294,69 @@ mod tests {
             "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
         );
     }
+
+    #[test]
+    fn test_verify_checksum_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello"), b"hello world").unwrap();
+        std::fs::write(dir.path().join("bye"), b"goodbye").unwrap();
+        generate_checksum_file(dir.path()).unwrap();
+
+        let entries = verify_checksum_file(dir.path()).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|e| e.status == VerifyStatus::Ok));
+
+        std::fs::write(dir.path().join("hello"), b"tampered").unwrap();
+        std::fs::remove_file(dir.path().join("bye")).unwrap();
+
+        let entries = verify_checksum_file(dir.path()).unwrap();
+        let status = |name: &str, algo: &str| {
+            entries
+                .iter()
+                .find(|e| e.filename == name && e.algorithm == algo)
+                .map(|e| e.status.clone())
+                .unwrap()
+        };
+        assert!(matches!(
+            status("hello", "BLAKE3"),
+            VerifyStatus::Mismatch { .. }
+        ));
+        assert_eq!(status("bye", "SHA256"), VerifyStatus::Missing);
+    }
+
+    #[test]
+    fn test_write_files_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello"), b"hello world").unwrap();
+        std::fs::create_dir(dir.path().join("packages")).unwrap();
+        std::fs::write(dir.path().join("packages/bye"), b"goodbye").unwrap();
+
+        let path = write_files_manifest(dir.path(), "hello").unwrap();
+        assert_eq!(path, dir.path().join("hello.files.json"));
+        // Rewriting doesn't list the old manifest
+        write_files_manifest(dir.path(), "hello").unwrap();
+
+        let entries: Vec<FileEntry> =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["hello", "packages/bye"]);
+        assert_eq!(entries[0].size, 11);
+        assert_eq!(
+            entries[0].b3sum,
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+    }
+
+    #[test]
+    fn test_diff_files() {
+        let entry = |path: &str, b3sum: &str| FileEntry {
+            path: path.to_string(),
+            size: 1,
+            b3sum: b3sum.to_string(),
+        };
+        let first = vec![entry("a", "1"), entry("b", "2"), entry("c", "3")];
+        let second = vec![entry("a", "1"), entry("b", "9"), entry("d", "4")];
+        assert_eq!(diff_files(&first, &second), vec!["b", "c", "d"]);
+        assert!(diff_files(&first, &first).is_empty());
+    }
 }
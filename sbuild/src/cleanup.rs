@@ -4,7 +4,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use sbuild_linter::build_config::BuildConfig;
+use sbuild_linter::{build_config::BuildConfig, validator::provide_base};
 
 use crate::{
     constant::{MIN_DESKTOP_SIZE, MIN_ICON_SIZE, XML_MAGIC_BYTES},
@@ -49,9 +49,10 @@ impl Finalize {
         // onelf is a portable single-binary format like static/dynamic: any
         // icon/desktop is extracted from its bundled `.onelf/` metadata during
         // the build. Don't fabricate fallback assets when none were bundled.
+        // Archives carry whatever assets they were packed with.
         if matches!(
             self.pkg_type,
-            PackageType::Static | PackageType::Dynamic | PackageType::Onelf
+            PackageType::Static | PackageType::Dynamic | PackageType::Onelf | PackageType::Archive
         ) {
             return Ok(());
         };
@@ -66,12 +67,7 @@ impl Finalize {
         };
 
         for provide in provides {
-            let cmd = provide
-                .split_once(|c| c == ':' || c == '=')
-                .map(|(p1, _)| p1.to_string())
-                .unwrap_or_else(|| provide.to_string());
-            // Strip @ prefix for binary-only entries
-            let cmd = cmd.strip_prefix('@').unwrap_or(&cmd);
+            let cmd = provide_base(&provide);
 
             self.validate_icon(&cmd).await?;
             self.validate_appstream(&cmd)?;
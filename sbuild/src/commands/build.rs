@@ -1,10 +1,11 @@
 use std::{
     env, fs,
+    io::Read,
     path::{Path, PathBuf},
     sync::{
         self,
         atomic::{AtomicBool, AtomicUsize, Ordering},
-        Arc,
+        Arc, LazyLock, Mutex,
     },
     thread,
     time::{Duration, Instant},
@@ -12,25 +13,43 @@ use std::{
 
 use clap::Parser;
 use colored::Colorize;
-use log::{error, info, warn, LevelFilter};
+use log::{error, info, trace, warn, LevelFilter};
 use sbuild::{
-    builder::Builder,
+    builder::{BuildError, Builder, OutdirClaims, OutputLayout, SkipCheck},
     checksum, fetch_recipe,
-    ghcr::{sanitize_oci_tag, GhcrClient, PackageAnnotations},
+    ghcr::{ghcr_repo_path, ghcr_tag, sanitize_oci_tag, GhcrClient, PackageAnnotations},
+    progress::Progress,
     read_recipe_metadata,
     signing::Signer,
-    types::SoarEnv,
+    types::{PackageType, SoarEnv},
     update_json_metadata,
 };
-use sbuild_linter::logger::{LogManager, LogMessage};
-use sbuild_meta::sanitize_oci_name;
+use sbuild_cache::current_host_triplet;
+use sbuild_linter::{
+    build_config::BuildConfig,
+    description::Description,
+    logger::{prefix_lines, LogManager, LogMessage, TaskLogger},
+    validator::provide_base,
+};
+use sbuild_meta::{manifest::download_url, RegistryClient, SBuildRecipe};
 
 #[derive(Parser)]
 #[command(about = "Build packages from SBUILD recipes")]
 pub struct BuildArgs {
-    #[arg(required = true)]
+    /// Recipe files, URLs or `github:owner/repo[@ref]:path` references; `-`
+    /// reads one recipe from stdin
+    #[arg(required_unless_present = "from_file")]
     pub recipes: Vec<String>,
 
+    /// Also build the recipes listed in FILE, one per line (`#` comments allowed)
+    #[arg(long, value_name = "FILE")]
+    pub from_file: Option<PathBuf>,
+
+    /// Only build shard I of N (1-based) of the recipes, chosen by hashing
+    /// each recipe path so a recipe stays in its shard as the list changes
+    #[arg(long, value_name = "I/N")]
+    pub shard: Option<Shard>,
+
     #[arg(short, long)]
     pub outdir: Option<PathBuf>,
 
@@ -49,6 +68,7 @@ pub struct BuildArgs {
     #[arg(long)]
     pub ci: bool,
 
+    /// Build even when the version is already on GHCR
     #[arg(long)]
     pub force: bool,
 
@@ -67,6 +87,15 @@ pub struct BuildArgs {
     #[arg(long)]
     pub push: bool,
 
+    /// Extra tag to push alongside the versioned one, suffixed with the host
+    /// (e.g. `latest` -> `latest-x86_64-linux`). Repeatable.
+    #[arg(long = "extra-tag")]
+    pub extra_tags: Vec<String>,
+
+    /// Retry transient GHCR push failures this many times
+    #[arg(long, default_value = "1")]
+    pub push_retries: u32,
+
     #[arg(long)]
     pub dry_run: bool,
 
@@ -84,6 +113,135 @@ pub struct BuildArgs {
 
     #[arg(long)]
     pub cache: Option<PathBuf>,
+
+    /// Validate extracted appstream metainfo with appstreamcli
+    #[arg(long)]
+    pub validate_appstream: bool,
+
+    /// Don't show the progress indicator (always off with --ci)
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Log output format; json writes one object per line with recipe context
+    #[arg(long, value_enum, default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Run x_exec.run without network access (build_asset downloads still happen)
+    #[arg(long)]
+    pub no_network: bool,
+
+    /// Fail the build if the finalized output is larger than this many bytes
+    #[arg(long, value_name = "BYTES")]
+    pub max_output_size: Option<u64>,
+
+    /// Warn before building if the output filesystem has less free space than this
+    #[arg(long, value_name = "BYTES", default_value = "1073741824")]
+    pub min_free_space: u64,
+
+    /// Share build_asset downloads between builds through this directory
+    #[arg(long, value_name = "DIR")]
+    pub asset_cache: Option<PathBuf>,
+
+    /// Refetch --asset-cache entries older than this many seconds
+    #[arg(long, value_name = "SECS", default_value = "86400")]
+    pub asset_cache_max_age: u64,
+
+    /// Put artifacts in a pkg_id or pkg subdirectory of --outdir, or (flat)
+    /// directly in it. Flat needs an empty --outdir and a single recipe.
+    #[arg(long, value_enum, default_value = "pkg-id", requires = "outdir")]
+    pub output_layout: Layout,
+
+    /// Keep BUILD.log in the outdir (and push it) after every build, or only
+    /// after failed ones
+    #[arg(long, value_enum, default_value = "always")]
+    pub log_retain: LogRetain,
+
+    /// Prefix build output lines with the recipe they come from
+    #[arg(long)]
+    pub log_prefix: bool,
+
+    /// Directory that `files/` and `icon`/`desktop` `file`/`dir` resources
+    /// are relative to [default: the recipe's directory]
+    #[arg(long, value_name = "DIR")]
+    pub recipe_root: Option<PathBuf>,
+
+    /// Fail AppImage, FlatImage and GameImage builds that end up with no
+    /// desktop entry or icon
+    #[arg(long)]
+    pub require_desktop_integration: bool,
+
+    /// Build each recipe a second time in a scratch directory and report
+    /// artifacts whose digests differ between the two builds
+    #[arg(long)]
+    pub repro_check: bool,
+}
+
+/// One of `count` disjoint subsets of the recipes, `index` being 1-based
+#[derive(Debug, Clone, Copy)]
+pub struct Shard {
+    index: u64,
+    count: u64,
+}
+
+impl std::str::FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected I/N, got '{}'", s))?;
+        let index: u64 = index
+            .parse()
+            .map_err(|_| format!("invalid index '{}'", index))?;
+        let count: u64 = count
+            .parse()
+            .map_err(|_| format!("invalid count '{}'", count))?;
+        if count == 0 || index == 0 || index > count {
+            return Err(format!(
+                "shard must be between 1/{0} and {0}/{0}",
+                count.max(1)
+            ));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl Shard {
+    fn contains(&self, recipe: &str) -> bool {
+        let hash = blake3::hash(recipe.as_bytes());
+        let bucket = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        bucket % self.count == self.index - 1
+    }
+}
+
+/// Recipes from the command line and --from-file, limited to --shard
+fn selected_recipes(args: &BuildArgs) -> Result<Vec<String>, String> {
+    let mut recipes = args.recipes.clone();
+    if let Some(ref path) = args.from_file {
+        let list = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        recipes.extend(
+            list.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(String::from),
+        );
+    }
+    if let Some(shard) = args.shard {
+        if recipes.iter().any(|r| r == "-") {
+            return Err("'-' (stdin) can't be sharded".to_string());
+        }
+        let total = recipes.len();
+        recipes.retain(|r| shard.contains(r));
+        info!(
+            "Shard {}/{}: {} of {} recipes",
+            shard.index,
+            shard.count,
+            recipes.len(),
+            total
+        );
+    }
+    Ok(recipes)
 }
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum, Default)]
@@ -94,6 +252,99 @@ pub enum LogLevel {
     Debug,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Default)]
+pub enum Layout {
+    #[default]
+    PkgId,
+    Pkg,
+    Flat,
+}
+
+impl From<Layout> for OutputLayout {
+    fn from(layout: Layout) -> Self {
+        match layout {
+            Layout::PkgId => OutputLayout::PkgId,
+            Layout::Pkg => OutputLayout::Pkg,
+            Layout::Flat => OutputLayout::Flat,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Default)]
+pub enum LogRetain {
+    #[default]
+    Always,
+    OnFailure,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Recipe being processed, attached to every JSON log line
+#[derive(Default)]
+struct LogContext {
+    recipe: Option<String>,
+    pkg: Option<String>,
+    phase: Option<&'static str>,
+}
+
+static LOG_CONTEXT: LazyLock<Mutex<LogContext>> = LazyLock::new(Default::default);
+
+/// Short name for a recipe in prefixed log lines: `family/recipe` for
+/// paths in a recipe tree, else the file stem.
+fn recipe_label(recipe_input: &str) -> String {
+    if recipe_input == "-" {
+        return "stdin".to_string();
+    }
+    if let Some((family, recipe)) = sbuild::parse_ghcr_path(recipe_input) {
+        return format!("{}/{}", family, recipe);
+    }
+    Path::new(recipe_input)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| recipe_input.to_string())
+}
+
+fn set_log_recipe(recipe: Option<&str>, phase: &'static str) {
+    let mut ctx = LOG_CONTEXT.lock().unwrap();
+    ctx.recipe = recipe.map(String::from);
+    ctx.pkg = None;
+    ctx.phase = Some(phase);
+}
+
+fn set_log_pkg(pkg: Option<&str>, phase: &'static str) {
+    let mut ctx = LOG_CONTEXT.lock().unwrap();
+    ctx.pkg = pkg.map(String::from);
+    ctx.phase = Some(phase);
+}
+
+/// Prefix a log message with the label of the recipe being processed, as
+/// the build's `TaskLogger` does with `--log-prefix`
+fn prefixed_log_message(message: &str) -> String {
+    let ctx = LOG_CONTEXT.lock().unwrap();
+    match ctx.recipe.as_deref() {
+        Some(recipe) => prefix_lines(&recipe_label(recipe), message),
+        None => message.to_string(),
+    }
+}
+
+fn json_log_line(level: &str, message: &str) -> String {
+    let ctx = LOG_CONTEXT.lock().unwrap();
+    serde_json::json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "level": level,
+        "message": message.trim_end(),
+        "recipe": ctx.recipe,
+        "pkg": ctx.pkg,
+        "phase": ctx.phase,
+    })
+    .to_string()
+}
+
 impl From<LogLevel> for u8 {
     fn from(level: LogLevel) -> u8 {
         match level {
@@ -107,29 +358,67 @@ impl From<LogLevel> for u8 {
 impl From<LogLevel> for LevelFilter {
     fn from(level: LogLevel) -> LevelFilter {
         match level {
-            LogLevel::Debug | LogLevel::Verbose => LevelFilter::Debug,
+            LogLevel::Verbose => LevelFilter::Debug,
+            LogLevel::Debug => LevelFilter::Trace,
             LogLevel::Info => LevelFilter::Info,
         }
     }
 }
 
-pub async fn run(args: BuildArgs, soar_env: Option<SoarEnv>) -> Result<(), String> {
-    init_logging(args.ci, args.log_level);
-
-    println!(
-        "{} v{}",
-        "sbuild".bright_cyan().bold(),
-        env!("CARGO_PKG_VERSION")
+pub async fn run(args: BuildArgs, soar_env: Option<SoarEnv>, quiet: bool) -> Result<(), String> {
+    init_logging(
+        args.ci,
+        args.log_level,
+        args.log_format,
+        args.log_prefix,
+        quiet,
     );
+    let json_logs = args.log_format == LogFormat::Json;
+
+    if !quiet && !json_logs {
+        println!(
+            "{} v{}",
+            "sbuild".bright_cyan().bold(),
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+
+    let recipes = selected_recipes(&args)?;
+    if recipes.is_empty() {
+        info!("No recipes to build");
+        return Ok(());
+    }
+
+    let stdin_recipe = match recipes.iter().filter(|r| *r == "-").count() {
+        0 => None,
+        1 => {
+            let mut content = String::new();
+            std::io::stdin()
+                .read_to_string(&mut content)
+                .map_err(|e| format!("Failed to read recipe from stdin: {}", e))?;
+            Some(content)
+        }
+        _ => return Err("'-' (stdin) can only be given once".to_string()),
+    };
+    if args.output_layout == Layout::Flat && recipes.len() > 1 {
+        return Err("--output-layout flat builds one recipe at a time".to_string());
+    }
 
     let soar_env = soar_env.unwrap_or_default();
+    spawn_signal_handler();
 
     let now = Instant::now();
     let success = Arc::new(AtomicUsize::new(0));
     let fail = Arc::new(AtomicUsize::new(0));
+    let skipped = Arc::new(AtomicUsize::new(0));
     // A build can succeed while its GHCR push fails; track that separately so
     // the build count stays accurate but the process still exits non-zero.
     let post_build_failed = Arc::new(AtomicBool::new(false));
+    let outdir_claims = OutdirClaims::default();
+    // Exit code of the first failure, so callers can tell e.g. a lint error
+    // from a push error without parsing the log.
+    let mut exit_code = 0;
+    let progress = Progress::new(recipes.len(), !args.ci && !args.no_progress && !json_logs);
 
     let (tx, rx) = sync::mpsc::channel();
     let log_manager = LogManager::new(tx.clone());
@@ -140,43 +429,84 @@ pub async fn run(args: BuildArgs, soar_env: Option<SoarEnv>) -> Result<(), Strin
         let warning = "⚠".bright_yellow().bold();
 
         while let Ok(log) = rx.recv() {
+            if json_logs {
+                match log {
+                    LogMessage::Info(msg) if !quiet => println!("{}", json_log_line("INFO", &msg)),
+                    LogMessage::Success(msg) if !quiet => {
+                        println!("{}", json_log_line("INFO", &msg))
+                    }
+                    LogMessage::Error(msg) | LogMessage::CustomError(msg) => {
+                        eprintln!("{}", json_log_line("ERROR", &msg))
+                    }
+                    LogMessage::Warn(msg) => eprintln!("{}", json_log_line("WARN", &msg)),
+                    LogMessage::Done => break,
+                    _ => {}
+                }
+                continue;
+            }
             match log {
-                LogMessage::Info(msg) => println!("{}", msg),
+                LogMessage::Info(msg) if !quiet => println!("{}", msg),
                 LogMessage::Error(msg) => eprintln!("[{}] {}", cross, msg),
                 LogMessage::Warn(msg) => eprintln!("[{}] {}", warning, msg),
-                LogMessage::Success(msg) => println!("[{}] {}", check, msg),
+                LogMessage::Success(msg) if !quiet => println!("[{}] {}", check, msg),
                 LogMessage::CustomError(msg) => eprintln!("{}", msg),
                 LogMessage::Done => break,
+                _ => {}
             }
         }
     });
 
-    for recipe_input in &args.recipes {
-        let (recipe_path, recipe_url) =
-            if recipe_input.starts_with("http://") || recipe_input.starts_with("https://") {
-                match fetch_recipe(recipe_input).await {
-                    Ok(content) => {
-                        let temp_path =
-                            std::env::temp_dir().join(format!("sbuild-{}.yaml", uuid_simple()));
-                        if let Err(e) = std::fs::write(&temp_path, &content) {
-                            error!("Failed to write temp recipe: {}", e);
-                            fail.fetch_add(1, Ordering::SeqCst);
-                            continue;
-                        }
-                        (
-                            temp_path.to_string_lossy().to_string(),
-                            Some(recipe_input.as_str()),
-                        )
+    for recipe_input in &recipes {
+        set_log_recipe(Some(recipe_input), "fetch");
+        let remote_url = sbuild::recipe_url(recipe_input);
+        let content = if recipe_input == "-" {
+            stdin_recipe.clone()
+        } else if let Some(url) = &remote_url {
+            match fetch_recipe(url).await {
+                Ok(content) => Some(content),
+                Err(e) => {
+                    error!("Failed to fetch recipe {}: {}", recipe_input, e);
+                    if args.ci {
+                        gha_error(
+                            Some(recipe_input),
+                            &format!("Failed to fetch recipe: {}", e),
+                        );
                     }
-                    Err(e) => {
-                        error!("Failed to fetch recipe {}: {}", recipe_input, e);
-                        fail.fetch_add(1, Ordering::SeqCst);
-                        continue;
+                    fail.fetch_add(1, Ordering::SeqCst);
+                    progress.inc();
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+
+        // Fetched and stdin recipes are built from a temp copy, removed
+        // afterwards unless --keep.
+        let temp_recipe = match content {
+            Some(content) => {
+                let temp_path = std::env::temp_dir().join(format!("sbuild-{}.yaml", uuid_simple()));
+                if let Err(e) = std::fs::write(&temp_path, &content) {
+                    error!("Failed to write temp recipe: {}", e);
+                    if args.ci {
+                        gha_error(
+                            Some(recipe_input),
+                            &format!("Failed to write temp recipe: {}", e),
+                        );
                     }
+                    fail.fetch_add(1, Ordering::SeqCst);
+                    progress.inc();
+                    continue;
                 }
-            } else {
-                (recipe_input.clone(), None)
-            };
+                Some(temp_path)
+            }
+            None => None,
+        };
+        let recipe_path = temp_recipe
+            .as_ref()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| recipe_input.clone());
+        let recipe_url = remote_url.as_deref();
 
         let named_temp_file = tempfile::Builder::new()
             .prefix("sbuild-log-")
@@ -184,7 +514,10 @@ pub async fn run(args: BuildArgs, soar_env: Option<SoarEnv>) -> Result<(), Strin
             .tempfile()
             .expect("Failed to create temp file");
         let tmp_file_path = named_temp_file.path().to_path_buf();
-        let logger = log_manager.create_logger(Some(tmp_file_path));
+        let mut logger = log_manager.create_logger(Some(tmp_file_path));
+        if args.log_prefix {
+            logger = logger.with_prefix(recipe_label(recipe_input));
+        }
 
         let now_time = chrono::Utc::now();
         logger.write_to_file(format!(
@@ -193,23 +526,41 @@ pub async fn run(args: BuildArgs, soar_env: Option<SoarEnv>) -> Result<(), Strin
             now_time.format("%A, %B %d, %Y %H:%M:%S UTC")
         ));
 
-        let mut builder = Builder::new(
-            logger.clone(),
-            soar_env.clone(),
-            true,
-            args.log_level.into(),
-            args.keep,
-            Duration::from_secs(args.timeout),
-        );
-
+        let new_builder = |logger: TaskLogger| {
+            Builder::new(
+                logger,
+                soar_env.clone(),
+                true,
+                args.log_level.into(),
+                args.keep,
+                Duration::from_secs(args.timeout),
+            )
+            .with_appstream_validation(args.validate_appstream)
+            .with_network_isolation(args.no_network)
+            .with_max_output_size(args.max_output_size)
+            .with_min_free_space(args.min_free_space)
+            .with_asset_cache(
+                args.asset_cache.clone(),
+                Duration::from_secs(args.asset_cache_max_age),
+            )
+            .with_output_layout(args.output_layout.into())
+            .with_outdir_claims(outdir_claims.clone())
+            .with_recipe_root(args.recipe_root.clone())
+            .with_desktop_integration_required(args.require_desktop_integration)
+        };
+        let mut builder =
+            new_builder(logger.clone()).with_skip_check(ghcr_skip_check(&args, recipe_url));
+
+        set_log_recipe(Some(recipe_input), "build");
         info!("Building: {}", recipe_input);
+        let recipe_started = Instant::now();
 
         let outdir_str = args
             .outdir
             .as_ref()
             .map(|p| p.to_string_lossy().to_string());
 
-        if let Some(build_outdir) = builder
+        match builder
             .build(
                 &recipe_path,
                 outdir_str.clone(),
@@ -218,42 +569,156 @@ pub async fn run(args: BuildArgs, soar_env: Option<SoarEnv>) -> Result<(), Strin
             )
             .await
         {
-            success.fetch_add(1, Ordering::SeqCst);
+            Ok(build_outdir) => {
+                trace!("Built {} in {:.2?}", recipe_input, recipe_started.elapsed());
+                success.fetch_add(1, Ordering::SeqCst);
+
+                if args.ci {
+                    write_github_env("SBUILD_SUCCESSFUL", "YES");
+                }
 
-            if args.ci {
-                write_github_env("SBUILD_SUCCESSFUL", "YES");
+                let build_log = build_outdir.join("BUILD.log");
+                if args.log_retain == LogRetain::OnFailure {
+                    let _ = fs::remove_file(&build_log);
+                } else if args.ci && !(args.push && args.ghcr_repo.is_some()) {
+                    // Pushed builds record the log's download URL instead
+                    write_github_output("build_log", &build_log.to_string_lossy());
+                }
+
+                if args.repro_check {
+                    let mut repro_logger = log_manager.create_logger(None::<PathBuf>);
+                    if args.log_prefix {
+                        repro_logger = repro_logger.with_prefix(recipe_label(recipe_input));
+                    }
+                    set_log_recipe(Some(recipe_input), "repro_check");
+                    match repro_check(
+                        new_builder(repro_logger),
+                        &recipe_path,
+                        &build_outdir,
+                        Duration::from_secs(args.timeout_linter),
+                    )
+                    .await
+                    {
+                        Ok(differing) if differing.is_empty() => {
+                            info!("{} is reproducible", recipe_input);
+                            if args.ci {
+                                write_github_output("reproducible", "true");
+                            }
+                        }
+                        Ok(differing) => {
+                            warn!(
+                                "{} is not reproducible; differing artifacts: {}",
+                                recipe_input,
+                                differing.join(", ")
+                            );
+                            if args.ci {
+                                gha_warning(
+                                    Some(recipe_input),
+                                    &format!("Build is not reproducible: {}", differing.join(", ")),
+                                );
+                                write_github_output("reproducible", "false");
+                            }
+                        }
+                        Err(e) => warn!("Reproducibility check for {} failed: {}", recipe_input, e),
+                    }
+                }
+
+                // The outdir isn't named after the package in every layout
+                let pkg_name = builder.build_config().map(|c| c.pkg_id.clone());
+                set_log_pkg(pkg_name.as_deref(), "post_build");
+                let pkg_type = match builder.pkg_type() {
+                    PackageType::Unknown => None,
+                    pkg_type => Some(pkg_type.to_string()),
+                };
+                if let Err(e) = post_build_processing(
+                    &build_outdir,
+                    &args,
+                    recipe_url,
+                    pkg_name.as_deref(),
+                    builder.build_config(),
+                    pkg_type,
+                )
+                .await
+                {
+                    error!("Post-build processing failed: {}", e);
+                    post_build_failed.store(true, Ordering::SeqCst);
+                    if exit_code == 0 {
+                        exit_code = e.exit_code();
+                    }
+                    if args.ci {
+                        gha_error(
+                            Some(recipe_input),
+                            &format!("Post-build processing failed: {}", e),
+                        );
+                        write_github_env("GHA_BUILD_FAILED", "YES");
+                    }
+                }
+            }
+            Err(BuildError::Skipped(reason)) => {
+                info!("Skipped {}: {}", recipe_input, reason);
+                skipped.fetch_add(1, Ordering::SeqCst);
+                if !args.dry_run {
+                    if let Some(config) = builder.build_config() {
+                        record_skip(args.cache.as_deref(), config, &reason).await;
+                    }
+                }
             }
+            Err(e) => {
+                error!("Build failed for {}: {}", recipe_input, e);
+                fail.fetch_add(1, Ordering::SeqCst);
+                if exit_code == 0 {
+                    exit_code = e.exit_code();
+                }
 
-            let pkg_name = build_outdir
-                .file_name()
-                .and_then(|n| n.to_str())
-                .map(|s| s.to_string());
-            if let Err(e) =
-                post_build_processing(&build_outdir, &args, recipe_url, pkg_name.as_deref()).await
-            {
-                error!("Post-build processing failed: {}", e);
-                post_build_failed.store(true, Ordering::SeqCst);
                 if args.ci {
+                    gha_error(Some(recipe_input), &format!("Build failed: {}", e));
+                    write_github_env("SBUILD_SUCCESSFUL", "NO");
                     write_github_env("GHA_BUILD_FAILED", "YES");
                 }
             }
-        } else {
-            fail.fetch_add(1, Ordering::SeqCst);
-
-            if args.ci {
-                write_github_env("SBUILD_SUCCESSFUL", "NO");
-                write_github_env("GHA_BUILD_FAILED", "YES");
-            }
         }
+        if let Some(temp_recipe) = temp_recipe.filter(|_| !args.keep) {
+            let _ = fs::remove_file(temp_recipe);
+        }
+        progress.inc();
     }
+    progress.finish();
 
     log_manager.done();
     logger_handle.join().unwrap();
+    set_log_recipe(None, "summary");
 
-    println!();
     let success_count = success.load(Ordering::SeqCst);
     let fail_count = fail.load(Ordering::SeqCst);
+    let skipped_count = skipped.load(Ordering::SeqCst);
     let total = success_count + fail_count;
+    let push_failed = post_build_failed.load(Ordering::SeqCst);
+
+    if args.ci {
+        write_github_output("success_count", &success_count.to_string());
+        write_github_output("fail_count", &fail_count.to_string());
+        write_github_output("skipped_count", &skipped_count.to_string());
+    }
+
+    if json_logs {
+        info!(
+            "{} of {} packages built successfully, {} failed, {} skipped, completed in {:.2?}",
+            success_count,
+            total,
+            fail_count,
+            skipped_count,
+            now.elapsed()
+        );
+        if push_failed {
+            error!("Post-build processing (e.g. GHCR push) failed");
+        }
+        if fail_count > 0 || push_failed {
+            std::process::exit(if exit_code == 0 { 1 } else { exit_code });
+        }
+        return Ok(());
+    }
+
+    println!();
 
     println!(
         "[{}] {} of {} packages built successfully",
@@ -270,14 +735,16 @@ pub async fn run(args: BuildArgs, soar_env: Option<SoarEnv>) -> Result<(), Strin
         );
     }
 
-    println!("[{}] Completed in {:.2?}", "⏱".bright_blue(), now.elapsed());
-
-    if args.ci {
-        write_github_output("success_count", &success_count.to_string());
-        write_github_output("fail_count", &fail_count.to_string());
+    if skipped_count > 0 {
+        println!(
+            "[{}] {} packages already on GHCR, skipped",
+            "-".bright_yellow().bold(),
+            skipped_count
+        );
     }
 
-    let push_failed = post_build_failed.load(Ordering::SeqCst);
+    println!("[{}] Completed in {:.2?}", "⏱".bright_blue(), now.elapsed());
+
     if push_failed {
         println!(
             "[{}] Post-build processing (e.g. GHCR push) failed",
@@ -286,18 +753,274 @@ pub async fn run(args: BuildArgs, soar_env: Option<SoarEnv>) -> Result<(), Strin
     }
 
     if fail_count > 0 || push_failed {
-        std::process::exit(1);
+        std::process::exit(if exit_code == 0 { 1 } else { exit_code });
     }
 
     Ok(())
 }
 
-fn init_logging(_ci_mode: bool, log_level: LogLevel) {
-    env_logger::Builder::new()
-        .filter_level(log_level.into())
-        .format_target(false)
-        .format_timestamp(None)
-        .init();
+/// Build the recipe again into a scratch directory and compare artifact
+/// digests with the first build's `outdir`. Returns the differing paths;
+/// BUILD.log always differs, so it's left out.
+async fn repro_check(
+    mut builder: Builder,
+    recipe_path: &str,
+    outdir: &Path,
+    timeout_linter: Duration,
+) -> Result<Vec<String>, String> {
+    let scratch = tempfile::Builder::new()
+        .prefix("sbuild-repro-")
+        .tempdir()
+        .map_err(|e| format!("Failed to create scratch directory: {}", e))?;
+    let rebuilt = builder
+        .build(
+            recipe_path,
+            Some(scratch.path().to_string_lossy().to_string()),
+            timeout_linter,
+            false,
+        )
+        .await
+        .map_err(|e| format!("second build failed: {}", e))?;
+
+    let listing = |dir: &Path| -> Result<Vec<checksum::FileEntry>, String> {
+        let mut entries = checksum::list_files(dir)
+            .map_err(|e| format!("Failed to list {}: {}", dir.display(), e))?;
+        entries.retain(|e| e.path != "BUILD.log");
+        Ok(entries)
+    };
+    Ok(checksum::diff_files(&listing(outdir)?, &listing(&rebuilt)?))
+}
+
+/// Version a build is published under, with what the cache knows about it
+struct PublishVersion {
+    /// The pkgver, with a `-rN` suffix once the same pkgver has been rebuilt
+    version: String,
+    revision: i32,
+    snapshots: Option<Vec<String>>,
+}
+
+/// Record a builder-side skip and its reason in the build cache
+/// (`SBUILD_CACHE_URI` first, then `cache`), creating the package if the
+/// cache hasn't seen it yet. Cache errors only warn.
+async fn record_skip(cache: Option<&Path>, config: &BuildConfig, reason: &str) {
+    let host = current_host_triplet();
+    match std::env::var("SBUILD_CACHE_URI") {
+        Ok(uri) if !uri.is_empty() => match sbuild_cache::MongoDatabase::connect(&uri).await {
+            Ok(mongo_db) => {
+                let result = match mongo_db
+                    .get_or_create_package(&config.pkg_id, &config.pkg, &host)
+                    .await
+                {
+                    Ok(_) => mongo_db.mark_skipped(&config.pkg_id, &host, reason).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    warn!("Failed to record skip in MongoDB cache: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to connect to MongoDB cache: {}", e),
+        },
+        Ok(_) => {}
+        Err(_) => match cache.map(sbuild_cache::CacheDatabase::open) {
+            Some(Ok(cache_db)) => {
+                let result = cache_db
+                    .get_or_create_package(&config.pkg_id, &config.pkg, &host)
+                    .and_then(|_| cache_db.mark_skipped(&config.pkg_id, &host, reason));
+                if let Err(e) = result {
+                    warn!("Failed to record skip in build cache: {}", e);
+                }
+            }
+            Some(Err(e)) => warn!("Failed to open build cache: {}", e),
+            None => {}
+        },
+    }
+}
+
+/// Look up the revision and snapshots of `pkg_id` in the build cache
+/// (`SBUILD_CACHE_URI` first, then `cache`). Shared by the push and the
+/// pre-build GHCR check so both agree on the tag.
+async fn publish_version(
+    cache: Option<&Path>,
+    pkg_id: &str,
+    host: &str,
+    base_version: &str,
+    remote_version: Option<&str>,
+) -> PublishVersion {
+    let (revision, snapshots) = match std::env::var("SBUILD_CACHE_URI") {
+        Ok(uri) if !uri.is_empty() => match sbuild_cache::MongoDatabase::connect(&uri).await {
+            Ok(mongo_db) => {
+                let rev = mongo_db
+                    .get_revision(pkg_id, host, base_version, remote_version, None)
+                    .await
+                    .ok();
+                let snapshots = mongo_db.get_snapshots(pkg_id, host).await.ok();
+                (rev, snapshots)
+            }
+            Err(e) => {
+                warn!("Failed to connect to MongoDB cache: {}", e);
+                (None, None)
+            }
+        },
+        Ok(_) => (None, None),
+        Err(_) => match cache.map(sbuild_cache::CacheDatabase::open) {
+            Some(Ok(cache_db)) => {
+                let rev = cache_db
+                    .get_revision(pkg_id, host, base_version, remote_version, None)
+                    .ok();
+                let snapshots = cache_db.get_snapshots(pkg_id, host).ok();
+                (rev, snapshots)
+            }
+            Some(Err(e)) => {
+                warn!("Failed to open build cache: {}", e);
+                (None, None)
+            }
+            None => (None, None),
+        },
+    };
+
+    match revision {
+        Some(rev) if rev > 0 => PublishVersion {
+            version: format!("{}-r{}", base_version, rev),
+            revision: rev,
+            snapshots,
+        },
+        _ => PublishVersion {
+            version: base_version.to_string(),
+            revision: 0,
+            snapshots,
+        },
+    }
+}
+
+/// When pushing without `--force`, skip recipes whose pkgver is already
+/// tagged in every GHCR repo the build would push to. Lookup errors never
+/// skip a build.
+fn ghcr_skip_check(args: &BuildArgs, recipe_url: Option<&str>) -> Option<SkipCheck> {
+    if args.force || args.ghcr_token.is_none() {
+        return None;
+    }
+    let base_repo = args.ghcr_repo.clone()?;
+    let recipe_url = recipe_url.map(str::to_string);
+    let cache = args.cache.clone();
+
+    Some(Arc::new(
+        move |validated: PathBuf, pkgver: String, remote_pkgver: Option<String>| {
+            let base_repo = base_repo.clone();
+            let recipe_url = recipe_url.clone();
+            let cache = cache.clone();
+            Box::pin(async move {
+                let recipe = SBuildRecipe::from_file(&validated).ok()?;
+                let (pkg_family, recipe_name) =
+                    sbuild::ghcr_family(recipe_url.as_deref(), &recipe.pkg_id);
+                let host = current_host_triplet();
+                let version = publish_version(
+                    cache.as_deref(),
+                    &recipe.pkg_id,
+                    &host,
+                    &pkgver,
+                    remote_pkgver.as_deref(),
+                )
+                .await
+                .version;
+                let tag = ghcr_tag(&version, &host);
+
+                let client = RegistryClient::new();
+                for pkg in recipe.get_provided_packages() {
+                    let repo = ghcr_repo_path(
+                        &base_repo,
+                        &pkg_family,
+                        &recipe_name,
+                        &pkg,
+                        recipe.ghcr_pkg.as_deref(),
+                    );
+                    match client.list_tags(&repo).await {
+                        Ok(list) if list.tags.contains(&tag) => {}
+                        Ok(_) => return None,
+                        Err(e) => {
+                            warn!("Couldn't check {} on GHCR, building: {}", repo, e);
+                            return None;
+                        }
+                    }
+                }
+                Some(format!("{} is already on GHCR", tag))
+            })
+        },
+    ))
+}
+
+/// On SIGINT/SIGTERM, tear down the running build and exit with the
+/// conventional 128+signal status.
+fn spawn_signal_handler() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async {
+        let (Ok(mut sigint), Ok(mut sigterm)) = (
+            signal(SignalKind::interrupt()),
+            signal(SignalKind::terminate()),
+        ) else {
+            warn!("Failed to install signal handlers; interrupted builds won't be cleaned up");
+            return;
+        };
+
+        let code = tokio::select! {
+            _ = sigint.recv() => 130,
+            _ = sigterm.recv() => 143,
+        };
+        warn!("Interrupted, cleaning up...");
+        sbuild::interrupt::cleanup();
+        std::process::exit(code);
+    });
+}
+
+fn init_logging(
+    _ci_mode: bool,
+    log_level: LogLevel,
+    log_format: LogFormat,
+    log_prefix: bool,
+    quiet: bool,
+) {
+    let level = if quiet {
+        LevelFilter::Warn
+    } else {
+        log_level.into()
+    };
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+    match log_format {
+        LogFormat::Text => {
+            builder
+                .write_style(super::log_write_style())
+                .format_target(false)
+                .format_timestamp(None);
+            if log_prefix {
+                builder.format(|buf, record| {
+                    use std::io::Write;
+                    let style = buf.default_level_style(record.level());
+                    writeln!(
+                        buf,
+                        "[{style}{:<5}{style:#}] {}",
+                        record.level(),
+                        prefixed_log_message(&record.args().to_string())
+                    )
+                });
+            }
+        }
+        LogFormat::Json => {
+            // Escape codes would end up inside the JSON message strings.
+            colored::control::set_override(false);
+            builder
+                .write_style(env_logger::WriteStyle::Never)
+                .format(|buf, record| {
+                    use std::io::Write;
+                    writeln!(
+                        buf,
+                        "{}",
+                        json_log_line(record.level().as_str(), &record.args().to_string())
+                    )
+                });
+        }
+    }
+    builder.init();
 }
 
 fn write_github_env(key: &str, value: &str) {
@@ -318,6 +1041,54 @@ fn write_github_output(key: &str, value: &str) {
     }
 }
 
+/// Write the first pushed BUILD.log URL to `GITHUB_OUTPUT` as `build_log`
+fn record_build_log(cli: &BuildArgs, annotations: &PackageAnnotations, recorded: &mut bool) {
+    if let Some(url) = annotations
+        .build_log
+        .as_ref()
+        .filter(|_| cli.ci && !*recorded)
+    {
+        write_github_output("build_log", url);
+        *recorded = true;
+    }
+}
+
+/// Print a GitHub Actions `::error` workflow command, optionally tied to a file.
+fn gha_error(file: Option<&str>, message: &str) {
+    gha_annotation("error", file, message);
+}
+
+/// Print a GitHub Actions `::warning` workflow command, optionally tied to a file.
+fn gha_warning(file: Option<&str>, message: &str) {
+    gha_annotation("warning", file, message);
+}
+
+fn gha_annotation(level: &str, file: Option<&str>, message: &str) {
+    match file {
+        Some(file) => println!(
+            "::{} file={}::{}",
+            level,
+            gha_escape_property(file),
+            gha_escape_data(message)
+        ),
+        None => println!("::{}::{}", level, gha_escape_data(message)),
+    }
+}
+
+/// Escape a workflow command message so multi-line errors stay in one annotation.
+fn gha_escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn gha_escape_property(value: &str) -> String {
+    gha_escape_data(value)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
 fn uuid_simple() -> String {
     use std::time::{SystemTime, UNIX_EPOCH};
     let duration = SystemTime::now()
@@ -326,22 +1097,76 @@ fn uuid_simple() -> String {
     format!("{:x}{:x}", duration.as_secs(), duration.subsec_nanos())
 }
 
-/// The binary file name a `provides` entry refers to: the part before any
-/// `:`, `==`, or `=>` separator, with a leading `@` stripped. For example
-/// `x_gui==x` -> `x_gui`, `@x` -> `x`.
-fn provide_binary_name(entry: &str) -> String {
-    let entry = entry.strip_prefix('@').unwrap_or(entry);
-    entry
-        .split("=>")
-        .next()
-        .unwrap_or(entry)
-        .split("==")
-        .next()
-        .unwrap_or(entry)
-        .split(':')
-        .next()
-        .unwrap_or(entry)
-        .to_string()
+const ICON_EXTENSIONS: &[&str] = &["png", "svg", "xpm"];
+
+/// File name of the first pushed file with one of the given extensions.
+fn pushed_file_name(files: &[PathBuf], extensions: &[&str]) -> Option<String> {
+    files
+        .iter()
+        .filter(|f| {
+            f.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| extensions.contains(&e))
+        })
+        .find_map(|f| f.file_name().and_then(|n| n.to_str()).map(String::from))
+}
+
+/// Recipe fields carried into the GHCR manifest annotations.
+///
+/// Taken from the linter-validated `BuildConfig` when the build ran in this
+/// process, falling back to the SBUILD copied into the output directory.
+struct RecipeAnnotations {
+    pkg_id: String,
+    pkg_type: Option<String>,
+    description: Option<Description>,
+    homepage: Option<String>,
+    license: Option<String>,
+}
+
+impl RecipeAnnotations {
+    fn new(
+        config: Option<&BuildConfig>,
+        metadata: Option<&SBuildRecipe>,
+        detected_pkg_type: Option<&str>,
+    ) -> Self {
+        let non_empty = |s: String| (!s.is_empty()).then_some(s);
+
+        match config {
+            Some(config) => Self {
+                pkg_id: config.pkg_id.clone(),
+                pkg_type: config
+                    .pkg_type
+                    .clone()
+                    .or_else(|| detected_pkg_type.map(String::from)),
+                description: config.description.clone(),
+                homepage: config.homepage.as_ref().and_then(|h| h.first().cloned()),
+                license: config
+                    .license
+                    .as_ref()
+                    .and_then(|l| non_empty(l.join(", "))),
+            },
+            None => Self {
+                pkg_id: metadata.map(|m| m.pkg_id.clone()).unwrap_or_default(),
+                pkg_type: metadata
+                    .and_then(|m| m.pkg_type.clone())
+                    .or_else(|| detected_pkg_type.map(String::from)),
+                description: metadata
+                    .and_then(|m| non_empty(m.description.clone()))
+                    .map(Description::Simple),
+                homepage: metadata.and_then(|m| m.homepage.first().cloned()),
+                license: metadata.and_then(|m| non_empty(m.license.join(", "))),
+            },
+        }
+    }
+
+    /// Description for `pkg`; per-package descriptions fall back to the first entry.
+    fn description_for(&self, pkg: &str) -> Option<String> {
+        match self.description.as_ref()? {
+            Description::Simple(desc) => Some(desc.clone()),
+            Description::Map(map) => map.get(pkg).or_else(|| map.values().next()).cloned(),
+        }
+        .filter(|s| !s.is_empty())
+    }
 }
 
 fn sign_file(signer: &Signer, file_path: &Path) -> Option<PathBuf> {
@@ -363,27 +1188,73 @@ fn sign_file(signer: &Signer, file_path: &Path) -> Option<PathBuf> {
     }
 }
 
+/// Write `<name>.files.json`, warning instead of failing the build
+fn write_artifact_manifest(outdir: &Path, name: &str, cli: &BuildArgs) -> Option<PathBuf> {
+    match checksum::write_files_manifest(outdir, name) {
+        Ok(path) => {
+            info!("Wrote artifact manifest {}", path.display());
+            Some(path)
+        }
+        Err(e) => {
+            warn!("Failed to write artifact manifest: {}", e);
+            if cli.ci {
+                gha_warning(None, &format!("Failed to write artifact manifest: {}", e));
+            }
+            None
+        }
+    }
+}
+
+/// Add the artifact manifest to a pending push and reference it in the
+/// annotations
+fn attach_artifact_manifest(push: &mut PendingPush, manifest: &Path) {
+    if !push.files.iter().any(|f| f == manifest) {
+        push.files.push(manifest.to_path_buf());
+    }
+    push.annotations.files_manifest = manifest
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(String::from);
+}
+
+/// A package ready to push: signed, with its JSON metadata updated
+struct PendingPush {
+    pkg: String,
+    repo: String,
+    files: Vec<PathBuf>,
+    annotations: PackageAnnotations,
+}
+
 async fn post_build_processing(
     outdir: &Path,
     cli: &BuildArgs,
     recipe_url: Option<&str>,
     pkg_name: Option<&str>,
-) -> Result<(), String> {
-    use sbuild::parse_ghcr_path;
-
+    build_config: Option<&BuildConfig>,
+    detected_pkg_type: Option<String>,
+) -> Result<(), BuildError> {
     if cli.checksums {
         info!("Generating checksums...");
         match checksum::generate_checksum_file(outdir) {
             Ok(_) => info!("Checksums generated"),
-            Err(e) => warn!("Failed to generate checksums: {}", e),
+            Err(e) => {
+                warn!("Failed to generate checksums: {}", e);
+                if cli.ci {
+                    gha_warning(None, &format!("Failed to generate checksums: {}", e));
+                }
+            }
         }
     }
 
+    let manifest_name = build_config
+        .map(|c| c.pkg.as_str())
+        .or(pkg_name)
+        .unwrap_or("package");
+    write_artifact_manifest(outdir, manifest_name, cli);
+
     let signer = if cli.sign {
         if let Some(ref key) = cli.minisign_key {
-            if let Err(e) = Signer::check_minisign() {
-                return Err(format!("Signing failed: {}", e));
-            }
+            Signer::check_minisign()?;
 
             let s = if Path::new(key).exists() {
                 Signer::with_key_file(key)
@@ -395,6 +1266,9 @@ async fn post_build_processing(
             Some(s)
         } else {
             warn!("--sign specified but no --minisign-key provided");
+            if cli.ci {
+                gha_warning(None, "--sign specified but no --minisign-key provided");
+            }
             None
         }
     } else {
@@ -410,16 +1284,12 @@ async fn post_build_processing(
             }
 
             if !cli.dry_run {
-                if let Err(e) = GhcrClient::check_oras() {
-                    return Err(format!("GHCR push failed: {}", e));
-                }
+                GhcrClient::check_oras()?;
             }
 
             let client = if !cli.dry_run {
-                let c = GhcrClient::new(token.clone());
-                if let Err(e) = c.login() {
-                    return Err(format!("GHCR login failed: {}", e));
-                }
+                let c = GhcrClient::new(token.clone()).with_retries(cli.push_retries);
+                c.login()?;
                 Some(c)
             } else {
                 None
@@ -463,96 +1333,58 @@ async fn post_build_processing(
                 }
             };
 
-            let arch = format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS);
+            let arch = current_host_triplet();
 
             // Fetch revision and snapshots from cache
-            let (version, revision, db_snapshots) = {
-                let meta = read_recipe_metadata(outdir);
-                let cache_pkg_id = meta
-                    .as_ref()
-                    .map(|m| m.pkg_id.as_str())
-                    .filter(|s| !s.is_empty())
-                    .unwrap_or_else(|| pkg_name.unwrap_or("unknown"));
-                let host = arch.to_lowercase();
-
-                let (rev_result, snapshots_result) =
-                    if let Ok(uri) = std::env::var("SBUILD_CACHE_URI") {
-                        if !uri.is_empty() {
-                            match sbuild_cache::MongoDatabase::connect(&uri).await {
-                                Ok(mongo_db) => {
-                                    let rev = mongo_db
-                                        .get_revision(
-                                            cache_pkg_id,
-                                            &host,
-                                            &base_version,
-                                            remote_version.as_deref(),
-                                            None,
-                                        )
-                                        .await
-                                        .ok();
-                                    let snapshots =
-                                        mongo_db.get_snapshots(cache_pkg_id, &host).await.ok();
-                                    (rev, snapshots)
-                                }
-                                Err(e) => {
-                                    warn!("Failed to connect to MongoDB cache: {}", e);
-                                    (None, None)
-                                }
-                            }
-                        } else {
-                            (None, None)
-                        }
-                    } else if let Some(ref cache_path) = cli.cache {
-                        match sbuild_cache::CacheDatabase::open(cache_path) {
-                            Ok(cache_db) => {
-                                let rev = cache_db
-                                    .get_revision(
-                                        cache_pkg_id,
-                                        &host,
-                                        &base_version,
-                                        remote_version.as_deref(),
-                                        None,
-                                    )
-                                    .ok();
-                                let snapshots = cache_db.get_snapshots(cache_pkg_id, &host).ok();
-                                (rev, snapshots)
-                            }
-                            Err(e) => {
-                                warn!("Failed to open build cache: {}", e);
-                                (None, None)
-                            }
-                        }
-                    } else {
-                        (None, None)
-                    };
-
-                let versioned = match rev_result {
-                    Some(rev) if rev > 0 => {
-                        let versioned = format!("{}-r{}", base_version, rev);
-                        info!(
-                            "Revision {}: version {} -> {}",
-                            rev, base_version, versioned
-                        );
-                        (versioned, rev)
-                    }
-                    _ => (base_version.clone(), 0),
-                };
-
-                (versioned.0, versioned.1, snapshots_result)
-            };
+            let cache_pkg_id = read_recipe_metadata(outdir)
+                .map(|m| m.pkg_id)
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| pkg_name.unwrap_or("unknown").to_string());
+            let PublishVersion {
+                version,
+                revision,
+                snapshots: db_snapshots,
+            } = publish_version(
+                cli.cache.as_deref(),
+                &cache_pkg_id,
+                &arch,
+                &base_version,
+                remote_version.as_deref(),
+            )
+            .await;
+            if revision > 0 {
+                info!(
+                    "Revision {}: version {} -> {}",
+                    revision, base_version, version
+                );
+            }
 
-            let tag = format!("{}-{}", sanitize_oci_tag(&version), arch.to_lowercase());
+            let tag = ghcr_tag(&version, &arch);
+            let tags: Vec<String> = std::iter::once(tag.clone())
+                .chain(
+                    cli.extra_tags
+                        .iter()
+                        .map(|extra| format!("{}-{}", sanitize_oci_tag(extra), arch)),
+                )
+                .collect();
 
             let (pkg_family, recipe_name) =
-                recipe_url.and_then(parse_ghcr_path).unwrap_or_else(|| {
-                    let default = pkg_name.unwrap_or("unknown").to_string();
-                    (default.clone(), default)
-                });
+                sbuild::ghcr_family(recipe_url, pkg_name.unwrap_or("unknown"));
 
             let metadata = read_recipe_metadata(outdir);
+            let recipe = RecipeAnnotations::new(
+                build_config,
+                metadata.as_ref(),
+                detected_pkg_type.as_deref(),
+            );
 
             let mut push_success = true;
             let mut pushed_urls = Vec::new();
+            let mut build_log_recorded = false;
+
+            // Everything is signed and annotated before anything is pushed, so
+            // the artifact manifest can list the final files
+            let mut pending: Vec<PendingPush> = Vec::new();
 
             let packages_dir = outdir.join("packages");
 
@@ -560,7 +1392,7 @@ async fn post_build_processing(
                 info!("Found packages/ directory, using explicit package structure");
 
                 let shared_files: Vec<PathBuf> = std::fs::read_dir(outdir)
-                    .map_err(|e| format!("Failed to read output directory: {}", e))?
+                    .map_err(|e| BuildError::io(e, "Failed to read output directory"))?
                     .filter_map(|e| e.ok())
                     .map(|e| e.path())
                     .filter(|p| p.is_file())
@@ -572,7 +1404,7 @@ async fn post_build_processing(
                         meta.get_provided_packages()
                     } else {
                         std::fs::read_dir(&packages_dir)
-                            .map_err(|e| format!("Failed to read packages directory: {}", e))?
+                            .map_err(|e| BuildError::io(e, "Failed to read packages directory"))?
                             .filter_map(|e| e.ok())
                             .filter(|e| e.path().is_dir())
                             .filter_map(|e| e.path().file_name()?.to_str().map(|s| s.to_string()))
@@ -580,7 +1412,7 @@ async fn post_build_processing(
                     }
                 } else {
                     std::fs::read_dir(&packages_dir)
-                        .map_err(|e| format!("Failed to read packages directory: {}", e))?
+                        .map_err(|e| BuildError::io(e, "Failed to read packages directory"))?
                         .filter_map(|e| e.ok())
                         .filter(|e| e.path().is_dir())
                         .filter_map(|e| e.path().file_name()?.to_str().map(|s| s.to_string()))
@@ -595,24 +1427,19 @@ async fn post_build_processing(
                 for pkg_name_dir in &package_names {
                     let pkg_dir = packages_dir.join(pkg_name_dir);
 
-                    let sanitized_pkg_name = sanitize_oci_name(pkg_name_dir);
-                    let owner = base_repo.split('/').next().unwrap_or(base_repo);
-                    let full_repo = if let Some(ref custom_base) =
-                        metadata.as_ref().and_then(|m| m.ghcr_pkg.as_ref())
-                    {
-                        format!("{}/{}/{}", owner, custom_base, sanitized_pkg_name)
-                    } else {
-                        format!(
-                            "{}/{}/{}/{}",
-                            base_repo, pkg_family, recipe_name, sanitized_pkg_name
-                        )
-                    };
+                    let full_repo = ghcr_repo_path(
+                        base_repo,
+                        &pkg_family,
+                        &recipe_name,
+                        pkg_name_dir,
+                        metadata.as_ref().and_then(|m| m.ghcr_pkg.as_deref()),
+                    );
                     info!("Pushing package {} to {}", pkg_name_dir, full_repo);
 
                     // Collect files from the package directory
                     let mut files_to_push: Vec<PathBuf> = if pkg_dir.is_dir() {
                         std::fs::read_dir(&pkg_dir)
-                            .map_err(|e| format!("Failed to read package directory: {}", e))?
+                            .map_err(|e| BuildError::io(e, "Failed to read package directory"))?
                             .filter_map(|e| e.ok())
                             .map(|e| e.path())
                             .filter(|p| p.is_file())
@@ -652,7 +1479,7 @@ async fn post_build_processing(
                             provides
                                 .iter()
                                 .find(|p| !p.starts_with('@'))
-                                .map(|p| provide_binary_name(p))
+                                .map(|p| provide_base(p).to_string())
                         });
                     let main_binary = [main_binary_name.as_deref(), Some(pkg_name_dir.as_str())]
                         .into_iter()
@@ -660,9 +1487,7 @@ async fn post_build_processing(
                         .find_map(|name| {
                             files_to_push
                                 .iter()
-                                .find(|f| {
-                                    f.file_name().and_then(|n| n.to_str()) == Some(name)
-                                })
+                                .find(|f| f.file_name().and_then(|n| n.to_str()) == Some(name))
                                 .cloned()
                         });
 
@@ -732,21 +1557,12 @@ async fn post_build_processing(
 
                     let annotations = PackageAnnotations {
                         pkg: pkg_name_dir.to_string(),
-                        pkg_id: metadata
-                            .as_ref()
-                            .map(|m| m.pkg_id.clone())
-                            .unwrap_or_default(),
-                        pkg_type: metadata.as_ref().and_then(|m| m.pkg_type.clone()),
+                        pkg_id: recipe.pkg_id.clone(),
+                        pkg_type: recipe.pkg_type.clone(),
                         version: version.clone(),
-                        description: metadata
-                            .as_ref()
-                            .map(|m| m.description.clone())
-                            .filter(|s| !s.is_empty()),
-                        homepage: metadata.as_ref().and_then(|m| m.homepage.first().cloned()),
-                        license: metadata
-                            .as_ref()
-                            .map(|m| m.license.join(", "))
-                            .filter(|s| !s.is_empty()),
+                        description: recipe.description_for(pkg_name_dir),
+                        homepage: recipe.homepage.clone(),
+                        license: recipe.license.clone(),
                         build_date: chrono::Utc::now().to_rfc3339(),
                         build_id: env::var("GITHUB_RUN_ID").ok(),
                         build_gha: env::var("GITHUB_RUN_ID").ok().map(|id| {
@@ -760,42 +1576,26 @@ async fn post_build_processing(
                         bsum,
                         shasum,
                         checksum_bsum,
+                        provides: pkg_provides.map(|p| p.to_vec()).unwrap_or_default(),
+                        icon: pushed_file_name(&files_to_push, ICON_EXTENSIONS),
+                        desktop: pushed_file_name(&files_to_push, &["desktop"]),
+                        build_log: files_to_push
+                            .iter()
+                            .any(|f| f.ends_with("BUILD.log"))
+                            .then(|| download_url(&full_repo, &tag, "BUILD.log")),
+                        files_manifest: None,
                     };
 
-                    if cli.dry_run {
-                        let target = format!("ghcr.io/{}:{}", full_repo, tag);
-                        info!(
-                            "[DRY-RUN] Would push {} files to {}",
-                            files_to_push.len(),
-                            target
-                        );
-                        for f in &files_to_push {
-                            let name = f.file_name().and_then(|n| n.to_str()).unwrap_or("?");
-                            let size = fs::metadata(f).ok().map(|m| m.len()).unwrap_or(0);
-                            info!("  - {} ({} bytes)", name, size);
-                        }
-                        pushed_urls.push(target);
-                    } else {
-                        match client.as_ref().unwrap().push(
-                            &files_to_push,
-                            &full_repo,
-                            &tag,
-                            &annotations,
-                        ) {
-                            Ok(target) => {
-                                info!("Pushed {} to {}", pkg_name_dir, target);
-                                pushed_urls.push(target);
-                            }
-                            Err(e) => {
-                                error!("Failed to push {}: {}", pkg_name_dir, e);
-                                push_success = false;
-                            }
-                        }
-                    }
+                    pending.push(PendingPush {
+                        pkg: pkg_name_dir.to_string(),
+                        repo: full_repo,
+                        files: files_to_push,
+                        annotations,
+                    });
                 }
             } else {
                 let all_files: Vec<PathBuf> = std::fs::read_dir(outdir)
-                    .map_err(|e| format!("Failed to read output directory: {}", e))?
+                    .map_err(|e| BuildError::io(e, "Failed to read output directory"))?
                     .filter_map(|e| e.ok())
                     .map(|e| e.path())
                     .filter(|p| p.is_file() && !p.is_symlink())
@@ -821,18 +1621,13 @@ async fn post_build_processing(
                 }
 
                 for pkg_name_item in &packages_to_push {
-                    let sanitized_pkg_name = sanitize_oci_name(pkg_name_item);
-                    let owner = base_repo.split('/').next().unwrap_or(base_repo);
-                    let full_repo = if let Some(ref custom_base) =
-                        metadata.as_ref().and_then(|m| m.ghcr_pkg.as_ref())
-                    {
-                        format!("{}/{}/{}", owner, custom_base, sanitized_pkg_name)
-                    } else {
-                        format!(
-                            "{}/{}/{}/{}",
-                            base_repo, pkg_family, recipe_name, sanitized_pkg_name
-                        )
-                    };
+                    let full_repo = ghcr_repo_path(
+                        base_repo,
+                        &pkg_family,
+                        &recipe_name,
+                        pkg_name_item,
+                        metadata.as_ref().and_then(|m| m.ghcr_pkg.as_deref()),
+                    );
                     info!("Pushing {} to {}", pkg_name_item, full_repo);
 
                     let pkg_provides: Vec<String> = metadata
@@ -872,8 +1667,7 @@ async fn post_build_processing(
 
                     for provide in &pkg_provides {
                         if provide.starts_with('@') {
-                            let bin_name = provide.strip_prefix('@').unwrap();
-                            let bin_path = outdir.join(bin_name);
+                            let bin_path = outdir.join(provide_base(provide));
                             if bin_path.exists() && !binaries_to_sign.contains(&bin_path) {
                                 binaries_to_sign.push(bin_path);
                             }
@@ -938,21 +1732,12 @@ async fn post_build_processing(
 
                     let annotations = PackageAnnotations {
                         pkg: main_binary_name.clone(),
-                        pkg_id: metadata
-                            .as_ref()
-                            .map(|m| m.pkg_id.clone())
-                            .unwrap_or_default(),
-                        pkg_type: metadata.as_ref().and_then(|m| m.pkg_type.clone()),
+                        pkg_id: recipe.pkg_id.clone(),
+                        pkg_type: recipe.pkg_type.clone(),
                         version: version.clone(),
-                        description: metadata
-                            .as_ref()
-                            .map(|m| m.description.clone())
-                            .filter(|s| !s.is_empty()),
-                        homepage: metadata.as_ref().and_then(|m| m.homepage.first().cloned()),
-                        license: metadata
-                            .as_ref()
-                            .map(|m| m.license.join(", "))
-                            .filter(|s| !s.is_empty()),
+                        description: recipe.description_for(&main_binary_name),
+                        homepage: recipe.homepage.clone(),
+                        license: recipe.license.clone(),
                         build_date: chrono::Utc::now().to_rfc3339(),
                         build_id: env::var("GITHUB_RUN_ID").ok(),
                         build_gha: env::var("GITHUB_RUN_ID").ok().map(|id| {
@@ -966,36 +1751,68 @@ async fn post_build_processing(
                         bsum,
                         shasum,
                         checksum_bsum,
+                        provides: pkg_provides,
+                        icon: pushed_file_name(&files_to_push, ICON_EXTENSIONS),
+                        desktop: pushed_file_name(&files_to_push, &["desktop"]),
+                        build_log: files_to_push
+                            .iter()
+                            .any(|f| f.ends_with("BUILD.log"))
+                            .then(|| download_url(&full_repo, &tag, "BUILD.log")),
+                        files_manifest: None,
                     };
 
-                    if cli.dry_run {
-                        let target = format!("ghcr.io/{}:{}", full_repo, tag);
-                        info!(
-                            "[DRY-RUN] Would push {} files to {}",
-                            files_to_push.len(),
-                            target
-                        );
-                        for f in &files_to_push {
-                            let name = f.file_name().and_then(|n| n.to_str()).unwrap_or("?");
-                            let size = fs::metadata(f).ok().map(|m| m.len()).unwrap_or(0);
-                            info!("  - {} ({} bytes)", name, size);
+                    pending.push(PendingPush {
+                        pkg: pkg_name_item.to_string(),
+                        repo: full_repo,
+                        files: files_to_push,
+                        annotations,
+                    });
+                }
+            }
+
+            // Pick up the .sig files and updated JSON metadata
+            if !pending.is_empty() {
+                if let Some(manifest) = write_artifact_manifest(outdir, manifest_name, cli) {
+                    for push in &mut pending {
+                        attach_artifact_manifest(push, &manifest);
+                    }
+                }
+            }
+
+            for PendingPush {
+                pkg,
+                repo,
+                files,
+                annotations,
+            } in &pending
+            {
+                if cli.dry_run {
+                    let target = format!("ghcr.io/{}:{}", repo, tag);
+                    info!("[DRY-RUN] Would push {} files to {}", files.len(), target);
+                    if tags.len() > 1 {
+                        info!("[DRY-RUN] Additional tags: {}", tags[1..].join(", "));
+                    }
+                    for f in files {
+                        let name = f.file_name().and_then(|n| n.to_str()).unwrap_or("?");
+                        let size = fs::metadata(f).ok().map(|m| m.len()).unwrap_or(0);
+                        info!("  - {} ({} bytes)", name, size);
+                    }
+                    pushed_urls.push(target);
+                } else {
+                    match client
+                        .as_ref()
+                        .unwrap()
+                        .push(files, repo, &tags, annotations)
+                        .await
+                    {
+                        Ok(target) => {
+                            info!("Pushed {} to {}", pkg, target);
+                            record_build_log(cli, annotations, &mut build_log_recorded);
+                            pushed_urls.push(target);
                         }
-                        pushed_urls.push(target);
-                    } else {
-                        match client.as_ref().unwrap().push(
-                            &files_to_push,
-                            &full_repo,
-                            &tag,
-                            &annotations,
-                        ) {
-                            Ok(target) => {
-                                info!("Pushed {} to {}", pkg_name_item, target);
-                                pushed_urls.push(target);
-                            }
-                            Err(e) => {
-                                error!("Failed to push {}: {}", pkg_name_item, e);
-                                push_success = false;
-                            }
+                        Err(e) => {
+                            error!("Failed to push {}: {}", pkg, e);
+                            push_success = false;
                         }
                     }
                 }
@@ -1017,7 +1834,7 @@ async fn post_build_processing(
                     .map(|m| m.pkg_id.as_str())
                     .filter(|s| !s.is_empty())
                     .unwrap_or_else(|| pkg_name.unwrap_or("unknown"));
-                let host = arch.to_lowercase();
+                let host = arch.clone();
                 let build_id = env::var("GITHUB_RUN_ID").ok();
                 let cache_pkg_name = meta
                     .as_ref()
@@ -1031,14 +1848,24 @@ async fn post_build_processing(
                         id
                     )
                 });
+                let cache_pkg = sbuild_cache::PackageRecordInit {
+                    build_script: recipe_url.map(String::from),
+                    ..sbuild_cache::PackageRecordInit::new(cache_pkg_id, cache_pkg_name, &host)
+                }
+                .with_family(pkg_family.as_str())
+                .with_ghcr_pkg(ghcr_repo_path(
+                    base_repo,
+                    &pkg_family,
+                    &recipe_name,
+                    cache_pkg_name,
+                    meta.as_ref().and_then(|m| m.ghcr_pkg.as_deref()),
+                ));
 
                 if let Ok(uri) = std::env::var("SBUILD_CACHE_URI") {
                     if !uri.is_empty() {
                         match sbuild_cache::MongoDatabase::connect(&uri).await {
                             Ok(mongo_db) => {
-                                let _ = mongo_db
-                                    .get_or_create_package(cache_pkg_id, cache_pkg_name, &host)
-                                    .await;
+                                let _ = mongo_db.create_package_full(&cache_pkg).await;
                                 if let Err(e) = mongo_db
                                     .update_build_result(
                                         cache_pkg_id,
@@ -1054,6 +1881,7 @@ async fn post_build_processing(
                                         None,
                                         None,
                                         build_log_url.as_deref(),
+                                        None,
                                     )
                                     .await
                                 {
@@ -1067,7 +1895,7 @@ async fn post_build_processing(
                     }
                 } else if let Some(ref cache_path) = cli.cache {
                     if let Ok(cache_db) = sbuild_cache::CacheDatabase::open(cache_path) {
-                        let _ = cache_db.get_or_create_package(cache_pkg_id, cache_pkg_name, &host);
+                        let _ = cache_db.create_package_full(&cache_pkg);
                         if let Err(e) = cache_db.update_build_result(
                             cache_pkg_id,
                             &host,
@@ -1079,6 +1907,7 @@ async fn post_build_processing(
                             Some(&base_version),
                             remote_version.as_deref(),
                             revision,
+                            None,
                         ) {
                             warn!("Failed to update build cache: {}", e);
                         } else {
@@ -1089,12 +1918,84 @@ async fn post_build_processing(
             }
 
             if !push_success {
-                return Err("One or more GHCR pushes failed".to_string());
+                return Err(BuildError::Push(
+                    "one or more GHCR pushes failed".to_string(),
+                ));
             }
         } else {
             warn!("--push specified but --ghcr-token or --ghcr-repo not provided");
+            if cli.ci {
+                gha_warning(
+                    None,
+                    "--push specified but --ghcr-token or --ghcr-repo not provided",
+                );
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recipe_label() {
+        assert_eq!(recipe_label("binaries/htop/static.yaml"), "htop/static");
+        assert_eq!(recipe_label("/tmp/hello.SBUILD"), "hello");
+        assert_eq!(recipe_label("-"), "stdin");
+    }
+
+    #[test]
+    fn test_log_messages_carry_the_recipe_label() {
+        set_log_recipe(Some("packages/htop/appimage.yaml"), "build");
+        assert_eq!(
+            prefixed_log_message("Fetching\nDone"),
+            "[htop/appimage] Fetching\n[htop/appimage] Done"
+        );
+        set_log_recipe(None, "summary");
+        assert_eq!(prefixed_log_message("Summary"), "Summary");
+    }
+
+    #[test]
+    fn test_shard_from_str() {
+        let shard: Shard = "2/3".parse().unwrap();
+        assert_eq!((shard.index, shard.count), (2, 3));
+        for bad in ["0/3", "4/3", "1/0", "a/b", "1", "1/-2"] {
+            assert!(bad.parse::<Shard>().is_err(), "{} parsed", bad);
+        }
+    }
+
+    #[test]
+    fn test_shards_partition_recipes() {
+        let recipes: Vec<String> = (0..200).map(|i| format!("recipes/{}.yaml", i)).collect();
+        let shards: Vec<Shard> = (1..=4)
+            .map(|i| format!("{}/4", i).parse().unwrap())
+            .collect();
+        for recipe in &recipes {
+            let owners = shards.iter().filter(|s| s.contains(recipe)).count();
+            assert_eq!(owners, 1, "{} is in {} shards", recipe, owners);
+        }
+        assert!(shards.iter().all(|s| recipes.iter().any(|r| s.contains(r))));
+    }
+
+    #[test]
+    fn test_selected_recipes_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("recipes.txt");
+        fs::write(&list, "# comment\na.yaml\n\n  b.yaml  \n   \n#c.yaml\n").unwrap();
+        let args = BuildArgs::try_parse_from([
+            "build",
+            "first.yaml",
+            "--from-file",
+            &list.to_string_lossy(),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            selected_recipes(&args).unwrap(),
+            ["first.yaml", "a.yaml", "b.yaml"]
+        );
+    }
+}
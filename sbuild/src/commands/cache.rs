@@ -1,7 +1,15 @@
 use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
-use sbuild_cache::{BuildStatus, CacheDatabase, MongoDatabase, Result};
+use sbuild::{parse_ghcr_path, utils::parse_duration};
+use sbuild_cache::{
+    normalize_host_triplet, BackoffPolicy, BuildStats, BuildStatus, CacheDatabase, DailyBuildStats,
+    MongoDatabase, PackageRecord, PackageRecordInit, Result,
+};
+use sbuild_meta::{
+    hash::{compute_recipe_hash_with, HashOptions},
+    recipe::scan_recipes,
+};
 
 #[derive(Parser)]
 #[command(about = "Build cache management for SBUILD packages")]
@@ -19,17 +27,37 @@ enum StatusFilter {
     Failed,
     Pending,
     Skipped,
+    Timeout,
+    Cancelled,
     Outdated,
     All,
 }
 
-#[derive(Clone, ValueEnum)]
+#[derive(Clone, Copy, ValueEnum)]
 enum ReportFormat {
     Markdown,
     Html,
     Json,
 }
 
+#[derive(Clone, ValueEnum)]
+enum ExportFormat {
+    /// MongoDB cache (SBUILD_CACHE_URI) to a SQLite file
+    Sqlite,
+    /// Local SQLite cache (--cache) to a portable JSON document
+    Json,
+}
+
+impl ExportFormat {
+    /// File written when `--output` isn't given
+    fn default_output(&self) -> PathBuf {
+        match self {
+            ExportFormat::Sqlite => PathBuf::from("build_cache.sdb"),
+            ExportFormat::Json => PathBuf::from("build_cache.json"),
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum CacheCommands {
     Init,
@@ -38,7 +66,7 @@ enum CacheCommands {
         #[arg(short, long)]
         package: String,
 
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(short, long)]
@@ -55,13 +83,48 @@ enum CacheCommands {
 
         #[arg(long)]
         hash: Option<String>,
+
+        /// Error message recorded with a failed status
+        #[arg(short, long)]
+        error: Option<String>,
+
+        /// Why the build was skipped, recorded with a skipped status
+        #[arg(long)]
+        skip_reason: Option<String>,
+
+        /// Retry delay after the first failure, in minutes [default: 60].
+        /// SQLite caches only.
+        #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+        backoff_base_minutes: Option<i64>,
+
+        /// Longest retry delay, in hours [default: 24]. SQLite caches only.
+        #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+        backoff_max_hours: Option<i64>,
+
+        /// Factor the retry delay grows by with each further failure, at
+        /// least 1 [default: 2]. SQLite caches only.
+        #[arg(long, value_parser = parse_backoff_multiplier)]
+        backoff_multiplier: Option<f64>,
+    },
+
+    /// Record a skipped build and why it was skipped
+    MarkSkipped {
+        #[arg(short, long)]
+        package: String,
+
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
+        host: String,
+
+        /// e.g. "already built", "disabled", "host unsupported", "backoff"
+        #[arg(short, long)]
+        reason: String,
     },
 
     MarkOutdated {
         #[arg(short, long)]
         package: String,
 
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(short, long)]
@@ -69,15 +132,19 @@ enum CacheCommands {
     },
 
     Stats {
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
+        /// Report every host in the cache, plus totals
+        #[arg(long, conflicts_with = "host")]
+        all_hosts: bool,
+
         #[arg(long)]
         json: bool,
     },
 
     List {
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(short, long, value_enum, default_value = "all")]
@@ -90,8 +157,46 @@ enum CacheCommands {
         limit: Option<usize>,
     },
 
+    /// Packages in failure backoff, soonest retry first
+    Failures {
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
+        host: String,
+
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Claim the next package needing a rebuild for a worker
+    Claim {
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
+        host: String,
+
+        /// Worker identifier recorded on the claim
+        #[arg(short, long)]
+        worker: String,
+
+        /// Minutes after which another worker's claim is considered stale
+        #[arg(long, default_value = "360")]
+        claim_timeout: i64,
+
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Release a worker's claim without recording a build result
+    Release {
+        #[arg(short, long)]
+        package: String,
+
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
+        host: String,
+
+        #[arg(short, long)]
+        worker: String,
+    },
+
     NeedsRebuild {
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(long)]
@@ -99,7 +204,7 @@ enum CacheCommands {
     },
 
     Report {
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(short, long, value_enum, default_value = "markdown")]
@@ -110,15 +215,39 @@ enum CacheCommands {
 
         #[arg(long, default_value = "20")]
         history_limit: i64,
+
+        /// Markdown/HTML template with {{placeholder}} fields (host, generated,
+        /// total, successful, failed, pending, outdated, success_rate, stats,
+        /// failed_table, outdated_table, recent_table, trend)
+        #[arg(long)]
+        template: Option<PathBuf>,
+
+        /// Days of daily success-rate trend to include (0 to omit)
+        #[arg(long, default_value = "14")]
+        trend_days: i64,
+
+        /// Previous JSON report to compare against
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+
+        /// Exit 1 if a previously successful package now fails or the
+        /// failed count rose compared to --baseline
+        #[arg(long, requires = "baseline")]
+        fail_on_regression: bool,
     },
 
     Recent {
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(short, long, default_value = "20")]
         limit: i64,
 
+        /// Show every build within this window (e.g. 24h, 7d) instead of
+        /// the last --limit builds
+        #[arg(long, value_parser = parse_duration, conflicts_with = "limit")]
+        since: Option<chrono::Duration>,
+
         #[arg(long)]
         json: bool,
     },
@@ -126,13 +255,18 @@ enum CacheCommands {
     Prune {
         #[arg(short, long, default_value = "10")]
         keep: i64,
+
+        /// Delete history older than this (e.g. 30d), keeping each
+        /// package's latest build
+        #[arg(long, value_parser = parse_duration, conflicts_with = "keep")]
+        older_than: Option<chrono::Duration>,
     },
 
     Get {
         #[arg(short, long)]
         package: String,
 
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(long)]
@@ -140,17 +274,27 @@ enum CacheCommands {
     },
 
     GhSummary {
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(short, long, default_value = "Build Status")]
         title: String,
     },
 
-    /// Export MongoDB cache to SQLite file
+    /// Export MongoDB cache to SQLite file, or the local cache to JSON
     Export {
-        #[arg(short, long, default_value = "build_cache.sdb")]
-        output: PathBuf,
+        /// Defaults to build_cache.sdb, or build_cache.json with --format json
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        #[arg(short, long, value_enum, default_value = "sqlite")]
+        format: ExportFormat,
+    },
+
+    /// Import packages and build history from a JSON export into the local cache
+    Import {
+        #[arg(short, long)]
+        input: PathBuf,
     },
 
     /// List snapshots for a package
@@ -158,13 +302,37 @@ enum CacheCommands {
         #[arg(short, long)]
         package: String,
 
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
 
         #[arg(long)]
         json: bool,
     },
 
+    /// Recompute recipe hashes and store them, without marking anything
+    /// outdated. Packages are matched to recipes by their recorded build
+    /// script, then by pkg_id; run from the recipe repo's root.
+    Rehash {
+        /// Directories to scan for recipes
+        #[arg(short, long, num_args = 1.., required = true)]
+        recipes: Vec<PathBuf>,
+
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
+        host: String,
+
+        /// Hash without the `version` field
+        #[arg(long)]
+        exclude_version: bool,
+
+        /// Hash without trailing comments
+        #[arg(long)]
+        ignore_comments: bool,
+
+        /// Show what would change without writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// Migrate snapshots from recipe files to MongoDB
     MigrateSnapshots {
         /// Recipe file path (YAML)
@@ -175,29 +343,60 @@ enum CacheCommands {
         #[arg(short, long)]
         package: Option<String>,
 
-        #[arg(short = 'H', long, default_value = "x86_64-linux")]
+        #[arg(short = 'H', long, default_value = "x86_64-linux", value_parser = parse_host)]
         host: String,
     },
+
+    /// Lowercase host triplets stored in MongoDB before they were
+    /// normalized. SQLite caches are migrated automatically when opened.
+    MigrateHosts,
 }
 
 /// Get the cache URI from environment variable
+fn parse_host(host: &str) -> std::result::Result<String, String> {
+    Ok(normalize_host_triplet(host))
+}
+
+fn parse_backoff_multiplier(value: &str) -> std::result::Result<f64, String> {
+    let multiplier: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if !multiplier.is_finite() || multiplier < 1.0 {
+        return Err("must be a number of at least 1".to_string());
+    }
+    Ok(multiplier)
+}
+
+/// The time `age` before now, for `--older-than`/`--since`
+fn since_now(age: chrono::Duration) -> Result<chrono::DateTime<chrono::Utc>> {
+    chrono::Utc::now()
+        .checked_sub_signed(age)
+        .ok_or_else(|| sbuild_cache::Error::Other(format!("{} is too far in the past", age)))
+}
+
 fn get_cache_uri() -> Option<String> {
     std::env::var("SBUILD_CACHE_URI")
         .ok()
         .filter(|s| !s.is_empty())
 }
 
-pub async fn run(args: CacheArgs) -> Result<()> {
+/// With `quiet`, confirmations of what a command changed aren't printed;
+/// query results and errors still are.
+pub async fn run(args: CacheArgs, quiet: bool, compact_json: bool) -> Result<()> {
     match args.command {
         CacheCommands::Init => {
             if let Some(uri) = get_cache_uri() {
                 let _db = MongoDatabase::connect(&uri).await?;
-                println!("Connected to MongoDB cache");
+                if !quiet {
+                    println!("Connected to MongoDB cache");
+                }
             } else {
                 let db = CacheDatabase::open(&args.cache)?;
-                println!("Initialized cache database at {:?}", args.cache);
+                if !quiet {
+                    println!("Initialized cache database at {:?}", args.cache);
+                }
                 let stats = db.get_stats("x86_64-linux")?;
-                println!("Total packages: {}", stats.total_packages);
+                if !quiet {
+                    println!("Total packages: {}", stats.total_packages);
+                }
             }
             Ok(())
         }
@@ -209,12 +408,35 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             build_id,
             tag,
             hash,
+            error,
+            skip_reason,
+            backoff_base_minutes,
+            backoff_max_hours,
+            backoff_multiplier,
         } => {
-            let build_status = BuildStatus::from_str(&status)
-                .ok_or_else(|| sbuild_cache::Error::InvalidStatus(status.clone()))?;
+            let build_status: BuildStatus = status.parse()?;
+            if error.is_some() && !build_status.is_failure() {
+                return Err(sbuild_cache::Error::Other(
+                    "--error can only be used with --status failed or timeout".to_string(),
+                ));
+            }
+            if skip_reason.is_some() && build_status != BuildStatus::Skipped {
+                return Err(sbuild_cache::Error::Other(
+                    "--skip-reason can only be used with --status skipped".to_string(),
+                ));
+            }
             let pkg_name = package.rsplit('.').next().unwrap_or(&package);
+            let custom_backoff = backoff_base_minutes.is_some()
+                || backoff_max_hours.is_some()
+                || backoff_multiplier.is_some();
 
             if let Some(uri) = get_cache_uri() {
+                // Mongo derives retry delays from build history with a fixed policy
+                if custom_backoff {
+                    return Err(sbuild_cache::Error::Other(
+                        "--backoff-* options are only supported with a SQLite cache".to_string(),
+                    ));
+                }
                 let db = MongoDatabase::connect(&uri).await?;
                 db.get_or_create_package(&package, pkg_name, &host).await?;
                 db.update_build_result(
@@ -229,12 +451,18 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                     None,
                     0,
                     None,
+                    error.as_deref(),
                     None,
-                    None,
+                    skip_reason.as_deref(),
                 )
                 .await?;
             } else {
-                let db = CacheDatabase::open(&args.cache)?;
+                let defaults = BackoffPolicy::default();
+                let db = CacheDatabase::open(&args.cache)?.with_backoff_policy(BackoffPolicy {
+                    base_minutes: backoff_base_minutes.unwrap_or(defaults.base_minutes),
+                    max_hours: backoff_max_hours.unwrap_or(defaults.max_hours),
+                    multiplier: backoff_multiplier.unwrap_or(defaults.multiplier),
+                });
                 db.get_or_create_package(&package, pkg_name, &host)?;
                 db.update_build_result(
                     &package,
@@ -247,16 +475,47 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                     None,
                     None,
                     0,
+                    skip_reason.as_deref(),
                 )?;
-                if build_status == BuildStatus::Success {
-                    db.clear_failure(&package, &host)?;
+                match build_status {
+                    BuildStatus::Success => db.clear_failure(&package, &host)?,
+                    BuildStatus::Failed => db.record_failure(
+                        &package,
+                        &host,
+                        error.as_deref().unwrap_or("build failed"),
+                    )?,
+                    BuildStatus::Timeout => db.record_failure(
+                        &package,
+                        &host,
+                        error.as_deref().unwrap_or("build timed out"),
+                    )?,
+                    _ => {}
                 }
             }
 
-            println!(
-                "Updated {} on {} to version {} ({})",
-                package, host, version, status
-            );
+            if !quiet {
+                println!(
+                    "Updated {} on {} to version {} ({})",
+                    package, host, version, status
+                );
+            }
+            Ok(())
+        }
+        CacheCommands::MarkSkipped {
+            package,
+            host,
+            reason,
+        } => {
+            if let Some(uri) = get_cache_uri() {
+                let db = MongoDatabase::connect(&uri).await?;
+                db.mark_skipped(&package, &host, &reason).await?;
+            } else {
+                let db = CacheDatabase::open(&args.cache)?;
+                db.mark_skipped(&package, &host, &reason)?;
+            }
+            if !quiet {
+                println!("Marked {} on {} as skipped ({})", package, host, reason);
+            }
             Ok(())
         }
         CacheCommands::MarkOutdated {
@@ -271,13 +530,79 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                 let db = CacheDatabase::open(&args.cache)?;
                 db.mark_outdated(&package, &host, &upstream_version)?;
             }
-            println!(
-                "Marked {} as outdated (upstream: {})",
-                package, upstream_version
-            );
+            if !quiet {
+                println!(
+                    "Marked {} as outdated (upstream: {})",
+                    package, upstream_version
+                );
+            }
             Ok(())
         }
-        CacheCommands::Stats { host, json } => {
+        CacheCommands::Stats {
+            all_hosts: true,
+            json,
+            ..
+        } => {
+            let per_host = if let Some(uri) = get_cache_uri() {
+                let db = MongoDatabase::connect(&uri).await?;
+                let mut per_host = Vec::new();
+                for host in db.list_hosts().await? {
+                    let stats = db.get_stats(&host).await?;
+                    per_host.push((host, stats));
+                }
+                per_host
+            } else {
+                let db = CacheDatabase::open(&args.cache)?;
+                db.list_hosts()?
+                    .into_iter()
+                    .map(|host| db.get_stats(&host).map(|stats| (host, stats)))
+                    .collect::<Result<Vec<_>>>()?
+            };
+
+            let mut totals = BuildStats::default();
+            for (_, stats) in &per_host {
+                totals.total_packages += stats.total_packages;
+                totals.successful += stats.successful;
+                totals.failed += stats.failed;
+                totals.pending += stats.pending;
+                totals.outdated += stats.outdated;
+            }
+
+            if json {
+                let hosts: serde_json::Map<String, serde_json::Value> = per_host
+                    .iter()
+                    .map(|(host, stats)| Ok((host.clone(), serde_json::to_value(stats)?)))
+                    .collect::<std::result::Result<_, serde_json::Error>>()?;
+                let report = serde_json::json!({ "hosts": hosts, "total": totals });
+                println!("{}", super::to_json(&report, compact_json)?);
+            } else {
+                // Hosts with the most outdated packages first
+                let mut rows: Vec<_> = per_host.iter().collect();
+                rows.sort_by(|a, b| b.1.outdated.cmp(&a.1.outdated).then(a.0.cmp(&b.0)));
+
+                println!(
+                    "{:<20} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                    "Host", "Total", "Success", "Failed", "Pending", "Outdated"
+                );
+                let print_row = |name: &str, stats: &BuildStats| {
+                    println!(
+                        "{:<20} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                        name,
+                        stats.total_packages,
+                        stats.successful,
+                        stats.failed,
+                        stats.pending,
+                        stats.outdated
+                    );
+                };
+                for (host, stats) in rows {
+                    print_row(host, stats);
+                }
+                print_row("TOTAL", &totals);
+            }
+            Ok(())
+        }
+        CacheCommands::Stats { host, json, .. } => {
             let stats = if let Some(uri) = get_cache_uri() {
                 let db = MongoDatabase::connect(&uri).await?;
                 db.get_stats(&host).await?
@@ -287,7 +612,7 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             };
 
             if json {
-                println!("{}", serde_json::to_string_pretty(&stats)?);
+                println!("{}", super::to_json(&stats, compact_json)?);
             } else {
                 println!("Build Statistics for {}", host);
                 println!("========================");
@@ -299,6 +624,113 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             }
             Ok(())
         }
+        CacheCommands::Claim {
+            host,
+            worker,
+            claim_timeout,
+            json,
+        } => {
+            if get_cache_uri().is_some() {
+                return Err(sbuild_cache::Error::Other(
+                    "Claiming work needs the SQLite cache; unset SBUILD_CACHE_URI".to_string(),
+                ));
+            }
+            let db = CacheDatabase::open(&args.cache)?
+                .with_claim_timeout(chrono::Duration::minutes(claim_timeout));
+            let claimed = db.claim_next_rebuild(&host, &worker)?;
+
+            if json {
+                println!("{}", super::to_json(&claimed, compact_json)?);
+            } else if let Some(pkg) = claimed {
+                println!("{}", pkg.pkg_id);
+            } else {
+                eprintln!("No packages to claim on {}", host);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        CacheCommands::Release {
+            package,
+            host,
+            worker,
+        } => {
+            if get_cache_uri().is_some() {
+                return Err(sbuild_cache::Error::Other(
+                    "Releasing claims needs the SQLite cache; unset SBUILD_CACHE_URI".to_string(),
+                ));
+            }
+            let db = CacheDatabase::open(&args.cache)?;
+            if db.release_claim(&package, &host, &worker)? {
+                if !quiet {
+                    println!("Released claim on {}", package);
+                }
+            } else {
+                eprintln!("{} is not claimed by {}", package, worker);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        CacheCommands::Failures { host, json } => {
+            if get_cache_uri().is_some() {
+                return Err(sbuild_cache::Error::Other(
+                    "The failures report needs the SQLite cache; unset SBUILD_CACHE_URI"
+                        .to_string(),
+                ));
+            }
+            let db = CacheDatabase::open(&args.cache)?;
+            let failures = db.list_failures(&host)?;
+            let now = chrono::Utc::now();
+
+            if json {
+                let report: Vec<_> = failures
+                    .iter()
+                    .map(|(pkg, failure)| {
+                        serde_json::json!({
+                            "pkg_id": pkg.pkg_id,
+                            "pkg_name": pkg.pkg_name,
+                            "failure_count": failure.failure_count,
+                            "last_failure_date": failure.last_failure_date,
+                            "last_error_message": failure.last_error_message,
+                            "next_retry_date": failure.next_retry_date,
+                            "retry_allowed": failure.next_retry_date.is_none_or(|d| d <= now),
+                        })
+                    })
+                    .collect();
+                println!("{}", super::to_json(&report, compact_json)?);
+            } else if failures.is_empty() {
+                println!("No failing packages on {}", host);
+            } else {
+                println!("Failing packages on {}:", host);
+                println!();
+                println!(
+                    "{:<30} {:>8}  {:<20}  Last error",
+                    "Package", "Failures", "Next retry"
+                );
+                for (pkg, failure) in &failures {
+                    let retry = match failure.next_retry_date {
+                        Some(date) if date > now => date.format("%Y-%m-%d %H:%M UTC").to_string(),
+                        _ => "now".to_string(),
+                    };
+                    let error = failure
+                        .last_error_message
+                        .as_deref()
+                        .map(|e| e.lines().next().unwrap_or_default())
+                        .map(|e| {
+                            if e.chars().count() > 60 {
+                                format!("{}...", e.chars().take(57).collect::<String>())
+                            } else {
+                                e.to_string()
+                            }
+                        })
+                        .unwrap_or_default();
+                    println!(
+                        "{:<30} {:>8}  {:<20}  {}",
+                        pkg.pkg_name, failure.failure_count, retry, error
+                    );
+                }
+            }
+            Ok(())
+        }
         CacheCommands::NeedsRebuild { host, json } => {
             let packages = if let Some(uri) = get_cache_uri() {
                 let db = MongoDatabase::connect(&uri).await?;
@@ -309,7 +741,7 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             };
 
             if json {
-                println!("{}", serde_json::to_string_pretty(&packages)?);
+                println!("{}", super::to_json(&packages, compact_json)?);
             } else {
                 println!("Packages needing rebuild on {}:", host);
                 println!();
@@ -331,11 +763,16 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             }
             Ok(())
         }
-        CacheCommands::Prune { keep } => {
+        CacheCommands::Prune { keep, older_than } => {
             // Prune only applies to SQLite (MongoDB handles this via $slice)
             let db = CacheDatabase::open(&args.cache)?;
-            let deleted = db.prune_history(keep)?;
-            println!("Pruned {} old build history entries", deleted);
+            let deleted = match older_than {
+                Some(age) => db.prune_history_older_than(since_now(age)?)?,
+                None => db.prune_history(keep)?,
+            };
+            if !quiet {
+                println!("Pruned {} old build history entries", deleted);
+            }
             Ok(())
         }
         CacheCommands::Get {
@@ -343,20 +780,26 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             host,
             json,
         } => {
-            let packages = if let Some(uri) = get_cache_uri() {
+            let (packages, failures) = if let Some(uri) = get_cache_uri() {
                 let db = MongoDatabase::connect(&uri).await?;
-                if let Some(pkg) = db.get_package(&package, &host).await? {
+                let packages = if let Some(pkg) = db.get_package(&package, &host).await? {
                     vec![pkg]
                 } else {
                     db.find_packages_by_name(&package, &host).await?
-                }
+                };
+                (packages, Vec::new())
             } else {
                 let db = CacheDatabase::open(&args.cache)?;
-                if let Some(pkg) = db.get_package(&package, &host)? {
+                let packages = if let Some(pkg) = db.get_package(&package, &host)? {
                     vec![pkg]
                 } else {
                     db.find_packages_by_name(&package, &host)?
-                }
+                };
+                let failures = packages
+                    .iter()
+                    .map(|pkg| db.get_failure(&pkg.pkg_id, &pkg.host_triplet))
+                    .collect::<Result<Vec<_>>>()?;
+                (packages, failures)
             };
 
             if packages.is_empty() {
@@ -366,9 +809,9 @@ pub async fn run(args: CacheArgs) -> Result<()> {
 
             if json {
                 if packages.len() == 1 {
-                    println!("{}", serde_json::to_string_pretty(&packages[0])?);
+                    println!("{}", super::to_json(&packages[0], compact_json)?);
                 } else {
-                    println!("{}", serde_json::to_string_pretty(&packages)?);
+                    println!("{}", super::to_json(&packages, compact_json)?);
                 }
             } else {
                 for (i, pkg) in packages.iter().enumerate() {
@@ -399,6 +842,15 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                     if let Some(ref tag) = pkg.ghcr_tag {
                         println!("GHCR Tag: {}", tag);
                     }
+                    if let Some(Some(failure)) = failures.get(i) {
+                        println!("Failures: {}", failure.failure_count);
+                        if let Some(ref message) = failure.last_error_message {
+                            println!("Last Error: {}", message);
+                        }
+                        if let Some(retry) = failure.next_retry_date {
+                            println!("Next Retry: {}", retry.format("%Y-%m-%d %H:%M UTC"));
+                        }
+                    }
                 }
             }
             Ok(())
@@ -414,6 +866,8 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                 StatusFilter::Failed => (Some(BuildStatus::Failed), false),
                 StatusFilter::Pending => (Some(BuildStatus::Pending), false),
                 StatusFilter::Skipped => (Some(BuildStatus::Skipped), false),
+                StatusFilter::Timeout => (Some(BuildStatus::Timeout), false),
+                StatusFilter::Cancelled => (Some(BuildStatus::Cancelled), false),
                 StatusFilter::Outdated => (None, true),
                 StatusFilter::All => (None, false),
             };
@@ -432,13 +886,15 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             }
 
             if json {
-                println!("{}", serde_json::to_string_pretty(&packages)?);
+                println!("{}", super::to_json(&packages, compact_json)?);
             } else {
                 let status_icon = |s: Option<BuildStatus>| match s {
                     Some(BuildStatus::Success) => "✓",
                     Some(BuildStatus::Failed) => "✗",
                     Some(BuildStatus::Pending) => "○",
                     Some(BuildStatus::Skipped) => "⊘",
+                    Some(BuildStatus::Timeout) => "⏱",
+                    Some(BuildStatus::Cancelled) => "⊗",
                     None => "?",
                 };
 
@@ -468,13 +924,25 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             }
             Ok(())
         }
-        CacheCommands::Recent { host, limit, json } => {
+        CacheCommands::Recent {
+            host,
+            limit,
+            since,
+            json,
+        } => {
+            let since = since.map(since_now).transpose()?;
             let builds = if let Some(uri) = get_cache_uri() {
                 let db = MongoDatabase::connect(&uri).await?;
-                db.get_recent_builds(&host, limit).await?
+                match since {
+                    Some(since) => db.get_recent_builds_since(&host, since).await?,
+                    None => db.get_recent_builds(&host, limit).await?,
+                }
             } else {
                 let db = CacheDatabase::open(&args.cache)?;
-                db.get_recent_builds(&host, limit)?
+                match since {
+                    Some(since) => db.get_recent_builds_since(&host, since)?,
+                    None => db.get_recent_builds(&host, limit)?,
+                }
             };
 
             if json {
@@ -487,10 +955,11 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                             "status": hist.build_status.to_string(),
                             "build_id": hist.build_id,
                             "build_date": hist.build_date.to_rfc3339(),
+                            "skip_reason": hist.skip_reason,
                         })
                     })
                     .collect();
-                println!("{}", serde_json::to_string_pretty(&output)?);
+                println!("{}", super::to_json(&output, compact_json)?);
             } else {
                 println!("Recent builds on {}:", host);
                 println!();
@@ -506,11 +975,18 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                         BuildStatus::Failed => "✗",
                         BuildStatus::Pending => "○",
                         BuildStatus::Skipped => "⊘",
+                        BuildStatus::Timeout => "⏱",
+                        BuildStatus::Cancelled => "⊗",
                     };
                     let date = hist.build_date.format("%Y-%m-%d %H:%M");
+                    let reason = hist
+                        .skip_reason
+                        .as_deref()
+                        .map(|r| format!(" ({})", r))
+                        .unwrap_or_default();
                     println!(
-                        "{:<3} {:<25} {:<12} {:<10} {:<20}",
-                        icon, pkg.pkg_name, hist.version, hist.build_status, date
+                        "{:<3} {:<25} {:<12} {:<10} {:<20}{}",
+                        icon, pkg.pkg_name, hist.version, hist.build_status, date, reason
                     );
                 }
             }
@@ -521,54 +997,142 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             format,
             output,
             history_limit,
+            template,
+            trend_days,
+            baseline,
+            fail_on_regression,
         } => {
-            let (stats, failed, outdated, recent) = if let Some(uri) = get_cache_uri() {
+            let baseline: Option<serde_json::Value> = match &baseline {
+                Some(path) => {
+                    let content = std::fs::read_to_string(path).map_err(|e| {
+                        sbuild_cache::Error::Other(format!(
+                            "Failed to read baseline {:?}: {}",
+                            path, e
+                        ))
+                    })?;
+                    Some(serde_json::from_str(&content)?)
+                }
+                None => None,
+            };
+            let baseline_host = baseline
+                .as_ref()
+                .and_then(|b| b["host"].as_str())
+                .map(normalize_host_triplet);
+            if let Some(baseline_host) = baseline_host.filter(|h| *h != host) {
+                return Err(sbuild_cache::Error::Other(format!(
+                    "Baseline report is for {}, not {}",
+                    baseline_host, host
+                )));
+            }
+
+            let template = match template {
+                Some(_) if matches!(format, ReportFormat::Json) => {
+                    return Err(sbuild_cache::Error::Other(
+                        "--template only applies to markdown and html reports".to_string(),
+                    ));
+                }
+                Some(path) => Some(std::fs::read_to_string(&path).map_err(|e| {
+                    sbuild_cache::Error::Other(format!("Failed to read template {:?}: {}", path, e))
+                })?),
+                None => None,
+            };
+
+            let (stats, all, outdated, recent, trend) = if let Some(uri) = get_cache_uri() {
                 let db = MongoDatabase::connect(&uri).await?;
                 let stats = db.get_stats(&host).await?;
-                let failed = db
-                    .list_packages(&host, Some(BuildStatus::Failed), false)
-                    .await?;
+                let all = db.list_packages(&host, None, false).await?;
                 let outdated = db.list_packages(&host, None, true).await?;
                 let recent = db.get_recent_builds(&host, history_limit).await?;
-                (stats, failed, outdated, recent)
+                let trend = if trend_days > 0 {
+                    db.get_stats_history(&host, trend_days).await?
+                } else {
+                    Vec::new()
+                };
+                (stats, all, outdated, recent, trend)
             } else {
                 let db = CacheDatabase::open(&args.cache)?;
                 let stats = db.get_stats(&host)?;
-                let failed = db.list_packages(&host, Some(BuildStatus::Failed), false)?;
+                let all = db.list_packages(&host, None, false)?;
                 let outdated = db.list_packages(&host, None, true)?;
                 let recent = db.get_recent_builds(&host, history_limit)?;
-                (stats, failed, outdated, recent)
+                let trend = if trend_days > 0 {
+                    db.get_stats_history(&host, trend_days)?
+                } else {
+                    Vec::new()
+                };
+                (stats, all, outdated, recent, trend)
             };
-
-            let report = match format {
-                ReportFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
-                    "host": host,
-                    "stats": stats,
-                    "failed_packages": failed,
-                    "outdated_packages": outdated,
-                    "recent_builds": recent.iter().map(|(p, h)| {
-                        serde_json::json!({
-                            "package": p.pkg_name,
-                            "version": h.version,
-                            "status": h.build_status.to_string(),
-                            "date": h.build_date.to_rfc3339(),
-                        })
-                    }).collect::<Vec<_>>(),
-                }))?,
-                ReportFormat::Markdown => {
-                    generate_markdown_report(&host, &stats, &failed, &outdated, &recent)
+            let failed: Vec<_> = all
+                .iter()
+                .filter(|p| p.last_build_status.is_some_and(|s| s.is_failure()))
+                .cloned()
+                .collect();
+            let package_statuses: serde_json::Map<String, serde_json::Value> = all
+                .iter()
+                .filter_map(|p| {
+                    let status = p.last_build_status?;
+                    Some((p.pkg_id.clone(), status.as_str().into()))
+                })
+                .collect();
+
+            let report = match (format, template) {
+                (ReportFormat::Markdown | ReportFormat::Html, Some(template)) => {
+                    let fields =
+                        report_fields(format, &host, &stats, &failed, &outdated, &recent, &trend);
+                    render_template(&template, &fields)
                 }
-                ReportFormat::Html => {
-                    generate_html_report(&host, &stats, &failed, &outdated, &recent)
+                (ReportFormat::Json, _) => super::to_json(
+                    &serde_json::json!({
+                        "host": host,
+                        "stats": stats,
+                        "failed_packages": failed,
+                        "outdated_packages": outdated,
+                        "recent_builds": recent.iter().map(|(p, h)| {
+                            serde_json::json!({
+                                "package": p.pkg_name,
+                                "version": h.version,
+                                "status": h.build_status.to_string(),
+                                "date": h.build_date.to_rfc3339(),
+                                "skip_reason": h.skip_reason,
+                            })
+                        }).collect::<Vec<_>>(),
+                        "trend": trend,
+                        "package_statuses": package_statuses,
+                    }),
+                    compact_json,
+                )?,
+                (ReportFormat::Markdown, None) => {
+                    generate_markdown_report(&host, &stats, &failed, &outdated, &recent, &trend)
+                }
+                (ReportFormat::Html, None) => {
+                    generate_html_report(&host, &stats, &failed, &outdated, &recent, &trend)
                 }
             };
 
             if let Some(path) = output {
                 std::fs::write(&path, &report)?;
-                println!("Report written to {:?}", path);
+                if !quiet {
+                    println!("Report written to {:?}", path);
+                }
             } else {
                 println!("{}", report);
             }
+
+            if let Some(baseline) = baseline {
+                let regression = compare_to_baseline(&baseline, &failed);
+                eprintln!(
+                    "Failed packages: {} (baseline {})",
+                    regression.failed_now, regression.failed_before
+                );
+                for pkg_id in &regression.newly_failed {
+                    eprintln!("  regressed: {}", pkg_id);
+                }
+                if fail_on_regression && regression.is_regression() {
+                    return Err(sbuild_cache::Error::Other(
+                        "Build regression against baseline".to_string(),
+                    ));
+                }
+            }
             Ok(())
         }
         CacheCommands::GhSummary { host, title } => {
@@ -623,13 +1187,44 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                     .create(true)
                     .open(&summary_path)?;
                 writeln!(file, "{}", summary)?;
-                println!("Summary written to GITHUB_STEP_SUMMARY");
+                if !quiet {
+                    println!("Summary written to GITHUB_STEP_SUMMARY");
+                }
             } else {
                 println!("{}", summary);
             }
             Ok(())
         }
-        CacheCommands::Export { output } => {
+        CacheCommands::Export {
+            output,
+            format: ExportFormat::Json,
+        } => {
+            let output = output.unwrap_or_else(|| ExportFormat::Json.default_output());
+            let db = CacheDatabase::open(&args.cache)?;
+            let dump = sbuild_cache::export::export_to_json(&db)?;
+            std::fs::write(&output, super::to_json(&dump, compact_json)?)?;
+            if !quiet {
+                println!(
+                    "Exported {} packages from {:?} to {:?}",
+                    dump.packages.len(),
+                    args.cache,
+                    output
+                );
+            }
+            Ok(())
+        }
+        CacheCommands::Import { input } => {
+            let content = std::fs::read_to_string(&input)?;
+            let dump: sbuild_cache::export::CacheDump = serde_json::from_str(&content)?;
+            let db = CacheDatabase::open(&args.cache)?;
+            let count = sbuild_cache::export::import_from_json(&db, &dump)?;
+            if !quiet {
+                println!("Imported {} packages into {:?}", count, args.cache);
+            }
+            Ok(())
+        }
+        CacheCommands::Export { output, format } => {
+            let output = output.unwrap_or_else(|| format.default_output());
             let uri = get_cache_uri().ok_or_else(|| {
                 sbuild_cache::Error::Other(
                     "SBUILD_CACHE_URI environment variable is required for export".to_string(),
@@ -637,7 +1232,9 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             })?;
             let mongo = MongoDatabase::connect(&uri).await?;
             sbuild_cache::export::export_to_sqlite(&mongo, &output).await?;
-            println!("Exported MongoDB cache to {:?}", output);
+            if !quiet {
+                println!("Exported MongoDB cache to {:?}", output);
+            }
             Ok(())
         }
         CacheCommands::Snapshots {
@@ -654,7 +1251,7 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             let snapshots = mongo.get_snapshots(&package, &host).await?;
 
             if json {
-                println!("{}", serde_json::to_string_pretty(&snapshots)?);
+                println!("{}", super::to_json(&snapshots, compact_json)?);
             } else {
                 if snapshots.is_empty() {
                     println!("No snapshots found for {} on {}", package, host);
@@ -668,6 +1265,53 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             }
             Ok(())
         }
+        CacheCommands::Rehash {
+            recipes,
+            host,
+            exclude_version,
+            ignore_comments,
+            dry_run,
+        } => {
+            let options = HashOptions::new()
+                .with_exclude_version(exclude_version)
+                .with_ignore_comments(ignore_comments);
+            let rehash = if let Some(uri) = get_cache_uri() {
+                let db = MongoDatabase::connect(&uri).await?;
+                let records = db.list_packages(&host, None, false).await?;
+                let rehash = rehash_updates(&recipes, &records, options)?;
+                if !dry_run {
+                    for (pkg_id, hash) in &rehash.updates {
+                        db.update_recipe_hash(pkg_id, &host, hash).await?;
+                    }
+                }
+                rehash
+            } else {
+                let db = CacheDatabase::open(&args.cache)?;
+                let records = db.list_packages(&host, None, false)?;
+                let rehash = rehash_updates(&recipes, &records, options)?;
+                if !dry_run {
+                    for (pkg_id, hash) in &rehash.updates {
+                        db.update_recipe_hash(pkg_id, &host, hash)?;
+                    }
+                }
+                rehash
+            };
+
+            for (pkg_id, hash) in &rehash.updates {
+                println!("{}: {}", pkg_id, hash);
+            }
+            if !quiet {
+                println!(
+                    "{} {}, {} unchanged, {} recipe(s) not in the cache for {}",
+                    rehash.updates.len(),
+                    if dry_run { "to update" } else { "updated" },
+                    rehash.unchanged,
+                    rehash.unmatched,
+                    host
+                );
+            }
+            Ok(())
+        }
         CacheCommands::MigrateSnapshots {
             recipe,
             package,
@@ -699,16 +1343,25 @@ pub async fn run(args: CacheArgs) -> Result<()> {
             let recipe_snapshots = parsed.snapshots.clone();
 
             if recipe_snapshots.is_empty() {
-                println!("No snapshots found in recipe file");
+                if !quiet {
+                    println!("No snapshots found in recipe file");
+                }
                 return Ok(());
             }
 
             let mongo = MongoDatabase::connect(&uri).await?;
 
-            // Ensure package exists
-            mongo
-                .get_or_create_package(&pkg_id, &pkg_name, &host)
-                .await?;
+            // Ensure package exists, recording where its recipe lives
+            let recipe_path = recipe.to_string_lossy();
+            let mut init = PackageRecordInit::new(&pkg_id, &pkg_name, &host)
+                .with_build_script(recipe_path.as_ref());
+            if let Some((family, _)) = parse_ghcr_path(&recipe_path) {
+                init = init.with_family(family);
+            }
+            if let Some(ref ghcr_pkg) = parsed.ghcr_pkg {
+                init = init.with_ghcr_pkg(ghcr_pkg.as_str());
+            }
+            mongo.create_package_full(&init).await?;
 
             // Get existing snapshots from DB
             let existing = mongo.get_snapshots(&pkg_id, &host).await?;
@@ -723,22 +1376,104 @@ pub async fn run(args: CacheArgs) -> Result<()> {
                 }
             }
 
-            println!("Migrated snapshots for {} on {}:", pkg_id, host);
-            println!("  Recipe snapshots: {}", recipe_snapshots.len());
-            println!("  Already in DB: {}", recipe_snapshots.len() - added);
-            println!("  Newly added: {}", added);
+            if !quiet {
+                println!("Migrated snapshots for {} on {}:", pkg_id, host);
+                println!("  Recipe snapshots: {}", recipe_snapshots.len());
+                println!("  Already in DB: {}", recipe_snapshots.len() - added);
+                println!("  Newly added: {}", added);
+            }
 
             Ok(())
         }
+        CacheCommands::MigrateHosts => {
+            let uri = get_cache_uri().ok_or_else(|| {
+                sbuild_cache::Error::Other(
+                    "SBUILD_CACHE_URI environment variable is required".to_string(),
+                )
+            })?;
+            let mongo = MongoDatabase::connect(&uri).await?;
+            let changed = mongo.normalize_host_triplets().await?;
+            if !quiet {
+                println!("Normalized host triplets of {} package(s)", changed);
+            }
+            Ok(())
+        }
     }
 }
 
+/// Outcome of recomputing recipe hashes for `cache rehash`
+struct Rehash {
+    /// `(pkg_id, hash)` for cached packages whose recipe hash changed
+    updates: Vec<(String, String)>,
+    /// Cached packages whose hash already matches
+    unchanged: usize,
+    /// Recipes matching no cached package
+    unmatched: usize,
+}
+
+/// Recompute the hash of every recipe under `dirs` and compare it to the
+/// cached packages built from it
+fn rehash_updates(
+    dirs: &[PathBuf],
+    records: &[PackageRecord],
+    options: HashOptions,
+) -> Result<Rehash> {
+    let mut updates = Vec::new();
+    let mut unchanged = 0;
+    let mut unmatched = 0;
+
+    for dir in dirs {
+        let recipes = scan_recipes(dir).map_err(|e| {
+            sbuild_cache::Error::Other(format!("Failed to scan {}: {}", dir.display(), e))
+        })?;
+        for (path, recipe) in recipes {
+            let content = std::fs::read_to_string(&path).map_err(|e| {
+                sbuild_cache::Error::Other(format!("Failed to read {}: {}", path.display(), e))
+            })?;
+            let hash = compute_recipe_hash_with(&content, options);
+
+            let recipe_path = path.to_string_lossy();
+            let mut matched: Vec<&PackageRecord> = records
+                .iter()
+                .filter(|r| r.built_from(&recipe_path))
+                .collect();
+            if matched.is_empty() {
+                let pkg_id = if recipe.pkg_id.is_empty() {
+                    &recipe.pkg
+                } else {
+                    &recipe.pkg_id
+                };
+                matched = records.iter().filter(|r| &r.pkg_id == pkg_id).collect();
+            }
+            if matched.is_empty() {
+                unmatched += 1;
+                continue;
+            }
+
+            for record in matched {
+                if record.recipe_hash.as_deref() == Some(hash.as_str()) {
+                    unchanged += 1;
+                } else {
+                    updates.push((record.pkg_id.clone(), hash.clone()));
+                }
+            }
+        }
+    }
+
+    Ok(Rehash {
+        updates,
+        unchanged,
+        unmatched,
+    })
+}
+
 fn generate_markdown_report(
     host: &str,
     stats: &sbuild_cache::BuildStats,
     failed: &[sbuild_cache::PackageRecord],
     outdated: &[sbuild_cache::PackageRecord],
     recent: &[(sbuild_cache::PackageRecord, sbuild_cache::BuildHistoryEntry)],
+    trend: &[DailyBuildStats],
 ) -> String {
     let mut md = String::new();
     md.push_str(&format!("# Build Report: {}\n\n", host));
@@ -748,89 +1483,429 @@ fn generate_markdown_report(
     ));
 
     md.push_str("## Summary\n\n");
-    md.push_str("| Metric | Count |\n");
-    md.push_str("|--------|-------|\n");
-    md.push_str(&format!("| Total Packages | {} |\n", stats.total_packages));
-    md.push_str(&format!("| Successful | {} |\n", stats.successful));
-    md.push_str(&format!("| Failed | {} |\n", stats.failed));
-    md.push_str(&format!("| Pending | {} |\n", stats.pending));
-    md.push_str(&format!("| Outdated | {} |\n\n", stats.outdated));
+    md.push_str(&markdown_stats_table(stats));
+    md.push('\n');
 
     if stats.total_packages > 0 {
-        let success_rate = (stats.successful as f64 / stats.total_packages as f64) * 100.0;
-        md.push_str(&format!("**Success Rate: {:.1}%**\n\n", success_rate));
+        md.push_str(&format!(
+            "**Success Rate: {:.1}%**\n\n",
+            success_rate(stats)
+        ));
+    }
+
+    if !trend.is_empty() {
+        md.push_str(&format!("## Trend (last {} days)\n\n", trend.len()));
+        md.push_str(&markdown_trend(trend));
+        md.push('\n');
     }
 
     if !failed.is_empty() {
         md.push_str("## Failed Packages\n\n");
-        md.push_str("| Package | Version | Last Build |\n");
-        md.push_str("|---------|---------|------------|\n");
-        for pkg in failed.iter().take(20) {
-            let version = pkg.current_version.as_deref().unwrap_or("-");
-            let date = pkg
-                .last_build_date
-                .map(|d| d.format("%Y-%m-%d").to_string())
-                .unwrap_or_else(|| "-".to_string());
-            md.push_str(&format!("| {} | {} | {} |\n", pkg.pkg_name, version, date));
-        }
-        if failed.len() > 20 {
-            md.push_str(&format!("\n*...and {} more*\n", failed.len() - 20));
-        }
+        md.push_str(&markdown_failed_table(failed));
         md.push('\n');
     }
 
     if !outdated.is_empty() {
         md.push_str("## Outdated Packages\n\n");
-        md.push_str("| Package | Current | Upstream |\n");
-        md.push_str("|---------|---------|----------|\n");
-        for pkg in outdated.iter().take(20) {
-            let current = pkg.current_version.as_deref().unwrap_or("-");
-            let upstream = pkg.upstream_version.as_deref().unwrap_or("-");
-            md.push_str(&format!(
-                "| {} | {} | {} |\n",
-                pkg.pkg_name, current, upstream
-            ));
-        }
-        if outdated.len() > 20 {
-            md.push_str(&format!("\n*...and {} more*\n", outdated.len() - 20));
-        }
+        md.push_str(&markdown_outdated_table(outdated));
         md.push('\n');
     }
 
     if !recent.is_empty() {
         md.push_str("## Recent Builds\n\n");
-        md.push_str("| Status | Package | Version | Date |\n");
-        md.push_str("|--------|---------|---------|------|\n");
-        for (pkg, hist) in recent {
-            let icon = match hist.build_status {
-                BuildStatus::Success => "✅",
-                BuildStatus::Failed => "❌",
-                BuildStatus::Pending => "⏳",
-                BuildStatus::Skipped => "⏭️",
-            };
-            let date = hist.build_date.format("%Y-%m-%d %H:%M");
-            md.push_str(&format!(
-                "| {} | {} | {} | {} |\n",
-                icon, pkg.pkg_name, hist.version, date
-            ));
-        }
+        md.push_str(&markdown_recent_table(recent));
         md.push('\n');
     }
 
     md
 }
 
+/// Maximum rows shown in the failed and outdated tables
+const REPORT_TABLE_ROWS: usize = 20;
+
+fn success_rate(stats: &sbuild_cache::BuildStats) -> f64 {
+    if stats.total_packages > 0 {
+        (stats.successful as f64 / stats.total_packages as f64) * 100.0
+    } else {
+        0.0
+    }
+}
+
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Daily success rate as a sparkline; `·` marks days without builds
+fn trend_sparkline(trend: &[DailyBuildStats]) -> String {
+    trend
+        .iter()
+        .map(|day| match day.success_rate() {
+            Some(rate) => SPARK_CHARS[((rate / 100.0) * 7.0).round() as usize],
+            None => '·',
+        })
+        .collect()
+}
+
+fn trend_summary(trend: &[DailyBuildStats]) -> String {
+    let (successful, failed) = trend
+        .iter()
+        .fold((0, 0), |(s, f), day| (s + day.successful, f + day.failed));
+    let (Some(first), Some(last)) = (trend.first(), trend.last()) else {
+        return String::new();
+    };
+    format!(
+        "{} to {}: {} successful, {} failed",
+        first.date, last.date, successful, failed
+    )
+}
+
+fn markdown_trend(trend: &[DailyBuildStats]) -> String {
+    format!(
+        "`{}`\n\n{} (daily success rate, `·` = no builds)\n",
+        trend_sparkline(trend),
+        trend_summary(trend)
+    )
+}
+
+/// Daily success rate as an inline SVG line chart
+fn html_trend(trend: &[DailyBuildStats]) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 120.0;
+    let step = if trend.len() > 1 {
+        WIDTH / (trend.len() - 1) as f64
+    } else {
+        0.0
+    };
+    let points: Vec<String> = trend
+        .iter()
+        .enumerate()
+        .filter_map(|(i, day)| {
+            let rate = day.success_rate()?;
+            Some(format!(
+                "{:.1},{:.1}",
+                i as f64 * step,
+                HEIGHT - rate / 100.0 * HEIGHT
+            ))
+        })
+        .collect();
+    format!(
+        r##"<svg viewBox="-5 -5 {w} {h}" width="{w}" height="{h}" role="img">
+<title>{summary}</title>
+<line x1="0" y1="{h0}" x2="{w0}" y2="{h0}" stroke="#ddd"/>
+<polyline fill="none" stroke="#2e7d32" stroke-width="2" points="{points}"/>
+{dots}
+</svg>
+<p>{summary}</p>"##,
+        w = WIDTH + 10.0,
+        h = HEIGHT + 10.0,
+        w0 = WIDTH,
+        h0 = HEIGHT,
+        points = points.join(" "),
+        dots = points
+            .iter()
+            .map(|p| {
+                let (x, y) = p.split_once(',').unwrap_or_default();
+                format!(r##"<circle cx="{}" cy="{}" r="3" fill="#2e7d32"/>"##, x, y)
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        summary = html_escape(&trend_summary(trend)),
+    )
+}
+
+fn status_emoji(status: BuildStatus) -> &'static str {
+    match status {
+        BuildStatus::Success => "✅",
+        BuildStatus::Failed => "❌",
+        BuildStatus::Pending => "⏳",
+        BuildStatus::Skipped => "⏭️",
+        BuildStatus::Timeout => "⏱️",
+        BuildStatus::Cancelled => "🚫",
+    }
+}
+
+/// Status emoji of a build, followed by why it was skipped if it was
+fn history_status(hist: &sbuild_cache::BuildHistoryEntry) -> String {
+    match hist.skip_reason {
+        Some(ref reason) => format!("{} {}", status_emoji(hist.build_status), reason),
+        None => status_emoji(hist.build_status).to_string(),
+    }
+}
+
+fn markdown_stats_table(stats: &sbuild_cache::BuildStats) -> String {
+    let mut md = String::new();
+    md.push_str("| Metric | Count |\n");
+    md.push_str("|--------|-------|\n");
+    md.push_str(&format!("| Total Packages | {} |\n", stats.total_packages));
+    md.push_str(&format!("| Successful | {} |\n", stats.successful));
+    md.push_str(&format!("| Failed | {} |\n", stats.failed));
+    md.push_str(&format!("| Pending | {} |\n", stats.pending));
+    md.push_str(&format!("| Outdated | {} |\n", stats.outdated));
+    md
+}
+
+fn markdown_failed_table(failed: &[sbuild_cache::PackageRecord]) -> String {
+    let mut md = String::new();
+    md.push_str("| Package | Version | Last Build |\n");
+    md.push_str("|---------|---------|------------|\n");
+    for pkg in failed.iter().take(REPORT_TABLE_ROWS) {
+        let version = pkg.current_version.as_deref().unwrap_or("-");
+        let date = pkg
+            .last_build_date
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        md.push_str(&format!("| {} | {} | {} |\n", pkg.pkg_name, version, date));
+    }
+    if failed.len() > REPORT_TABLE_ROWS {
+        md.push_str(&format!(
+            "\n*...and {} more*\n",
+            failed.len() - REPORT_TABLE_ROWS
+        ));
+    }
+    md
+}
+
+fn markdown_outdated_table(outdated: &[sbuild_cache::PackageRecord]) -> String {
+    let mut md = String::new();
+    md.push_str("| Package | Current | Upstream |\n");
+    md.push_str("|---------|---------|----------|\n");
+    for pkg in outdated.iter().take(REPORT_TABLE_ROWS) {
+        let current = pkg.current_version.as_deref().unwrap_or("-");
+        let upstream = pkg.upstream_version.as_deref().unwrap_or("-");
+        md.push_str(&format!(
+            "| {} | {} | {} |\n",
+            pkg.pkg_name, current, upstream
+        ));
+    }
+    if outdated.len() > REPORT_TABLE_ROWS {
+        md.push_str(&format!(
+            "\n*...and {} more*\n",
+            outdated.len() - REPORT_TABLE_ROWS
+        ));
+    }
+    md
+}
+
+fn markdown_recent_table(
+    recent: &[(sbuild_cache::PackageRecord, sbuild_cache::BuildHistoryEntry)],
+) -> String {
+    let mut md = String::new();
+    md.push_str("| Status | Package | Version | Date |\n");
+    md.push_str("|--------|---------|---------|------|\n");
+    for (pkg, hist) in recent {
+        let date = hist.build_date.format("%Y-%m-%d %H:%M");
+        md.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            history_status(hist),
+            pkg.pkg_name,
+            hist.version,
+            date
+        ));
+    }
+    md
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut html = String::from("<table>\n<tr>");
+    for header in headers {
+        html.push_str(&format!("<th>{}</th>", header));
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            html.push_str(&format!("<td>{}</td>", html_escape(cell)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Placeholder values for `--template`, rendered as markdown or HTML
+fn report_fields(
+    format: ReportFormat,
+    host: &str,
+    stats: &sbuild_cache::BuildStats,
+    failed: &[sbuild_cache::PackageRecord],
+    outdated: &[sbuild_cache::PackageRecord],
+    recent: &[(sbuild_cache::PackageRecord, sbuild_cache::BuildHistoryEntry)],
+    trend: &[DailyBuildStats],
+) -> Vec<(&'static str, String)> {
+    let or_dash = |v: Option<&str>| v.unwrap_or("-").to_string();
+    let (stats_table, failed_table, outdated_table, recent_table) =
+        if matches!(format, ReportFormat::Html) {
+            let stats_rows = vec![
+                vec![
+                    "Total Packages".to_string(),
+                    stats.total_packages.to_string(),
+                ],
+                vec!["Successful".to_string(), stats.successful.to_string()],
+                vec!["Failed".to_string(), stats.failed.to_string()],
+                vec!["Pending".to_string(), stats.pending.to_string()],
+                vec!["Outdated".to_string(), stats.outdated.to_string()],
+            ];
+            let failed_rows: Vec<_> = failed
+                .iter()
+                .take(REPORT_TABLE_ROWS)
+                .map(|pkg| {
+                    vec![
+                        pkg.pkg_name.clone(),
+                        or_dash(pkg.current_version.as_deref()),
+                        pkg.last_build_date
+                            .map(|d| d.format("%Y-%m-%d").to_string())
+                            .unwrap_or_else(|| "-".to_string()),
+                    ]
+                })
+                .collect();
+            let outdated_rows: Vec<_> = outdated
+                .iter()
+                .take(REPORT_TABLE_ROWS)
+                .map(|pkg| {
+                    vec![
+                        pkg.pkg_name.clone(),
+                        or_dash(pkg.current_version.as_deref()),
+                        or_dash(pkg.upstream_version.as_deref()),
+                    ]
+                })
+                .collect();
+            let recent_rows: Vec<_> = recent
+                .iter()
+                .map(|(pkg, hist)| {
+                    vec![
+                        history_status(hist),
+                        pkg.pkg_name.clone(),
+                        hist.version.clone(),
+                        hist.build_date.format("%Y-%m-%d %H:%M").to_string(),
+                    ]
+                })
+                .collect();
+            (
+                html_table(&["Metric", "Count"], &stats_rows),
+                html_table(&["Package", "Version", "Last Build"], &failed_rows),
+                html_table(&["Package", "Current", "Upstream"], &outdated_rows),
+                html_table(&["Status", "Package", "Version", "Date"], &recent_rows),
+            )
+        } else {
+            (
+                markdown_stats_table(stats),
+                markdown_failed_table(failed),
+                markdown_outdated_table(outdated),
+                markdown_recent_table(recent),
+            )
+        };
+
+    let (host, trend) = if matches!(format, ReportFormat::Html) {
+        (html_escape(host), html_trend(trend))
+    } else {
+        (host.to_string(), markdown_trend(trend))
+    };
+
+    vec![
+        ("host", host),
+        (
+            "generated",
+            chrono::Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+        ),
+        ("total", stats.total_packages.to_string()),
+        ("successful", stats.successful.to_string()),
+        ("failed", stats.failed.to_string()),
+        ("pending", stats.pending.to_string()),
+        ("outdated", stats.outdated.to_string()),
+        ("success_rate", format!("{:.1}", success_rate(stats))),
+        ("stats", stats_table),
+        ("failed_table", failed_table),
+        ("outdated_table", outdated_table),
+        ("recent_table", recent_table),
+        ("trend", trend),
+    ]
+}
+
+/// Failures compared to a previous JSON report
+struct BaselineComparison {
+    failed_before: i64,
+    failed_now: i64,
+    /// Failing packages that the baseline recorded as successful
+    newly_failed: Vec<String>,
+}
+
+impl BaselineComparison {
+    fn is_regression(&self) -> bool {
+        self.failed_now > self.failed_before || !self.newly_failed.is_empty()
+    }
+}
+
+/// Compare against a report written with `--format json`. Both failed and
+/// timed out builds count as failures.
+///
+/// Uses the baseline's `package_statuses` when present; older reports only
+/// list failures, so there any failure missing from `failed_packages` counts.
+fn compare_to_baseline(
+    baseline: &serde_json::Value,
+    failed: &[sbuild_cache::PackageRecord],
+) -> BaselineComparison {
+    let statuses = baseline["package_statuses"].as_object();
+    let failed_before = match statuses {
+        Some(statuses) => statuses
+            .values()
+            .filter_map(|s| s.as_str().and_then(BuildStatus::from_str))
+            .filter(BuildStatus::is_failure)
+            .count() as i64,
+        None => baseline["stats"]["failed"].as_i64().unwrap_or(0),
+    };
+    let previously_failed: Vec<&str> = baseline["failed_packages"]
+        .as_array()
+        .map(|pkgs| pkgs.iter().filter_map(|p| p["pkg_id"].as_str()).collect())
+        .unwrap_or_default();
+
+    let newly_failed = failed
+        .iter()
+        .map(|p| p.pkg_id.as_str())
+        .filter(|pkg_id| match statuses {
+            Some(statuses) => statuses.get(*pkg_id).and_then(|s| s.as_str()) == Some("success"),
+            None => !previously_failed.contains(pkg_id),
+        })
+        .map(String::from)
+        .collect();
+
+    BaselineComparison {
+        failed_before,
+        failed_now: failed.len() as i64,
+        newly_failed,
+    }
+}
+
+/// Replace `{{name}}` placeholders; unknown placeholders are left as-is
+fn render_template(template: &str, fields: &[(&str, String)]) -> String {
+    let mut out = template.to_string();
+    for (name, value) in fields {
+        out = out.replace(&format!("{{{{{}}}}}", name), value);
+    }
+    out
+}
+
 fn generate_html_report(
     host: &str,
     stats: &sbuild_cache::BuildStats,
     _failed: &[sbuild_cache::PackageRecord],
     _outdated: &[sbuild_cache::PackageRecord],
     _recent: &[(sbuild_cache::PackageRecord, sbuild_cache::BuildHistoryEntry)],
+    trend: &[DailyBuildStats],
 ) -> String {
-    let success_rate = if stats.total_packages > 0 {
-        (stats.successful as f64 / stats.total_packages as f64) * 100.0
+    let success_rate = success_rate(stats);
+    let trend = if trend.is_empty() {
+        String::new()
     } else {
-        0.0
+        format!(
+            "<h3>Trend (last {} days)</h3>\n    {}",
+            trend.len(),
+            html_trend(trend)
+        )
     };
 
     format!(
@@ -858,6 +1933,7 @@ fn generate_html_report(
         <div class="stat"><div class="stat-value">{pending}</div><div class="stat-label">Pending</div></div>
     </div>
     <h3>Success Rate: {success_rate:.1}%</h3>
+    {trend}
 </body>
 </html>"#,
         host = host,
@@ -867,5 +1943,60 @@ fn generate_html_report(
         fail = stats.failed,
         pending = stats.pending,
         success_rate = success_rate,
+        trend = trend,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_since_now_rejects_overflow() {
+        let week = parse_duration("1w").unwrap();
+        assert!(since_now(week).unwrap() < chrono::Utc::now());
+        assert!(since_now(parse_duration("9999999999w").unwrap()).is_err());
+    }
+
+    fn package(pkg_id: &str, status: BuildStatus) -> PackageRecord {
+        let mut pkg = PackageRecord::new(pkg_id.into(), pkg_id.into(), "x86_64-linux".into());
+        pkg.last_build_status = Some(status);
+        pkg
+    }
+
+    #[test]
+    fn baseline_comparison_counts_timeouts_as_failures() {
+        let baseline = serde_json::json!({
+            "stats": { "failed": 1 },
+            "package_statuses": {
+                "a": "success",
+                "b": "failed",
+                "c": "timeout",
+                "d": "success",
+            },
+        });
+
+        let unchanged = compare_to_baseline(
+            &baseline,
+            &[
+                package("b", BuildStatus::Timeout),
+                package("c", BuildStatus::Failed),
+            ],
+        );
+        assert_eq!(unchanged.failed_before, 2);
+        assert_eq!(unchanged.failed_now, 2);
+        assert!(!unchanged.is_regression());
+
+        let regressed = compare_to_baseline(
+            &baseline,
+            &[
+                package("a", BuildStatus::Timeout),
+                package("b", BuildStatus::Failed),
+                package("c", BuildStatus::Timeout),
+            ],
+        );
+        assert_eq!(regressed.failed_now, 3);
+        assert_eq!(regressed.newly_failed, vec!["a".to_string()]);
+        assert!(regressed.is_regression());
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use colored::Colorize;
+use sbuild::checksum::{verify_checksum_file, VerifyStatus};
+
+#[derive(Parser)]
+#[command(about = "Verify build artifacts against their CHECKSUM file")]
+pub struct ChecksumVerifyArgs {
+    /// Directory containing the artifacts and CHECKSUM file
+    #[arg(required = true)]
+    pub dir: PathBuf,
+}
+
+/// With `quiet`, only failures are printed.
+pub fn run(args: ChecksumVerifyArgs, quiet: bool) -> Result<(), String> {
+    let entries = verify_checksum_file(&args.dir)
+        .map_err(|e| format!("Failed to verify {}: {}", args.dir.display(), e))?;
+
+    let mut failed = 0;
+    for entry in &entries {
+        match &entry.status {
+            VerifyStatus::Ok => {
+                if !quiet {
+                    println!(
+                        "[{}] {} ({})",
+                        "✔".bright_green().bold(),
+                        entry.filename,
+                        entry.algorithm
+                    );
+                }
+            }
+            VerifyStatus::Mismatch { expected, actual } => {
+                failed += 1;
+                eprintln!(
+                    "[{}] {} ({}): expected {}, got {}",
+                    "✗".bright_red().bold(),
+                    entry.filename,
+                    entry.algorithm,
+                    expected,
+                    actual
+                );
+            }
+            VerifyStatus::Missing => {
+                failed += 1;
+                eprintln!(
+                    "[{}] {} ({}): missing",
+                    "✗".bright_red().bold(),
+                    entry.filename,
+                    entry.algorithm
+                );
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!(
+            "{} of {} checksum entries failed",
+            failed,
+            entries.len()
+        ));
+    }
+
+    if !quiet {
+        println!("{} checksum entries verified", entries.len());
+    }
+    Ok(())
+}
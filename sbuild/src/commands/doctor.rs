@@ -0,0 +1,201 @@
+use std::{path::Path, process::Command};
+
+use clap::Parser;
+use colored::Colorize;
+use sbuild::{ghcr::GhcrClient, signing::Signer, types::SoarEnv};
+
+#[derive(Parser)]
+#[command(about = "Check that the external tools sbuild relies on are installed")]
+pub struct DoctorArgs {
+    /// Also require oras, for `build --push`
+    #[arg(long)]
+    push: bool,
+
+    /// Also require minisign, for `build --sign`
+    #[arg(long)]
+    sign: bool,
+
+    /// Also require shellcheck, for `lint`
+    #[arg(long)]
+    lint: bool,
+}
+
+/// External tools, with the arguments that make them print their version
+pub(crate) const TOOLS: [(&str, &[&str]); 5] = [
+    ("soar", &["--version"]),
+    ("oras", &["version"]),
+    ("minisign", &["-v"]),
+    ("shellcheck", &["--version"]),
+    ("unsquashfs", &["-version"]),
+];
+
+struct Tool {
+    name: &'static str,
+    /// What the tool is for, shown when it's missing
+    purpose: &'static str,
+    required: bool,
+    installed: bool,
+}
+
+/// Version of one of [`TOOLS`], as it reports it
+pub(crate) fn tool_version(name: &str) -> Option<String> {
+    let (_, args) = TOOLS.iter().find(|(tool, _)| *tool == name)?;
+    let output = Command::new(name).args(*args).output().ok()?;
+    let text = if output.stdout.is_empty() {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    parse_version(name, &String::from_utf8_lossy(&text))
+}
+
+/// First line with a number in what a tool prints when asked for its
+/// version, minus its name and any `version:` label
+fn parse_version(name: &str, text: &str) -> Option<String> {
+    text.lines()
+        .map(str::trim)
+        .find(|l| l.contains(|c: char| c.is_ascii_digit()))
+        .map(|l| {
+            let l = l.strip_prefix(name).unwrap_or(l).trim_start();
+            let l = ["version:", "Version:", "version"]
+                .iter()
+                .find_map(|label| l.strip_prefix(label))
+                .unwrap_or(l);
+            l.trim().to_string()
+        })
+}
+
+/// Probe each tool and `soar env`, failing if anything required is missing.
+pub fn run(args: DoctorArgs, soar_env: Option<SoarEnv>, quiet: bool) -> Result<(), String> {
+    let tools = [
+        Tool {
+            name: "soar",
+            purpose: "installing build_util and build_deps",
+            required: true,
+            installed: which::which("soar").is_ok(),
+        },
+        Tool {
+            name: "oras",
+            purpose: "pushing to GHCR with build --push",
+            required: args.push,
+            installed: GhcrClient::check_oras().is_ok(),
+        },
+        Tool {
+            name: "minisign",
+            purpose: "signing with build --sign",
+            required: args.sign,
+            installed: Signer::check_minisign().is_ok(),
+        },
+        Tool {
+            name: "shellcheck",
+            purpose: "checking x_exec scripts in lint",
+            required: args.lint,
+            installed: which::which("shellcheck").is_ok(),
+        },
+        Tool {
+            name: "unsquashfs",
+            purpose: "converting AppImages; skipped without it",
+            required: false,
+            installed: which::which("unsquashfs").is_ok(),
+        },
+    ];
+
+    let mut missing = Vec::new();
+    for tool in &tools {
+        if tool.installed {
+            if !quiet {
+                let version = tool_version(tool.name).unwrap_or_default();
+                println!("[{}] {} {}", "✔".bright_green().bold(), tool.name, version);
+            }
+        } else if tool.required {
+            eprintln!(
+                "[{}] {} not found, needed for {}",
+                "〤".bright_red().bold(),
+                tool.name,
+                tool.purpose
+            );
+            missing.push(tool.name);
+        } else if !quiet {
+            println!(
+                "[{}] {} not found (optional, for {})",
+                "-".bright_yellow().bold(),
+                tool.name,
+                tool.purpose
+            );
+        }
+    }
+
+    // `soar env` is how builds find where soar installs tools
+    if tools[0].installed {
+        match soar_env {
+            Some(env) => {
+                for (var, path) in [("SOAR_BIN", &env.bin_path), ("SOAR_CACHE", &env.cache_path)] {
+                    if path.is_empty() {
+                        eprintln!(
+                            "[{}] soar env doesn't report {}",
+                            "〤".bright_red().bold(),
+                            var
+                        );
+                        missing.push(var);
+                    } else if !Path::new(path).is_dir() {
+                        println!(
+                            "[{}] {}={} doesn't exist yet",
+                            "-".bright_yellow().bold(),
+                            var,
+                            path
+                        );
+                    } else if !quiet {
+                        println!("[{}] {}={}", "✔".bright_green().bold(), var, path);
+                    }
+                }
+            }
+            None => {
+                eprintln!(
+                    "[{}] `soar env` failed; run it to see why",
+                    "〤".bright_red().bold()
+                );
+                missing.push("soar env");
+            }
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(format!("missing: {}", missing.join(", ")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("soar", "soar 0.5.2\n").as_deref(),
+            Some("0.5.2")
+        );
+        assert_eq!(
+            parse_version("oras", "Version:        1.2.0\nGo version:     go1.22\n").as_deref(),
+            Some("1.2.0")
+        );
+        assert_eq!(
+            parse_version(
+                "shellcheck",
+                "ShellCheck - shell script analysis tool\nversion: 0.10.0\nlicense: GPLv3\n"
+            )
+            .as_deref(),
+            Some("0.10.0")
+        );
+        assert_eq!(
+            parse_version("unsquashfs", "unsquashfs version 4.6.1 (2023/03/25)\n").as_deref(),
+            Some("4.6.1 (2023/03/25)")
+        );
+        assert_eq!(parse_version("minisign", "no digits here\n"), None);
+    }
+
+    #[test]
+    fn test_unknown_tool_has_no_version() {
+        assert_eq!(tool_version("not-a-listed-tool"), None);
+    }
+}
@@ -1,7 +1,13 @@
 use clap::{Parser, ValueEnum};
 use colored::Colorize;
 use saphyr::{LoadableYamlNode, YamlOwned};
-use sbuild::fetch_recipe;
+use sbuild::{
+    fetch_recipe,
+    ghcr::{ghcr_repo_path, ghcr_tag},
+    ghcr_family, parse_ghcr_path, recipe_url,
+};
+use sbuild_cache::current_host_triplet;
+use sbuild_meta::{recipe::ProvideKind, SBuildRecipe};
 
 #[derive(Parser)]
 #[command(about = "Get information about an SBUILD recipe")]
@@ -17,6 +23,19 @@ pub struct InfoArgs {
 
     #[arg(long)]
     pub field: Option<String>,
+
+    /// Print the GHCR repositories and tag a build of this recipe would push to
+    #[arg(long, requires = "ghcr_repo")]
+    pub ghcr_path: bool,
+
+    /// Base GHCR repository (owner/repo) for --ghcr-path
+    #[arg(long)]
+    pub ghcr_repo: Option<String>,
+
+    /// Print each `provides` entry: the binary, how it's installed (alias
+    /// `:`, symlink `==`, rename `=>`) and the package it ends up in
+    #[arg(long, alias = "list-provides")]
+    pub provides: bool,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum, Default)]
@@ -84,9 +103,10 @@ fn get_hosts(yaml: &YamlOwned) -> Option<Vec<String>> {
     )
 }
 
-pub async fn run(args: InfoArgs) -> Result<(), String> {
-    let content = if args.recipe.starts_with("http://") || args.recipe.starts_with("https://") {
-        fetch_recipe(&args.recipe).await?
+/// With `quiet`, `--check-host` only reports through its exit status.
+pub async fn run(args: InfoArgs, quiet: bool, compact_json: bool) -> Result<(), String> {
+    let content = if let Some(url) = recipe_url(&args.recipe) {
+        fetch_recipe(&url).await?
     } else {
         std::fs::read_to_string(&args.recipe)
             .map_err(|e| format!("Failed to read recipe: {}", e))?
@@ -99,6 +119,15 @@ pub async fn run(args: InfoArgs) -> Result<(), String> {
         .next()
         .ok_or_else(|| "Empty YAML document".to_string())?;
 
+    if args.ghcr_path {
+        let base_repo = args.ghcr_repo.as_deref().unwrap_or_default();
+        return print_ghcr_targets(&args.recipe, &content, base_repo, args.format, compact_json);
+    }
+
+    if args.provides {
+        return print_provides(&content, args.format, compact_json);
+    }
+
     if let Some(ref check_host) = args.check_host {
         if let Some(host_list) = get_hosts(&yaml) {
             let is_supported = host_list.iter().any(|h| h.eq_ignore_ascii_case(check_host));
@@ -110,10 +139,14 @@ pub async fn run(args: InfoArgs) -> Result<(), String> {
                 std::process::exit(1);
             }
 
-            println!("Host {} is supported", check_host);
+            if !quiet {
+                println!("Host {} is supported", check_host);
+            }
             return Ok(());
         } else {
-            println!("Host {} is supported (no restrictions)", check_host);
+            if !quiet {
+                println!("Host {} is supported (no restrictions)", check_host);
+            }
             return Ok(());
         }
     }
@@ -145,7 +178,7 @@ pub async fn run(args: InfoArgs) -> Result<(), String> {
     } else {
         match args.format {
             OutputFormat::Json => {
-                let json = serde_json::to_string_pretty(&yaml_to_json(&yaml))
+                let json = super::to_json(&yaml_to_json(&yaml), compact_json)
                     .map_err(|e| format!("Failed to convert to JSON: {}", e))?;
                 println!("{}", json);
             }
@@ -186,3 +219,142 @@ pub async fn run(args: InfoArgs) -> Result<(), String> {
         Ok(())
     }
 }
+
+/// Show where `sbuild build --push` would put this recipe's packages, using
+/// the same path derivation as the build. The version comes from the recipe's
+/// `pkgver`; real builds may add a `-rN` revision suffix.
+///
+/// Fails after printing when the family can't be derived, since the build
+/// then falls back to the pkg_id.
+fn print_ghcr_targets(
+    recipe_path: &str,
+    content: &str,
+    base_repo: &str,
+    format: OutputFormat,
+    compact_json: bool,
+) -> Result<(), String> {
+    let mut recipe =
+        SBuildRecipe::from_yaml(content).map_err(|e| format!("Failed to parse recipe: {}", e))?;
+    // The linter fills in a missing pkg_id before the build sees it
+    if recipe.pkg_id.is_empty() {
+        if let Some(pkg_id) = recipe
+            .src_url
+            .first()
+            .and_then(|src| sbuild_linter::get_pkg_id(src))
+        {
+            recipe.pkg_id = pkg_id;
+        }
+    }
+
+    // Builds only derive the family from a recipe URL, never a local path
+    let url = recipe_url(recipe_path);
+    let derived = url.as_deref().and_then(parse_ghcr_path).is_some();
+    let (pkg_family, recipe_name) = ghcr_family(url.as_deref(), &recipe.pkg_id);
+
+    let version = recipe.pkgver.as_deref().unwrap_or("latest");
+    let arch = current_host_triplet();
+    let tag = ghcr_tag(version, &arch);
+
+    let targets: Vec<(String, String)> = recipe
+        .get_provided_packages()
+        .into_iter()
+        .map(|pkg| {
+            let repo = ghcr_repo_path(
+                base_repo,
+                &pkg_family,
+                &recipe_name,
+                &pkg,
+                recipe.ghcr_pkg.as_deref(),
+            );
+            (pkg, repo)
+        })
+        .collect();
+
+    match format {
+        OutputFormat::Json => {
+            let json = serde_json::json!({
+                "derived": derived,
+                "pkg_family": pkg_family,
+                "recipe_name": recipe_name,
+                "tag": tag,
+                "targets": targets
+                    .iter()
+                    .map(|(pkg, repo)| serde_json::json!({
+                        "package": pkg,
+                        "repository": repo,
+                        "target": format!("ghcr.io/{}:{}", repo, tag),
+                    }))
+                    .collect::<Vec<_>>(),
+            });
+            let json = super::to_json(&json, compact_json)
+                .map_err(|e| format!("Failed to convert to JSON: {}", e))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            println!("{}: {}", "pkg_family".bright_cyan(), pkg_family);
+            println!("{}: {}", "recipe_name".bright_cyan(), recipe_name);
+            println!("{}: {}", "tag".bright_cyan(), tag);
+            for (pkg, repo) in &targets {
+                println!("{}: ghcr.io/{}:{}", pkg.bright_cyan(), repo, tag);
+            }
+        }
+    }
+
+    if !derived {
+        return Err(format!(
+            "Can't derive the package family from '{}' (expected a recipe URL ending in \
+             .../binaries/<family>/<recipe>.yaml or .../packages/<family>/<recipe>.yaml); \
+             the build falls back to '{}'",
+            recipe_path, recipe.pkg_id
+        ));
+    }
+    Ok(())
+}
+
+/// Show what each `provides` entry installs. Recipes without `provides`
+/// produce one binary named after `pkg`.
+fn print_provides(content: &str, format: OutputFormat, compact_json: bool) -> Result<(), String> {
+    let recipe =
+        SBuildRecipe::from_yaml(content).map_err(|e| format!("Failed to parse recipe: {}", e))?;
+    let provides = recipe.provides_breakdown();
+    let packages = recipe.get_provided_packages();
+
+    match format {
+        OutputFormat::Json => {
+            let json = serde_json::json!({
+                "pkg": recipe.pkg,
+                "packages": packages,
+                "provides": provides,
+            });
+            let json = super::to_json(&json, compact_json)
+                .map_err(|e| format!("Failed to convert to JSON: {}", e))?;
+            println!("{}", json);
+        }
+        OutputFormat::Text => {
+            println!("{}: {}", "packages".bright_cyan(), packages.join(", "));
+            if provides.is_empty() && !recipe.has_packages() {
+                println!("{} (binary)", recipe.pkg);
+            }
+            for provide in &provides {
+                let how = match (provide.kind, provide.target.as_deref()) {
+                    (ProvideKind::Alias, Some(target)) => format!("alias {}", target),
+                    (ProvideKind::Symlink, Some(target)) => format!("symlink {}", target),
+                    (ProvideKind::Rename, Some(target)) => format!("renamed to {}", target),
+                    _ => "binary".to_string(),
+                };
+                let package = match provide.package {
+                    Some(ref package) => format!("package {}", package),
+                    None => "binary only".to_string(),
+                };
+                println!(
+                    "{} ({}, {}) [{}]",
+                    provide.command.bright_cyan(),
+                    how,
+                    package,
+                    provide.raw
+                );
+            }
+        }
+    }
+    Ok(())
+}
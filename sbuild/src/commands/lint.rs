@@ -1,13 +1,14 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     env,
-    fs::OpenOptions,
-    io::Write,
-    path::PathBuf,
+    fs::{self, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::Command,
     sync::{
         self,
         atomic::{AtomicUsize, Ordering},
-        Arc, LazyLock,
+        Arc, LazyLock, Mutex,
     },
     thread,
     time::{Duration, Instant},
@@ -15,11 +16,20 @@ use std::{
 
 use clap::Parser;
 use colored::Colorize;
+use sbuild::{
+    fetch_recipe,
+    linkcheck::{check_link, link_client, LinkStatus},
+    progress::Progress,
+    recipe_url,
+};
 use sbuild_linter::{
+    include::include_path,
     logger::{LogManager, LogMessage},
+    policy::compile_deny_patterns,
     semaphore::Semaphore,
-    Linter,
+    LintOptions, Linter,
 };
+use serde::{Deserialize, Serialize};
 
 static CHECK_MARK: LazyLock<colored::ColoredString> = LazyLock::new(|| "✔".bright_green().bold());
 static CROSS_MARK: LazyLock<colored::ColoredString> = LazyLock::new(|| "〤".bright_red().bold());
@@ -28,10 +38,30 @@ static WARN: LazyLock<colored::ColoredString> = LazyLock::new(|| "⚠️".bright
 #[derive(Parser)]
 #[command(about = "Linter for SBUILD package files")]
 pub struct LintArgs {
-    /// Files to lint
-    #[arg(required = true)]
+    /// Files to lint, URLs or `github:owner/repo[@ref]:path` references, or
+    /// `-` to read a recipe from stdin. With --changed, recipe directories
+    /// (or files) to limit the changed set to
+    #[arg(required_unless_present = "changed")]
     files: Vec<String>,
 
+    /// Only lint recipes changed between this git ref and HEAD
+    #[arg(long, value_name = "BASE_REF")]
+    changed: Option<String>,
+
+    /// Skip files that passed with the same content and linter version, as
+    /// recorded in this JSON file. Skipped files get no .validated output.
+    #[arg(long, value_name = "FILE")]
+    lint_cache: Option<PathBuf>,
+
+    /// With `-`, print the validated recipe to stdout
+    #[arg(long)]
+    stdout: bool,
+
+    /// Only canonicalize formatting (no shellcheck) and list files that
+    /// aren't formatted; with --inplace, rewrite them
+    #[arg(long)]
+    fmt: bool,
+
     /// Enable pkgver mode
     #[arg(short = 'P', long)]
     pkgver: bool,
@@ -59,20 +89,85 @@ pub struct LintArgs {
     /// Timeout duration in seconds
     #[arg(long, default_value = "30")]
     timeout: u64,
+
+    /// Don't show the progress indicator
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Check that icon/desktop file and dir resources exist under this directory
+    #[arg(long, value_name = "BASE")]
+    check_paths: Option<PathBuf>,
+
+    /// Fail when a line of x_exec.run or x_exec.pkgver matches REGEX (repeatable)
+    #[arg(long, value_name = "REGEX")]
+    deny_pattern: Vec<String>,
+
+    /// Also apply the bundled deny patterns (curl | sh, sudo, writes to system dirs)
+    #[arg(long)]
+    default_deny: bool,
+
+    /// Fail on unknown fields and packages without provides instead of
+    /// warning about them
+    #[arg(long)]
+    strict: bool,
+
+    /// Warn about src_url and homepage links that don't respond (needs network)
+    #[arg(long)]
+    check_src_url: bool,
+
+    /// Per-request timeout in seconds for --check-src-url
+    #[arg(long, value_name = "SECS", default_value = "10")]
+    src_url_timeout: u64,
+
+    /// Write per-file error and warning counts to this JSON file
+    #[arg(long, value_name = "PATH")]
+    report_json: Option<PathBuf>,
 }
 
-pub fn run(args: LintArgs) -> Result<(), String> {
-    let files: HashSet<String> = args.files.iter().cloned().collect();
+pub async fn run(mut args: LintArgs, quiet: bool, compact_json: bool) -> Result<(), String> {
+    if args.files.iter().any(|f| f == "-") {
+        return lint_stdin(&args);
+    }
+
+    // Remote recipes are linted from temp copies, removed when this returns
+    let _remote_dir = fetch_remote_recipes(&mut args).await?;
+
+    let files: HashSet<String> = match args.changed {
+        Some(ref base_ref) => match changed_recipes(base_ref, &args.files) {
+            Ok(changed) => {
+                if changed.is_empty() {
+                    println!("No recipes changed since {}", base_ref);
+                    return Ok(());
+                }
+                changed.into_iter().collect()
+            }
+            Err(e) => {
+                eprintln!(
+                    "[{}] Can't determine changed files ({}); linting everything",
+                    &*WARN, e
+                );
+                expand_recipe_dirs(&args.files)
+            }
+        },
+        None => args.files.iter().cloned().collect(),
+    };
 
     if files.is_empty() {
         return Err("No files specified".to_string());
     }
 
+    if args.fmt {
+        return format_files(&files, &args);
+    }
+
     if !args.no_shellcheck && which::which("shellcheck").is_err() {
         return Err("shellcheck not found. Please install.".to_string());
     }
+    let deny_patterns = compile_deny_patterns(&args.deny_pattern, args.default_deny)?;
 
-    println!("sbuild lint v{}", env!("CARGO_PKG_VERSION"));
+    if !quiet {
+        println!("sbuild lint v{}", env!("CARGO_PKG_VERSION"));
+    }
 
     let now = Instant::now();
     let success = Arc::new(AtomicUsize::new(0));
@@ -106,7 +201,7 @@ pub fn run(args: LintArgs) -> Result<(), String> {
     let parallel = args.parallel;
     let file_count = files.len();
     let logger_handle = thread::spawn(move || {
-        let show_all = parallel == 1 || file_count == 1;
+        let show_all = !quiet && (parallel == 1 || file_count == 1);
         while let Ok(log) = rx.recv() {
             match log {
                 LogMessage::Info(msg) if show_all => println!("{}", msg),
@@ -120,7 +215,55 @@ pub fn run(args: LintArgs) -> Result<(), String> {
         }
     });
 
+    let lint_cache = match args.lint_cache {
+        Some(_) if args.pkgver || args.check_src_url => {
+            eprintln!(
+                "[{}] --lint-cache is ignored with --pkgver or --check-src-url, which always need a fresh run",
+                &*WARN
+            );
+            None
+        }
+        Some(ref path) => Some(Arc::new(LintCache::load(path))),
+        None => None,
+    };
+    // Options that change the outcome are part of the cache key.
+    let cache_flavor = format!(
+        "{}{}{}",
+        if args.no_shellcheck {
+            "-noshellcheck"
+        } else {
+            ""
+        },
+        match &args.check_paths {
+            Some(base) => format!(
+                "-paths:{}",
+                std::path::absolute(base)
+                    .unwrap_or_else(|_| base.clone())
+                    .display()
+            ),
+            None => String::new(),
+        },
+        if args.strict { "-strict" } else { "" }
+    );
+    let cache_flavor = if deny_patterns.is_empty() {
+        cache_flavor
+    } else {
+        let patterns: Vec<&str> = deny_patterns.iter().map(|p| p.as_str()).collect();
+        format!("{}-deny:{}", cache_flavor, patterns.join("\u{1f}"))
+    };
+
+    // (file, field, url) for every link in a recipe that passed
+    let links = args
+        .check_src_url
+        .then(|| Arc::new(Mutex::new(Vec::<(String, &str, String)>::new())));
+
+    let report = args
+        .report_json
+        .is_some()
+        .then(|| Arc::new(Mutex::new(Vec::<FileReport>::new())));
+
     let semaphore = Arc::new(Semaphore::new(args.parallel));
+    let progress = Arc::new(Progress::new(file_count, !args.no_progress));
     let mut handles = Vec::new();
 
     for file_path in &files {
@@ -131,18 +274,60 @@ pub fn run(args: LintArgs) -> Result<(), String> {
         let fail = Arc::clone(&fail);
         let success_store = success_store.clone();
         let fail_store = fail_store.clone();
-        let inplace = args.inplace;
-        let no_shellcheck = args.no_shellcheck;
-        let pkgver = args.pkgver;
+        let options = LintOptions::new()
+            .with_inplace(args.inplace)
+            .with_shellcheck(!args.no_shellcheck)
+            .with_pkgver(args.pkgver)
+            .with_resource_base(args.check_paths.clone())
+            .with_deny_patterns(deny_patterns.clone())
+            .with_strict(args.strict);
         let timeout = args.timeout;
+        let progress = Arc::clone(&progress);
+        let lint_cache = lint_cache.clone();
+        let cache_key = lint_cache
+            .as_ref()
+            .and_then(|_| LintCache::key(&file_path, &cache_flavor));
+        let links = links.clone();
+        let report = report.clone();
 
         semaphore.acquire();
         let handle = thread::spawn(move || {
-            let linter = Linter::new(logger, Duration::from_secs(timeout));
-            if linter
-                .lint(&file_path, inplace, no_shellcheck, pkgver)
-                .is_some()
-            {
+            let cached = lint_cache
+                .as_ref()
+                .zip(cache_key.as_ref())
+                .and_then(|(cache, key)| cache.passed(key));
+
+            let (passed, errors, warnings) = cached.map_or_else(
+                || {
+                    let linter = Linter::new(logger, Duration::from_secs(timeout));
+                    let config = linter.lint(&file_path, &options);
+                    if let (Some(config), Some(links)) = (&config, &links) {
+                        let homepage = config.homepage.iter().flatten();
+                        links.lock().unwrap().extend(
+                            (config.src_url.iter().map(|url| ("src_url", url)))
+                                .chain(homepage.map(|url| ("homepage", url)))
+                                .map(|(field, url)| (file_path.clone(), field, url.clone())),
+                        );
+                    }
+                    let passed = config.is_some();
+                    if let (Some(cache), Some(key)) = (&lint_cache, cache_key) {
+                        cache.record(key, passed, linter.warning_count());
+                    }
+                    (passed, linter.error_count(), linter.warning_count())
+                },
+                |warnings| (true, 0, warnings),
+            );
+
+            if let Some(report) = report {
+                report.lock().unwrap().push(FileReport {
+                    file: file_path.clone(),
+                    passed,
+                    errors,
+                    warnings,
+                });
+            }
+
+            if passed {
                 if let Some(mut success_store) = success_store {
                     let fp = format!("{}\n", file_path);
                     let _ = success_store.write_all(fp.as_bytes());
@@ -155,6 +340,7 @@ pub fn run(args: LintArgs) -> Result<(), String> {
                 }
                 fail.fetch_add(1, Ordering::SeqCst);
             }
+            progress.inc();
             semaphore.release();
         });
 
@@ -164,6 +350,13 @@ pub fn run(args: LintArgs) -> Result<(), String> {
     for handle in handles {
         handle.join().unwrap();
     }
+    progress.finish();
+
+    if let Some(ref cache) = lint_cache {
+        if let Err(e) = cache.save(compact_json) {
+            eprintln!("[{}] Failed to write lint cache: {}", &*WARN, e);
+        }
+    }
 
     log_manager.done();
     logger_handle.join().unwrap();
@@ -188,6 +381,22 @@ pub fn run(args: LintArgs) -> Result<(), String> {
         now.elapsed()
     );
 
+    if let (Some(path), Some(report)) = (&args.report_json, report) {
+        let mut report = std::mem::take(&mut *report.lock().unwrap());
+        report.sort_by(|a, b| a.file.cmp(&b.file));
+        let json = super::to_json(&report, compact_json).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+
+    if let Some(links) = links {
+        let links = std::mem::take(&mut *links.lock().unwrap());
+        check_links(
+            links,
+            args.parallel,
+            Duration::from_secs(args.src_url_timeout),
+        )?;
+    }
+
     if fail.load(Ordering::SeqCst) > 0 {
         return Err(format!(
             "{} file(s) failed validation",
@@ -197,3 +406,441 @@ pub fn run(args: LintArgs) -> Result<(), String> {
 
     Ok(())
 }
+
+/// Check each distinct link once with at most `parallel` requests in flight,
+/// warning on broken ones and noting redirects. Never fails the lint.
+fn check_links(
+    mut links: Vec<(String, &str, String)>,
+    parallel: usize,
+    timeout: Duration,
+) -> Result<(), String> {
+    links.sort();
+    let urls: HashSet<&str> = links.iter().map(|(_, _, url)| url.as_str()).collect();
+    let results = Mutex::new(HashMap::new());
+    let semaphore = Semaphore::new(parallel.max(1));
+
+    // The blocking client can't be created or dropped on the async runtime's
+    // thread, so it lives on its own.
+    thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                let client = link_client(timeout).map_err(|e| e.to_string())?;
+                thread::scope(|checks| {
+                    for url in &urls {
+                        semaphore.acquire();
+                        let (client, results, semaphore) = (&client, &results, &semaphore);
+                        checks.spawn(move || {
+                            let status = check_link(client, url);
+                            results.lock().unwrap().insert(*url, status);
+                            semaphore.release();
+                        });
+                    }
+                });
+                Ok::<_, String>(())
+            })
+            .join()
+            .unwrap()
+    })?;
+    let results = results.into_inner().unwrap();
+
+    let (mut broken, mut redirected) = (0, 0);
+    for (file, field, url) in &links {
+        match &results[url.as_str()] {
+            LinkStatus::Ok => {}
+            LinkStatus::Redirected { final_url } => {
+                redirected += 1;
+                println!(
+                    "[{}] {}: {} {} redirects to {}",
+                    "→".bright_blue().bold(),
+                    file,
+                    field,
+                    url,
+                    final_url
+                );
+            }
+            LinkStatus::Broken { status } => {
+                broken += 1;
+                eprintln!(
+                    "[{}] {}: {} {} returned {}",
+                    &*WARN, file, field, url, status
+                );
+            }
+            LinkStatus::Unreachable { error } => {
+                broken += 1;
+                eprintln!(
+                    "[{}] {}: {} {} is unreachable: {}",
+                    &*WARN, file, field, url, error
+                );
+            }
+        }
+    }
+    println!(
+        "[{}] Checked {} link(s): {} broken, {} redirected",
+        "+".bright_blue().bold(),
+        urls.len(),
+        broken,
+        redirected
+    );
+    Ok(())
+}
+
+/// Print the files whose canonical form differs from what's on disk, like
+/// `gofmt -l`, rewriting them when `--inplace` is set.
+fn format_files(files: &HashSet<String>, args: &LintArgs) -> Result<(), String> {
+    let (tx, rx) = sync::mpsc::channel();
+    let log_manager = LogManager::new(tx);
+    let logger_handle = thread::spawn(move || {
+        while let Ok(log) = rx.recv() {
+            match log {
+                LogMessage::Error(msg) => eprintln!("[{}] {}", &*CROSS_MARK, msg),
+                LogMessage::Warn(msg) => eprintln!("[{}] {}", &*WARN, msg),
+                LogMessage::CustomError(msg) => eprintln!("{}", msg),
+                LogMessage::Done => break,
+                _ => {}
+            }
+        }
+    });
+
+    let mut files: Vec<&String> = files.iter().collect();
+    files.sort();
+
+    let mut failed = 0;
+    for file_path in files {
+        let linter = Linter::new(
+            log_manager.create_logger::<PathBuf>(None),
+            Duration::from_secs(args.timeout),
+        );
+        let formatted = fs::read_to_string(file_path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                // Formatting works on the merged recipe and would inline the fragment
+                if include_path(&content, Path::new(file_path)).is_some() {
+                    eprintln!(
+                        "[{}] {}: skipped, recipes using _include aren't formatted",
+                        &*WARN, file_path
+                    );
+                    return Ok((content.clone(), content));
+                }
+                linter
+                    .format_str(&content)
+                    .map(|formatted| (content, formatted))
+                    .ok_or_else(|| "validation failed".to_string())
+            });
+
+        match formatted {
+            Ok((content, formatted)) if content != formatted => {
+                println!("{}", file_path);
+                if args.inplace {
+                    // Keep going so the logger thread is still shut down below
+                    if let Err(e) = fs::write(file_path, formatted) {
+                        eprintln!("[{}] Failed to write {}: {}", &*CROSS_MARK, file_path, e);
+                        failed += 1;
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("[{}] {}: {}", &*CROSS_MARK, file_path, e);
+                failed += 1;
+            }
+        }
+    }
+
+    log_manager.done();
+    logger_handle.join().unwrap();
+
+    if failed > 0 {
+        return Err(format!("{} file(s) could not be formatted", failed));
+    }
+    Ok(())
+}
+
+/// Download URL and `github:` arguments into a temp dir and point
+/// `args.files` at the copies.
+async fn fetch_remote_recipes(args: &mut LintArgs) -> Result<Option<tempfile::TempDir>, String> {
+    if !args.files.iter().any(|f| recipe_url(f).is_some()) {
+        return Ok(None);
+    }
+    if args.changed.is_some() || args.inplace {
+        return Err("--changed and --inplace can't be used with remote recipes".to_string());
+    }
+
+    let dir = tempfile::Builder::new()
+        .prefix("sbuild-lint-")
+        .tempdir()
+        .map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    for (i, file) in args.files.iter_mut().enumerate() {
+        let Some(url) = recipe_url(file) else {
+            continue;
+        };
+        let content = fetch_recipe(&url)
+            .await
+            .map_err(|e| format!("Failed to fetch recipe {}: {}", file, e))?;
+        let name = url.rsplit('/').next().unwrap_or("recipe.yaml");
+        let path = dir.path().join(format!("{}-{}", i, name));
+        fs::write(&path, content).map_err(|e| format!("Failed to write temp recipe: {}", e))?;
+        *file = path.to_string_lossy().into_owned();
+    }
+    Ok(Some(dir))
+}
+
+/// Lint a recipe piped on stdin. All logs go to stderr so the validated
+/// recipe can be taken from stdout.
+fn lint_stdin(args: &LintArgs) -> Result<(), String> {
+    if args.files.len() > 1 {
+        return Err("'-' (stdin) can't be combined with other files".to_string());
+    }
+    if args.inplace {
+        return Err("--inplace can't be used when reading from stdin".to_string());
+    }
+    if args.pkgver {
+        return Err("--pkgver can't be used when reading from stdin".to_string());
+    }
+    if !args.no_shellcheck && which::which("shellcheck").is_err() {
+        return Err("shellcheck not found. Please install.".to_string());
+    }
+
+    let mut content = String::new();
+    std::io::stdin()
+        .read_to_string(&mut content)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+
+    let (tx, rx) = sync::mpsc::channel();
+    let log_manager = LogManager::new(tx);
+    let logger_handle = thread::spawn(move || {
+        while let Ok(log) = rx.recv() {
+            match log {
+                LogMessage::Info(msg) => eprintln!("{}", msg),
+                LogMessage::Success(msg) => eprintln!("[{}] {}", &*CHECK_MARK, msg),
+                LogMessage::Error(msg) => eprintln!("[{}] {}", &*CROSS_MARK, msg),
+                LogMessage::Warn(msg) => eprintln!("[{}] {}", &*WARN, msg),
+                LogMessage::CustomError(msg) => eprintln!("{}", msg),
+                LogMessage::Done => break,
+            }
+        }
+    });
+
+    let options = LintOptions::new()
+        .with_shellcheck(!args.no_shellcheck)
+        .with_resource_base(args.check_paths.clone())
+        .with_deny_patterns(compile_deny_patterns(
+            &args.deny_pattern,
+            args.default_deny,
+        )?)
+        .with_strict(args.strict);
+    let linter = Linter::new(
+        log_manager.create_logger::<PathBuf>(None),
+        Duration::from_secs(args.timeout),
+    );
+    let result = linter.lint_str(&content, &options);
+
+    log_manager.done();
+    logger_handle.join().unwrap();
+
+    let (_, validated) = result.ok_or("stdin failed validation")?;
+    if args.stdout {
+        print!("{}", validated);
+    }
+    Ok(())
+}
+
+/// One file's line in the `--report-json` output
+#[derive(Serialize)]
+struct FileReport {
+    file: String,
+    passed: bool,
+    errors: usize,
+    warnings: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LintCacheEntry {
+    passed: bool,
+    warnings: usize,
+}
+
+/// Lint outcomes keyed by recipe content hash and linter version
+struct LintCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, LintCacheEntry>>,
+}
+
+impl LintCache {
+    /// Load the cache, starting empty if it's missing or unreadable
+    fn load(path: &Path) -> Self {
+        let entries = fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self {
+            path: path.to_path_buf(),
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Hash of the recipe and any `_include`d fragment, linter version and flavor
+    fn key(file_path: &str, flavor: &str) -> Option<String> {
+        let content = fs::read(file_path).ok()?;
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&content);
+        let fragment = std::str::from_utf8(&content)
+            .ok()
+            .and_then(|c| include_path(c, Path::new(file_path)));
+        if let Some(fragment) = fragment {
+            hasher.update(&fs::read(fragment).unwrap_or_default());
+        }
+        Some(format!(
+            "{}+{}{}",
+            hasher.finalize().to_hex(),
+            sbuild_linter::VERSION,
+            flavor
+        ))
+    }
+
+    /// Warning count of a recorded pass
+    fn passed(&self, key: &str) -> Option<usize> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|e| e.passed)
+            .map(|e| e.warnings)
+    }
+
+    fn record(&self, key: String, passed: bool, warnings: usize) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, LintCacheEntry { passed, warnings });
+    }
+
+    fn save(&self, compact_json: bool) -> std::io::Result<()> {
+        let json = super::to_json(&*self.entries.lock().unwrap(), compact_json)?;
+        fs::write(&self.path, json)
+    }
+}
+
+fn is_recipe_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("yaml" | "yml" | "toml")
+    )
+}
+
+/// Recipes added or modified between `base_ref` and HEAD, limited to those
+/// under `roots` when any are given.
+fn changed_recipes(base_ref: &str, roots: &[String]) -> Result<Vec<String>, String> {
+    let git = |args: &[&str]| -> Result<String, String> {
+        let output = Command::new("git")
+            .args(args)
+            .output()
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let toplevel = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+    let diff = git(&[
+        "diff",
+        "--name-only",
+        "--diff-filter=d",
+        &format!("{}...HEAD", base_ref),
+    ])?;
+
+    let cwd = env::current_dir().map_err(|e| e.to_string())?;
+    let roots: Vec<PathBuf> = roots.iter().map(|r| cwd.join(r)).collect();
+
+    Ok(diff
+        .lines()
+        .map(|line| toplevel.join(line))
+        .filter(|path| is_recipe_file(path))
+        .filter(|path| roots.is_empty() || roots.iter().any(|root| path.starts_with(root)))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+/// Replace directories in `paths` with the recipe files they contain.
+fn expand_recipe_dirs(paths: &[String]) -> HashSet<String> {
+    let mut files = HashSet::new();
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            files.insert(path.clone());
+            continue;
+        }
+        let pattern = Path::new(path).join("**/*");
+        for entry in glob::glob(&pattern.to_string_lossy())
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            if entry.is_file() && is_recipe_file(&entry) {
+                files.insert(entry.to_string_lossy().into_owned());
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPE: &str = r#"#!/SBUILD
+_disabled: false
+pkg: bat
+description: A cat clone
+src_url:
+  - https://github.com/sharkdp/bat
+x_exec:
+  shell: bash
+  run: echo hi
+"#;
+
+    #[tokio::test]
+    async fn test_report_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.yaml");
+        let bad = dir.path().join("bad.yaml");
+        let report_path = dir.path().join("report.json");
+        let cache_path = dir.path().join("lint-cache.json");
+        fs::write(&good, RECIPE).unwrap();
+        fs::write(&bad, RECIPE.replace("pkg: bat\n", "")).unwrap();
+
+        let args = || {
+            LintArgs::try_parse_from([
+                "lint".as_ref(),
+                "--no-shellcheck".as_ref(),
+                "--no-progress".as_ref(),
+                "--lint-cache".as_ref(),
+                cache_path.as_os_str(),
+                "--report-json".as_ref(),
+                report_path.as_os_str(),
+                good.as_os_str(),
+                bad.as_os_str(),
+            ])
+            .unwrap()
+        };
+        let read_report = || -> serde_json::Value {
+            serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap()
+        };
+
+        let err = run(args(), true, false).await.unwrap_err();
+        assert_eq!(err, "1 file(s) failed validation");
+        let report = read_report();
+        let entries = report.as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        // Sorted by file name
+        assert!(entries[0]["file"].as_str().unwrap().ends_with("bad.yaml"));
+        assert_eq!(entries[0]["passed"], false);
+        assert!(entries[0]["errors"].as_u64().unwrap() > 0);
+        assert!(entries[1]["file"].as_str().unwrap().ends_with("good.yaml"));
+        assert_eq!(entries[1]["passed"], true);
+        assert_eq!(entries[1]["errors"], 0);
+
+        // A cached pass reports the same counts
+        run(args(), true, false).await.unwrap_err();
+        assert_eq!(read_report(), report);
+    }
+}
@@ -1,16 +1,27 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
 
 use clap::{Parser, Subcommand};
+use futures::StreamExt;
 use log::{debug, info, warn};
-use sbuild_cache::MongoDatabase;
+use sbuild_cache::{normalize_host_triplet, MongoDatabase};
 use sbuild_meta::{
-    hash::{compute_recipe_hash, compute_recipe_hash_excluding_version},
-    manifest::OciManifest,
-    metadata::PackageMetadata,
+    hash::{compute_recipe_hash_with, HashOptions},
+    manifest::{diff_package_json, OciManifest},
+    metadata::{
+        assign_ranks, combine_arches, format_size, merge_index, MergePolicy, PackageMetadata,
+        Source,
+    },
     recipe::{filter_by_arch, filter_enabled, scan_recipes, SBuildRecipe},
     registry::RegistryClient,
+    upstream::{UpdateSource, UpstreamClient},
     Error, Result,
 };
+use sha2::{Digest, Sha256};
 
 #[derive(Parser)]
 #[command(about = "Metadata generator for SBUILD packages")]
@@ -42,6 +53,50 @@ enum MetaCommands {
 
         #[arg(long, default_value = "pkgforge")]
         ghcr_owner: String,
+
+        /// JSON file mapping pkg_id to rank; unlisted packages are ranked
+        /// after these by provides, icon and desktop entry
+        #[arg(long)]
+        rank_from: Option<PathBuf>,
+
+        /// Download single-file payloads to fill in missing bsum/shasum
+        #[arg(long)]
+        compute_checksums: bool,
+
+        /// HEAD every download_url and drop (default) or flag broken ones
+        #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "drop")]
+        verify_urls: Option<UrlCheck>,
+
+        /// Update the existing output in place, keeping entries that failed
+        /// to fetch this run (listed in <output>.stale.json)
+        #[arg(long, overrides_with = "no_merge")]
+        merge: bool,
+
+        /// Regenerate the output from scratch (default)
+        #[arg(long, overrides_with = "merge")]
+        no_merge: bool,
+
+        /// Output format. `ndjson` writes one compact object per line as each
+        /// package is processed: entries are in recipe order rather than
+        /// sorted, only --rank-from ranks are set, and --merge and
+        /// --verify-urls aren't supported.
+        #[arg(long, value_enum, default_value = "json")]
+        format: OutputFormat,
+
+        /// Whether the recipe or the pushed manifest wins when both set a
+        /// package's version or description
+        #[arg(long, value_enum, default_value = "manifest")]
+        prefer: Prefer,
+
+        /// Override --prefer for the description only
+        #[arg(long, value_enum)]
+        prefer_description: Option<Prefer>,
+
+        /// Override --prefer for the version only. Download URLs are tagged
+        /// with the version, so preferring an unbuilt recipe version points
+        /// them at a missing tag.
+        #[arg(long, value_enum)]
+        prefer_version: Option<Prefer>,
     },
 
     ShouldRebuild {
@@ -91,6 +146,35 @@ enum MetaCommands {
 
         #[arg(long)]
         exclude_version: bool,
+
+        /// Also ignore trailing `# comments` after values. Comment-only lines,
+        /// blank lines and leading/trailing whitespace are always ignored.
+        #[arg(long)]
+        ignore_comments: bool,
+    },
+
+    /// Merge per-arch metadata files into one, with arch-specific fields
+    /// (version, download_url, size, ghcr_*, checksums, build info) nested
+    /// under `arches` and the rest shared
+    Combine {
+        /// Metadata files from `generate`; each file's name without
+        /// extension (e.g. x86_64-Linux) names its arch, lowercased like
+        /// every other host triplet
+        #[arg(short, long, num_args = 1.., required = true)]
+        inputs: Vec<PathBuf>,
+
+        #[arg(short, long, default_value = "combined.json")]
+        output: PathBuf,
+    },
+
+    /// Find the recipe(s) that produce a GHCR package
+    Which {
+        /// GHCR path, e.g. pkgforge/bincache/bat/static[/bat][:tag]
+        #[arg(long)]
+        ghcr: String,
+
+        #[arg(short, long, num_args = 1.., required = true)]
+        recipes: Vec<PathBuf>,
     },
 
     FetchManifest {
@@ -105,31 +189,97 @@ enum MetaCommands {
 
         #[arg(long, env = "GITHUB_TOKEN")]
         github_token: Option<String>,
+
+        /// Diff the embedded package JSON against a local metadata JSON file
+        #[arg(long)]
+        compare_json: Option<PathBuf>,
+
+        /// Print the manifest summary as JSON
+        #[arg(long)]
+        json: bool,
     },
 }
 
-pub async fn run(args: MetaArgs) -> Result<()> {
-    setup_logging();
+/// Layout of the `generate` output file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// A sorted, pretty-printed JSON array
+    Json,
+    /// Newline-delimited JSON, streamed as packages are processed
+    Ndjson,
+}
+
+/// Source preferred for fields set by both the recipe and the manifest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Prefer {
+    /// Use the recipe's value, e.g. for metadata fixes not rebuilt yet
+    Recipe,
+    /// Use what the pushed package was built with
+    Manifest,
+}
+
+impl From<Prefer> for Source {
+    fn from(prefer: Prefer) -> Self {
+        match prefer {
+            Prefer::Recipe => Source::Recipe,
+            Prefer::Manifest => Source::Manifest,
+        }
+    }
+}
+
+/// What to do with packages whose download_url doesn't resolve
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum UrlCheck {
+    /// Leave the package out of the index
+    Drop,
+    /// Keep the package with `download_url_broken: true`
+    Flag,
+}
+
+pub async fn run(args: MetaArgs, quiet: bool, compact_json: bool) -> Result<()> {
+    setup_logging(quiet);
 
     match args.command {
         MetaCommands::Generate {
             arch,
             recipes,
             output,
-            cache,
+            cache: _,
             parallel,
             github_token,
             ghcr_owner,
+            rank_from,
+            compute_checksums,
+            verify_urls,
+            merge,
+            no_merge,
+            format,
+            prefer,
+            prefer_description,
+            prefer_version,
         } => {
-            cmd_generate(
+            let mut policy = MergePolicy::prefer(prefer.into());
+            if let Some(prefer) = prefer_description {
+                policy.description = prefer.into();
+            }
+            if let Some(prefer) = prefer_version {
+                policy.version = prefer.into();
+            }
+            cmd_generate(GenerateOptions {
                 arch,
-                recipes,
+                recipe_dirs: recipes,
                 output,
-                cache,
                 parallel,
                 github_token,
                 ghcr_owner,
-            )
+                rank_from,
+                compute_checksums,
+                verify_urls,
+                merge: merge && !no_merge,
+                format,
+                policy,
+                compact_json,
+            })
             .await
         }
 
@@ -145,49 +295,135 @@ pub async fn run(args: MetaArgs) -> Result<()> {
             output,
             parallel,
             timeout,
-        } => cmd_check_updates(recipes, cache, output, parallel, timeout).await,
+        } => cmd_check_updates(recipes, cache, output, parallel, timeout, compact_json).await,
 
         MetaCommands::Inspect {
             recipe,
             arch,
             ghcr_owner,
             live,
-        } => cmd_inspect(recipe, arch, ghcr_owner, live).await,
+        } => cmd_inspect(recipe, arch, ghcr_owner, live, compact_json).await,
 
         MetaCommands::Hash {
             recipe,
             exclude_version,
-        } => cmd_hash(recipe, exclude_version),
+            ignore_comments,
+        } => cmd_hash(
+            recipe,
+            HashOptions::new()
+                .with_exclude_version(exclude_version)
+                .with_ignore_comments(ignore_comments),
+        ),
+
+        MetaCommands::Combine { inputs, output } => cmd_combine(inputs, output, compact_json),
+
+        MetaCommands::Which { ghcr, recipes } => cmd_which(ghcr, recipes),
 
         MetaCommands::FetchManifest {
             repository,
             tag,
             arch,
             github_token,
-        } => cmd_fetch_manifest(repository, tag, arch, github_token).await,
+            compare_json,
+            json,
+        } => {
+            cmd_fetch_manifest(
+                repository,
+                tag,
+                arch,
+                github_token,
+                compare_json,
+                json,
+                compact_json,
+            )
+            .await
+        }
     }
 }
 
-fn setup_logging() {
+fn setup_logging(quiet: bool) {
+    let level = if quiet {
+        log::LevelFilter::Warn
+    } else {
+        log::LevelFilter::Info
+    };
     env_logger::Builder::new()
-        .filter_level(log::LevelFilter::Info)
+        .filter_level(level)
+        .write_style(super::log_write_style())
         .format_target(false)
         .format_timestamp(None)
         .init();
 }
 
-async fn cmd_generate(
+/// Settings for `meta generate`, resolved from its CLI flags
+struct GenerateOptions {
     arch: String,
     recipe_dirs: Vec<PathBuf>,
     output: Option<PathBuf>,
-    _cache: Option<PathBuf>,
-    _parallel: usize,
+    parallel: usize,
     github_token: Option<String>,
     ghcr_owner: String,
-) -> Result<()> {
-    let arch = arch.to_lowercase();
+    rank_from: Option<PathBuf>,
+    compute_checksums: bool,
+    verify_urls: Option<UrlCheck>,
+    merge: bool,
+    format: OutputFormat,
+    policy: MergePolicy,
+    compact_json: bool,
+}
+
+async fn cmd_generate(opts: GenerateOptions) -> Result<()> {
+    let GenerateOptions {
+        arch,
+        recipe_dirs,
+        output,
+        parallel,
+        github_token,
+        ghcr_owner,
+        rank_from,
+        compute_checksums,
+        verify_urls,
+        merge,
+        format,
+        policy,
+        compact_json,
+    } = opts;
+    let arch = normalize_host_triplet(&arch);
     info!("Generating metadata for {}", arch);
 
+    if format == OutputFormat::Ndjson && (merge || verify_urls.is_some()) {
+        return Err(Error::Other(
+            "--format ndjson can't be combined with --merge or --verify-urls".to_string(),
+        ));
+    }
+    let extension = match format {
+        OutputFormat::Json => "json",
+        OutputFormat::Ndjson => "ndjson",
+    };
+    let output_path = output.unwrap_or_else(|| PathBuf::from(format!("{}.{}", arch, extension)));
+    let previous: Vec<PackageMetadata> = if merge && output_path.exists() {
+        let content = std::fs::read_to_string(&output_path)?;
+        let previous: Vec<PackageMetadata> = serde_json::from_str(&content)?;
+        info!(
+            "Merging into {} existing entries from {:?}",
+            previous.len(),
+            output_path
+        );
+        previous
+    } else {
+        Vec::new()
+    };
+
+    let external_ranks: HashMap<String, u32> = match &rank_from {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)?;
+            let ranks: HashMap<String, u32> = serde_json::from_str(&content)?;
+            info!("Loaded {} ranks from {:?}", ranks.len(), path);
+            ranks
+        }
+        None => HashMap::new(),
+    };
+
     let _ = github_token;
     let client = RegistryClient::new();
 
@@ -223,7 +459,22 @@ async fn cmd_generate(
     let recipes = filter_enabled(filter_by_arch(all_recipes, &arch));
     info!("After filtering: {} recipes for {}", recipes.len(), arch);
 
+    // Streamed entries go to a temporary file, moved over the output when done
+    let partial_path = output_path.with_extension(format!("{}.part", extension));
+    let mut stream = match format {
+        OutputFormat::Json => None,
+        OutputFormat::Ndjson => {
+            if let Some(parent) = partial_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            Some(BufWriter::new(File::create(&partial_path)?))
+        }
+    };
+    let mut streamed = 0;
+
     let mut metadata: Vec<PackageMetadata> = Vec::new();
+    // Registry errors this run: the only entries kept from the previous index
+    let mut errored: Vec<((String, String), String)> = Vec::new();
 
     for (path, recipe) in recipes {
         let ghcr_packages = recipe.ghcr_packages_from_path(&path, &ghcr_owner);
@@ -261,17 +512,29 @@ async fn cmd_generate(
                 recipe_path_str
             ));
 
+            let mut fetch_error = None;
             match client.list_tags(&ghcr_info.ghcr_path).await {
                 Ok(tag_list) => {
                     if let Some(tag) = RegistryClient::get_latest_arch_tag(&tag_list.tags, &arch) {
-                        match client.fetch_manifest(&ghcr_info.ghcr_path, tag).await {
-                            Ok(manifest_str) => {
-                                if let Ok(manifest) = OciManifest::from_json(&manifest_str) {
-                                    pkg_metadata.enrich_from_manifest(
+                        match client
+                            .fetch_image_manifest(&ghcr_info.ghcr_path, tag, &arch)
+                            .await
+                        {
+                            Ok(manifest) => {
+                                pkg_metadata.enrich_from_manifest_with(
+                                    &manifest,
+                                    &ghcr_info.ghcr_path,
+                                    &arch,
+                                    policy,
+                                );
+                                if compute_checksums {
+                                    fill_checksums(
+                                        &client,
+                                        &mut pkg_metadata,
                                         &manifest,
                                         &ghcr_info.ghcr_path,
-                                        &arch,
-                                    );
+                                    )
+                                    .await;
                                 }
                             }
                             Err(e) => {
@@ -279,6 +542,7 @@ async fn cmd_generate(
                                     "Failed to fetch manifest for {}: {}",
                                     ghcr_info.ghcr_path, e
                                 );
+                                fetch_error = Some(e.to_string());
                             }
                         }
                     } else {
@@ -287,10 +551,12 @@ async fn cmd_generate(
                 }
                 Err(e) => {
                     warn!("Failed to list tags for {}: {}", ghcr_info.ghcr_path, e);
+                    fetch_error = Some(e.to_string());
                 }
             }
 
             pkg_metadata.parse_note_flags();
+            pkg_metadata.infer_flags();
 
             // Fetch snapshots from MongoDB cache if available
             if let Some(ref db) = &mongo_db {
@@ -307,7 +573,16 @@ async fn cmd_generate(
             }
 
             if pkg_metadata.is_valid() {
-                metadata.push(pkg_metadata);
+                if let Some(ref mut writer) = stream {
+                    pkg_metadata.rank = external_ranks.get(&pkg_metadata.pkg_id).copied();
+                    serde_json::to_writer(&mut *writer, &pkg_metadata)?;
+                    writeln!(writer)?;
+                    streamed += 1;
+                } else {
+                    metadata.push(pkg_metadata);
+                }
+            } else if let Some(error) = fetch_error {
+                errored.push((pkg_metadata.index_key(), error));
             } else {
                 debug!(
                     "Skipping {}: not in GHCR or invalid metadata",
@@ -317,20 +592,69 @@ async fn cmd_generate(
         }
     }
 
+    if let Some(mut writer) = stream {
+        writer.flush()?;
+        drop(writer);
+        if streamed == 0 {
+            std::fs::remove_file(&partial_path)?;
+            info!("No packages to write");
+        } else {
+            std::fs::rename(&partial_path, &output_path)?;
+            info!(
+                "Generated metadata for {} packages -> {:?}",
+                streamed, output_path
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(mode) = verify_urls {
+        metadata = verify_download_urls(&client, metadata, mode, parallel).await;
+    }
+
+    let stale_path = output_path.with_extension("stale.json");
+    if merge {
+        let previous_keys: HashSet<_> = previous.iter().map(|p| p.index_key()).collect();
+        let stale: Vec<_> = errored
+            .iter()
+            .filter(|(key, _)| previous_keys.contains(key))
+            .map(|((pkg_id, pkg), error)| {
+                serde_json::json!({ "pkg_id": pkg_id, "pkg": pkg, "error": error })
+            })
+            .collect();
+        let errored_keys: HashSet<_> = errored.iter().map(|(key, _)| key.clone()).collect();
+        metadata = merge_index(previous, metadata, &errored_keys);
+
+        if stale.is_empty() {
+            if stale_path.exists() {
+                std::fs::remove_file(&stale_path)?;
+            }
+        } else {
+            warn!(
+                "Kept {} stale entries from the previous index -> {:?}",
+                stale.len(),
+                stale_path
+            );
+            if let Some(parent) = stale_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&stale_path, super::to_json(&stale, compact_json)?)?;
+        }
+    }
+
     if metadata.is_empty() {
         info!("No packages to write");
         return Ok(());
     }
 
     metadata.sort_by(|a, b| a.pkg.cmp(&b.pkg));
-
-    let output_path = output.unwrap_or_else(|| PathBuf::from(format!("{}.json", arch)));
+    assign_ranks(&mut metadata, &external_ranks);
 
     if let Some(parent) = output_path.parent() {
         std::fs::create_dir_all(parent)?;
     }
 
-    let json = serde_json::to_string_pretty(&metadata)?;
+    let json = super::to_json(&metadata, compact_json)?;
     std::fs::write(&output_path, json)?;
 
     info!(
@@ -342,6 +666,85 @@ async fn cmd_generate(
     Ok(())
 }
 
+/// Check every package's download URL, dropping or flagging the broken ones
+async fn verify_download_urls(
+    client: &RegistryClient,
+    metadata: Vec<PackageMetadata>,
+    mode: UrlCheck,
+    parallel: usize,
+) -> Vec<PackageMetadata> {
+    info!("Verifying {} download URLs", metadata.len());
+
+    let checked: Vec<_> = futures::stream::iter(metadata)
+        .map(|pkg| async move {
+            let result = client.check_url(&pkg.resolved_download_url()).await;
+            (pkg, result)
+        })
+        .buffer_unordered(parallel.max(1))
+        .collect()
+        .await;
+
+    let mut broken = 0;
+    let ok = checked.iter().filter(|(_, r)| r.is_ok()).count();
+    let mut verified = Vec::with_capacity(checked.len());
+    for (mut pkg, result) in checked {
+        match result {
+            Ok(()) => verified.push(pkg),
+            Err(e) => {
+                broken += 1;
+                warn!("Broken download URL for {}: {}", pkg.pkg_id, e);
+                if mode == UrlCheck::Flag {
+                    pkg.download_url_broken = Some(true);
+                    verified.push(pkg);
+                }
+            }
+        }
+    }
+
+    let action = match mode {
+        UrlCheck::Drop => "dropped",
+        UrlCheck::Flag => "flagged",
+    };
+    info!(
+        "URL verification: {} ok, {} broken ({})",
+        ok, broken, action
+    );
+    verified
+}
+
+/// Download a single-file package's blob and compute the checksums its
+/// manifest didn't carry. Failures only warn; the package is still emitted.
+async fn fill_checksums(
+    client: &RegistryClient,
+    pkg_metadata: &mut PackageMetadata,
+    manifest: &OciManifest,
+    ghcr_path: &str,
+) {
+    let Some(digest) = pkg_metadata.checksum_blob_digest(manifest) else {
+        return;
+    };
+
+    let data = match client.fetch_blob(ghcr_path, &digest).await {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to download {} for checksums: {}", ghcr_path, e);
+            return;
+        }
+    };
+
+    let sha256 = format!("sha256:{:x}", Sha256::digest(&data));
+    if sha256 != digest {
+        warn!(
+            "Blob for {} doesn't match its digest ({} != {}), skipping checksums",
+            ghcr_path, sha256, digest
+        );
+        return;
+    }
+
+    pkg_metadata.apply_blob_checksums(&data);
+    debug!("Computed checksums for {} from {}", ghcr_path, digest);
+}
+
 async fn cmd_should_rebuild(
     recipe_path: PathBuf,
     _cache: Option<PathBuf>,
@@ -374,6 +777,7 @@ async fn cmd_check_updates(
     output: PathBuf,
     _parallel: usize,
     timeout: u64,
+    compact_json: bool,
 ) -> Result<()> {
     info!("Checking for upstream updates (timeout: {}s)", timeout);
 
@@ -397,6 +801,7 @@ async fn cmd_check_updates(
     }
 
     let mut updates: Vec<UpdateInfo> = Vec::new();
+    let upstream = UpstreamClient::new(std::time::Duration::from_secs(timeout))?;
 
     for (path, recipe) in enabled_recipes {
         let current_pkgver = match &recipe.pkgver {
@@ -409,17 +814,26 @@ async fn cmd_check_updates(
             None => current_pkgver.clone(),
         };
 
-        let pkgver_script = match recipe.pkgver_script() {
-            Some(s) => s,
-            None => continue,
-        };
+        let source = recipe.update_source();
+        if source == UpdateSource::Script && recipe.pkgver_script().is_none() {
+            continue;
+        }
 
         info!(
             "Checking {} (current: {})",
             recipe.pkg, current_remote_version
         );
 
-        match execute_pkgver(pkgver_script, timeout).await {
+        let checked = match (&source, recipe.pkgver_script()) {
+            (UpdateSource::Script, Some(script)) => {
+                execute_pkgver(script, recipe.shell(), timeout).await
+            }
+            _ => upstream
+                .latest_version(&source)
+                .await
+                .map(|version| (version, None)),
+        };
+        match checked {
             Ok((upstream_version, upstream_remote_version)) => {
                 let upstream_version = upstream_version.trim().to_string();
                 let upstream_for_comparison = upstream_remote_version
@@ -448,49 +862,145 @@ async fn cmd_check_updates(
         }
     }
 
-    let json = serde_json::to_string_pretty(&updates)?;
+    let json = super::to_json(&updates, compact_json)?;
     std::fs::write(&output, json)?;
 
     info!("Found {} updates -> {:?}", updates.len(), output);
     Ok(())
 }
 
-async fn execute_pkgver(script: &str, timeout_secs: u64) -> Result<(String, Option<String>)> {
+/// Most output kept from a pkgver script; a version is a line or two
+const PKGVER_OUTPUT_LIMIT: u64 = 64 * 1024;
+
+/// Run a pkgver script the way the linter does at build time: as a file with
+/// a `#!/usr/bin/env <shell>` line.
+async fn execute_pkgver(
+    script: &str,
+    shell: &str,
+    timeout_secs: u64,
+) -> Result<(String, Option<String>)> {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt, process::Stdio};
+    use tokio::io::AsyncReadExt;
     use tokio::process::Command;
     use tokio::time::{timeout, Duration};
 
-    let result = timeout(
-        Duration::from_secs(timeout_secs),
-        Command::new("bash").arg("-c").arg(script).output(),
-    )
-    .await;
+    let mut file = tempfile::Builder::new()
+        .prefix("sbuild-pkgver-")
+        .tempfile()?;
+    write!(file, "#!/usr/bin/env {}\n{}", shell, script)?;
+    file.as_file()
+        .set_permissions(Permissions::from_mode(0o755))?;
+    // Close our handle so the script isn't "text file busy"
+    let script_path = file.into_temp_path();
+
+    let run = async {
+        let mut child = Command::new(&script_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // Keep draining stderr past the cap so the script can't block on it
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr_task = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            let _ = (&mut stderr)
+                .take(PKGVER_OUTPUT_LIMIT)
+                .read_to_end(&mut buf)
+                .await;
+            let _ = tokio::io::copy(&mut stderr, &mut tokio::io::sink()).await;
+            buf
+        });
+
+        let mut stdout = Vec::new();
+        child
+            .stdout
+            .take()
+            .expect("stdout is piped")
+            .take(PKGVER_OUTPUT_LIMIT + 1)
+            .read_to_end(&mut stdout)
+            .await?;
+        if stdout.len() as u64 > PKGVER_OUTPUT_LIMIT {
+            return Err(Error::PkgverFailed(format!(
+                "output exceeds {} bytes",
+                PKGVER_OUTPUT_LIMIT
+            )));
+        }
 
-    match result {
-        Ok(Ok(output)) => {
-            if output.status.success() {
-                let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                let lines: Vec<&str> = stdout.lines().collect();
+        let status = child.wait().await?;
+        let stderr = stderr_task.await.unwrap_or_default();
+        Ok((status, stdout, stderr))
+    };
 
-                if lines.is_empty() {
-                    Ok((String::new(), None))
-                } else {
-                    let pkgver = lines[0].trim().to_string();
-                    let remote_pkgver = if lines.len() >= 2 {
-                        Some(lines[1].trim().to_string())
-                    } else {
-                        None
-                    };
-                    Ok((pkgver, remote_pkgver))
-                }
-            } else {
-                Err(Error::PkgverFailed(
-                    String::from_utf8_lossy(&output.stderr).to_string(),
-                ))
+    let (status, stdout, stderr) = match timeout(Duration::from_secs(timeout_secs), run).await {
+        Ok(result) => result?,
+        Err(_) => return Err(Error::PkgverFailed("Timeout".to_string())),
+    };
+    if !status.success() {
+        return Err(Error::PkgverFailed(
+            String::from_utf8_lossy(&stderr).to_string(),
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&stdout);
+    let mut lines = stdout.lines().map(|line| line.trim().to_string());
+    let pkgver = lines.next().unwrap_or_default();
+    Ok((pkgver, lines.next()))
+}
+
+fn cmd_combine(inputs: Vec<PathBuf>, output: PathBuf, compact_json: bool) -> Result<()> {
+    let mut arches = Vec::new();
+    for path in &inputs {
+        let arch = path
+            .file_stem()
+            .map(|s| normalize_host_triplet(&s.to_string_lossy()))
+            .ok_or_else(|| Error::Other(format!("Can't tell the arch of {:?}", path)))?;
+        if arches.iter().any(|(a, _)| *a == arch) {
+            return Err(Error::Other(format!("{} given more than once", arch)));
+        }
+        let content = std::fs::read_to_string(path)?;
+        let packages: Vec<PackageMetadata> = serde_json::from_str(&content)?;
+        info!(
+            "Loaded {} packages for {} from {:?}",
+            packages.len(),
+            arch,
+            path
+        );
+        arches.push((arch, packages));
+    }
+
+    let combined = combine_arches(arches);
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&output, super::to_json(&combined, compact_json)?)?;
+
+    info!(
+        "Combined {} packages from {} arches -> {:?}",
+        combined.len(),
+        inputs.len(),
+        output
+    );
+    Ok(())
+}
+
+fn cmd_which(ghcr: String, recipe_dirs: Vec<PathBuf>) -> Result<()> {
+    let mut found = 0;
+    for dir in &recipe_dirs {
+        for (path, recipe) in scan_recipes(dir)? {
+            if recipe.produces_ghcr_path(&path, &ghcr) {
+                println!("{}", path.display());
+                found += 1;
             }
         }
-        Ok(Err(e)) => Err(Error::PkgverFailed(e.to_string())),
-        Err(_) => Err(Error::PkgverFailed("Timeout".to_string())),
     }
+
+    if found == 0 {
+        return Err(Error::Recipe(format!("No recipe produces {}", ghcr)));
+    }
+    Ok(())
 }
 
 async fn cmd_inspect(
@@ -498,8 +1008,9 @@ async fn cmd_inspect(
     arch: String,
     ghcr_owner: String,
     live: bool,
+    compact_json: bool,
 ) -> Result<()> {
-    let arch = arch.to_lowercase();
+    let arch = normalize_host_triplet(&arch);
     let recipe = SBuildRecipe::from_file(&recipe_path)?;
     let ghcr_packages = recipe.ghcr_packages_from_path(&recipe_path, &ghcr_owner);
 
@@ -543,15 +1054,16 @@ async fn cmd_inspect(
             match client.list_tags(&ghcr_info.ghcr_path).await {
                 Ok(tag_list) => {
                     if let Some(tag) = RegistryClient::get_latest_arch_tag(&tag_list.tags, &arch) {
-                        match client.fetch_manifest(&ghcr_info.ghcr_path, tag).await {
-                            Ok(manifest_str) => {
-                                if let Ok(manifest) = OciManifest::from_json(&manifest_str) {
-                                    pkg_metadata.enrich_from_manifest(
-                                        &manifest,
-                                        &ghcr_info.ghcr_path,
-                                        &arch,
-                                    );
-                                }
+                        match client
+                            .fetch_image_manifest(&ghcr_info.ghcr_path, tag, &arch)
+                            .await
+                        {
+                            Ok(manifest) => {
+                                pkg_metadata.enrich_from_manifest(
+                                    &manifest,
+                                    &ghcr_info.ghcr_path,
+                                    &arch,
+                                );
                             }
                             Err(e) => {
                                 warn!(
@@ -572,21 +1084,14 @@ async fn cmd_inspect(
         all_metadata.push(pkg_metadata);
     }
 
-    let json = serde_json::to_string_pretty(&all_metadata)?;
+    let json = super::to_json(&all_metadata, compact_json)?;
     println!("{}", json);
     Ok(())
 }
 
-fn cmd_hash(recipe_path: PathBuf, exclude_version: bool) -> Result<()> {
+fn cmd_hash(recipe_path: PathBuf, options: HashOptions) -> Result<()> {
     let content = std::fs::read_to_string(&recipe_path)?;
-
-    let hash = if exclude_version {
-        compute_recipe_hash_excluding_version(&content)
-    } else {
-        compute_recipe_hash(&content)
-    };
-
-    println!("{}", hash);
+    println!("{}", compute_recipe_hash_with(&content, options));
     Ok(())
 }
 
@@ -595,8 +1100,11 @@ async fn cmd_fetch_manifest(
     tag: Option<String>,
     arch: String,
     _github_token: Option<String>,
+    compare_json: Option<PathBuf>,
+    json: bool,
+    compact_json: bool,
 ) -> Result<()> {
-    let arch = arch.to_lowercase();
+    let arch = normalize_host_triplet(&arch);
     let client = RegistryClient::new();
 
     let tag = match tag {
@@ -612,8 +1120,56 @@ async fn cmd_fetch_manifest(
     };
 
     info!("Fetching manifest for {}:{}", repository, tag);
-    let manifest_str = client.fetch_manifest(&repository, &tag).await?;
-    let manifest = OciManifest::from_json(&manifest_str)?;
+    let manifest = client
+        .fetch_image_manifest(&repository, &tag, &arch)
+        .await?;
+
+    let local_json = match compare_json {
+        Some(ref local_path) => {
+            let content = std::fs::read_to_string(local_path)?;
+            let local: serde_json::Value = serde_json::from_str(&content)?;
+            let remote = manifest.get_package_json()?.ok_or_else(|| {
+                Error::Other("Manifest has no embedded dev.pkgforge.soar.json".to_string())
+            })?;
+            Some((remote, local))
+        }
+        None => None,
+    };
+
+    if json {
+        let layers: Vec<_> = manifest
+            .layers_by_size()
+            .into_iter()
+            .map(|l| {
+                serde_json::json!({
+                    "filename": l.filename(),
+                    "digest": l.digest,
+                    "size": l.size,
+                    "size_human": format_size(l.size),
+                })
+            })
+            .collect();
+        let mut output = serde_json::json!({
+            "repository": repository,
+            "tag": tag,
+            "schema_version": manifest.schema_version,
+            "total_size": manifest.total_size(),
+            "total_size_human": manifest.total_size_human(),
+            "layers": layers,
+            "ghcr_pkg": manifest.ghcr_pkg(),
+            "build_id": manifest.build_id(),
+        });
+        match local_json {
+            Some((ref remote, ref local)) => {
+                output["diff"] = serde_json::to_value(diff_package_json(remote, local))?;
+            }
+            None => {
+                output["package_json"] = manifest.get_package_json().ok().flatten().into();
+            }
+        }
+        println!("{}", super::to_json(&output, compact_json)?);
+        return Ok(());
+    }
 
     println!("Repository: {}", repository);
     println!("Tag: {}", tag);
@@ -629,10 +1185,103 @@ async fn cmd_fetch_manifest(
         println!("Build ID: {}", build_id);
     }
 
+    print_layer_sizes(&manifest);
+
+    if let (Some((remote, local)), Some(local_path)) = (local_json, compare_json) {
+        print_package_json_diff(&remote, &local, &local_path);
+        return Ok(());
+    }
+
     if let Ok(Some(pkg_json)) = manifest.get_package_json() {
         println!("\nEmbedded Package JSON:");
-        println!("{}", serde_json::to_string_pretty(&pkg_json)?);
+        println!("{}", super::to_json(&pkg_json, compact_json)?);
     }
 
     Ok(())
 }
+
+fn print_layer_sizes(manifest: &OciManifest) {
+    let layers = manifest.layers_by_size();
+    if layers.is_empty() {
+        return;
+    }
+
+    let width = layers
+        .iter()
+        .map(|l| l.filename().unwrap_or(&l.digest).len())
+        .max()
+        .unwrap_or(0)
+        .max("File".len());
+
+    println!("\n{:<width$}  {:>10}", "File", "Size", width = width);
+    println!("{}", "-".repeat(width + 12));
+    for layer in layers {
+        println!(
+            "{:<width$}  {:>10}",
+            layer.filename().unwrap_or(&layer.digest),
+            format_size(layer.size),
+            width = width
+        );
+    }
+}
+
+fn print_package_json_diff(
+    remote: &serde_json::Value,
+    local: &serde_json::Value,
+    local_path: &std::path::Path,
+) {
+    let diffs = diff_package_json(remote, local);
+    println!(
+        "\nComparing remote package JSON with {}:",
+        local_path.display()
+    );
+
+    if diffs.is_empty() {
+        println!("  No differences");
+        return;
+    }
+
+    let show = |v: &Option<serde_json::Value>| match v {
+        Some(v) => v.to_string(),
+        None => "(missing)".to_string(),
+    };
+    for diff in &diffs {
+        println!("  {}:", diff.field);
+        println!("    - remote: {}", show(&diff.remote));
+        println!("    + local:  {}", show(&diff.local));
+    }
+    println!("\n{} field(s) differ", diffs.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_execute_pkgver_with_sh() {
+        let (pkgver, remote) = execute_pkgver("echo ' 1.2.3 '\necho 1.2.4", "sh", 10)
+            .await
+            .unwrap();
+        assert_eq!(pkgver, "1.2.3");
+        assert_eq!(remote.as_deref(), Some("1.2.4"));
+
+        match execute_pkgver("echo broken >&2\nexit 1", "sh", 10).await {
+            Err(Error::PkgverFailed(msg)) => assert_eq!(msg.trim(), "broken"),
+            other => panic!("expected a pkgver failure, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_pkgver_caps_output() {
+        let script = format!("yes 1.0 | head -c {}", PKGVER_OUTPUT_LIMIT + 1);
+        match execute_pkgver(&script, "sh", 10).await {
+            Err(Error::PkgverFailed(msg)) => assert!(msg.contains("exceeds"), "{}", msg),
+            other => panic!("expected the output cap to trip, got {:?}", other),
+        }
+
+        // Right at the cap is fine
+        let script = format!("yes 1.0 | head -c {}", PKGVER_OUTPUT_LIMIT);
+        let (pkgver, _) = execute_pkgver(&script, "sh", 10).await.unwrap();
+        assert_eq!(pkgver, "1.0");
+    }
+}
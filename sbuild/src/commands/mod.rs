@@ -1,5 +1,28 @@
 pub mod build;
 pub mod cache;
+pub mod checksum;
+pub mod doctor;
 pub mod info;
 pub mod lint;
 pub mod meta;
+pub mod versions;
+
+use serde::Serialize;
+
+/// Serialize JSON output, pretty-printed unless `compact` (`--compact-json`)
+pub fn to_json<T: Serialize + ?Sized>(value: &T, compact: bool) -> serde_json::Result<String> {
+    if compact {
+        serde_json::to_string(value)
+    } else {
+        serde_json::to_string_pretty(value)
+    }
+}
+
+/// env_logger write style matching the `colored` override set by `--no-color`
+pub fn log_write_style() -> env_logger::WriteStyle {
+    if colored::control::SHOULD_COLORIZE.should_colorize() {
+        env_logger::WriteStyle::Auto
+    } else {
+        env_logger::WriteStyle::Never
+    }
+}
@@ -0,0 +1,39 @@
+use clap::Parser;
+
+use super::doctor::{tool_version, TOOLS};
+
+#[derive(Parser)]
+#[command(about = "Print the versions of the sbuild crates and the external tools they use")]
+pub struct VersionsArgs {
+    /// One `name version` per line instead of a single line
+    #[arg(long)]
+    multiline: bool,
+}
+
+pub fn run(args: VersionsArgs) -> Result<(), String> {
+    let mut versions = vec![
+        ("sbuild", env!("CARGO_PKG_VERSION").to_string()),
+        ("sbuild-linter", sbuild_linter::VERSION.to_string()),
+        ("sbuild-meta", sbuild_meta::VERSION.to_string()),
+        ("sbuild-cache", sbuild_cache::VERSION.to_string()),
+    ];
+    for (name, _) in TOOLS {
+        let version = if which::which(name).is_ok() {
+            tool_version(name).unwrap_or_else(|| "unknown".to_string())
+        } else {
+            "missing".to_string()
+        };
+        versions.push((name, version));
+    }
+
+    let entries: Vec<String> = versions
+        .iter()
+        .map(|(name, version)| format!("{} {}", name, version))
+        .collect();
+    if args.multiline {
+        println!("{}", entries.join("\n"));
+    } else {
+        println!("{}", entries.join(", "));
+    }
+    Ok(())
+}
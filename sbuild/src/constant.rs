@@ -1,6 +1,7 @@
 pub const ELF_MAGIC_BYTES: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46];
 pub const APPIMAGE_MAGIC_BYTES: [u8; 4] = [0x41, 0x49, 0x02, 0x00];
 pub const FLATIMAGE_MAGIC_BYTES: [u8; 4] = [0x46, 0x49, 0x01, 0x00];
+pub const SQUASHFS_MAGIC_BYTES: [u8; 4] = [0x68, 0x73, 0x71, 0x73];
 
 // onelf packs a directory into a self-extracting ELF whose last 76 bytes are a
 // fixed footer. The footer starts with "ONELF\0\x01\x00"; the file itself ends
@@ -9,9 +10,20 @@ pub const FLATIMAGE_MAGIC_BYTES: [u8; 4] = [0x46, 0x49, 0x01, 0x00];
 pub const ONELF_MAGIC_BYTES: [u8; 8] = [0x4f, 0x4e, 0x45, 0x4c, 0x46, 0x00, 0x01, 0x00];
 pub const ONELF_FOOTER_SIZE: u64 = 76;
 
+// Archives: gzip, xz and zstd streams start with their magic; a tar
+// archive has "ustar" at TAR_MAGIC_OFFSET in its first header.
+pub const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+pub const XZ_MAGIC_BYTES: [u8; 6] = [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+pub const ZSTD_MAGIC_BYTES: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+pub const TAR_MAGIC_BYTES: [u8; 5] = [0x75, 0x73, 0x74, 0x61, 0x72];
+pub const TAR_MAGIC_OFFSET: usize = 257;
+
 pub const PNG_MAGIC_BYTES: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
 pub const SVG_MAGIC_BYTES: [u8; 4] = [0x3c, 0x73, 0x76, 0x67];
 pub const XML_MAGIC_BYTES: [u8; 5] = [0x3c, 0x3f, 0x78, 0x6d, 0x6c];
 
+/// User agent for outgoing requests when `$USER_AGENT` isn't set
+pub const DEFAULT_USER_AGENT: &str = "pkgforge/soar";
+
 pub const MIN_ICON_SIZE: u64 = 20;
 pub const MIN_DESKTOP_SIZE: u64 = 8;
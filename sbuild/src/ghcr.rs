@@ -5,7 +5,13 @@
 use std::collections::HashMap;
 use std::path::Path;
 use std::process::Command;
+use std::time::Duration;
 
+use sbuild_cache::normalize_host_triplet;
+use sbuild_meta::{
+    manifest::{PACKAGE_JSON_ANNOTATION, PACKAGE_JSON_SCHEMA, PACKAGE_JSON_SCHEMA_KEY},
+    sanitize_oci_name,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -44,12 +50,61 @@ pub struct PackageAnnotations {
     pub shasum: Option<String>,
     /// BLAKE3 checksum of the CHECKSUM file
     pub checksum_bsum: Option<String>,
+    /// Binaries provided by the package
+    pub provides: Vec<String>,
+    /// File name of the pushed icon
+    pub icon: Option<String>,
+    /// File name of the pushed desktop entry
+    pub desktop: Option<String>,
+    /// Download URL of the pushed BUILD.log
+    pub build_log: Option<String>,
+    /// File name of the pushed `<pkg>.files.json` artifact manifest
+    pub files_manifest: Option<String>,
+}
+
+impl PackageAnnotations {
+    /// Metadata embedded as the `dev.pkgforge.soar.json` annotation, in the
+    /// shape `sbuild-meta` merges into generated package metadata.
+    pub fn to_package_json(&self) -> serde_json::Value {
+        let mut json = serde_json::Map::new();
+        json.insert(
+            PACKAGE_JSON_SCHEMA_KEY.to_string(),
+            PACKAGE_JSON_SCHEMA.into(),
+        );
+        let mut put = |key: &str, value: Option<&String>| {
+            if let Some(value) = value {
+                json.insert(key.to_string(), value.clone().into());
+            }
+        };
+
+        put("pkg", Some(&self.pkg));
+        put("pkg_id", Some(&self.pkg_id));
+        put("pkg_type", self.pkg_type.as_ref());
+        put("version", Some(&self.version));
+        put("description", self.description.as_ref());
+        put("build_date", Some(&self.build_date));
+        put("build_script", self.build_script.as_ref());
+        put("bsum", self.bsum.as_ref());
+        put("shasum", self.shasum.as_ref());
+        put("checksum_bsum", self.checksum_bsum.as_ref());
+        put("icon", self.icon.as_ref());
+        put("desktop", self.desktop.as_ref());
+        put("build_log", self.build_log.as_ref());
+        put("files_manifest", self.files_manifest.as_ref());
+
+        if !self.provides.is_empty() {
+            json.insert("provides".to_string(), self.provides.clone().into());
+        }
+
+        serde_json::Value::Object(json)
+    }
 }
 
 /// GHCR client for pushing packages
 pub struct GhcrClient {
     token: String,
     registry: String,
+    retries: u32,
 }
 
 impl GhcrClient {
@@ -57,9 +112,16 @@ impl GhcrClient {
         Self {
             token,
             registry: "ghcr.io".to_string(),
+            retries: 0,
         }
     }
 
+    /// Retry transient push failures up to `retries` times with exponential backoff
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
     /// Check if oras is available
     pub fn check_oras() -> Result<(), GhcrError> {
         if which::which("oras").is_err() {
@@ -82,15 +144,23 @@ impl GhcrClient {
         Ok(())
     }
 
-    /// Push a package to GHCR
-    pub fn push<P: AsRef<Path>>(
+    /// Push a package to GHCR under one or more tags.
+    ///
+    /// All tags point at the same manifest, so extra tags (e.g. `latest-<arch>`)
+    /// cost no additional upload. Returns the reference of the first tag.
+    pub async fn push<P: AsRef<Path>>(
         &self,
         files: &[P],
         repository: &str,
-        tag: &str,
+        tags: &[String],
         annotations: &PackageAnnotations,
     ) -> Result<String, GhcrError> {
-        let target = format!("{}/{}:{}", self.registry, repository, tag);
+        let Some(primary_tag) = tags.first() else {
+            return Err(GhcrError::PushFailed("no tags to push".to_string()));
+        };
+        let target = format!("{}/{}:{}", self.registry, repository, primary_tag);
+        // oras tags the pushed manifest with every comma-separated tag.
+        let push_ref = format!("{}/{}:{}", self.registry, repository, tags.join(","));
 
         let mut cmd = Command::new("oras");
         cmd.arg("push")
@@ -105,7 +175,7 @@ impl GhcrClient {
         }
 
         // Add target
-        cmd.arg(&target);
+        cmd.arg(&push_ref);
 
         // oras stores each pushed file under its basename relative to the
         // working directory. Our file set can span multiple directories (the
@@ -140,14 +210,33 @@ impl GhcrClient {
         }
         cmd.current_dir(staging.path());
 
-        let output = cmd.output()?;
+        let mut attempt = 0;
+        loop {
+            let output = cmd.output()?;
+            if output.status.success() {
+                return Ok(target);
+            }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(GhcrError::PushFailed(stderr.to_string()));
-        }
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            if is_auth_failure(&stderr) {
+                return Err(GhcrError::AuthFailed(stderr));
+            }
+            if attempt >= self.retries || !is_transient_failure(&stderr) {
+                return Err(GhcrError::PushFailed(stderr));
+            }
 
-        Ok(target)
+            attempt += 1;
+            let backoff = Duration::from_secs(2u64.pow(attempt.min(6)));
+            log::warn!(
+                "Push to {} failed (attempt {}/{}), retrying in {}s: {}",
+                target,
+                attempt,
+                self.retries + 1,
+                backoff.as_secs(),
+                stderr.trim()
+            );
+            tokio::time::sleep(backoff).await;
+        }
     }
 
     /// Build OCI annotations from package metadata
@@ -208,6 +297,15 @@ impl GhcrClient {
         if let Some(ref build_gha) = meta.build_gha {
             annotations.insert("dev.pkgforge.soar.build_gha".to_string(), build_gha.clone());
         }
+        if let Some(ref build_log) = meta.build_log {
+            annotations.insert("dev.pkgforge.soar.build_log".to_string(), build_log.clone());
+        }
+        if let Some(ref files_manifest) = meta.files_manifest {
+            annotations.insert(
+                "dev.pkgforge.soar.files_manifest".to_string(),
+                files_manifest.clone(),
+            );
+        }
         if let Some(ref build_script) = meta.build_script {
             annotations.insert(
                 "dev.pkgforge.soar.build_script".to_string(),
@@ -226,11 +324,80 @@ impl GhcrClient {
                 checksum_bsum.clone(),
             );
         }
+        annotations.insert(
+            PACKAGE_JSON_ANNOTATION.to_string(),
+            meta.to_package_json().to_string(),
+        );
 
         annotations
     }
 }
 
+/// Lines oras reports the failure on (`Error: ...`), or the last line of
+/// output when none is marked
+fn error_lines(stderr: &str) -> Vec<&str> {
+    let lines: Vec<&str> = stderr
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let errors: Vec<&str> = lines
+        .iter()
+        .copied()
+        .filter(|l| l.starts_with("Error"))
+        .collect();
+    if errors.is_empty() {
+        lines.last().copied().into_iter().collect()
+    } else {
+        errors
+    }
+}
+
+/// HTTP status in an oras error line, e.g. `response status code 503: ...`
+fn http_status(line: &str) -> Option<u16> {
+    line.match_indices("status").find_map(|(i, word)| {
+        let rest = line[i + word.len()..].trim_start();
+        let rest = rest.strip_prefix("code").unwrap_or(rest).trim_start();
+        let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        if digits.len() == 3 {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether oras rejected the credentials (HTTP 401/403, or the registry's
+/// `UNAUTHORIZED`/`DENIED` error codes); retrying won't help.
+fn is_auth_failure(stderr: &str) -> bool {
+    error_lines(stderr).into_iter().any(|line| {
+        let lower = line.to_lowercase();
+        matches!(http_status(line), Some(401 | 403))
+            || lower.contains("unauthorized:")
+            || lower.contains("denied:")
+    })
+}
+
+/// Whether oras failed on a network or registry hiccup worth retrying: a
+/// throttled or 5xx response, or a dropped connection.
+fn is_transient_failure(stderr: &str) -> bool {
+    const NETWORK_ERRORS: &[&str] = &[
+        "i/o timeout",
+        "tls handshake timeout",
+        "context deadline exceeded",
+        "connection reset by peer",
+        "connection refused",
+        "broken pipe",
+        "unexpected eof",
+        "temporary failure in name resolution",
+    ];
+    error_lines(stderr).into_iter().any(|line| {
+        let lower = line.to_lowercase();
+        matches!(http_status(line), Some(408 | 429 | 500 | 502 | 503 | 504))
+            || NETWORK_ERRORS.iter().any(|e| lower.contains(e))
+    })
+}
+
 /// Generate GHCR repository path from recipe info
 pub fn ghcr_path(pkg_family: &str, recipe_name: &str, pkg_name: &str) -> String {
     format!("pkgforge/{}/{}/{}", pkg_family, recipe_name, pkg_name)
@@ -255,8 +422,144 @@ pub fn sanitize_oci_tag(s: &str) -> String {
         .join("-")
 }
 
+/// Full repository path a package is pushed to under `base_repo`
+/// (`owner/repo`). A recipe's `ghcr_pkg` replaces the family/recipe part.
+pub fn ghcr_repo_path(
+    base_repo: &str,
+    pkg_family: &str,
+    recipe_name: &str,
+    pkg_name: &str,
+    custom_base: Option<&str>,
+) -> String {
+    let pkg_name = sanitize_oci_name(pkg_name);
+    match custom_base {
+        Some(custom_base) => {
+            let owner = base_repo.split('/').next().unwrap_or(base_repo);
+            format!("{}/{}/{}", owner, custom_base, pkg_name)
+        }
+        None => format!("{}/{}/{}/{}", base_repo, pkg_family, recipe_name, pkg_name),
+    }
+}
+
 /// Generate GHCR tag from version and architecture
 pub fn ghcr_tag(version: &str, arch: &str) -> String {
     let sanitized_version = sanitize_oci_tag(version);
-    format!("{}-{}", sanitized_version, arch.to_lowercase())
+    format!("{}-{}", sanitized_version, normalize_host_triplet(arch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ghcr_repo_path() {
+        assert_eq!(
+            ghcr_repo_path("pkgforge/bincache", "htop", "static", "htop", None),
+            "pkgforge/bincache/htop/static/htop"
+        );
+        assert_eq!(
+            ghcr_repo_path(
+                "pkgforge/bincache",
+                "htop",
+                "static",
+                "htop",
+                Some("custom")
+            ),
+            "pkgforge/custom/htop"
+        );
+    }
+
+    #[test]
+    fn test_ghcr_tag_uses_canonical_host() {
+        assert_eq!(ghcr_tag("1.0", "x86_64-Linux"), "1.0-x86_64-linux");
+        assert_eq!(
+            ghcr_tag("1.0", "x86_64-linux"),
+            ghcr_tag("1.0", "X86_64-LINUX")
+        );
+    }
+
+    #[test]
+    fn test_auth_failures_are_not_transient() {
+        let stderr = "Uploading 1a2b3c foo\nError: failed to push: PUT \"https://ghcr.io/v2/o/r/manifests/1.0\": response status code 401: unauthorized: authentication required";
+        assert!(is_auth_failure(stderr));
+        assert!(!is_transient_failure(stderr));
+        assert!(is_auth_failure(
+            "Error response from registry: denied: permission_denied: write_package"
+        ));
+        assert!(!is_auth_failure("Error: dial tcp: i/o timeout"));
+    }
+
+    #[test]
+    fn test_transient_failures() {
+        assert!(is_transient_failure(
+            "Error: Post \"https://ghcr.io/v2/\": dial tcp 140.82.0.1:443: i/o timeout"
+        ));
+        assert!(is_transient_failure(
+            "Error: read tcp 10.0.0.1:1234->140.82.0.1:443: read: connection reset by peer"
+        ));
+        assert!(is_transient_failure(
+            "Error: failed to push: response status code 503: Service Unavailable"
+        ));
+        assert!(is_transient_failure(
+            "Error: response status code 429: toomanyrequests"
+        ));
+        assert!(!is_transient_failure("Error: invalid reference format"));
+    }
+
+    #[test]
+    fn test_failures_are_classified_by_the_error_line() {
+        // Numbers and words elsewhere in the output don't count
+        let stderr = "Uploading 5003a1 timeout.tar.gz\nUploaded  401b2c denied.txt\nError: invalid reference: missing repository";
+        assert!(!is_auth_failure(stderr));
+        assert!(!is_transient_failure(stderr));
+        assert_eq!(
+            http_status("response status code 502: Bad Gateway"),
+            Some(502)
+        );
+        assert_eq!(http_status("unexpected status 401 Unauthorized"), Some(401));
+        assert_eq!(http_status("status is fine"), None);
+    }
+
+    #[test]
+    fn test_package_json_annotation() {
+        let meta = PackageAnnotations {
+            pkg: "hello".to_string(),
+            pkg_id: "github.com.hello.hello".to_string(),
+            pkg_type: None,
+            version: "1.0.0".to_string(),
+            description: Some("Say hello".to_string()),
+            homepage: None,
+            license: None,
+            build_date: "2025-01-01T00:00:00Z".to_string(),
+            build_id: None,
+            build_gha: None,
+            build_script: None,
+            bsum: Some("abc".to_string()),
+            shasum: None,
+            checksum_bsum: None,
+            provides: vec!["hello".to_string(), "@hello-cli".to_string()],
+            icon: Some("hello.png".to_string()),
+            desktop: None,
+            build_log: Some("https://example.com/BUILD.log".to_string()),
+            files_manifest: Some("hello.files.json".to_string()),
+        };
+
+        let annotations = GhcrClient::new(String::new()).build_annotations(&meta);
+        let json: serde_json::Value =
+            serde_json::from_str(&annotations[PACKAGE_JSON_ANNOTATION]).unwrap();
+
+        assert_eq!(json["schema_version"], PACKAGE_JSON_SCHEMA);
+        assert_eq!(json["version"], "1.0.0");
+        assert_eq!(json["bsum"], "abc");
+        assert_eq!(json["icon"], "hello.png");
+        assert_eq!(json["build_log"], "https://example.com/BUILD.log");
+        assert_eq!(json["files_manifest"], "hello.files.json");
+        assert_eq!(
+            annotations["dev.pkgforge.soar.files_manifest"],
+            "hello.files.json"
+        );
+        assert_eq!(json["provides"][1], "@hello-cli");
+        assert!(json.get("desktop").is_none());
+        assert!(json.get("shasum").is_none());
+    }
 }
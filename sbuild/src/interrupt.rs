@@ -0,0 +1,81 @@
+//! Cleanup for builds interrupted by SIGINT/SIGTERM
+//!
+//! The builder records what it has touched (working directory, output
+//! directory, running build script) here so a signal handler can undo it
+//! before the process exits.
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::Command,
+    sync::{Mutex, MutexGuard},
+};
+
+#[derive(Default)]
+struct ActiveBuild {
+    pwd: Option<PathBuf>,
+    outdir: Option<PathBuf>,
+    /// `kill` target of the running build script (a pid, or `-pgid`)
+    child: Option<String>,
+    keep: bool,
+}
+
+static ACTIVE_BUILD: Mutex<Option<ActiveBuild>> = Mutex::new(None);
+
+fn active() -> MutexGuard<'static, Option<ActiveBuild>> {
+    ACTIVE_BUILD.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Start tracking a build that was started from `pwd`
+pub(crate) fn begin(pwd: PathBuf, keep: bool) {
+    *active() = Some(ActiveBuild {
+        pwd: Some(pwd),
+        keep,
+        ..Default::default()
+    });
+}
+
+pub(crate) fn set_outdir(outdir: PathBuf) {
+    if let Some(build) = active().as_mut() {
+        build.outdir = Some(outdir);
+    }
+}
+
+pub(crate) fn set_child(kill_target: Option<String>) {
+    if let Some(build) = active().as_mut() {
+        build.child = kill_target;
+    }
+}
+
+/// Stop tracking the current build; it finished on its own
+pub(crate) fn finish() {
+    *active() = None;
+}
+
+/// Kill the running build script, remove the partial output unless the build
+/// was started with `keep`, and restore the original working directory.
+///
+/// Safe to call when no build is running.
+pub fn cleanup() {
+    let Some(build) = active().take() else {
+        return;
+    };
+
+    if let Some(ref target) = build.child {
+        let _ = Command::new("kill").args(["-9", "--", target]).output();
+    }
+
+    if let Some(ref pwd) = build.pwd {
+        let _ = env::set_current_dir(pwd);
+    }
+
+    // The output of an interrupted build is incomplete, so drop it along
+    // with SBUILD_TEMP unless asked to keep things around for debugging.
+    if let Some(outdir) = build.outdir.filter(|_| !build.keep) {
+        if outdir.exists() {
+            if let Err(e) = fs::remove_dir_all(&outdir) {
+                log::warn!("Failed to remove {}: {}", outdir.display(), e);
+            }
+        }
+    }
+}
@@ -1,9 +1,13 @@
+pub mod asset_cache;
 pub mod builder;
 pub mod checksum;
 pub mod cleanup;
 pub mod constant;
 pub mod ghcr;
+pub mod interrupt;
+pub mod linkcheck;
 pub mod onelf;
+pub mod progress;
 pub mod signing;
 pub mod types;
 pub mod utils;
@@ -27,13 +31,14 @@ pub fn parse_ghcr_path(recipe_path: &str) -> Option<(String, String)> {
 
     let path_part = path_part?;
 
-    let parts: Vec<&str> = path_part.split('/').collect();
-    if parts.len() < 2 {
+    // Grouped layouts (`group/app/recipe.yaml`) nest deeper; the family is
+    // always the directory holding the recipe.
+    let parts: Vec<&str> = path_part.split('/').filter(|p| !p.is_empty()).collect();
+    let [.., family, filename] = parts[..] else {
         return None;
-    }
+    };
 
-    let pkg_family = parts[0].to_string();
-    let filename = parts[1];
+    let pkg_family = family.to_string();
 
     let recipe_name = filename
         .strip_suffix(".yaml")
@@ -44,6 +49,15 @@ pub fn parse_ghcr_path(recipe_path: &str) -> Option<(String, String)> {
     Some((pkg_family, recipe_name))
 }
 
+/// GHCR package family and recipe name a build pushes under: parsed from the
+/// recipe URL, or `pkg_id` for both when there's no URL (a local recipe) or
+/// it doesn't follow the `binaries/`/`packages/` layout.
+pub fn ghcr_family(recipe_url: Option<&str>, pkg_id: &str) -> (String, String) {
+    recipe_url
+        .and_then(parse_ghcr_path)
+        .unwrap_or_else(|| (pkg_id.to_string(), pkg_id.to_string()))
+}
+
 pub fn update_json_metadata(
     json_path: &Path,
     pkg_name: &str,
@@ -150,6 +164,35 @@ pub fn read_recipe_metadata(outdir: &Path) -> Option<SBuildRecipe> {
     SBuildRecipe::from_file(&sbuild_path).ok()
 }
 
+/// Raw URL for a `github:owner/repo[@ref]:path` recipe reference, e.g.
+/// `github:pkgforge/soarpkgs@main:binaries/bat/static.yaml`. Without a ref,
+/// the repository's default branch is used.
+pub fn github_recipe_url(input: &str) -> Option<String> {
+    let (repo, path) = input.strip_prefix("github:")?.split_once(':')?;
+    let (repo, git_ref) = repo.split_once('@').unwrap_or((repo, "HEAD"));
+    let path = path.trim_start_matches('/');
+
+    let (owner, name) = repo.split_once('/')?;
+    if [owner, name, git_ref, path].iter().any(|s| s.is_empty()) || name.contains('/') {
+        return None;
+    }
+    Some(format!(
+        "https://raw.githubusercontent.com/{}/{}/{}/{}",
+        owner, name, git_ref, path
+    ))
+}
+
+/// URL to fetch a recipe argument from: an `http(s)://` URL as-is, or a
+/// `github:` reference resolved by [`github_recipe_url`]. `None` for local
+/// paths.
+pub fn recipe_url(input: &str) -> Option<String> {
+    if input.starts_with("http://") || input.starts_with("https://") {
+        Some(input.to_string())
+    } else {
+        github_recipe_url(input)
+    }
+}
+
 /// Fetch a recipe from a URL.
 pub async fn fetch_recipe(url: &str) -> Result<String, String> {
     use std::time::Duration;
@@ -174,3 +217,95 @@ pub async fn fetch_recipe(url: &str) -> Result<String, String> {
         .await
         .map_err(|e| format!("Failed to read response: {}", e))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghcr_family_falls_back_to_pkg_id() {
+        assert_eq!(
+            ghcr_family(
+                Some("https://example.com/soarpkgs/binaries/htop/static.yaml"),
+                "github.com.htop-dev.htop"
+            ),
+            ("htop".to_string(), "static".to_string())
+        );
+        let fallback = (
+            "github.com.htop-dev.htop".to_string(),
+            "github.com.htop-dev.htop".to_string(),
+        );
+        assert_eq!(ghcr_family(None, "github.com.htop-dev.htop"), fallback);
+        assert_eq!(
+            ghcr_family(
+                Some("https://example.com/htop.yaml"),
+                "github.com.htop-dev.htop"
+            ),
+            fallback
+        );
+    }
+
+    #[test]
+    fn parse_ghcr_path_two_segments() {
+        assert_eq!(
+            parse_ghcr_path("binaries/htop/static.yaml"),
+            Some(("htop".to_string(), "static".to_string()))
+        );
+        assert_eq!(
+            parse_ghcr_path("https://example.com/repo/packages/htop/appimage.yml"),
+            Some(("htop".to_string(), "appimage".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_ghcr_path_nested_segments() {
+        assert_eq!(
+            parse_ghcr_path("packages/group/app/appimage.yaml"),
+            Some(("app".to_string(), "appimage".to_string()))
+        );
+        assert_eq!(
+            parse_ghcr_path("https://example.com/packages/a/b/app/static.yaml"),
+            Some(("app".to_string(), "static".to_string()))
+        );
+    }
+
+    #[test]
+    fn github_recipe_url_resolves_shorthand() {
+        let url = github_recipe_url("github:pkgforge/soarpkgs@main:binaries/bat/static.yaml");
+        assert_eq!(
+            url.as_deref(),
+            Some(
+                "https://raw.githubusercontent.com/pkgforge/soarpkgs/main/binaries/bat/static.yaml"
+            )
+        );
+        assert_eq!(
+            parse_ghcr_path(&url.unwrap()),
+            Some(("bat".to_string(), "static".to_string()))
+        );
+        assert_eq!(
+            github_recipe_url("github:pkgforge/soarpkgs:/packages/htop/appimage.yaml").as_deref(),
+            Some("https://raw.githubusercontent.com/pkgforge/soarpkgs/HEAD/packages/htop/appimage.yaml")
+        );
+
+        assert_eq!(
+            github_recipe_url("pkgforge/soarpkgs:binaries/bat/static.yaml"),
+            None
+        );
+        assert_eq!(
+            github_recipe_url("github:pkgforge:binaries/bat/static.yaml"),
+            None
+        );
+        assert_eq!(github_recipe_url("github:pkgforge/soarpkgs@main"), None);
+        assert_eq!(recipe_url("binaries/bat/static.yaml"), None);
+        assert_eq!(
+            recipe_url("https://example.com/static.yaml").as_deref(),
+            Some("https://example.com/static.yaml")
+        );
+    }
+
+    #[test]
+    fn parse_ghcr_path_rejects_bare_file() {
+        assert_eq!(parse_ghcr_path("packages/static.yaml"), None);
+        assert_eq!(parse_ghcr_path("other/htop/static.yaml"), None);
+    }
+}
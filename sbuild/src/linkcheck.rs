@@ -0,0 +1,102 @@
+//! Reachability checks for recipe `src_url` and `homepage` links
+
+use std::{env, time::Duration};
+
+use reqwest::{
+    blocking::{Client, Response},
+    header::RANGE,
+    StatusCode,
+};
+
+use crate::constant::DEFAULT_USER_AGENT;
+
+/// Outcome of checking one URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkStatus {
+    Ok,
+    /// Reachable, but only after following redirects to `final_url`
+    Redirected {
+        final_url: String,
+    },
+    /// The server answered with an error status
+    Broken {
+        status: u16,
+    },
+    /// No usable response (DNS, TLS, timeout, ...)
+    Unreachable {
+        error: String,
+    },
+}
+
+impl LinkStatus {
+    /// Classify the final response to a request for `url`
+    pub fn classify(url: &str, status: StatusCode, final_url: &str) -> Self {
+        if !(status.is_success() || status.is_redirection()) {
+            LinkStatus::Broken {
+                status: status.as_u16(),
+            }
+        } else if final_url.trim_end_matches('/') != url.trim_end_matches('/') {
+            LinkStatus::Redirected {
+                final_url: final_url.to_string(),
+            }
+        } else {
+            LinkStatus::Ok
+        }
+    }
+}
+
+/// Client for link checks, sending `$USER_AGENT` like build scripts do
+pub fn link_client(timeout: Duration) -> reqwest::Result<Client> {
+    let user_agent = env::var("USER_AGENT").unwrap_or_else(|_| DEFAULT_USER_AGENT.to_string());
+    Client::builder()
+        .timeout(timeout)
+        .user_agent(user_agent)
+        .build()
+}
+
+fn send(client: &Client, url: &str) -> reqwest::Result<Response> {
+    let response = client.head(url).send()?;
+    // Some servers don't implement HEAD; a one-byte GET is the next cheapest probe
+    if matches!(response.status().as_u16(), 405 | 501) {
+        return client.get(url).header(RANGE, "bytes=0-0").send();
+    }
+    Ok(response)
+}
+
+/// HEAD `url`, following redirects
+pub fn check_link(client: &Client, url: &str) -> LinkStatus {
+    match send(client, url) {
+        Ok(response) => LinkStatus::classify(url, response.status(), response.url().as_str()),
+        Err(e) => LinkStatus::Unreachable {
+            error: e.without_url().to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let url = "https://github.com/foo/bar";
+        assert_eq!(
+            LinkStatus::classify(url, StatusCode::OK, url),
+            LinkStatus::Ok
+        );
+        assert_eq!(
+            LinkStatus::classify(url, StatusCode::OK, "https://github.com/foo/bar/"),
+            LinkStatus::Ok
+        );
+        assert_eq!(
+            LinkStatus::classify(url, StatusCode::OK, "https://github.com/baz/bar"),
+            LinkStatus::Redirected {
+                final_url: "https://github.com/baz/bar".to_string()
+            }
+        );
+        assert_eq!(
+            LinkStatus::classify(url, StatusCode::NOT_FOUND, url),
+            LinkStatus::Broken { status: 404 }
+        );
+    }
+}
@@ -9,6 +9,18 @@ use sbuild::types::SoarEnv;
 #[command(about = "Toolchain for building, linting, and managing SBUILD packages", long_about = None)]
 #[command(version)]
 struct Cli {
+    /// Disable colored output (also honors NO_COLOR)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Suppress the banner and informational output; errors are still shown
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Print and write JSON on one line instead of pretty-printed
+    #[arg(long, global = true)]
+    compact_json: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -20,6 +32,10 @@ enum Commands {
     Cache(commands::cache::CacheArgs),
     Lint(commands::lint::LintArgs),
     Meta(commands::meta::MetaArgs),
+    ChecksumVerify(commands::checksum::ChecksumVerifyArgs),
+    Doctor(commands::doctor::DoctorArgs),
+    #[command(alias = "version")]
+    Versions(commands::versions::VersionsArgs),
 }
 
 fn get_soar_env() -> Option<SoarEnv> {
@@ -49,12 +65,25 @@ fn get_soar_env() -> Option<SoarEnv> {
 async fn main() {
     let cli = Cli::parse();
 
+    if cli.no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        colored::control::set_override(false);
+    }
+    let quiet = cli.quiet;
+    let compact_json = cli.compact_json;
+
     let result = match cli.command {
-        Commands::Build(args) => commands::build::run(args, get_soar_env()).await,
-        Commands::Info(args) => commands::info::run(args).await,
-        Commands::Cache(args) => commands::cache::run(args).await.map_err(|e| e.to_string()),
-        Commands::Lint(args) => commands::lint::run(args),
-        Commands::Meta(args) => commands::meta::run(args).await.map_err(|e| e.to_string()),
+        Commands::Build(args) => commands::build::run(args, get_soar_env(), quiet).await,
+        Commands::Info(args) => commands::info::run(args, quiet, compact_json).await,
+        Commands::Cache(args) => commands::cache::run(args, quiet, compact_json)
+            .await
+            .map_err(|e| e.to_string()),
+        Commands::Lint(args) => commands::lint::run(args, quiet, compact_json).await,
+        Commands::Meta(args) => commands::meta::run(args, quiet, compact_json)
+            .await
+            .map_err(|e| e.to_string()),
+        Commands::ChecksumVerify(args) => commands::checksum::run(args, quiet),
+        Commands::Doctor(args) => commands::doctor::run(args, get_soar_env(), quiet),
+        Commands::Versions(args) => commands::versions::run(args),
     };
 
     if let Err(e) = result {
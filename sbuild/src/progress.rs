@@ -0,0 +1,77 @@
+//! Single-line progress indicator for batch lint/build runs
+
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Processed/total counter with throughput and ETA, redrawn in place on stderr.
+///
+/// Rendering is skipped entirely when disabled or when stdout is piped, so
+/// callers can update it unconditionally.
+pub struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    start: Instant,
+    enabled: bool,
+}
+
+impl Progress {
+    pub fn new(total: usize, enabled: bool) -> Self {
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            start: Instant::now(),
+            enabled: enabled && total > 1 && io::stdout().is_terminal(),
+        }
+    }
+
+    /// Mark one item as processed and redraw
+    pub fn inc(&self) {
+        let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+        if !self.enabled {
+            return;
+        }
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = if rate > 0.0 {
+            format_duration(Duration::from_secs_f64(
+                self.total.saturating_sub(done) as f64 / rate,
+            ))
+        } else {
+            "-".to_string()
+        };
+
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r\x1b[2K[{}/{}] {:.2}/s, ETA {}",
+            done, self.total, rate, eta
+        );
+        let _ = stderr.flush();
+    }
+
+    /// Clear the progress line
+    pub fn finish(&self) {
+        if self.enabled {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
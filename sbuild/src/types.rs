@@ -13,6 +13,7 @@ pub enum PackageType {
     FlatImage,
     NixAppImage,
     Onelf,
+    Archive,
     Unknown,
 }
 
@@ -25,6 +26,7 @@ impl Display for PackageType {
             PackageType::NixAppImage => write!(f, "nixappimage"),
             PackageType::FlatImage => write!(f, "flatimage"),
             PackageType::Onelf => write!(f, "onelf"),
+            PackageType::Archive => write!(f, "archive"),
             PackageType::Unknown => write!(f, "unknown"),
         }
     }
@@ -2,9 +2,12 @@ use std::{
     env,
     fs::{self, File, OpenOptions},
     io::{BufReader, Read, Seek, SeekFrom, Write},
-    path::{Path, PathBuf},
+    os::unix::process::CommandExt,
+    path::{Component, Path, PathBuf},
     process::{Command, Stdio},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use futures::StreamExt;
@@ -14,23 +17,26 @@ use memmap2::Mmap;
 use reqwest::header::USER_AGENT;
 use sbuild_linter::logger::TaskLogger;
 
+use crate::constant::DEFAULT_USER_AGENT;
+
 pub async fn download<P: AsRef<Path>>(url: &str, out: P) -> Result<(), String> {
     let client = reqwest::Client::new();
     let response = client
         .get(url)
-        .header(USER_AGENT, "pkgforge/soar")
+        .header(USER_AGENT, DEFAULT_USER_AGENT)
         .send()
         .await
-        .unwrap();
+        .map_err(|e| e.without_url().to_string())?;
 
     if !response.status().is_success() {
-        return Err(format!("Error downloading {}", url));
+        return Err(format!("HTTP {}", response.status()));
     }
 
     let output_path = out.as_ref();
     if let Some(output_dir) = output_path.parent() {
         if !output_dir.exists() {
-            fs::create_dir_all(output_dir).unwrap();
+            fs::create_dir_all(output_dir)
+                .map_err(|e| format!("Failed to create {}: {}", output_dir.display(), e))?;
         }
     }
 
@@ -38,16 +44,19 @@ pub async fn download<P: AsRef<Path>>(url: &str, out: P) -> Result<(), String> {
     let mut stream = response.bytes_stream();
     let mut file = OpenOptions::new()
         .create(true)
-        .append(true)
+        .write(true)
+        .truncate(true)
         .open(&temp_path)
-        .unwrap();
+        .map_err(|e| format!("Failed to open {}: {}", temp_path, e))?;
 
     while let Some(chunk) = stream.next().await {
-        let chunk = chunk.unwrap();
-        file.write_all(&chunk).unwrap();
+        let chunk = chunk.map_err(|e| e.without_url().to_string())?;
+        file.write_all(&chunk)
+            .map_err(|e| format!("Failed to write {}: {}", temp_path, e))?;
     }
 
-    fs::rename(&temp_path, output_path).unwrap();
+    fs::rename(&temp_path, output_path)
+        .map_err(|e| format!("Failed to move {} into place: {}", temp_path, e))?;
 
     Ok(())
 }
@@ -179,6 +188,171 @@ pub fn self_extract_appimage(cmd: &str, mut pattern: String, dest: &str) {
     }
 }
 
+/// End of an ELF file's section header table, where an AppImage runtime
+/// stops and its payload starts
+fn elf_end(data: &[u8]) -> Option<u64> {
+    let header = Elf::parse_header(data).ok()?;
+    let table_size = u64::from(header.e_shentsize).checked_mul(u64::from(header.e_shnum))?;
+    header.e_shoff.checked_add(table_size)
+}
+
+/// Offset of the SquashFS image in an AppImage, for when squishy can't work
+/// it out: the end of the ELF runtime, as the AppImage tooling computes it.
+/// If the superblock isn't right there, the first one after the runtime is
+/// used; a `hsqs` inside the runtime itself never counts.
+pub fn find_squashfs_offset<P: AsRef<Path>>(file_path: P) -> Option<u64> {
+    use crate::constant::SQUASHFS_MAGIC_BYTES;
+
+    let file = File::open(file_path).ok()?;
+    let mmap = unsafe { Mmap::map(&file).ok()? };
+    let start = usize::try_from(elf_end(&mmap)?).ok()?;
+    mmap.get(start..)?
+        .windows(SQUASHFS_MAGIC_BYTES.len())
+        .position(|w| w == SQUASHFS_MAGIC_BYTES)
+        .map(|pos| (start + pos) as u64)
+}
+
+/// Paths in the SquashFS image at `offset`, relative to its root
+pub fn unsquashfs_list(unsquashfs: &Path, file_path: &Path, offset: u64) -> Option<Vec<String>> {
+    let output = Command::new(unsquashfs)
+        .args(["-offset", &offset.to_string(), "-l"])
+        .arg(file_path)
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.strip_prefix("squashfs-root/"))
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Resolve `.` and `..` in a path inside an image, without touching the disk
+fn normalize_image_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::ParentDir => {
+                out.pop();
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Extract `entry` from the SquashFS image at `offset` and copy it to
+/// `dest`, following symlinks inside the image. `tmp_dir` is used as the
+/// extraction root.
+pub fn unsquashfs_extract(
+    unsquashfs: &Path,
+    file_path: &Path,
+    offset: u64,
+    entry: &str,
+    tmp_dir: &Path,
+    dest: &Path,
+) -> bool {
+    let mut entry = PathBuf::from(entry);
+    for _ in 0..10 {
+        let extracted = Command::new(unsquashfs)
+            .args(["-offset", &offset.to_string(), "-force", "-dest"])
+            .arg(tmp_dir)
+            .arg(file_path)
+            .arg(&entry)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .stdin(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !extracted {
+            return false;
+        }
+
+        let extracted_path = tmp_dir.join(&entry);
+        match fs::read_link(&extracted_path) {
+            // Absolute targets are relative to the image root
+            Ok(target) => {
+                let parent = entry.parent().unwrap_or_else(|| Path::new(""));
+                entry = normalize_image_path(&parent.join(target));
+            }
+            Err(_) => return fs::copy(&extracted_path, dest).is_ok(),
+        }
+    }
+    false
+}
+
+/// How long a FlatImage gets to dump its desktop integration
+const FLATIMAGE_DUMP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Run a FlatImage's built-in `fim-desktop dump <what>` command.
+///
+/// FlatImage keeps its desktop integration in the reserved space of the
+/// binary, so it can be dumped without mounting the filesystem. The image
+/// runs in its own process group, killed with everything it spawned if it
+/// hasn't finished within [`FLATIMAGE_DUMP_TIMEOUT`].
+fn flatimage_desktop_dump<P: AsRef<Path>>(file_path: P, args: &[&str]) -> Option<Vec<u8>> {
+    flatimage_desktop_dump_within(file_path.as_ref(), args, FLATIMAGE_DUMP_TIMEOUT)
+}
+
+fn flatimage_desktop_dump_within(
+    file_path: &Path,
+    args: &[&str],
+    timeout: Duration,
+) -> Option<Vec<u8>> {
+    let exe = if file_path.is_relative() {
+        Path::new(".").join(file_path)
+    } else {
+        file_path.to_path_buf()
+    };
+
+    let child = Command::new(exe)
+        .args(["fim-desktop", "dump"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .ok()?;
+
+    let group = format!("-{}", child.id());
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let watchdog = thread::spawn(move || {
+        let timed_out = done_rx.recv_timeout(timeout).is_err();
+        if timed_out {
+            let _ = Command::new("kill").args(["-9", "--", &group]).output();
+        }
+        timed_out
+    });
+
+    let output = child.wait_with_output();
+    let _ = done_tx.send(());
+    let timed_out = watchdog.join().unwrap_or(true);
+    let output = output.ok()?;
+
+    (!timed_out && output.status.success()).then_some(output.stdout)
+}
+
+/// Dump the icon embedded in a FlatImage to `dest`.
+pub fn flatimage_extract_icon<P: AsRef<Path>, Q: AsRef<Path>>(file_path: P, dest: Q) -> bool {
+    let dest = dest.as_ref();
+    flatimage_desktop_dump(file_path, &["icon", &dest.to_string_lossy()]).is_some() && dest.exists()
+}
+
+/// Dump the desktop entry embedded in a FlatImage to `dest`.
+pub fn flatimage_extract_desktop<P: AsRef<Path>, Q: AsRef<Path>>(file_path: P, dest: Q) -> bool {
+    match flatimage_desktop_dump(file_path, &["entry"]) {
+        Some(entry) if !entry.trim_ascii().is_empty() => fs::write(dest, entry).is_ok(),
+        _ => false,
+    }
+}
+
 /// Detect an onelf-packed binary by its trailing footer magic.
 ///
 /// onelf files start with an ELF runtime stub, so they cannot be distinguished
@@ -210,6 +384,29 @@ pub fn is_onelf<P: AsRef<Path>>(file_path: P) -> bool {
     magic == ONELF_MAGIC_BYTES
 }
 
+/// Whether the file is a gzip, xz or zstd stream or an uncompressed tar
+pub fn is_archive<P: AsRef<Path>>(file_path: P) -> bool {
+    use crate::constant::{
+        GZIP_MAGIC_BYTES, TAR_MAGIC_BYTES, TAR_MAGIC_OFFSET, XZ_MAGIC_BYTES, ZSTD_MAGIC_BYTES,
+    };
+
+    let Ok(file) = File::open(file_path) else {
+        return false;
+    };
+    let mut header = Vec::with_capacity(TAR_MAGIC_OFFSET + TAR_MAGIC_BYTES.len());
+    if file
+        .take((TAR_MAGIC_OFFSET + TAR_MAGIC_BYTES.len()) as u64)
+        .read_to_end(&mut header)
+        .is_err()
+    {
+        return false;
+    }
+    header.starts_with(&GZIP_MAGIC_BYTES)
+        || header.starts_with(&XZ_MAGIC_BYTES)
+        || header.starts_with(&ZSTD_MAGIC_BYTES)
+        || header.get(TAR_MAGIC_OFFSET..) == Some(&TAR_MAGIC_BYTES[..])
+}
+
 pub fn is_static_elf<P: AsRef<Path>>(file_path: P) -> bool {
     let file = File::open(&file_path).unwrap();
     let mmap = unsafe { Mmap::map(&file).unwrap() };
@@ -225,6 +422,77 @@ pub fn expand_env_vars(input: &str, vars: &[(String, String)]) -> String {
     result
 }
 
+/// Total size of the regular files under `dir`, not following symlinks and
+/// ignoring anything under `skip`.
+pub fn dir_size<P: AsRef<Path>>(dir: P, skip: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.path() != skip)
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            if meta.is_dir() {
+                Some(dir_size(entry.path(), skip))
+            } else if meta.is_file() {
+                Some(meta.len())
+            } else {
+                None
+            }
+        })
+        .sum()
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`
+pub fn available_space<P: AsRef<Path>>(path: P) -> Option<u64> {
+    let output = Command::new("df")
+        .arg("-Pk")
+        .arg(path.as_ref())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let kib: u64 = stdout
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// Parse a human duration such as `90s`, `30m`, `24h`, `30d` or `2w`
+pub fn parse_duration(input: &str) -> Result<chrono::Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in '{}' (use s, m, h, d or w)", input))?;
+    let (value, unit) = input.split_at(split);
+    let value: i64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", input))?;
+
+    let duration = match unit {
+        "s" => chrono::Duration::try_seconds(value),
+        "m" => chrono::Duration::try_minutes(value),
+        "h" => chrono::Duration::try_hours(value),
+        "d" => chrono::Duration::try_days(value),
+        "w" => chrono::Duration::try_weeks(value),
+        _ => {
+            return Err(format!(
+                "unknown unit '{}' in '{}' (use s, m, h, d or w)",
+                unit, input
+            ))
+        }
+    };
+    duration.ok_or_else(|| format!("duration '{}' is too large", input))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -245,6 +513,96 @@ mod tests {
         assert!(is_onelf(file.path()));
     }
 
+    #[test]
+    fn squashfs_offset_starts_after_the_elf_runtime() {
+        use crate::constant::SQUASHFS_MAGIC_BYTES;
+
+        // 64-bit little-endian ELF whose section headers end at 0x180
+        let mut runtime = vec![0u8; 0x180];
+        runtime[..4].copy_from_slice(&[0x7f, 0x45, 0x4c, 0x46]);
+        runtime[4] = 2;
+        runtime[5] = 1;
+        runtime[0x28..0x30].copy_from_slice(&0x100u64.to_le_bytes());
+        runtime[0x3A..0x3C].copy_from_slice(&64u16.to_le_bytes());
+        runtime[0x3C..0x3E].copy_from_slice(&2u16.to_le_bytes());
+        // The runtime embeds the magic as a string literal
+        runtime[0x90..0x94].copy_from_slice(&SQUASHFS_MAGIC_BYTES);
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&runtime).unwrap();
+        file.write_all(&SQUASHFS_MAGIC_BYTES).unwrap();
+        file.write_all(&[0u8; 32]).unwrap();
+        file.flush().unwrap();
+        assert_eq!(find_squashfs_offset(file.path()), Some(0x180));
+
+        // Padding between the runtime and the image
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&runtime).unwrap();
+        file.write_all(&[0u8; 16]).unwrap();
+        file.write_all(&SQUASHFS_MAGIC_BYTES).unwrap();
+        file.flush().unwrap();
+        assert_eq!(find_squashfs_offset(file.path()), Some(0x190));
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&SQUASHFS_MAGIC_BYTES).unwrap();
+        file.flush().unwrap();
+        assert_eq!(find_squashfs_offset(file.path()), None);
+    }
+
+    #[test]
+    fn flatimage_dump_is_killed_after_timeout() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = |name: &str, body: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+
+        let quick = script("quick", "echo \"$3\"");
+        assert_eq!(
+            flatimage_desktop_dump_within(&quick, &["entry"], Duration::from_secs(10)),
+            Some(b"entry\n".to_vec())
+        );
+
+        // A child holding stdout open must not keep us waiting either
+        let hung = script("hung", "sleep 30 &\nsleep 30");
+        let started = std::time::Instant::now();
+        assert_eq!(
+            flatimage_desktop_dump_within(&hung, &["entry"], Duration::from_millis(200)),
+            None
+        );
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn parses_human_durations() {
+        assert_eq!(parse_duration("24h"), Ok(chrono::Duration::hours(24)));
+        assert_eq!(parse_duration("30d"), Ok(chrono::Duration::days(30)));
+        assert_eq!(parse_duration("2w"), Ok(chrono::Duration::days(14)));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("9223372036854775807w").is_err());
+        assert!(parse_duration("99999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn dir_size_skips_excluded_dir_and_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp = dir.path().join("SBUILD_TEMP");
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::create_dir_all(&tmp).unwrap();
+        fs::write(dir.path().join("a"), [0u8; 10]).unwrap();
+        fs::write(dir.path().join("sub/b"), [0u8; 5]).unwrap();
+        fs::write(tmp.join("c"), [0u8; 100]).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("a"), dir.path().join("link")).unwrap();
+
+        assert_eq!(dir_size(dir.path(), &tmp), 15);
+    }
+
     #[test]
     fn plain_elf_is_not_onelf() {
         let mut file = NamedTempFile::new().unwrap();
@@ -256,6 +614,34 @@ mod tests {
         assert!(!is_onelf(file.path()));
     }
 
+    #[test]
+    fn detects_archives() {
+        let archive = |bytes: &[u8]| {
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(bytes).unwrap();
+            file.flush().unwrap();
+            is_archive(file.path())
+        };
+        assert!(archive(&[0x1f, 0x8b, 0x08, 0x00]));
+        assert!(archive(&[0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00]));
+        assert!(archive(&[0x28, 0xb5, 0x2f, 0xfd, 0x04]));
+
+        let mut tar = vec![0u8; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert!(archive(&tar));
+
+        assert!(!archive(&[0x7f, 0x45, 0x4c, 0x46]));
+        assert!(!archive(b""));
+    }
+
+    #[test]
+    fn normalizes_image_paths() {
+        assert_eq!(
+            normalize_image_path(Path::new("usr/share/../../icon.png")),
+            PathBuf::from("icon.png")
+        );
+    }
+
     #[test]
     fn too_small_file_is_not_onelf() {
         let mut file = NamedTempFile::new().unwrap();